@@ -0,0 +1,163 @@
+#ifndef RUST_NES_H
+#define RUST_NES_H
+
+/* Generated with cbindgen from capi/src/lib.rs, don't edit by hand */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define RNES_CONTROLLER_ONE 0
+
+#define RNES_CONTROLLER_TWO 1
+
+#define RNES_BUTTON_A 0
+
+#define RNES_BUTTON_B 1
+
+#define RNES_BUTTON_SELECT 2
+
+#define RNES_BUTTON_START 3
+
+#define RNES_BUTTON_UP 4
+
+#define RNES_BUTTON_DOWN 5
+
+#define RNES_BUTTON_LEFT 6
+
+#define RNES_BUTTON_RIGHT 7
+
+typedef enum RnesResult {
+  RNES_RESULT_OK = 0,
+  /**
+   * A null pointer, a path which isn't UTF-8 or a controller or button out of range
+   */
+  RNES_RESULT_INVALID_ARGUMENT,
+  /**
+   * There's no rom loaded, either `rnes_load_rom` hasn't succeeded or the emulator panicked
+   */
+  RNES_RESULT_NO_ROM,
+  /**
+   * The rom couldn't be loaded, the previous rom (if any) is still running
+   */
+  RNES_RESULT_ROM_ERROR,
+  /**
+   * The state couldn't be loaded, the console is left in an undefined state
+   */
+  RNES_RESULT_STATE_ERROR,
+  /**
+   * The buffer is too small for the state, the size needed has been written to `size`
+   */
+  RNES_RESULT_BUFFER_TOO_SMALL,
+  /**
+   * The emulator panicked and the rom has been unloaded
+   */
+  RNES_RESULT_PANICKED,
+} RnesResult;
+
+/**
+ * An emulator instance, opaque to hosts
+ */
+typedef struct RustNes RustNes;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create an emulator without a rom, free it with `rnes_destroy`
+ */
+RustNes *rnes_create(void);
+
+/**
+ * # Safety
+ *
+ * `handle` must have come from `rnes_create` and not already have been destroyed, null is ignored.
+ */
+void rnes_destroy(RustNes *handle);
+
+/**
+ * Load a rom (or zip/gz/7z archive containing one), replacing any rom already running
+ *
+ * # Safety
+ *
+ * `handle` must be a live handle from `rnes_create` and `path` a null terminated string.
+ */
+RnesResult rnes_load_rom(RustNes *handle, const char *path);
+
+/**
+ * Run the console to the end of the next frame, afterwards the frame is available from
+ * `rnes_framebuffer` and its audio from `rnes_audio_samples`
+ *
+ * # Safety
+ *
+ * `handle` must be a live handle from `rnes_create`.
+ */
+RnesResult rnes_run_frame(RustNes *handle);
+
+/**
+ * The last frame as BGRA, 4 bytes per pixel in rows of `width` pixels. The frame is 256x240
+ * unless an HD pack is loaded. Returns null if there's no rom loaded, otherwise the pointer is
+ * valid until the next call to any other function with this handle.
+ *
+ * # Safety
+ *
+ * `handle` must be a live handle from `rnes_create`, `width` and `height` may be null.
+ */
+const uint8_t *rnes_framebuffer(RustNes *handle, uint32_t *width, uint32_t *height);
+
+/**
+ * The audio produced during the last frame, one sample in the range 0..1 per CPU cycle (i.e.
+ * at 1789773Hz on NTSC) for the host to resample. The pointer is valid until the next call to
+ * any other function with this handle.
+ *
+ * # Safety
+ *
+ * `handle` must be a live handle from `rnes_create` and `count` not null.
+ */
+const float *rnes_audio_samples(RustNes *handle, size_t *count);
+
+/**
+ * Press or release one of the `RNES_BUTTON_` buttons on one of the `RNES_CONTROLLER_` controllers
+ *
+ * # Safety
+ *
+ * `handle` must be a live handle from `rnes_create`.
+ */
+RnesResult rnes_set_button(RustNes *handle, uint32_t controller, uint32_t button, bool pressed);
+
+/**
+ * Save the console's state into `buffer`, the size of the state is written to `size` whether or
+ * not it fits so hosts can call this with a null buffer and a capacity of 0 to size the buffer.
+ *
+ * # Safety
+ *
+ * `handle` must be a live handle from `rnes_create`, `buffer` must have room for `capacity`
+ * bytes (or be null if `capacity` is 0) and `size` may be null.
+ */
+RnesResult rnes_save_state(RustNes *handle, uint8_t *buffer, size_t capacity, size_t *size);
+
+/**
+ * Restore a state from `rnes_save_state`, the console must be running the same rom
+ *
+ * # Safety
+ *
+ * `handle` must be a live handle from `rnes_create` and `buffer` must hold `size` bytes.
+ */
+RnesResult rnes_load_state(RustNes *handle, const uint8_t *buffer, size_t size);
+
+/**
+ * Why the last function to fail did so, empty if nothing has failed. The string is owned by
+ * the handle and valid until the next call to any other function with it.
+ *
+ * # Safety
+ *
+ * `handle` must be a live handle from `rnes_create`.
+ */
+const char *rnes_last_error(const RustNes *handle);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RUST_NES_H */
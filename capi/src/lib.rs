@@ -0,0 +1,374 @@
+//! A C ABI for embedding the emulator in hosts written in other languages (C, C++, C# and Python
+//! through ctypes), the header is `include/rust_nes.h`.
+//!
+//! A host creates a handle with `rnes_create`, loads a rom into it and then alternates between
+//! setting buttons and running frames. Functions which can fail return an `RnesResult` and the
+//! reason for the last failure is available from `rnes_last_error`.
+
+extern crate rust_nes;
+
+use rust_nes::io::{Button, Controller};
+use rust_nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rust_nes::{run_frame, Frame, Nes, VideoSink};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+pub const RNES_CONTROLLER_ONE: u32 = 0;
+pub const RNES_CONTROLLER_TWO: u32 = 1;
+
+pub const RNES_BUTTON_A: u32 = 0;
+pub const RNES_BUTTON_B: u32 = 1;
+pub const RNES_BUTTON_SELECT: u32 = 2;
+pub const RNES_BUTTON_START: u32 = 3;
+pub const RNES_BUTTON_UP: u32 = 4;
+pub const RNES_BUTTON_DOWN: u32 = 5;
+pub const RNES_BUTTON_LEFT: u32 = 6;
+pub const RNES_BUTTON_RIGHT: u32 = 7;
+
+/// Indexed by the `RNES_CONTROLLER_` constants
+const CONTROLLERS: [Controller; 2] = [Controller::One, Controller::Two];
+
+/// Indexed by the `RNES_BUTTON_` constants
+const BUTTONS: [Button; 8] = [
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RnesResult {
+    Ok = 0,
+    /// A null pointer, a path which isn't UTF-8 or a controller or button out of range
+    InvalidArgument,
+    /// There's no rom loaded, either `rnes_load_rom` hasn't succeeded or the emulator panicked
+    NoRom,
+    /// The rom couldn't be loaded, the previous rom (if any) is still running
+    RomError,
+    /// The state couldn't be loaded, the console is left in an undefined state
+    StateError,
+    /// The buffer is too small for the state, the size needed has been written to `size`
+    BufferTooSmall,
+    /// The emulator panicked and the rom has been unloaded
+    Panicked,
+}
+
+/// An emulator instance, opaque to hosts
+pub struct RustNes {
+    nes: Option<Nes>,
+    /// The audio from the last frame run
+    samples: Vec<f32>,
+    last_error: CString,
+}
+
+impl RustNes {
+    fn fail(&mut self, result: RnesResult, message: &str) -> RnesResult {
+        self.last_error = CString::new(message).unwrap_or_default();
+        result
+    }
+
+    /// Unwinding into the host is undefined behaviour so panics are caught at the boundary, the
+    /// console may be half way through an update so it's dropped
+    fn panicked(&mut self) -> RnesResult {
+        self.nes = None;
+        self.fail(RnesResult::Panicked, "The emulator panicked, load the rom again")
+    }
+}
+
+/// Frames are read back with `rnes_framebuffer` rather than presented
+struct NoVideo;
+
+impl VideoSink for NoVideo {
+    fn present(&mut self, _: &Frame) {}
+}
+
+/// Create an emulator without a rom, free it with `rnes_destroy`
+#[no_mangle]
+pub extern "C" fn rnes_create() -> *mut RustNes {
+    Box::into_raw(Box::new(RustNes {
+        nes: None,
+        samples: vec![],
+        last_error: CString::default(),
+    }))
+}
+
+/// # Safety
+///
+/// `handle` must have come from `rnes_create` and not already have been destroyed, null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_destroy(handle: *mut RustNes) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Load a rom (or zip/gz/7z archive containing one), replacing any rom already running
+///
+/// # Safety
+///
+/// `handle` must be a live handle from `rnes_create` and `path` a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_load_rom(handle: *mut RustNes, path: *const c_char) -> RnesResult {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return RnesResult::InvalidArgument,
+    };
+    if path.is_null() {
+        return handle.fail(RnesResult::InvalidArgument, "The rom path is null");
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(_) => return handle.fail(RnesResult::InvalidArgument, "The rom path isn't UTF-8"),
+    };
+
+    match catch_unwind(|| rust_nes::get_cartridge(path).map(Nes::new)) {
+        Ok(Ok(nes)) => {
+            handle.nes = Some(nes);
+            handle.samples.clear();
+            RnesResult::Ok
+        }
+        Ok(Err(why)) => handle.fail(RnesResult::RomError, &why.message),
+        Err(_) => handle.panicked(),
+    }
+}
+
+/// Run the console to the end of the next frame, afterwards the frame is available from
+/// `rnes_framebuffer` and its audio from `rnes_audio_samples`
+///
+/// # Safety
+///
+/// `handle` must be a live handle from `rnes_create`.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_run_frame(handle: *mut RustNes) -> RnesResult {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return RnesResult::InvalidArgument,
+    };
+    let nes = match handle.nes.as_mut() {
+        Some(nes) => nes,
+        None => return handle.fail(RnesResult::NoRom, "No rom is loaded"),
+    };
+
+    let samples = &mut handle.samples;
+    samples.clear();
+    match catch_unwind(AssertUnwindSafe(|| run_frame(nes, &mut NoVideo, samples))) {
+        Ok(()) => RnesResult::Ok,
+        Err(_) => handle.panicked(),
+    }
+}
+
+/// The last frame as BGRA, 4 bytes per pixel in rows of `width` pixels. The frame is 256x240
+/// unless an HD pack is loaded. Returns null if there's no rom loaded, otherwise the pointer is
+/// valid until the next call to any other function with this handle.
+///
+/// # Safety
+///
+/// `handle` must be a live handle from `rnes_create`, `width` and `height` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_framebuffer(handle: *mut RustNes, width: *mut u32, height: *mut u32) -> *const u8 {
+    let nes = match handle.as_mut().and_then(|handle| handle.nes.as_mut()) {
+        Some(nes) => nes,
+        None => return ptr::null(),
+    };
+
+    let (pixels, scale) = match nes.get_hd_framebuffer().map(|(pixels, scale)| (pixels.as_ptr(), scale)) {
+        Some(hd) => hd,
+        None => (nes.get_framebuffer().as_ptr(), 1),
+    };
+    if !width.is_null() {
+        *width = SCREEN_WIDTH * scale;
+    }
+    if !height.is_null() {
+        *height = SCREEN_HEIGHT * scale;
+    }
+
+    pixels
+}
+
+/// The audio produced during the last frame, one sample in the range 0..1 per CPU cycle (i.e.
+/// at 1789773Hz on NTSC) for the host to resample. The pointer is valid until the next call to
+/// any other function with this handle.
+///
+/// # Safety
+///
+/// `handle` must be a live handle from `rnes_create` and `count` not null.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_audio_samples(handle: *mut RustNes, count: *mut usize) -> *const f32 {
+    match handle.as_ref() {
+        Some(handle) if !count.is_null() => {
+            *count = handle.samples.len();
+            handle.samples.as_ptr()
+        }
+        _ => ptr::null(),
+    }
+}
+
+/// Press or release one of the `RNES_BUTTON_` buttons on one of the `RNES_CONTROLLER_` controllers
+///
+/// # Safety
+///
+/// `handle` must be a live handle from `rnes_create`.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_set_button(
+    handle: *mut RustNes,
+    controller: u32,
+    button: u32,
+    pressed: bool,
+) -> RnesResult {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return RnesResult::InvalidArgument,
+    };
+    let (controller, button) = match (CONTROLLERS.get(controller as usize), BUTTONS.get(button as usize)) {
+        (Some(controller), Some(button)) => (*controller, *button),
+        _ => return handle.fail(RnesResult::InvalidArgument, "Unknown controller or button"),
+    };
+    let nes = match handle.nes.as_mut() {
+        Some(nes) => nes,
+        None => return handle.fail(RnesResult::NoRom, "No rom is loaded"),
+    };
+
+    if pressed {
+        nes.button_down(controller, button);
+    } else {
+        nes.button_up(controller, button);
+    }
+    RnesResult::Ok
+}
+
+/// Save the console's state into `buffer`, the size of the state is written to `size` whether or
+/// not it fits so hosts can call this with a null buffer and a capacity of 0 to size the buffer.
+///
+/// # Safety
+///
+/// `handle` must be a live handle from `rnes_create`, `buffer` must have room for `capacity`
+/// bytes (or be null if `capacity` is 0) and `size` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_save_state(
+    handle: *mut RustNes,
+    buffer: *mut u8,
+    capacity: usize,
+    size: *mut usize,
+) -> RnesResult {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return RnesResult::InvalidArgument,
+    };
+    let nes = match handle.nes.as_mut() {
+        Some(nes) => nes,
+        None => return handle.fail(RnesResult::NoRom, "No rom is loaded"),
+    };
+
+    let state = match catch_unwind(AssertUnwindSafe(|| nes.save_state())) {
+        Ok(state) => state,
+        Err(_) => return handle.panicked(),
+    };
+    if !size.is_null() {
+        *size = state.len();
+    }
+    if state.len() > capacity {
+        return handle.fail(RnesResult::BufferTooSmall, "The buffer is too small for the state");
+    }
+    if buffer.is_null() {
+        return handle.fail(RnesResult::InvalidArgument, "The buffer is null");
+    }
+
+    ptr::copy_nonoverlapping(state.as_ptr(), buffer, state.len());
+    RnesResult::Ok
+}
+
+/// Restore a state from `rnes_save_state`, the console must be running the same rom
+///
+/// # Safety
+///
+/// `handle` must be a live handle from `rnes_create` and `buffer` must hold `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_load_state(handle: *mut RustNes, buffer: *const u8, size: usize) -> RnesResult {
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return RnesResult::InvalidArgument,
+    };
+    if buffer.is_null() {
+        return handle.fail(RnesResult::InvalidArgument, "The buffer is null");
+    }
+    let nes = match handle.nes.as_mut() {
+        Some(nes) => nes,
+        None => return handle.fail(RnesResult::NoRom, "No rom is loaded"),
+    };
+
+    let state = slice::from_raw_parts(buffer, size);
+    match catch_unwind(AssertUnwindSafe(|| nes.load_state(state))) {
+        Ok(Ok(())) => RnesResult::Ok,
+        Ok(Err(why)) => handle.fail(RnesResult::StateError, &why.message),
+        Err(_) => handle.panicked(),
+    }
+}
+
+/// Why the last function to fail did so, empty if nothing has failed. The string is owned by
+/// the handle and valid until the next call to any other function with it.
+///
+/// # Safety
+///
+/// `handle` must be a live handle from `rnes_create`.
+#[no_mangle]
+pub unsafe extern "C" fn rnes_last_error(handle: *const RustNes) -> *const c_char {
+    match handle.as_ref() {
+        Some(handle) => handle.last_error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod capi_tests {
+    use super::*;
+
+    #[test]
+    fn test_run_frames_and_save_states_through_handle() {
+        unsafe {
+            let handle = rnes_create();
+            assert_eq!(rnes_run_frame(handle), RnesResult::NoRom);
+
+            let path = CString::new("../roms/test/nestest.nes").unwrap();
+            assert_eq!(rnes_load_rom(handle, path.as_ptr()), RnesResult::Ok);
+            assert_eq!(
+                rnes_set_button(handle, RNES_CONTROLLER_ONE, RNES_BUTTON_START, true),
+                RnesResult::Ok
+            );
+            assert_eq!(
+                rnes_set_button(handle, 2, RNES_BUTTON_START, true),
+                RnesResult::InvalidArgument
+            );
+            assert_eq!(rnes_run_frame(handle), RnesResult::Ok);
+
+            let (mut width, mut height, mut count) = (0, 0, 0);
+            assert!(!rnes_framebuffer(handle, &mut width, &mut height).is_null());
+            assert_eq!((width, height), (SCREEN_WIDTH, SCREEN_HEIGHT));
+            assert!(!rnes_audio_samples(handle, &mut count).is_null());
+            assert!(count > 0);
+
+            let mut size = 0;
+            assert_eq!(
+                rnes_save_state(handle, ptr::null_mut(), 0, &mut size),
+                RnesResult::BufferTooSmall
+            );
+            let mut state = vec![0; size];
+            assert_eq!(
+                rnes_save_state(handle, state.as_mut_ptr(), state.len(), &mut size),
+                RnesResult::Ok
+            );
+            assert_eq!(rnes_load_state(handle, state.as_ptr(), size), RnesResult::Ok);
+            assert_eq!(rnes_load_state(handle, state.as_ptr(), 4), RnesResult::StateError);
+            assert!(!CStr::from_ptr(rnes_last_error(handle)).to_bytes().is_empty());
+
+            rnes_destroy(handle);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt::{Display, Formatter, Result};
+use std::str::FromStr;
+
+/// Trades emulation fidelity for speed by toggling behaviours which are
+/// expensive to emulate and which very few games rely on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccuracyProfile {
+    /// Emulate everything we know how to, regardless of cost
+    Accurate,
+    /// Skip behaviours which only matter to test ROMs and a handful of games, this
+    /// is the profile used by `Nes::new`
+    Balanced,
+    /// Cut every corner available, intended for weak devices
+    Fast,
+}
+
+impl AccuracyProfile {
+    /// Read-modify-write instructions write the unmodified value back to the
+    /// bus before writing the result, visible to mapper registers and $2007.
+    pub(crate) fn rmw_dummy_writes(self) -> bool {
+        self != AccuracyProfile::Fast
+    }
+
+    /// The PPU I/O latch returned on open bus reads decays to 0 if it isn't refreshed.
+    pub(crate) fn open_bus_decay(self) -> bool {
+        self == AccuracyProfile::Accurate
+    }
+
+    /// Draw each visible line in one pass at dot 256 so mid line changes to the scroll, PPUMASK
+    /// and palette only apply from the next line. Fetches still happen every dot for mappers
+    /// watching the PPU bus, and lines where sprite 0 could hit are drawn a dot at a time.
+    pub(crate) fn scanline_rendering(self) -> bool {
+        self == AccuracyProfile::Fast
+    }
+
+    /// Mix APU channels using the non linear DAC curves rather than a linear approximation
+    pub(crate) fn precise_audio_mixing(self) -> bool {
+        self != AccuracyProfile::Fast
+    }
+}
+
+impl Display for AccuracyProfile {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            AccuracyProfile::Accurate => write!(f, "accurate"),
+            AccuracyProfile::Balanced => write!(f, "balanced"),
+            AccuracyProfile::Fast => write!(f, "fast"),
+        }
+    }
+}
+
+impl FromStr for AccuracyProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "accurate" => Ok(AccuracyProfile::Accurate),
+            "balanced" => Ok(AccuracyProfile::Balanced),
+            "fast" => Ok(AccuracyProfile::Fast),
+            _ => Err(format!(
+                "Unknown accuracy profile {}, expected accurate, balanced or fast",
+                s
+            )),
+        }
+    }
+}
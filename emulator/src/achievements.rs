@@ -0,0 +1,93 @@
+//! Memory exposed to achievement runtimes such as rcheevos, see `Nes::peek_achievement_memory`.
+//!
+//! Achievement definitions address the console's memory by flat addresses which match the CPU
+//! address space, so the same definition works in any emulator:
+//!
+//! | Flat address    | Contents                                                            |
+//! |-----------------|---------------------------------------------------------------------|
+//! | `$0000-$07FF`   | The 2KB of internal RAM                                             |
+//! | `$0800-$1FFF`   | Mirrors of internal RAM                                             |
+//! | `$2000-$3FFF`   | PPU registers and their mirrors, read as 0                          |
+//! | `$4000-$401F`   | APU and controller registers, read as 0                             |
+//! | `$4020-$5FFF`   | Cartridge expansion area, e.g. MMC5 ExRAM, whatever the mapper maps |
+//! | `$6000-$7FFF`   | Cartridge (usually battery backed) RAM, the currently mapped bank   |
+//! | `$8000-$FFFF`   | Cartridge ROM, the currently mapped banks                           |
+//!
+//! Registers read as 0 because reading them has side effects (e.g. clearing the vblank flag)
+//! which would change how the game runs. Addresses beyond `$FFFF` read as 0.
+
+use self::AchievementMemoryType::{HardwareRegister, Mirror, ReadOnly, SaveRam, SystemRam};
+use nes::Nes;
+
+/// The kind of memory in a region of the flat address space, matching the memory types
+/// achievement runtimes use to decide which regions are worth searching
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AchievementMemoryType {
+    SystemRam,
+    /// Another view of memory which appears elsewhere in the map
+    Mirror,
+    HardwareRegister,
+    SaveRam,
+    ReadOnly,
+}
+
+/// A contiguous range of the flat address space
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AchievementMemoryRegion {
+    pub start: u32,
+    /// Inclusive
+    pub end: u32,
+    /// The address this region is a view of, differs from `start` for mirrors
+    pub real_address: u32,
+    pub memory_type: AchievementMemoryType,
+    pub description: &'static str,
+}
+
+const fn region(
+    start: u32,
+    end: u32,
+    real_address: u32,
+    memory_type: AchievementMemoryType,
+    description: &'static str,
+) -> AchievementMemoryRegion {
+    AchievementMemoryRegion {
+        start,
+        end,
+        real_address,
+        memory_type,
+        description,
+    }
+}
+
+/// The regions of the flat address space in order, see the module documentation
+pub const ACHIEVEMENT_MEMORY_MAP: [AchievementMemoryRegion; 10] = [
+    region(0x0000, 0x07FF, 0x0000, SystemRam, "System RAM"),
+    region(0x0800, 0x0FFF, 0x0000, Mirror, "Mirror RAM"),
+    region(0x1000, 0x17FF, 0x0000, Mirror, "Mirror RAM"),
+    region(0x1800, 0x1FFF, 0x0000, Mirror, "Mirror RAM"),
+    region(0x2000, 0x2007, 0x2000, HardwareRegister, "PPU Register"),
+    region(0x2008, 0x3FFF, 0x2000, Mirror, "Mirrored PPU Register"),
+    region(0x4000, 0x401F, 0x4000, HardwareRegister, "Hardware Register"),
+    region(0x4020, 0x5FFF, 0x4020, ReadOnly, "Cartridge data"),
+    region(0x6000, 0x7FFF, 0x6000, SaveRam, "Cartridge RAM"),
+    region(0x8000, 0xFFFF, 0x8000, ReadOnly, "Cartridge ROM"),
+];
+
+/// Called once a frame has completed, e.g. to evaluate achievements, see `Nes::set_frame_callback`
+pub type FrameCallback = Box<dyn FnMut(&Nes) + Send>;
+
+#[cfg(test)]
+mod achievements_tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_map_covers_address_space() {
+        let mut next = 0;
+        for region in ACHIEVEMENT_MEMORY_MAP.iter() {
+            assert_eq!(region.start, next);
+            assert!(region.end >= region.start);
+            next = region.end + 1;
+        }
+        assert_eq!(next, 0x10000);
+    }
+}
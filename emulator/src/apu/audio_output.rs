@@ -0,0 +1,99 @@
+use apu::resampler::{Resampler, ResamplerQuality};
+
+/// How a frontend should play the APU output, larger buffers and more latency trade
+/// responsiveness for fewer underruns (crackling) on slow or busy machines
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AudioOutputConfig {
+    /// Output samples per second
+    pub sample_rate: u32,
+    /// Samples in each buffer the audio device plays, a power of two for SDL
+    pub buffer_size: u16,
+    /// How much audio to keep queued ahead of the device, in milliseconds
+    pub target_latency_ms: u32,
+    pub quality: ResamplerQuality,
+}
+
+impl Default for AudioOutputConfig {
+    fn default() -> Self {
+        AudioOutputConfig {
+            sample_rate: 44_100,
+            buffer_size: 1024,
+            target_latency_ms: 40,
+            quality: ResamplerQuality::Medium,
+        }
+    }
+}
+
+impl AudioOutputConfig {
+    /// The target latency in output samples
+    pub fn target_latency_samples(&self) -> usize {
+        self.sample_rate as usize * self.target_latency_ms as usize / 1000
+    }
+
+    /// A resampler from the APU's rate to this output rate
+    pub fn resampler(&self, input_rate: f64) -> Resampler {
+        Resampler::new(input_rate, self.sample_rate as f64, self.quality)
+    }
+}
+
+/// Counts the times an audio queue ran dry before it was refilled, each of which is heard as a
+/// click or gap
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct UnderrunCounter {
+    started: bool,
+    underruns: u32,
+}
+
+impl UnderrunCounter {
+    pub fn new() -> Self {
+        UnderrunCounter::default()
+    }
+
+    /// Called with the number of samples still queued just before queueing more, returns
+    /// whether the queue had run dry. The first call is ignored as nothing has been queued yet.
+    pub fn record(&mut self, queued_samples: usize) -> bool {
+        let underrun = self.started && queued_samples == 0;
+        self.started = true;
+        if underrun {
+            self.underruns += 1;
+        }
+
+        underrun
+    }
+
+    /// Forget about the queue, e.g. while paused, so that the next call to `record` is ignored
+    pub fn restart(&mut self) {
+        self.started = false;
+    }
+
+    pub fn underruns(&self) -> u32 {
+        self.underruns
+    }
+}
+
+#[cfg(test)]
+mod audio_output_tests {
+    use super::*;
+
+    #[test]
+    fn test_target_latency_samples() {
+        let config = AudioOutputConfig {
+            sample_rate: 48_000,
+            target_latency_ms: 25,
+            ..AudioOutputConfig::default()
+        };
+        assert_eq!(config.target_latency_samples(), 1200);
+    }
+
+    #[test]
+    fn test_underrun_counter() {
+        let mut counter = UnderrunCounter::new();
+        assert!(!counter.record(0));
+        assert!(!counter.record(512));
+        assert!(counter.record(0));
+        counter.restart();
+        assert!(!counter.record(0));
+        assert!(counter.record(0));
+        assert_eq!(counter.underruns(), 2);
+    }
+}
@@ -2,6 +2,10 @@ const RATE_TABLE: [u16; 0x10] = [
     428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
 ];
 
+/// When reducing pops the output moves one level towards the direct load value every
+/// this many CPU cycles, so a full scale jump is spread over roughly half a millisecond.
+const POP_REDUCTION_CYCLES_PER_LEVEL: u8 = 8;
+
 #[derive(Debug)]
 struct DmcOutputUnit {
     shift_register: u8,
@@ -10,6 +14,13 @@ struct DmcOutputUnit {
     silence_flag: bool,
 }
 
+save_state_fields!(DmcOutputUnit {
+    shift_register,
+    bits_remaining_counter,
+    output_level,
+    silence_flag,
+});
+
 #[derive(Debug)]
 pub(super) struct DmcChannel {
     enabled: bool,
@@ -31,10 +42,16 @@ pub(super) struct DmcChannel {
     sample_address: u16,
     /// The number of bytes read from memory
     sample_length: u16,
+    /// Writes to $4011 jump the output level instantly which is heard as a pop, if set the
+    /// output moves towards the new level gradually instead.
+    reduce_pops: bool,
+    /// The level actually output when reducing pops
+    smoothed_output_level: u8,
+    pop_reduction_countdown: u8,
 }
 
 impl DmcChannel {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(reduce_pops: bool) -> Self {
         DmcChannel {
             enabled: false,
             rate: RATE_TABLE[0],
@@ -50,6 +67,9 @@ impl DmcChannel {
             },
             sample_address: 0xC000,
             sample_length: 1,
+            reduce_pops,
+            smoothed_output_level: 0,
+            pop_reduction_countdown: POP_REDUCTION_CYCLES_PER_LEVEL,
         }
     }
 
@@ -83,11 +103,79 @@ impl DmcChannel {
     }
 
     pub(super) fn clock_timer(&mut self) {
-        // TODO
+        // TODO - The memory reader. There's no sample playback yet, when it's added:
+        //  - The address after $FFFF is $8000 rather than $0000, a sample starting near the end
+        //    of the address space (up to $FFC0 + 4081 bytes) carries on from the start of PRG.
+        //  - Each fetch is a DMA which stalls the CPU for up to 4 cycles and repeats the CPU's
+        //    read on the stalled cycles. Reads of $4016/$4017 are seen twice by the controllers
+        //    and lose a bit (as are $2002/$2007 reads), games read the controllers repeatedly
+        //    until they agree to work around it. That should be emulated by default with an
+        //    option to skip the repeated controller read for playability, c.f.
+        //    https://wiki.nesdev.com/w/index.php/APU_DMC#Conflict_with_controller_and_PPU_read
+    }
+
+    /// Called once per CPU clock to move the output towards the last direct load
+    pub(super) fn clock_pop_reduction(&mut self) {
+        if !self.reduce_pops || self.smoothed_output_level == self.output_unit.output_level {
+            return;
+        }
+
+        self.pop_reduction_countdown -= 1;
+        if self.pop_reduction_countdown == 0 {
+            self.pop_reduction_countdown = POP_REDUCTION_CYCLES_PER_LEVEL;
+            if self.smoothed_output_level < self.output_unit.output_level {
+                self.smoothed_output_level += 1;
+            } else {
+                self.smoothed_output_level -= 1;
+            }
+        }
     }
 
     pub(super) fn mixer_value(&self) -> u8 {
-        // TODO
-        0
+        // TODO - Sample playback, only direct loads change the output level so far
+        if self.reduce_pops {
+            self.smoothed_output_level
+        } else {
+            self.output_unit.output_level
+        }
+    }
+}
+
+save_state_fields!(DmcChannel {
+    enabled,
+    rate,
+    timer_countdown,
+    irq_enabled_flag,
+    irq_flag,
+    loop_flag,
+    output_unit,
+    sample_address,
+    sample_length,
+    smoothed_output_level,
+    pop_reduction_countdown,
+});
+
+#[cfg(test)]
+mod dmc_channel_tests {
+    use super::*;
+
+    #[test]
+    fn test_direct_load_ramps_when_reducing_pops() {
+        let mut raw = DmcChannel::new(false);
+        let mut reduced = DmcChannel::new(true);
+        raw.direct_load(0x40);
+        reduced.direct_load(0x40);
+        assert_eq!(raw.mixer_value(), 0x40);
+        assert_eq!(reduced.mixer_value(), 0);
+
+        for _ in 0..POP_REDUCTION_CYCLES_PER_LEVEL as usize * 0x20 {
+            reduced.clock_pop_reduction();
+        }
+        assert_eq!(reduced.mixer_value(), 0x20);
+
+        for _ in 0..POP_REDUCTION_CYCLES_PER_LEVEL as usize * 0x40 {
+            reduced.clock_pop_reduction();
+        }
+        assert_eq!(reduced.mixer_value(), 0x40);
     }
 }
@@ -64,3 +64,12 @@ impl Envelope {
         }
     }
 }
+
+save_state_fields!(Envelope {
+    constant_volume,
+    loop_envelope,
+    use_envelope,
+    start_flag,
+    decay_level,
+    divider,
+});
@@ -7,6 +7,11 @@ pub(crate) const LENGTH_COUNTER_MAP: [u8; 0x20] = [
 pub(crate) struct LengthCounter {
     length_counter: u8,
     length_counter_halt: bool,
+    /// Writes to the halt flag and the counter take effect after the length counter has (or
+    /// hasn't) been clocked on the cycle of the write, see `apply_pending_writes`
+    pending_halt: Option<bool>,
+    pending_reload: Option<u8>,
+    length_counter_before_reload: u8,
 }
 
 impl LengthCounter {
@@ -14,6 +19,9 @@ impl LengthCounter {
         LengthCounter {
             length_counter: 0,
             length_counter_halt: false,
+            pending_halt: None,
+            pending_reload: None,
+            length_counter_before_reload: 0,
         }
     }
 
@@ -25,17 +33,110 @@ impl LengthCounter {
 
     pub(crate) fn disable(&mut self) {
         self.length_counter = 0;
+        self.pending_reload = None;
     }
 
     pub(crate) fn set(&mut self, value: u8) {
-        self.length_counter = LENGTH_COUNTER_MAP[(((value as usize) & 0b1111_1000) >> 3)];
+        self.pending_reload = Some(LENGTH_COUNTER_MAP[(((value as usize) & 0b1111_1000) >> 3)]);
+        self.length_counter_before_reload = self.length_counter;
     }
 
     pub(crate) fn set_halt(&mut self, halt: bool) {
-        self.length_counter_halt = halt;
+        self.pending_halt = Some(halt);
+    }
+
+    /// Called at the end of every CPU cycle. A reload written on the same cycle as the counter
+    /// is clocked is ignored unless the counter was zero (so wasn't changed by the clock), and
+    /// a halt flag written on that cycle doesn't affect the clock.
+    pub(crate) fn apply_pending_writes(&mut self) {
+        if let Some(reload) = self.pending_reload.take() {
+            if self.length_counter == self.length_counter_before_reload {
+                self.length_counter = reload;
+            }
+        }
+
+        if let Some(halt) = self.pending_halt.take() {
+            self.length_counter_halt = halt;
+        }
     }
 
     pub(crate) fn is_non_zero(&self) -> bool {
         self.length_counter > 0
     }
 }
+
+save_state_fields!(LengthCounter {
+    length_counter,
+    length_counter_halt,
+    pending_halt,
+    pending_reload,
+    length_counter_before_reload,
+});
+
+#[cfg(test)]
+mod length_counter_tests {
+    use super::*;
+
+    fn loaded_counter(value: u8) -> LengthCounter {
+        let mut counter = LengthCounter::new();
+        counter.set(value);
+        counter.apply_pending_writes();
+        counter
+    }
+
+    #[test]
+    fn test_load_while_halted() {
+        let mut counter = LengthCounter::new();
+        counter.set_halt(true);
+        counter.apply_pending_writes();
+        counter.set(0b0001_1000);
+        counter.apply_pending_writes();
+        assert_eq!(counter.length_counter, 2);
+
+        counter.clock();
+        assert_eq!(counter.length_counter, 2);
+    }
+
+    #[test]
+    fn test_reload_ignored_when_clocked_on_same_cycle() {
+        let mut counter = loaded_counter(0b0001_1000);
+        counter.set(0b0000_1000);
+        counter.clock();
+        counter.apply_pending_writes();
+        assert_eq!(counter.length_counter, 1);
+
+        // A zero counter isn't changed by the clock so the reload goes through
+        let mut counter = LengthCounter::new();
+        counter.set(0b0000_1000);
+        counter.clock();
+        counter.apply_pending_writes();
+        assert_eq!(counter.length_counter, 254);
+    }
+
+    #[test]
+    fn test_halt_written_on_same_cycle_as_clock_takes_effect_after() {
+        let mut counter = loaded_counter(0b0001_1000);
+        counter.set_halt(true);
+        counter.clock();
+        counter.apply_pending_writes();
+        assert_eq!(counter.length_counter, 1);
+        counter.clock();
+        assert_eq!(counter.length_counter, 1);
+
+        counter.set_halt(false);
+        counter.clock();
+        counter.apply_pending_writes();
+        assert_eq!(counter.length_counter, 1);
+        counter.clock();
+        assert_eq!(counter.length_counter, 0);
+    }
+
+    #[test]
+    fn test_disable_clears_counter_and_pending_reload() {
+        let mut counter = loaded_counter(0b0001_1000);
+        counter.set(0b0000_1000);
+        counter.disable();
+        counter.apply_pending_writes();
+        assert!(!counter.is_non_zero());
+    }
+}
@@ -55,3 +55,18 @@ pub(super) fn mixer_value(
 
     pulse_out + tnd_output
 }
+
+/// Cheaper linear approximation of the mixer, loses the non linearity of the DAC
+/// but avoids any table lookups. See https://wiki.nesdev.com/w/index.php/APU_Mixer
+pub(super) fn linear_mixer_value(
+    pulse_1_output: u8,
+    pulse_2_output: u8,
+    triangle_output: u8,
+    noise_output: u8,
+    dmc_output: u8,
+) -> f32 {
+    let pulse_out = 0.00752 * (pulse_1_output + pulse_2_output) as f32;
+    let tnd_output = 0.00851 * triangle_output as f32 + 0.00494 * noise_output as f32 + 0.00335 * dmc_output as f32;
+
+    pulse_out + tnd_output
+}
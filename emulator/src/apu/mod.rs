@@ -1,21 +1,72 @@
+use accuracy::AccuracyProfile;
 use apu::dmc_channel::DmcChannel;
 use apu::noise_channel::NoiseChannel;
 use apu::pulse_channel::PulseChannel;
 use apu::triangle_channel::TriangleChannel;
+use cpu::CpuCycle;
 use log::info;
+use scheduler::Scheduler;
 
+mod audio_output;
 mod dmc_channel;
 mod envelope;
 mod length_counter;
 mod mixer;
 mod noise_channel;
 mod pulse_channel;
+mod resampler;
 mod triangle_channel;
+mod wav;
+
+pub use apu::audio_output::{AudioOutputConfig, UnderrunCounter};
+pub use apu::resampler::{Resampler, ResamplerQuality};
+pub use apu::wav::write_wav;
+
+/// The rate at which the APU produces samples, one per CPU cycle on an NTSC console
+pub const NTSC_SAMPLE_RATE: f64 = 1_789_773.0;
 
 /// This type is used to represent an APU cycle to make it clearer when
 /// we're talking about cycles which type (PPU, CPU, APU) we mean.
 /// An APU cycle occurs once for every two CPU cycles.
-type ApuCycle = u32;
+type ApuCycle = u64;
+
+/// The frame counter sets the frame interrupt flag on this many consecutive CPU cycles at the
+/// end of each 4 step sequence, c.f. https://wiki.nesdev.com/w/index.php/APU_Frame_Counter
+const FRAME_INTERRUPT_SET_CYCLES: CpuCycle = 3;
+
+/// CPU cycles after the frame interrupt flag is set before the IRQ line is asserted, i.e. 4 APU
+/// cycles which is the delay the frame counter has always used
+const FRAME_IRQ_DELAY: CpuCycle = 8;
+
+/// The output of each channel on its own, as the mixer would output it if the others were silent
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ChannelSamples {
+    pub pulse_1: f32,
+    pub pulse_2: f32,
+    pub triangle: f32,
+    pub noise: f32,
+    pub dmc: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FrameCounterEvent {
+    /// The frame counter sequence restarts a few cycles after a write to $4017
+    Reset,
+    /// Scheduled whenever the frame interrupt flag is set, the IRQ line is asserted once it's due
+    Irq,
+    /// Until this is due the frame counter sets the frame interrupt flag on every cycle, so a read
+    /// of $4015 which clears it on one of those cycles sees it set again on the next
+    InterruptSetEnds,
+}
+
+save_state_enum!(
+    FrameCounterEvent {
+        Reset,
+        Irq,
+        InterruptSetEnds
+    },
+    default = Reset
+);
 
 #[derive(Debug, PartialEq)]
 enum FrameCounterMode {
@@ -23,8 +74,10 @@ enum FrameCounterMode {
     FiveStep,
 }
 
+save_state_enum!(FrameCounterMode { FourStep, FiveStep });
+
 impl FrameCounterMode {
-    fn wrapping_number(&self) -> u32 {
+    fn wrapping_number(&self) -> ApuCycle {
         match self {
             FrameCounterMode::FourStep => 14915,
             FrameCounterMode::FiveStep => 18641,
@@ -36,21 +89,43 @@ impl FrameCounterMode {
 struct FrameCounter {
     inhibit_interrupts: bool,
     mode: FrameCounterMode,
-    step: u8,
     sequence_cycles: ApuCycle,
-    timer_reset_countdown: u8,
 }
 
 impl FrameCounter {
-    fn set(&mut self, value: u8, is_apu_cycle: bool) {
+    fn set(&mut self, value: u8) {
         if value & 0b1000_0000 == 0 {
             self.mode = FrameCounterMode::FourStep
         } else {
             self.mode = FrameCounterMode::FiveStep
         }
         self.inhibit_interrupts = value & 0b0100_0000 == 0b0100_0000;
-        self.timer_reset_countdown = if is_apu_cycle { 3 } else { 4 };
     }
+
+    /// The last value written to $4017
+    fn value(&self) -> u8 {
+        let mode = match self.mode {
+            FrameCounterMode::FourStep => 0,
+            FrameCounterMode::FiveStep => 0b1000_0000,
+        };
+        mode | if self.inhibit_interrupts { 0b0100_0000 } else { 0 }
+    }
+}
+
+save_state_fields!(FrameCounter {
+    inhibit_interrupts,
+    mode,
+    sequence_cycles,
+});
+
+/// Optional changes to the audio output which deviate from hardware to remove artifacts that
+/// are unpleasant on modern speakers, all off by default so the output is raw.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct AudioEnhancements {
+    /// Hold the triangle channel at its midpoint rather than outputting ultrasonic frequencies
+    pub silence_ultrasonic_triangle: bool,
+    /// Ramp the DMC output level after $4011 writes instead of jumping straight to it
+    pub reduce_dmc_pops: bool,
 }
 
 pub struct Apu {
@@ -61,37 +136,49 @@ pub struct Apu {
     dmc_channel: DmcChannel,
     frame_counter: FrameCounter,
     total_apu_cycles: ApuCycle,
+    /// Counts every CPU cycle the APU is clocked on, used to stamp scheduled events
+    total_cpu_cycles: CpuCycle,
     is_apu_cycle: bool,
-    interrupt_triggered_cycles: Option<ApuCycle>,
+    scheduler: Scheduler<CpuCycle, FrameCounterEvent>,
+    accuracy: AccuracyProfile,
 }
 
 impl Default for Apu {
     fn default() -> Self {
-        Apu::new()
+        Apu::new(AccuracyProfile::Balanced, AudioEnhancements::default())
     }
 }
 
 impl Apu {
-    pub fn new() -> Self {
+    pub fn new(accuracy: AccuracyProfile, enhancements: AudioEnhancements) -> Self {
         Apu {
             pulse_channel_1: PulseChannel::new("Pulse 1".to_string()),
             pulse_channel_2: PulseChannel::new("Pulse 2".to_string()),
-            triangle_channel: TriangleChannel::new(),
+            triangle_channel: TriangleChannel::new(enhancements.silence_ultrasonic_triangle),
             noise_channel: NoiseChannel::new(),
-            dmc_channel: DmcChannel::new(),
+            dmc_channel: DmcChannel::new(enhancements.reduce_dmc_pops),
             frame_counter: FrameCounter {
                 inhibit_interrupts: false,
                 mode: FrameCounterMode::FourStep,
-                step: 0,
                 sequence_cycles: 4,
-                timer_reset_countdown: 0,
             },
             total_apu_cycles: 4, // TODO - What's the total number of APU cycles that occur during startup? 8/2?
+            total_cpu_cycles: 0,
             is_apu_cycle: false, // TODO - Guesswork, does the APU clock on cpu cycle 0 or 1?
-            interrupt_triggered_cycles: None,
+            scheduler: Scheduler::new(),
+            accuracy,
         }
     }
 
+    /// Pressing reset silences the channels, clears the frame interrupt flag and writes the last
+    /// value written to $4017 again, c.f. blargg's apu_reset tests
+    pub(crate) fn reset(&mut self) {
+        self.write_status_register(0);
+        self.scheduler.cancel(FrameCounterEvent::InterruptSetEnds);
+        self.clear_frame_interrupt();
+        self.write_byte(0x4017, self.frame_counter.value());
+    }
+
     fn write_status_register(&mut self, value: u8) {
         self.pulse_channel_1.set_enabled(value & 0b1 != 0);
         self.pulse_channel_2.set_enabled(value & 0b10 != 0);
@@ -117,27 +204,34 @@ impl Apu {
         // TODO - Read active flag from DMC channel
 
         // TODO - Set DMC interrupt flag
-        if let Some(c) = self.interrupt_triggered_cycles {
+        // Reading on a cycle that the frame counter sets the flag returns it set but doesn't
+        // clear it, as it's set again on the following cycle (or on this one if the read happens
+        // to be on the last of them)
+        if self.scheduler.is_scheduled(FrameCounterEvent::Irq) {
             mask |= 0b0100_0000;
-
-            // Don't clear the flag if it was only just set
-            if self.total_apu_cycles - c > 1 {
-                self.interrupt_triggered_cycles = None;
-            }
+            self.clear_frame_interrupt();
         }
 
         info!("Reading APU status register as {:02X}", mask);
         mask
     }
 
-    pub(crate) fn check_trigger_irq(&mut self) -> bool {
-        if let Some(c) = self.interrupt_triggered_cycles {
-            self.total_apu_cycles - c > 4
-        } else {
-            false
+    fn set_frame_interrupt(&mut self) {
+        // Setting the flag again while it's still set doesn't restart the IRQ delay
+        if !self.frame_counter.inhibit_interrupts && !self.scheduler.is_scheduled(FrameCounterEvent::Irq) {
+            self.scheduler
+                .schedule(FrameCounterEvent::Irq, self.total_cpu_cycles, FRAME_IRQ_DELAY);
         }
     }
 
+    fn clear_frame_interrupt(&mut self) {
+        self.scheduler.cancel(FrameCounterEvent::Irq);
+    }
+
+    pub(crate) fn check_trigger_irq(&mut self) -> bool {
+        self.scheduler.is_due(FrameCounterEvent::Irq, self.total_cpu_cycles)
+    }
+
     pub(crate) fn read_byte(&mut self, address: u16) -> u8 {
         info!("Reading byte from APU registers {:04X}", address);
         match address {
@@ -173,9 +267,12 @@ impl Apu {
             0x4014 => panic!("4014 isn't mapped to the APU"),
             0x4015 => self.write_status_register(value),
             0x4017 => {
-                self.frame_counter.set(value, self.is_apu_cycle);
+                self.frame_counter.set(value);
+                let reset_delay = if self.is_apu_cycle { 3 } else { 4 };
+                self.scheduler
+                    .schedule(FrameCounterEvent::Reset, self.total_cpu_cycles, reset_delay);
                 if self.frame_counter.inhibit_interrupts {
-                    self.interrupt_triggered_cycles = None;
+                    self.clear_frame_interrupt();
                 }
 
                 if self.frame_counter.mode == FrameCounterMode::FiveStep {
@@ -207,8 +304,16 @@ impl Apu {
         self.pulse_channel_2.clock_sweep_unit();
     }
 
+    fn mixer(&self) -> fn(u8, u8, u8, u8, u8) -> f32 {
+        if self.accuracy.precise_audio_mixing() {
+            mixer::mixer_value
+        } else {
+            mixer::linear_mixer_value
+        }
+    }
+
     fn get_current_output_byte(&self) -> f32 {
-        mixer::mixer_value(
+        self.mixer()(
             self.pulse_channel_1.mixer_value(),
             self.pulse_channel_2.mixer_value(),
             self.triangle_channel.mixer_value(),
@@ -216,17 +321,42 @@ impl Apu {
             self.dmc_channel.mixer_value(),
         )
     }
+
+    /// Each channel's contribution to the current output, used to render individual stems
+    pub(crate) fn channel_samples(&self) -> ChannelSamples {
+        let mixer_value = self.mixer();
+
+        ChannelSamples {
+            pulse_1: mixer_value(self.pulse_channel_1.mixer_value(), 0, 0, 0, 0),
+            pulse_2: mixer_value(0, self.pulse_channel_2.mixer_value(), 0, 0, 0),
+            triangle: mixer_value(0, 0, self.triangle_channel.mixer_value(), 0, 0),
+            noise: mixer_value(0, 0, 0, self.noise_channel.mixer_value(), 0),
+            dmc: mixer_value(0, 0, 0, 0, self.dmc_channel.mixer_value()),
+        }
+    }
 }
 
+save_state_fields!(Apu {
+    pulse_channel_1,
+    pulse_channel_2,
+    triangle_channel,
+    noise_channel,
+    dmc_channel,
+    frame_counter,
+    total_apu_cycles,
+    total_cpu_cycles,
+    is_apu_cycle,
+    scheduler,
+});
+
 impl Iterator for Apu {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.frame_counter.timer_reset_countdown > 0 {
-            self.frame_counter.timer_reset_countdown -= 1;
-            if self.frame_counter.timer_reset_countdown == 0 {
-                self.frame_counter.sequence_cycles = 0;
-            }
+        self.total_cpu_cycles += 1;
+
+        if self.scheduler.take_due(FrameCounterEvent::Reset, self.total_cpu_cycles) {
+            self.frame_counter.sequence_cycles = 0;
         }
 
         if self.is_apu_cycle {
@@ -243,10 +373,14 @@ impl Iterator for Apu {
                 && self.frame_counter.mode == FrameCounterMode::FourStep
             {
                 info!("Triggering APU IRQ at apu cycle {}", self.total_apu_cycles);
-                self.interrupt_triggered_cycles = Some(self.total_apu_cycles);
+                self.scheduler.schedule(
+                    FrameCounterEvent::InterruptSetEnds,
+                    self.total_cpu_cycles,
+                    FRAME_INTERRUPT_SET_CYCLES,
+                );
             }
 
-            self.total_apu_cycles = self.total_apu_cycles.wrapping_add(1);
+            self.total_apu_cycles += 1;
         } else {
             // Note that the clocking here actually occurs on the NON APU cycle deliberately
             match self.frame_counter.sequence_cycles {
@@ -258,8 +392,22 @@ impl Iterator for Apu {
             };
         }
 
+        if self
+            .scheduler
+            .is_pending(FrameCounterEvent::InterruptSetEnds, self.total_cpu_cycles)
+        {
+            self.set_frame_interrupt();
+        }
+
         // Note this is clocked on all CPU cycles
         self.triangle_channel.clock_timer();
+        self.dmc_channel.clock_pop_reduction();
+
+        // Register writes made on this cycle only reach the length counters once they've been clocked
+        self.pulse_channel_1.apply_length_counter_writes();
+        self.pulse_channel_2.apply_length_counter_writes();
+        self.triangle_channel.apply_length_counter_writes();
+        self.noise_channel.apply_length_counter_writes();
 
         // Every other cycle is an APU cycle (as clocked by the CPU)
         self.is_apu_cycle = !self.is_apu_cycle;
@@ -269,3 +417,173 @@ impl Iterator for Apu {
         Some(self.get_current_output_byte())
     }
 }
+
+#[cfg(test)]
+mod apu_tests {
+    use super::*;
+
+    /// Timings here are in CPU cycles after the $4017 write, they're the frame counter step
+    /// timings from https://wiki.nesdev.com/w/index.php/APU_Frame_Counter plus the 3 (write
+    /// on an APU cycle) or 4 (write between APU cycles) cycle delay before the sequence resets.
+    const RESET_DELAYS: [(bool, CpuCycle); 2] = [(true, 3), (false, 4)];
+
+    /// A pulse channel playing with a length counter of 2 and the frame counter just written
+    fn apu_after_4017_write(write_on_apu_cycle: bool, value: u8) -> Apu {
+        let mut apu = Apu::new(AccuracyProfile::Balanced, AudioEnhancements::default());
+        for _ in 0..10 {
+            apu.next();
+        }
+        // The length counter load takes a cycle to apply, leaving the write on the requested cycle
+        if apu.is_apu_cycle == write_on_apu_cycle {
+            apu.next();
+        }
+
+        apu.write_byte(0x4015, 0b1);
+        apu.write_byte(0x4003, 0b0001_1000);
+        apu.next();
+        apu.write_byte(0x4017, value);
+        apu
+    }
+
+    /// Clock the APU until the condition holds, returning how many cycles it took
+    fn cycles_until<F: Fn(&mut Apu) -> bool>(apu: &mut Apu, limit: CpuCycle, condition: F) -> Option<CpuCycle> {
+        (1..=limit).find(|_| {
+            apu.next();
+            condition(apu)
+        })
+    }
+
+    fn frame_interrupt_flag(apu: &mut Apu) -> bool {
+        apu.scheduler.is_scheduled(FrameCounterEvent::Irq)
+    }
+
+    #[test]
+    fn test_4_step_half_frames_clock_length_counters() {
+        for (write_on_apu_cycle, delay) in RESET_DELAYS.iter() {
+            let mut apu = apu_after_4017_write(*write_on_apu_cycle, 0x00);
+
+            // Half frames at 14913 and 29829, the second takes the counter from 1 to 0
+            let cycles = cycles_until(&mut apu, 40_000, |apu| !apu.pulse_channel_1.non_zero_length_counter());
+            assert_eq!(cycles, Some(29829 + delay));
+        }
+    }
+
+    #[test]
+    fn test_5_step_write_clocks_length_counters_immediately() {
+        for (write_on_apu_cycle, delay) in RESET_DELAYS.iter() {
+            let mut apu = apu_after_4017_write(*write_on_apu_cycle, 0x80);
+
+            // The immediate clock takes the counter to 1 so the first half frame at 14913 silences it
+            assert!(apu.pulse_channel_1.non_zero_length_counter());
+            let cycles = cycles_until(&mut apu, 40_000, |apu| !apu.pulse_channel_1.non_zero_length_counter());
+            assert_eq!(cycles, Some(14913 + delay));
+        }
+    }
+
+    #[test]
+    fn test_4_step_sets_frame_interrupt_flag() {
+        for (write_on_apu_cycle, delay) in RESET_DELAYS.iter() {
+            let mut apu = apu_after_4017_write(*write_on_apu_cycle, 0x00);
+
+            let cycles = cycles_until(&mut apu, 40_000, frame_interrupt_flag);
+            assert_eq!(cycles, Some(29828 + delay));
+
+            // The IRQ line follows the flag and reading $4015 acknowledges both
+            assert!(cycles_until(&mut apu, 20, |apu| apu.check_trigger_irq()).is_some());
+            assert_eq!(apu.read_byte(0x4015) & 0b0100_0000, 0b0100_0000);
+            assert_eq!(apu.read_byte(0x4015) & 0b0100_0000, 0);
+            assert!(!apu.check_trigger_irq());
+        }
+    }
+
+    #[test]
+    fn test_frame_irq_asserted_4_apu_cycles_after_flag_set() {
+        for (write_on_apu_cycle, _) in RESET_DELAYS.iter() {
+            let mut apu = apu_after_4017_write(*write_on_apu_cycle, 0x00);
+            cycles_until(&mut apu, 40_000, frame_interrupt_flag);
+
+            assert!(!apu.check_trigger_irq());
+            assert_eq!(cycles_until(&mut apu, 20, |apu| apu.check_trigger_irq()), Some(8));
+        }
+    }
+
+    #[test]
+    fn test_frame_interrupt_flag_not_cleared_by_read_on_same_cycle() {
+        let mut apu = apu_after_4017_write(true, 0x00);
+        cycles_until(&mut apu, 40_000, frame_interrupt_flag);
+
+        // A read on each of the cycles the flag is set returns it, only the last clears it
+        for _ in 0..FRAME_INTERRUPT_SET_CYCLES - 1 {
+            assert_eq!(apu.read_byte(0x4015) & 0b0100_0000, 0b0100_0000);
+            apu.next();
+            assert!(frame_interrupt_flag(&mut apu));
+        }
+        assert_eq!(apu.read_byte(0x4015) & 0b0100_0000, 0b0100_0000);
+        apu.next();
+        assert!(!frame_interrupt_flag(&mut apu));
+        assert_eq!(apu.read_byte(0x4015) & 0b0100_0000, 0);
+    }
+
+    #[test]
+    fn test_frame_interrupt_flag_set_again_asserts_irq() {
+        let mut apu = apu_after_4017_write(true, 0x00);
+        cycles_until(&mut apu, 40_000, frame_interrupt_flag);
+
+        // Cleared on the first cycle it's set, the IRQ is delayed from when it's set again
+        apu.read_byte(0x4015);
+        let cycles = cycles_until(&mut apu, 20, |apu| apu.check_trigger_irq());
+        assert_eq!(cycles, Some(FRAME_IRQ_DELAY + 1));
+    }
+
+    #[test]
+    fn test_5_step_and_inhibit_never_set_frame_interrupt_flag() {
+        for value in &[0x80, 0x40, 0xC0] {
+            let mut apu = apu_after_4017_write(true, *value);
+
+            assert_eq!(cycles_until(&mut apu, 80_000, frame_interrupt_flag), None);
+        }
+    }
+
+    #[test]
+    fn test_setting_inhibit_clears_frame_interrupt_flag() {
+        let mut apu = apu_after_4017_write(true, 0x00);
+        cycles_until(&mut apu, 40_000, frame_interrupt_flag);
+
+        apu.write_byte(0x4017, 0x40);
+        assert!(!frame_interrupt_flag(&mut apu));
+        assert_eq!(apu.read_byte(0x4015) & 0b0100_0000, 0);
+    }
+
+    #[test]
+    fn test_4015_clears_length_counters_and_ignores_loads_while_disabled() {
+        let mut apu = Apu::new(AccuracyProfile::Balanced, AudioEnhancements::default());
+        let length_registers = [0x4003, 0x4007, 0x400B, 0x400F];
+
+        apu.write_byte(0x4015, 0b1111);
+        for register in length_registers.iter() {
+            apu.write_byte(*register, 0b0000_1000);
+        }
+        apu.next();
+        assert_eq!(apu.read_byte(0x4015) & 0b1111, 0b1111);
+
+        apu.write_byte(0x4015, 0b0101);
+        apu.next();
+        assert_eq!(apu.read_byte(0x4015) & 0b1111, 0b0101);
+
+        apu.write_byte(0x4015, 0);
+        for register in length_registers.iter() {
+            apu.write_byte(*register, 0b0000_1000);
+        }
+        apu.next();
+        assert_eq!(apu.read_byte(0x4015) & 0b1111, 0);
+
+        // Loads work again as soon as the channel is re-enabled
+        apu.write_byte(0x4015, 0b1111);
+        apu.next();
+        for register in length_registers.iter() {
+            apu.write_byte(*register, 0b0000_1000);
+        }
+        apu.next();
+        assert_eq!(apu.read_byte(0x4015) & 0b1111, 0b1111);
+    }
+}
@@ -70,6 +70,10 @@ impl NoiseChannel {
         self.length_counter.clock();
     }
 
+    pub(super) fn apply_length_counter_writes(&mut self) {
+        self.length_counter.apply_pending_writes();
+    }
+
     pub(super) fn clock_envelope(&mut self) {
         self.envelope.clock();
     }
@@ -104,3 +108,13 @@ impl NoiseChannel {
         }
     }
 }
+
+save_state_fields!(NoiseChannel {
+    enabled,
+    length_counter,
+    lsfr_use_bit_6,
+    period,
+    timer,
+    shift_register,
+    envelope,
+});
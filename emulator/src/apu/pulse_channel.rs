@@ -33,6 +33,13 @@ impl SweepUnit {
     }
 }
 
+save_state_fields!(SweepUnit {
+    enabled,
+    divider_period,
+    is_negate,
+    shift_count,
+});
+
 #[derive(Debug)]
 pub(super) struct PulseChannel {
     name: String,
@@ -120,6 +127,10 @@ impl PulseChannel {
         self.length_counter.clock();
     }
 
+    pub(super) fn apply_length_counter_writes(&mut self) {
+        self.length_counter.apply_pending_writes();
+    }
+
     pub(super) fn clock_sweep_unit(&mut self) {
         // TODO
     }
@@ -152,3 +163,14 @@ impl PulseChannel {
         }
     }
 }
+
+save_state_fields!(PulseChannel {
+    enabled,
+    length_counter,
+    duty_cycle,
+    sequence,
+    timer_load,
+    timer,
+    sweep_unit,
+    envelope,
+});
@@ -0,0 +1,182 @@
+use std::f64::consts::PI;
+use std::fmt::{Display, Formatter, Result};
+use std::str::FromStr;
+
+/// The number of fractional sample positions the step kernel is precomputed for
+const KERNEL_PHASES: usize = 64;
+
+/// Fraction of the output nyquist frequency passed by the low pass filter, the rest is
+/// the transition band of the filter.
+const CUTOFF: f64 = 0.9;
+
+/// Trades CPU time for how well frequencies above the output nyquist are removed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResamplerQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl ResamplerQuality {
+    /// The number of output samples each change in the input signal is spread across
+    fn kernel_width(self) -> usize {
+        match self {
+            ResamplerQuality::Low => 8,
+            ResamplerQuality::Medium => 16,
+            ResamplerQuality::High => 32,
+        }
+    }
+}
+
+impl Display for ResamplerQuality {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            ResamplerQuality::Low => write!(f, "low"),
+            ResamplerQuality::Medium => write!(f, "medium"),
+            ResamplerQuality::High => write!(f, "high"),
+        }
+    }
+}
+
+impl FromStr for ResamplerQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(ResamplerQuality::Low),
+            "medium" => Ok(ResamplerQuality::Medium),
+            "high" => Ok(ResamplerQuality::High),
+            _ => Err(format!("Unknown resampler quality {}, expected low, medium or high", s)),
+        }
+    }
+}
+
+/// Converts the APU output (one sample per CPU cycle) to an output sample rate by band
+/// limited step synthesis, in the style of blip_buf.
+///
+/// The APU output is a step function which only changes a few times per thousand cycles, so
+/// rather than filtering every input sample each change is added to the output as a low pass
+/// filtered step (a windowed sinc) at its exact fractional position. The output is delayed by
+/// half the kernel width.
+pub struct Resampler {
+    /// KERNEL_PHASES rows of `width` taps, each row sums to 1
+    kernel: Vec<f32>,
+    width: usize,
+    /// Output samples per input sample
+    step: f64,
+    /// The position of the next input sample in output samples, relative to the start of `deltas`
+    position: f64,
+    last_input: f32,
+    /// Changes in the output signal which haven't been read yet
+    deltas: Vec<f32>,
+    integrator: f32,
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: f64, quality: ResamplerQuality) -> Self {
+        let width = quality.kernel_width();
+        let mut kernel = Vec::with_capacity(KERNEL_PHASES * width);
+
+        for phase in 0..KERNEL_PHASES {
+            let offset = phase as f64 / KERNEL_PHASES as f64;
+            let row = (0..width).map(|tap| {
+                let t = tap as f64 - offset - (width / 2) as f64 + 1.0;
+                let sinc = if t == 0.0 {
+                    1.0
+                } else {
+                    (PI * CUTOFF * t).sin() / (PI * CUTOFF * t)
+                };
+                // Blackman window over the width of the kernel
+                let x = (t + (width / 2) as f64) / width as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos();
+
+                sinc * window.max(0.0)
+            });
+            let row = row.collect::<Vec<f64>>();
+            let total = row.iter().sum::<f64>();
+            kernel.extend(row.iter().map(|value| (value / total) as f32));
+        }
+
+        Resampler {
+            kernel,
+            width,
+            step: output_rate / input_rate,
+            position: 0.0,
+            last_input: 0.0,
+            deltas: vec![0.0; width],
+            integrator: 0.0,
+        }
+    }
+
+    pub fn add_sample(&mut self, sample: f32) {
+        if sample != self.last_input {
+            let delta = sample - self.last_input;
+            self.last_input = sample;
+
+            let index = self.position as usize;
+            let phase = ((self.position - index as f64) * KERNEL_PHASES as f64) as usize;
+            if self.deltas.len() < index + self.width {
+                self.deltas.resize(index + self.width, 0.0);
+            }
+
+            let taps = &self.kernel[phase * self.width..(phase + 1) * self.width];
+            for (output, tap) in self.deltas[index..].iter_mut().zip(taps) {
+                *output += delta * tap;
+            }
+        }
+
+        self.position += self.step;
+    }
+
+    /// Moves all output samples which won't be affected by future input into `output`
+    pub fn read_samples(&mut self, output: &mut Vec<f32>) {
+        let ready = self.position as usize;
+        if self.deltas.len() < ready + self.width {
+            self.deltas.resize(ready + self.width, 0.0);
+        }
+
+        for delta in self.deltas.drain(..ready) {
+            self.integrator += delta;
+            output.push(self.integrator);
+        }
+        self.position -= ready as f64;
+    }
+}
+
+#[cfg(test)]
+mod resampler_tests {
+    use super::*;
+
+    #[test]
+    fn test_produces_output_rate_samples() {
+        let mut resampler = Resampler::new(1_789_773.0, 44_100.0, ResamplerQuality::Medium);
+        let mut output = vec![];
+        for _ in 0..1_789_773 {
+            resampler.add_sample(0.5);
+        }
+        resampler.read_samples(&mut output);
+
+        assert!((output.len() as i32 - 44_100).abs() <= 1, "{}", output.len());
+        // The step settles to the input level once it has passed through the kernel
+        assert!(output[100..].iter().all(|sample| (sample - 0.5).abs() < 0.001));
+    }
+
+    #[test]
+    fn test_ultrasonic_square_wave_is_removed() {
+        for quality in &[ResamplerQuality::Low, ResamplerQuality::Medium, ResamplerQuality::High] {
+            let mut resampler = Resampler::new(1_789_773.0, 44_100.0, *quality);
+            let mut output = vec![];
+
+            // A 55kHz square wave would alias down to ~11kHz with naive decimation
+            for cycle in 0..178_977 {
+                resampler.add_sample(if (cycle / 16) % 2 == 0 { 0.5 } else { 0.0 });
+            }
+            resampler.read_samples(&mut output);
+
+            let settled = &output[100..];
+            let mean = settled.iter().sum::<f32>() / settled.len() as f32;
+            let peak = settled.iter().map(|sample| (sample - mean).abs()).fold(0.0, f32::max);
+            assert!(peak < 0.1, "{} quality left {} of the wave", quality, peak);
+        }
+    }
+}
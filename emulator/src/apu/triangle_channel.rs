@@ -1,6 +1,12 @@
 use apu::length_counter::LengthCounter;
 use log::{debug, info};
 
+/// Timer periods below this produce frequencies well above human hearing (>27kHz)
+const ULTRASONIC_TIMER_PERIOD: u16 = 2;
+
+/// The output level held while ultrasonic, the midpoint of the waveform
+const ULTRASONIC_OUTPUT: u8 = 7;
+
 const TRIANGLE_SEQUENCE: [u8; 32] = [
     15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
 ];
@@ -15,10 +21,13 @@ pub(super) struct TriangleChannel {
     linear_counter_reload_flag: bool,
     linear_counter_reload: u8,
     linear_counter: u8,
+    /// Games silence the triangle by setting a tiny period rather than halting it, hardware
+    /// outputs the resulting ultrasonic wave which aliases into audible noise when resampled.
+    silence_ultrasonic: bool,
 }
 
 impl TriangleChannel {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(silence_ultrasonic: bool) -> Self {
         TriangleChannel {
             enabled: false,
             timer_load: 0,
@@ -29,6 +38,7 @@ impl TriangleChannel {
             linear_counter_reload_flag: false,
             linear_counter_reload: 0,
             linear_counter: 0,
+            silence_ultrasonic,
         }
     }
 
@@ -75,6 +85,10 @@ impl TriangleChannel {
         self.length_counter.clock();
     }
 
+    pub(super) fn apply_length_counter_writes(&mut self) {
+        self.length_counter.apply_pending_writes();
+    }
+
     pub(super) fn clock_linear_counter(&mut self) {
         info!("Clocking linear counter for triangle channel {:?}", self.linear_counter);
         if self.linear_counter_reload_flag {
@@ -105,10 +119,75 @@ impl TriangleChannel {
 
     /// The output volume for the channel
     pub(super) fn mixer_value(&self) -> u8 {
-        if self.linear_counter > 0 && self.length_counter.is_non_zero() {
-            TRIANGLE_SEQUENCE[self.sequence as usize]
-        } else {
+        if self.linear_counter == 0 || !self.length_counter.is_non_zero() {
             0
+        } else if self.silence_ultrasonic && self.timer_load < ULTRASONIC_TIMER_PERIOD {
+            ULTRASONIC_OUTPUT
+        } else {
+            TRIANGLE_SEQUENCE[self.sequence as usize]
+        }
+    }
+}
+
+save_state_fields!(TriangleChannel {
+    enabled,
+    timer_load,
+    timer,
+    sequence,
+    length_counter,
+    control_flag,
+    linear_counter_reload_flag,
+    linear_counter_reload,
+    linear_counter,
+});
+
+#[cfg(test)]
+mod triangle_channel_tests {
+    use super::*;
+
+    fn playing_channel(silence_ultrasonic: bool, period: u8) -> TriangleChannel {
+        let mut channel = TriangleChannel::new(silence_ultrasonic);
+        channel.set_enabled(true);
+        channel.load_linear_counter(0x7F);
+        channel.load_timer_low(period);
+        channel.load_length_timer_high(0b1111_1000);
+        channel.apply_length_counter_writes();
+        channel.clock_linear_counter();
+        channel
+    }
+
+    #[test]
+    fn test_ultrasonic_periods_only_silenced_when_enabled() {
+        let mut raw = playing_channel(false, 1);
+        let mut silenced = playing_channel(true, 1);
+        let mut raw_outputs = vec![];
+        for _ in 0..8 {
+            raw.clock_timer();
+            silenced.clock_timer();
+            raw_outputs.push(raw.mixer_value());
+            assert_eq!(silenced.mixer_value(), ULTRASONIC_OUTPUT);
         }
+        assert!(raw_outputs.windows(2).any(|pair| pair[0] != pair[1]));
+
+        let mut audible = playing_channel(true, 2);
+        let first = audible.mixer_value();
+        for _ in 0..3 {
+            audible.clock_timer();
+        }
+        assert_ne!(audible.mixer_value(), first);
+    }
+
+    #[test]
+    fn test_silenced_ultrasonic_channel_outputs_nothing_when_disabled() {
+        let mut channel = playing_channel(true, 1);
+        assert_eq!(channel.mixer_value(), ULTRASONIC_OUTPUT);
+
+        channel.set_enabled(false);
+        assert_eq!(channel.mixer_value(), 0);
+
+        let mut halted = TriangleChannel::new(true);
+        halted.set_enabled(true);
+        halted.load_timer_low(1);
+        assert_eq!(halted.mixer_value(), 0);
     }
 }
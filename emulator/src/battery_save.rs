@@ -0,0 +1,148 @@
+//! Keeps battery backed PRG RAM in a save file alongside the rom.
+//!
+//! Games write to save RAM in bursts so rather than writing the file on every change it's
+//! written once the game has stopped writing for a short while, and again when dropped so
+//! that nothing is lost when the frontend exits (or panics) in between.
+
+use log::{error, info};
+use memory_region::MemoryRegion;
+use nes::Nes;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long after the last write to PRG RAM before the save file is written
+const DEFAULT_FLUSH_DELAY: Duration = Duration::from_secs(3);
+
+/// ```no_run
+/// # let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge("../roms/test/nestest.nes").unwrap());
+/// let mut battery_save = rust_nes::BatterySave::new("game.sav");
+/// battery_save.load(&mut nes).unwrap();
+/// loop {
+///     nes.run_until(rust_nes::Event::Frame);
+///     battery_save.update(&mut nes).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BatterySave {
+    path: PathBuf,
+    flush_delay: Duration,
+    /// PRG RAM which hasn't been written to the file yet, along with when it last changed
+    pending: Option<(Instant, Vec<u8>)>,
+}
+
+impl BatterySave {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        BatterySave::with_flush_delay(path, DEFAULT_FLUSH_DELAY)
+    }
+
+    pub fn with_flush_delay<P: AsRef<Path>>(path: P, flush_delay: Duration) -> Self {
+        BatterySave {
+            path: path.as_ref().to_path_buf(),
+            flush_delay,
+            pending: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Copy the save file into PRG RAM, returns false if there's no save file yet
+    pub fn load(&self, nes: &mut Nes) -> io::Result<bool> {
+        let data = match fs::read(&self.path) {
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => return Ok(false),
+            result => result?,
+        };
+
+        nes.load_memory(MemoryRegion::PrgRam, &data)
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why.message))?;
+        info!("Loaded battery save from {}", self.path.display());
+
+        Ok(true)
+    }
+
+    /// Call regularly (e.g. once per frame) to pick up writes to PRG RAM, writes the save file
+    /// once PRG RAM has gone unchanged for the flush delay
+    pub fn update(&mut self, nes: &mut Nes) -> io::Result<()> {
+        let now = Instant::now();
+        if nes.take_prg_ram_written() {
+            self.pending = Some((now, nes.dump_memory(MemoryRegion::PrgRam)));
+        }
+
+        if self.flush_due(now) {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether there are changes which haven't been written to the save file yet
+    pub fn has_pending_changes(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Write any pending changes now rather than waiting for the flush delay
+    ///
+    /// The file is written alongside and then renamed over the save file so a crash part way
+    /// through can't leave it truncated.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some((_, ram)) = &self.pending {
+            let temp_path = self.path.with_extension("sav.tmp");
+            fs::write(&temp_path, ram)?;
+            fs::rename(&temp_path, &self.path)?;
+            info!("Wrote battery save to {}", self.path.display());
+        }
+        self.pending = None;
+
+        Ok(())
+    }
+
+    fn flush_due(&self, now: Instant) -> bool {
+        matches!(&self.pending, Some((written, _)) if now.saturating_duration_since(*written) >= self.flush_delay)
+    }
+}
+
+impl Drop for BatterySave {
+    fn drop(&mut self) {
+        if let Err(why) = self.flush() {
+            error!("Unable to write battery save to {}: {}", self.path.display(), why);
+        }
+    }
+}
+
+#[cfg(test)]
+mod battery_save_tests {
+    use super::*;
+    use std::env;
+
+    fn temp_save_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("rust_nes_{}_{}.sav", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_flush_waits_for_writes_to_stop() {
+        let mut battery_save = BatterySave::with_flush_delay(temp_save_path("delay"), Duration::from_secs(2));
+        let start = Instant::now();
+        assert!(!battery_save.flush_due(start));
+
+        battery_save.pending = Some((start, vec![1; 0x2000]));
+        assert!(!battery_save.flush_due(start + Duration::from_secs(1)));
+        assert!(battery_save.flush_due(start + Duration::from_secs(2)));
+
+        battery_save.pending = None;
+    }
+
+    #[test]
+    fn test_pending_changes_written_on_drop() {
+        let path = temp_save_path("drop");
+        let mut battery_save = BatterySave::new(&path);
+        battery_save.pending = Some((Instant::now(), vec![0x5A; 0x2000]));
+        drop(battery_save);
+
+        assert_eq!(fs::read(&path).unwrap(), vec![0x5A; 0x2000]);
+        assert!(!path.with_extension("sav.tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
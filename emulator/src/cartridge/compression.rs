@@ -0,0 +1,77 @@
+//! Decompression of roms stored in single file archives other than zip. Each format pulls in
+//! an extra dependency so is only available when the matching cargo feature is enabled.
+
+use cartridge::CartridgeError;
+
+/// Decompress a .gz file, which always contains exactly one file
+#[cfg(feature = "gzip")]
+pub(super) fn read_gzip(file_path: &str) -> Result<Vec<u8>, CartridgeError> {
+    use flate2::read::GzDecoder;
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut bytes = Vec::<u8>::new();
+    GzDecoder::new(File::open(file_path)?).read_to_end(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(not(feature = "gzip"))]
+pub(super) fn read_gzip(file_path: &str) -> Result<Vec<u8>, CartridgeError> {
+    Err(feature_disabled(file_path, "gzip"))
+}
+
+/// Read the single .nes file out of a 7z archive
+#[cfg(feature = "sevenz")]
+pub(super) fn read_7z(file_path: &str) -> Result<Vec<u8>, CartridgeError> {
+    use sevenz_rust::{Password, SevenZReader};
+    use std::io;
+
+    let to_error = |error: sevenz_rust::Error| CartridgeError {
+        message: error.to_string(),
+        mapper: None,
+    };
+
+    let mut archive = SevenZReader::open(file_path, Password::empty()).map_err(to_error)?;
+    let mut roms = Vec::<(String, Vec<u8>)>::new();
+    archive
+        .for_each_entries(|entry, reader| {
+            // Entries must be read in full even if they're skipped as they may share a stream
+            if !entry.is_directory() && entry.name().to_ascii_lowercase().ends_with(".nes") {
+                let mut bytes = Vec::<u8>::new();
+                reader.read_to_end(&mut bytes)?;
+                roms.push((entry.name().to_string(), bytes));
+            } else {
+                io::copy(reader, &mut io::sink())?;
+            }
+            Ok(true)
+        })
+        .map_err(to_error)?;
+
+    match roms.len() {
+        1 => Ok(roms.remove(0).1),
+        _ => Err(CartridgeError {
+            message: format!(
+                "The 7z file must contain only one file with the .nes extension, found {}",
+                roms.len()
+            ),
+            mapper: None,
+        }),
+    }
+}
+
+#[cfg(not(feature = "sevenz"))]
+pub(super) fn read_7z(file_path: &str) -> Result<Vec<u8>, CartridgeError> {
+    Err(feature_disabled(file_path, "sevenz"))
+}
+
+#[cfg(not(all(feature = "gzip", feature = "sevenz")))]
+fn feature_disabled(file_path: &str, feature: &str) -> CartridgeError {
+    CartridgeError {
+        message: format!(
+            "Unable to load {}, support for this archive format requires building with the \"{}\" feature",
+            file_path, feature
+        ),
+        mapper: None,
+    }
+}
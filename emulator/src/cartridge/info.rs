@@ -0,0 +1,126 @@
+use cartridge::{CartridgeHeader, MirroringMode, Region};
+use std::fmt;
+
+/// A description of a rom for frontends and tools, see `CartridgeHeader::info` or
+/// `rust_nes::get_cartridge_info` to read it without creating the mapper
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeInfo {
+    pub mapper: u8,
+    /// The name of the implementation which runs the rom, None if the mapper isn't supported
+    pub mapper_name: Option<&'static str>,
+    /// The most likely board given the mapper and memory sizes, the header doesn't say which
+    /// was used so this is only a guess
+    pub board: Option<&'static str>,
+    pub prg_rom_kb: u32,
+    pub chr_rom_kb: u32,
+    /// From an NES 2.0 header rounded up to 8KB, None leaves it to the mapper
+    pub prg_ram_kb: Option<u32>,
+    /// As `prg_ram_kb`
+    pub chr_ram_kb: Option<u32>,
+    pub battery: bool,
+    pub mirroring: MirroringMode,
+    pub region: Region,
+    pub prg_rom_crc32: u32,
+    pub chr_rom_crc32: Option<u32>,
+    /// Covers PRG and CHR ROM without the header, as listed in No-Intro style databases
+    pub rom_crc32: u32,
+    pub nes_2: bool,
+    /// Only present in NES 2.0 headers
+    pub submapper: Option<u8>,
+}
+
+impl CartridgeInfo {
+    pub(super) fn from_header(header: &CartridgeHeader) -> Self {
+        let prg_rom_kb = header.prg_rom_16kb_units as u32 * 16;
+        let chr_rom_kb = header.chr_rom_8kb_units as u32 * 8;
+        let prg_ram_kb = header.prg_ram_8kb_units.map(|units| units as u32 * 8);
+
+        CartridgeInfo {
+            mapper: header.mapper,
+            mapper_name: header.mapper_info.map(|info| info.name),
+            board: guess_board(
+                header.mapper,
+                prg_rom_kb,
+                chr_rom_kb,
+                prg_ram_kb,
+                header.ram_is_battery_backed,
+            ),
+            prg_rom_kb,
+            chr_rom_kb,
+            prg_ram_kb,
+            chr_ram_kb: header.chr_ram_8kb_units.map(|units| units as u32 * 8),
+            battery: header.ram_is_battery_backed,
+            mirroring: header.mirroring,
+            region: header.region,
+            prg_rom_crc32: header.prg_rom_crc32,
+            chr_rom_crc32: header.chr_rom_crc32,
+            rom_crc32: header.rom_crc32,
+            nes_2: header.nes_2,
+            submapper: header.submapper,
+        }
+    }
+}
+
+/// The common boards for each supported mapper told apart by their ROM and RAM sizes, c.f.
+/// http://wiki.nesdev.com/w/index.php/Cartridge_board_reference
+fn guess_board(
+    mapper: u8,
+    prg_rom_kb: u32,
+    chr_rom_kb: u32,
+    prg_ram_kb: Option<u32>,
+    battery: bool,
+) -> Option<&'static str> {
+    Some(match mapper {
+        0 if prg_rom_kb <= 16 => "NROM-128",
+        0 => "NROM-256",
+        1 if prg_rom_kb == 512 && prg_ram_kb == Some(32) => "SXROM",
+        1 if prg_rom_kb == 512 => "SUROM",
+        1 if prg_ram_kb == Some(16) => "SOROM",
+        1 if chr_rom_kb == 0 => "SNROM",
+        1 if battery => "SKROM",
+        1 => "SLROM",
+        2 if prg_rom_kb > 128 => "UOROM",
+        2 => "UNROM",
+        3 => "CNROM",
+        4 if chr_rom_kb == 0 && battery => "TNROM",
+        4 if chr_rom_kb == 0 => "TGROM",
+        4 if battery => "TKROM",
+        4 => "TLROM",
+        7 if prg_rom_kb > 128 => "AOROM",
+        7 => "ANROM",
+        9 => "PNROM",
+        10 if battery => "FKROM",
+        10 => "FJROM",
+        30 => "UNROM-512",
+        34 if chr_rom_kb == 0 => "BNROM",
+        34 => "NINA-001",
+        66 if prg_rom_kb <= 64 => "MHROM",
+        66 => "GNROM",
+        94 => "UN1ROM",
+        105 => "NES-EVENT",
+        111 => "GTROM",
+        180 => "UNROM",
+        _ => return None,
+    })
+}
+
+impl fmt::Display for CartridgeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mapper_name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "Mapper {}", self.mapper)?,
+        }
+        if let Some(board) = self.board {
+            write!(f, " ({})", board)?;
+        }
+        write!(f, ", {}KB PRG", self.prg_rom_kb)?;
+        match self.chr_rom_kb {
+            0 => write!(f, ", CHR RAM")?,
+            kb => write!(f, ", {}KB CHR", kb)?,
+        }
+        if self.battery {
+            write!(f, ", battery")?;
+        }
+        write!(f, ", {}", self.region)
+    }
+}
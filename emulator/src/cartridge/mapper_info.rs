@@ -0,0 +1,94 @@
+use std::fmt;
+
+/// How well a mapper is expected to run the roms which use it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CompatLevel {
+    /// Passes the test roms written for the board
+    Full,
+    /// Known games run but the board isn't covered by test roms
+    Playable,
+    /// Implemented from documentation without known good roms to check against
+    Experimental,
+}
+
+impl fmt::Display for CompatLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompatLevel::Full => write!(f, "full"),
+            CompatLevel::Playable => write!(f, "playable"),
+            CompatLevel::Experimental => write!(f, "experimental"),
+        }
+    }
+}
+
+/// Describes a supported mapper, see `supported_mappers`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapperInfo {
+    pub number: u8,
+    /// Identifies the implementation in savestates, unlike `name` this never changes
+    pub id: &'static str,
+    /// Bumped whenever the implementation changes how it behaves or what it saves, so that states
+    /// saved with an older implementation aren't silently loaded into a newer one
+    pub version: u16,
+    pub name: &'static str,
+    pub supports_savestate: bool,
+    pub has_expansion_audio: bool,
+    pub compat: CompatLevel,
+}
+
+macro_rules! mapper_info {
+    ($number:expr, $id:expr, $version:expr, $name:expr, $compat:ident) => {
+        MapperInfo {
+            number: $number,
+            id: $id,
+            version: $version,
+            name: $name,
+            supports_savestate: true,
+            has_expansion_audio: false,
+            compat: CompatLevel::$compat,
+        }
+    };
+}
+
+/// Ordered by mapper number, this must be kept in sync with the implementations `create_mapper`
+/// builds for each id. The first entry for a number is used by default, later entries with the
+/// same number (e.g. a rewrite alongside the implementation it replaces) are only used when
+/// chosen with `CartridgeOverrides::mapper_id`.
+const SUPPORTED_MAPPERS: &[MapperInfo] = &[
+    mapper_info!(0, "nrom", 1, "NROM", Full),
+    mapper_info!(1, "mmc1", 1, "MMC1", Full),
+    mapper_info!(2, "uxrom", 1, "UxROM", Full),
+    mapper_info!(3, "cnrom", 1, "CNROM", Full),
+    mapper_info!(4, "mmc3", 1, "MMC3", Full),
+    mapper_info!(7, "axrom", 1, "AxROM", Full),
+    mapper_info!(9, "mmc2", 1, "MMC2", Full),
+    mapper_info!(10, "mmc4", 1, "MMC4", Full),
+    mapper_info!(11, "color_dreams", 1, "Color Dreams", Full),
+    mapper_info!(28, "action53", 1, "Action 53", Full),
+    mapper_info!(30, "unrom_512", 1, "UNROM-512", Experimental),
+    mapper_info!(34, "bxrom", 1, "BxROM/NINA-001", Full),
+    mapper_info!(66, "gxrom", 1, "GxROM", Full),
+    mapper_info!(71, "camerica", 1, "Camerica", Playable),
+    mapper_info!(79, "nina_003_006", 1, "NINA-003/006", Playable),
+    mapper_info!(94, "un1rom", 1, "HVC-UN1ROM", Playable),
+    mapper_info!(105, "nwc", 1, "NWC", Experimental),
+    mapper_info!(111, "gtrom", 1, "GTROM", Experimental),
+    mapper_info!(155, "mmc1a", 1, "MMC1A", Playable),
+    mapper_info!(180, "unrom_180", 1, "UNROM (reverse)", Full),
+];
+
+/// Every mapper which can be loaded, so that frontends and tools can report whether a rom is
+/// expected to run from its header alone
+pub fn supported_mappers() -> &'static [MapperInfo] {
+    SUPPORTED_MAPPERS
+}
+
+/// The implementation used by default for a mapper number
+pub fn mapper_info(number: u8) -> Option<&'static MapperInfo> {
+    SUPPORTED_MAPPERS.iter().find(|info| info.number == number)
+}
+
+/// Look up an implementation by its `id`
+pub fn mapper_implementation(id: &str) -> Option<&'static MapperInfo> {
+    SUPPORTED_MAPPERS.iter().find(|info| info.id == id)
+}
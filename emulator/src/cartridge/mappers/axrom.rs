@@ -19,22 +19,28 @@ impl AxRomChrChip {
     }
 }
 
+save_state_fields!(AxRomChrChip { base });
+
 impl PpuCartridgeAddressBus for AxRomChrChip {
-    fn check_trigger_irq(&mut self, _: bool) -> bool {
+    fn check_trigger_irq(&mut self, _: bool, _: u64) -> bool {
         false
     }
 
-    fn update_vram_address(&mut self, _: u16, _: u32) {}
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
 
-    fn read_byte(&mut self, address: u16, _: u32) -> u8 {
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
     }
 
-    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u64) {
         if let 0x8000..=0xFFFF = address {
             self.base.mirroring_mode = if value & 0b1_0000 == 0 {
                 MirroringMode::OneScreenLowerBank
@@ -63,7 +69,7 @@ pub(crate) fn from_header(
     (
         Box::new(SingleBankedPrgChip::new(
             prg_rom,
-            None,
+            header.prg_ram(false),
             header.prg_rom_16kb_units as usize / 2,
             0b111,
             0,
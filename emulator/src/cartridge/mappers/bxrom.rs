@@ -28,22 +28,28 @@ impl Nina001ChrChip {
     }
 }
 
+save_state_fields!(Nina001ChrChip { base });
+
 impl PpuCartridgeAddressBus for Nina001ChrChip {
-    fn check_trigger_irq(&mut self, _: bool) -> bool {
+    fn check_trigger_irq(&mut self, _: bool, _: u64) -> bool {
         false
     }
 
-    fn update_vram_address(&mut self, _: u16, _: u32) {}
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
 
-    fn read_byte(&mut self, address: u16, _: u32) -> u8 {
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
     }
 
-    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u64) {
         match address {
             0x7FFE => {
                 self.base.banks[0] = value as usize & 0b1111;
@@ -75,7 +81,7 @@ pub(crate) fn from_header(
             (
                 Box::new(SingleBankedPrgChip::new(
                     prg_rom,
-                    None,
+                    header.prg_ram(false),
                     header.prg_rom_16kb_units as usize / 2,
                     0b11,
                     0,
@@ -91,7 +97,7 @@ pub(crate) fn from_header(
             (
                 Box::new(SingleBankedPrgChip::new(
                     prg_rom,
-                    Some([0; 0x2000]),
+                    header.prg_ram(true),
                     header.prg_rom_16kb_units as usize / 2,
                     0b1,
                     0,
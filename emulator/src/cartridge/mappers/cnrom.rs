@@ -20,7 +20,7 @@ pub(crate) fn from_header(
 ) {
     info!("Creating CNROM mapper for cartridge {:?}", header);
     (
-        Box::new(NoBankPrgChip::new(prg_rom)),
+        Box::new(NoBankPrgChip::new(prg_rom, header.prg_ram(true))),
         Box::new(SingleBankedChrChip::new(
             ChrData::from(chr_rom),
             header.mirroring,
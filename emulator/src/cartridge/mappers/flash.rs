@@ -0,0 +1,123 @@
+use log::info;
+
+/// Commands are written to these addresses in the flash chip's own address space, i.e. the
+/// offset into PRG ROM of the address written to with A15 and up ignored
+const FLASH_UNLOCK_ADDRESS_1: usize = 0x5555;
+const FLASH_UNLOCK_ADDRESS_2: usize = 0x2AAA;
+const FLASH_SECTOR_SIZE: usize = 0x1000;
+/// The SST39SF0x0 manufacturer ID, read from even addresses in software ID mode
+const FLASH_MANUFACTURER_ID: u8 = 0xBF;
+
+/// Where the flash chip is in a command sequence, every command starts with the same two unlock
+/// writes and anything out of sequence returns it to `Ready`
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FlashState {
+    Ready,
+    Unlock1,
+    Unlock2,
+    /// The next write programs a byte
+    ByteProgram,
+    /// Erasing takes a second unlock sequence before choosing the sector or the whole chip
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+}
+
+save_state_enum!(FlashState {
+    Ready,
+    Unlock1,
+    Unlock2,
+    ByteProgram,
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+});
+
+/// The command state of an SST39SF0x0 flash chip, used by homebrew boards which carry their PRG
+/// ROM in flash so games can save to it, c.f. https://wiki.nesdev.com/w/index.php/Flash_ROM
+///
+/// The board owns the PRG ROM and passes it in with the offset into it of each access.
+pub(super) struct FlashChip {
+    state: FlashState,
+    /// Reads return the manufacturer and device IDs rather than the flash contents
+    software_id: bool,
+    /// Set when a write changes the flash, as `PrgBaseData::prg_ram_written`
+    written: bool,
+}
+
+impl FlashChip {
+    pub(super) fn new() -> Self {
+        FlashChip {
+            state: FlashState::Ready,
+            software_id: false,
+            written: false,
+        }
+    }
+
+    /// The ID read at `flash_address` in software ID mode, None when reads return the contents.
+    /// The device ID depends on the size of the chip, 128KB, 256KB or 512KB.
+    pub(super) fn read_id(&self, flash_address: usize, size: usize) -> Option<u8> {
+        if !self.software_id {
+            return None;
+        }
+
+        Some(match (flash_address & 1, size) {
+            (0, _) => FLASH_MANUFACTURER_ID,
+            (_, 0..=0x20000) => 0xB5,
+            (_, 0x20001..=0x40000) => 0xB6,
+            _ => 0xB7,
+        })
+    }
+
+    pub(super) fn write(&mut self, rom: &mut [u8], flash_address: usize, value: u8) {
+        let command_address = flash_address & 0x7FFF;
+
+        self.state = match (self.state, command_address, value) {
+            (FlashState::ByteProgram, _, _) => {
+                // Programming can only clear bits, erasing sets them again
+                let byte = &mut rom[flash_address];
+                self.written |= *byte & value != *byte;
+                *byte &= value;
+                FlashState::Ready
+            }
+            (_, _, 0xF0) => {
+                self.software_id = false;
+                FlashState::Ready
+            }
+            (FlashState::Ready, FLASH_UNLOCK_ADDRESS_1, 0xAA) => FlashState::Unlock1,
+            (FlashState::Unlock1, FLASH_UNLOCK_ADDRESS_2, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, FLASH_UNLOCK_ADDRESS_1, 0xA0) => FlashState::ByteProgram,
+            (FlashState::Unlock2, FLASH_UNLOCK_ADDRESS_1, 0x80) => FlashState::Erase,
+            (FlashState::Unlock2, FLASH_UNLOCK_ADDRESS_1, 0x90) => {
+                self.software_id = true;
+                FlashState::Ready
+            }
+            (FlashState::Erase, FLASH_UNLOCK_ADDRESS_1, 0xAA) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, FLASH_UNLOCK_ADDRESS_2, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                let sector = flash_address & !(FLASH_SECTOR_SIZE - 1);
+                self.erase(&mut rom[sector..sector + FLASH_SECTOR_SIZE]);
+                FlashState::Ready
+            }
+            (FlashState::EraseUnlock2, FLASH_UNLOCK_ADDRESS_1, 0x10) => {
+                self.erase(rom);
+                FlashState::Ready
+            }
+            _ => FlashState::Ready,
+        };
+    }
+
+    fn erase(&mut self, bytes: &mut [u8]) {
+        info!("Erasing {:X} bytes of flash", bytes.len());
+        for byte in bytes.iter_mut() {
+            self.written |= *byte != 0xFF;
+            *byte = 0xFF;
+        }
+    }
+
+    pub(super) fn take_written(&mut self) -> bool {
+        std::mem::replace(&mut self.written, false)
+    }
+}
+
+save_state_fields!(FlashChip { state, software_id });
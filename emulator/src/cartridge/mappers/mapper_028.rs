@@ -0,0 +1,223 @@
+use cartridge::mappers::{ChrBaseData, ChrData, PrgBaseData};
+use cartridge::mirroring::MirroringMode;
+use cartridge::CartridgeHeader;
+use cartridge::CpuCartridgeAddressBus;
+use cartridge::PpuCartridgeAddressBus;
+use log::info;
+
+/// Action 53 is a multicart mapper, writes to 5000-5FFF select which of four registers the
+/// next write to 8000-FFFF goes to (only bits 7 and 0 are decoded)
+const REGISTER_SELECT_MASK: u8 = 0b1000_0001;
+const CHR_BANK_REGISTER: u8 = 0x00;
+const INNER_BANK_REGISTER: u8 = 0x01;
+const MODE_REGISTER: u8 = 0x80;
+const OUTER_BANK_REGISTER: u8 = 0x81;
+
+/// Mirroring from the low 2 bits of the mode register
+fn mirroring_mode(mode: u8) -> MirroringMode {
+    match mode & 0b11 {
+        0 => MirroringMode::OneScreenLowerBank,
+        1 => MirroringMode::OneScreenUpperBank,
+        2 => MirroringMode::Vertical,
+        _ => MirroringMode::Horizontal,
+    }
+}
+
+struct Mapper28PrgChip {
+    base: PrgBaseData,
+    register_select: u8,
+    mode: u8,
+    inner_bank: u8,
+    outer_bank: u8,
+}
+
+impl Mapper28PrgChip {
+    fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>, total_banks: usize) -> Self {
+        let mut chip = Mapper28PrgChip {
+            base: PrgBaseData {
+                prg_rom,
+                prg_ram,
+                prg_ram_written: false,
+                bank_size: 0x4000,
+                total_banks,
+                banks: vec![0, 0],
+                bank_offsets: vec![0, 0],
+            },
+            register_select: 0,
+            mode: 0,
+            inner_bank: 0,
+            // Power on with the last 32KB mapped so that the menu's reset vector is visible
+            outer_bank: 0xFF,
+        };
+        chip.update_banks();
+
+        chip
+    }
+
+    /// Each 16KB window is the inner bank within a game sized region of the outer bank, in
+    /// the UNROM like modes one of the windows is fixed to the outer bank instead
+    fn update_banks(&mut self) {
+        let bank_mode = (self.mode >> 2) & 0b11;
+        let game_size_mask = (2usize << ((self.mode >> 4) & 0b11)) - 1;
+        let outer_bank = (self.outer_bank as usize) << 1;
+
+        for window in 0..2 {
+            let bank = if (bank_mode ^ window as u8) & 0b11 == 0b10 {
+                outer_bank | window
+            } else if bank_mode & 0b10 == 0 {
+                // 32KB modes switch both windows together
+                ((self.inner_bank as usize) << 1) | window
+            } else {
+                self.inner_bank as usize
+            };
+
+            self.base.banks[window] =
+                ((bank & game_size_mask) | (outer_bank & !game_size_mask)) % self.base.total_banks;
+            self.base.bank_offsets[window] = self.base.banks[window] * self.base.bank_size;
+        }
+
+        info!(
+            "Mapper 28 bank switch {:?} => {:?}",
+            self.base.banks, self.base.bank_offsets
+        );
+    }
+}
+
+save_state_fields!(Mapper28PrgChip {
+    base,
+    register_select,
+    mode,
+    inner_bank,
+    outer_bank
+});
+
+impl CpuCartridgeAddressBus for Mapper28PrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
+    fn read_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
+        self.base.write_byte(address, value);
+
+        match address {
+            0x5000..=0x5FFF => self.register_select = value & REGISTER_SELECT_MASK,
+            0x8000..=0xFFFF => {
+                match self.register_select {
+                    INNER_BANK_REGISTER => self.inner_bank = value & 0b1111,
+                    MODE_REGISTER => self.mode = value & 0b11_1111,
+                    OUTER_BANK_REGISTER => self.outer_bank = value,
+                    _ => return,
+                }
+                self.update_banks();
+            }
+            _ => (),
+        }
+    }
+}
+
+struct Mapper28ChrChip {
+    base: ChrBaseData,
+    register_select: u8,
+    /// The mirroring bits of the mode register, tracked here as the PRG chip owns the rest
+    mirroring: u8,
+}
+
+impl Mapper28ChrChip {
+    fn new(chr_data: ChrData) -> Self {
+        Mapper28ChrChip {
+            base: ChrBaseData::new(mirroring_mode(0), chr_data, 0x2000, vec![0], vec![0]),
+            register_select: 0,
+            mirroring: 0,
+        }
+    }
+}
+
+save_state_fields!(Mapper28ChrChip {
+    base,
+    register_select,
+    mirroring
+});
+
+impl PpuCartridgeAddressBus for Mapper28ChrChip {
+    fn check_trigger_irq(&mut self, _: bool, _: u64) -> bool {
+        false
+    }
+
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
+
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
+        self.base.read_byte(address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
+        self.base.write_byte(address, value);
+    }
+
+    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u64) {
+        match address {
+            0x5000..=0x5FFF => self.register_select = value & REGISTER_SELECT_MASK,
+            0x8000..=0xFFFF => {
+                // Writes to the bank registers also select the one screen page when in a one screen mode
+                if self.register_select & 0b1000_0000 == 0 && self.mirroring & 0b10 == 0 {
+                    self.mirroring = (value >> 4) & 1;
+                }
+
+                match self.register_select {
+                    CHR_BANK_REGISTER => {
+                        self.base.banks[0] = (value & 0b11) as usize % self.base.total_banks;
+                        self.base.bank_offsets[0] = self.base.banks[0] * self.base.bank_size;
+                    }
+                    MODE_REGISTER => self.mirroring = value & 0b11,
+                    _ => (),
+                }
+                self.base.mirroring_mode = mirroring_mode(self.mirroring);
+            }
+            _ => (),
+        }
+    }
+}
+
+pub(crate) fn from_header(
+    prg_rom: Vec<u8>,
+    chr_rom: Option<Vec<u8>>,
+    header: CartridgeHeader,
+) -> (
+    Box<dyn CpuCartridgeAddressBus>,
+    Box<dyn PpuCartridgeAddressBus>,
+    CartridgeHeader,
+) {
+    info!("Creating Mapper 28 (Action 53) for cartridge {:?}", header);
+    (
+        Box::new(Mapper28PrgChip::new(
+            prg_rom,
+            header.prg_ram(false),
+            header.prg_rom_16kb_units as usize,
+        )),
+        // The board has 32KB of CHR RAM unless an NES 2.0 header says otherwise
+        Box::new(Mapper28ChrChip::new(ChrData::with_ram_8kb_units(
+            chr_rom,
+            header.chr_ram_8kb_units.unwrap_or(4),
+        ))),
+        header,
+    )
+}
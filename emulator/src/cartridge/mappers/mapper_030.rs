@@ -0,0 +1,309 @@
+use cartridge::mappers::flash::FlashChip;
+use cartridge::mappers::{ChrBaseData, ChrData, PrgBaseData};
+use cartridge::mirroring::MirroringMode;
+use cartridge::CartridgeHeader;
+use cartridge::CpuCartridgeAddressBus;
+use cartridge::PpuCartridgeAddressBus;
+use log::info;
+use savestate::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// Mapper 030 (UNROM-512), a homebrew board laid out as UNROM with up to 512KB of PRG ROM and
+/// 32KB of CHR RAM switched by the same register, c.f. https://wiki.nesdev.com/w/index.php/UNROM_512
+///
+/// Boards with the battery bit set carry the PRG ROM in an SST39SF0x0 flash chip which games
+/// write their saves to, these boards decode the register at C000-FFFF only as writes to
+/// 8000-BFFF are flash commands. The flash is exposed as PRG RAM so that battery saves persist it.
+///
+/// TODO - Boards without flash have bus conflicts, which no mapper here emulates yet
+struct UnRom512PrgChip {
+    base: PrgBaseData,
+    flashable: bool,
+    flash: FlashChip,
+}
+
+impl UnRom512PrgChip {
+    fn new(prg_rom: Vec<u8>, total_banks: usize, flashable: bool) -> Self {
+        UnRom512PrgChip {
+            base: PrgBaseData {
+                prg_rom,
+                prg_ram: None,
+                prg_ram_written: false,
+                bank_size: 0x4000,
+                total_banks,
+                banks: vec![0, total_banks - 1],
+                bank_offsets: vec![0, (total_banks - 1) * 0x4000],
+            },
+            flashable,
+            flash: FlashChip::new(),
+        }
+    }
+
+    fn write_flash(&mut self, address: u16, value: u8) {
+        let flash_address = self.base.bank_offsets[0] + (address as usize & 0x3FFF);
+        self.flash.write(&mut self.base.prg_rom, flash_address, value);
+    }
+}
+
+/// The flash is only saved on boards which can write to it, on the others it's ROM
+impl SaveState for UnRom512PrgChip {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.base.save_state(writer);
+        if self.flashable {
+            self.base.prg_rom[..].save_state(writer);
+        }
+        self.flash.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.base.load_state(reader)?;
+        if self.flashable {
+            self.base.prg_rom[..].load_state(reader)?;
+        }
+        self.flash.load_state(reader)
+    }
+}
+
+impl CpuCartridgeAddressBus for UnRom512PrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        if self.flashable {
+            Some(&self.base.prg_rom)
+        } else {
+            None
+        }
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        if self.flashable {
+            Some(&mut self.base.prg_rom)
+        } else {
+            None
+        }
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.flash.take_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
+    fn read_byte(&self, address: u16) -> u8 {
+        let flash_address = self.base.prg_rom_offset(address);
+        flash_address
+            .and_then(|flash_address| self.flash.read_id(flash_address, self.base.prg_rom.len()))
+            .unwrap_or_else(|| self.base.read_byte(address))
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
+        match address {
+            0x8000..=0xBFFF if self.flashable => self.write_flash(address, value),
+            0x8000..=0xFFFF => {
+                self.base.banks[0] = (value & 0b1_1111) as usize % self.base.total_banks;
+                self.base.bank_offsets[0] = self.base.banks[0] * self.base.bank_size;
+                info!(
+                    "UNROM-512 bank switch {:?} => {:?}",
+                    self.base.banks, self.base.bank_offsets
+                );
+            }
+            _ => (),
+        }
+    }
+}
+
+struct UnRom512ChrChip {
+    base: ChrBaseData,
+    flashable: bool,
+    /// Boards with the four screen bit set but not the vertical mirroring bit select a one screen
+    /// page with bit 7 of the register
+    one_screen: bool,
+}
+
+impl UnRom512ChrChip {
+    fn new(chr_data: ChrData, mirroring: MirroringMode, flashable: bool, one_screen: bool) -> Self {
+        UnRom512ChrChip {
+            base: ChrBaseData::new(mirroring, chr_data, 0x2000, vec![0], vec![0]),
+            flashable,
+            one_screen,
+        }
+    }
+}
+
+save_state_fields!(UnRom512ChrChip { base });
+
+impl PpuCartridgeAddressBus for UnRom512ChrChip {
+    fn check_trigger_irq(&mut self, _: bool, _: u64) -> bool {
+        false
+    }
+
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
+
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
+        self.base.read_byte(address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
+        self.base.write_byte(address, value);
+    }
+
+    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u64) {
+        match address {
+            0x8000..=0xBFFF if self.flashable => (),
+            0x8000..=0xFFFF => {
+                self.base.banks[0] = ((value >> 5) & 0b11) as usize % self.base.total_banks;
+                self.base.bank_offsets[0] = self.base.banks[0] * self.base.bank_size;
+                if self.one_screen {
+                    self.base.mirroring_mode = if value & 0b1000_0000 == 0 {
+                        MirroringMode::OneScreenLowerBank
+                    } else {
+                        MirroringMode::OneScreenUpperBank
+                    };
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub(crate) fn from_header(
+    prg_rom: Vec<u8>,
+    chr_rom: Option<Vec<u8>>,
+    header: CartridgeHeader,
+) -> (
+    Box<dyn CpuCartridgeAddressBus>,
+    Box<dyn PpuCartridgeAddressBus>,
+    CartridgeHeader,
+) {
+    info!("Creating UNROM-512 mapper for cartridge {:?}", header);
+    let flashable = header.ram_is_battery_backed;
+    // TODO - The four screen layout uses the last 8KB of CHR RAM for nametables rather than
+    // 4KB of its own, games which also bank that CHR RAM in as patterns will be drawn wrongly
+    let (mirroring, one_screen) = match header.mirroring {
+        MirroringMode::FourScreen if !header.vertical_mirroring_bit => (MirroringMode::OneScreenLowerBank, true),
+        mirroring => (mirroring, false),
+    };
+    (
+        Box::new(UnRom512PrgChip::new(
+            prg_rom,
+            header.prg_rom_16kb_units as usize,
+            flashable,
+        )),
+        // The board has 32KB of CHR RAM unless an NES 2.0 header says otherwise
+        Box::new(UnRom512ChrChip::new(
+            ChrData::with_ram_8kb_units(chr_rom, header.chr_ram_8kb_units.unwrap_or(4)),
+            mirroring,
+            flashable,
+            one_screen,
+        )),
+        header,
+    )
+}
+
+#[cfg(test)]
+mod unrom_512_tests {
+    use super::*;
+
+    /// 512KB of PRG ROM where every byte of each bank holds the bank number
+    fn prg_chip(flashable: bool) -> UnRom512PrgChip {
+        let prg_rom = (0..32).flat_map(|bank| vec![bank as u8; 0x4000]).collect();
+        UnRom512PrgChip::new(prg_rom, 32, flashable)
+    }
+
+    /// Write to the flash chip's address space through the bank register as games do
+    fn write_flash(chip: &mut UnRom512PrgChip, flash_address: usize, value: u8) {
+        chip.write_byte(0xC000, (flash_address >> 14) as u8, 0);
+        chip.write_byte(0x8000 | (flash_address & 0x3FFF) as u16, value, 0);
+    }
+
+    fn unlock(chip: &mut UnRom512PrgChip, command: u8) {
+        write_flash(chip, 0x5555, 0xAA);
+        write_flash(chip, 0x2AAA, 0x55);
+        write_flash(chip, 0x5555, command);
+    }
+
+    #[test]
+    fn test_bank_register_switches_first_window() {
+        let mut chip = prg_chip(false);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0xC000)), (0, 31));
+
+        chip.write_byte(0x8000, 0b1110_0101, 0);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0xC000)), (5, 31));
+        chip.write_byte(0xFFFF, 0b0001_1111, 0);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0xC000)), (31, 31));
+        assert_eq!(chip.prg_ram(), None);
+    }
+
+    #[test]
+    fn test_flash_programs_and_erases() {
+        let mut chip = prg_chip(true);
+
+        // Writes to 8000-BFFF outside of a command don't switch banks or change the flash
+        chip.write_byte(0x8000, 3, 0);
+        assert_eq!(chip.read_byte(0x8000), 0);
+        assert!(!chip.take_prg_ram_written());
+
+        // Bank 6 holds 0b0000_0110, programming can only clear bits
+        unlock(&mut chip, 0xA0);
+        write_flash(&mut chip, 6 * 0x4000 + 0x1234, 0b0000_0011);
+        chip.write_byte(0xC000, 6, 0);
+        assert_eq!(chip.read_byte(0x9234), 0b0000_0010);
+        assert_eq!(chip.read_byte(0x9235), 6);
+        assert!(chip.take_prg_ram_written());
+        assert_eq!(chip.prg_ram().unwrap()[6 * 0x4000 + 0x1234], 0b0000_0010);
+
+        // Without the unlock sequence the write is ignored
+        write_flash(&mut chip, 6 * 0x4000 + 0x1235, 0);
+        assert_eq!(chip.read_byte(0x9235), 6);
+
+        // Sector erase sets the 4KB sector containing the address
+        unlock(&mut chip, 0x80);
+        write_flash(&mut chip, 0x5555, 0xAA);
+        write_flash(&mut chip, 0x2AAA, 0x55);
+        write_flash(&mut chip, 6 * 0x4000 + 0x1000, 0x30);
+        chip.write_byte(0xC000, 6, 0);
+        assert_eq!(chip.read_byte(0x8FFF), 6);
+        assert_eq!(chip.read_byte(0x9000), 0xFF);
+        assert_eq!(chip.read_byte(0x9FFF), 0xFF);
+        assert_eq!(chip.read_byte(0xA000), 6);
+    }
+
+    #[test]
+    fn test_flash_software_id() {
+        let mut chip = prg_chip(true);
+        unlock(&mut chip, 0x90);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0x8001)), (0xBF, 0xB7));
+
+        write_flash(&mut chip, 0, 0xF0);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0xC001)), (0, 31));
+    }
+
+    #[test]
+    fn test_chr_bank_and_one_screen_mirroring() {
+        let mut chip = UnRom512ChrChip::new(
+            ChrData::with_ram_8kb_units(None, 4),
+            MirroringMode::OneScreenLowerBank,
+            true,
+            true,
+        );
+        chip.cpu_write_byte(0xC000, 0b1100_0000, 0);
+        chip.write_byte(0x0000, 0x12, 0);
+        chip.write_byte(0x2000, 0x34, 0);
+        assert_eq!(chip.base.mirroring_mode, MirroringMode::OneScreenUpperBank);
+
+        // Writes to 8000-BFFF are flash commands on flashable boards
+        chip.cpu_write_byte(0x8000, 0b0000_0000, 0);
+        assert_eq!(chip.read_byte(0x0000, 0), 0x12);
+
+        chip.cpu_write_byte(0xC000, 0b0000_0000, 0);
+        assert_eq!(chip.read_byte(0x0000, 0), 0);
+        assert_eq!(chip.base.mirroring_mode, MirroringMode::OneScreenLowerBank);
+        assert_eq!(chip.read_byte(0x2C00, 0), 0);
+        chip.cpu_write_byte(0xC000, 0b1100_0000, 0);
+        assert_eq!(chip.read_byte(0x0000, 0), 0x12);
+        assert_eq!(chip.read_byte(0x2C00, 0), 0x34);
+    }
+}
@@ -10,11 +10,12 @@ struct Mapper71PrgChip {
 }
 
 impl Mapper71PrgChip {
-    fn new(prg_rom: Vec<u8>, total_banks: usize) -> Self {
+    fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>, total_banks: usize) -> Self {
         Mapper71PrgChip {
             base: PrgBaseData {
                 prg_rom,
-                prg_ram: None,
+                prg_ram,
+                prg_ram_written: false,
                 bank_size: 0x4000,
                 total_banks,
                 banks: vec![0, total_banks - 1],
@@ -24,12 +25,30 @@ impl Mapper71PrgChip {
     }
 }
 
+save_state_fields!(Mapper71PrgChip { base });
+
 impl CpuCartridgeAddressBus for Mapper71PrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
 
         if let 0xC000..=0xFFFF = address {
@@ -55,22 +74,28 @@ impl Mapper71ChrChip {
     }
 }
 
+save_state_fields!(Mapper71ChrChip { base });
+
 impl PpuCartridgeAddressBus for Mapper71ChrChip {
-    fn check_trigger_irq(&mut self, _: bool) -> bool {
+    fn check_trigger_irq(&mut self, _: bool, _: u64) -> bool {
         false
     }
 
-    fn update_vram_address(&mut self, _: u16, _: u32) {}
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
 
-    fn read_byte(&mut self, address: u16, _: u32) -> u8 {
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
     }
 
-    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u64) {
         // This (8000..9FFF) register is only actually present on a specific submapper of mapper 71,
         // By moving it to 9000 instead we support both formats without needing to resort to trusting submappers
         // in rom dumps
@@ -95,7 +120,11 @@ pub(crate) fn from_header(
 ) {
     info!("Creating Mapper 71 for cartridge {:?}", header);
     (
-        Box::new(Mapper71PrgChip::new(prg_rom, header.prg_rom_16kb_units as usize)),
+        Box::new(Mapper71PrgChip::new(
+            prg_rom,
+            header.prg_ram(false),
+            header.prg_rom_16kb_units as usize,
+        )),
         Box::new(Mapper71ChrChip::new(ChrData::from(chr_rom), header.mirroring)),
         header,
     )
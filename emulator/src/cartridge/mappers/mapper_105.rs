@@ -0,0 +1,335 @@
+use cartridge::mappers::mmc1::LoadRegister;
+use cartridge::mappers::{ChrBaseData, ChrData, PrgBaseData};
+use cartridge::mirroring::MirroringMode;
+use cartridge::CartridgeHeader;
+use cartridge::CpuCartridgeAddressBus;
+use cartridge::PpuCartridgeAddressBus;
+use cpu::CpuCycle;
+use log::info;
+
+/// The NWC board is an MMC1 whose CHR bank 0 register instead selects between two 128KB PRG
+/// chips and holds a timer in reset. Once released the timer raises an IRQ to end the competition
+/// after 2^29 + (DIP switches * 2^25) CPU cycles, i.e. 5 minutes plus ~18.75 seconds per step.
+const TIMER_BASE_CYCLES: CpuCycle = 0x2000_0000;
+const TIMER_DIP_SWITCH_SHIFT: u32 = 25;
+/// 6:14.96, the time used for the competition
+const DEFAULT_DIP_SWITCHES: u8 = 0b0100;
+
+/// Bits of the CHR bank 0 register
+const TIMER_RESET_FLAG: u8 = 0b1_0000;
+const SECOND_CHIP_FLAG: u8 = 0b0_1000;
+
+fn mirroring_mode(control: u8) -> MirroringMode {
+    match control & 0b11 {
+        0b00 => MirroringMode::OneScreenLowerBank,
+        0b01 => MirroringMode::OneScreenUpperBank,
+        0b10 => MirroringMode::Vertical,
+        _ => MirroringMode::Horizontal,
+    }
+}
+
+/// The MMC1 registers, both chips keep a copy as each needs some of them
+struct NwcRegisters {
+    load_register: LoadRegister,
+    control: u8,
+    chr_bank_0: u8,
+    prg_bank: u8,
+}
+
+impl NwcRegisters {
+    fn new() -> Self {
+        NwcRegisters {
+            load_register: LoadRegister::new(),
+            control: 0x0C,
+            chr_bank_0: TIMER_RESET_FLAG,
+            prg_bank: 0,
+        }
+    }
+
+    /// Shift in a write to 8000-FFFF, returns whether one of the registers was updated
+    fn write(&mut self, address: u16, value: u8, cycles: CpuCycle) -> bool {
+        // Skip writes on consecutive cycles
+        if cycles == self.load_register.last_write_cycle + 1 {
+            return false;
+        }
+        self.load_register.last_write_cycle = cycles;
+
+        if value & 0b1000_0000 != 0 {
+            self.load_register.value = 0;
+            self.load_register.shift_writes = 0;
+            self.control |= 0x0C;
+            return true;
+        }
+
+        self.load_register.value |= (value & 1) << self.load_register.shift_writes;
+        self.load_register.shift_writes += 1;
+        if self.load_register.shift_writes < 5 {
+            return false;
+        }
+
+        match address {
+            0x8000..=0x9FFF => self.control = self.load_register.value,
+            0xA000..=0xBFFF => self.chr_bank_0 = self.load_register.value,
+            0xC000..=0xDFFF => (), // CHR bank 1 isn't used
+            _ => self.prg_bank = self.load_register.value,
+        }
+        self.load_register.value = 0;
+        self.load_register.shift_writes = 0;
+
+        true
+    }
+}
+
+save_state_fields!(NwcRegisters {
+    load_register,
+    control,
+    chr_bank_0,
+    prg_bank
+});
+
+/// At power on the first 32KB is locked in until the game has cleared and then set the timer
+/// reset flag
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PrgLock {
+    Locked,
+    TimerResetCleared,
+    Unlocked,
+}
+
+save_state_enum!(PrgLock {
+    Locked,
+    TimerResetCleared,
+    Unlocked
+});
+
+struct NwcPrgChip {
+    base: PrgBaseData,
+    registers: NwcRegisters,
+    lock: PrgLock,
+}
+
+impl NwcPrgChip {
+    fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>, total_banks: usize) -> Self {
+        NwcPrgChip {
+            base: PrgBaseData::new(prg_rom, prg_ram, total_banks, 0x4000, vec![0, 1], vec![0, 0x4000]),
+            registers: NwcRegisters::new(),
+            lock: PrgLock::Locked,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.registers.prg_bank & 0b1_0000 == 0
+    }
+
+    fn update_banks(&mut self) {
+        let timer_reset = self.registers.chr_bank_0 & TIMER_RESET_FLAG != 0;
+        self.lock = match (self.lock, timer_reset) {
+            (PrgLock::Locked, false) => PrgLock::TimerResetCleared,
+            (PrgLock::TimerResetCleared, true) => PrgLock::Unlocked,
+            (lock, _) => lock,
+        };
+
+        let banks = if self.lock != PrgLock::Unlocked {
+            [0, 1]
+        } else if self.registers.chr_bank_0 & SECOND_CHIP_FLAG == 0 {
+            let bank = ((self.registers.chr_bank_0 as usize >> 1) & 0b11) << 1;
+            [bank, bank | 1]
+        } else {
+            // The second chip is banked as a regular MMC1
+            let bank = (self.registers.prg_bank as usize & 0b111) | 0b1000;
+            match (self.registers.control >> 2) & 0b11 {
+                0b00 | 0b01 => [bank & !1, bank | 1],
+                0b10 => [0b1000, bank],
+                _ => [bank, 0b1111],
+            }
+        };
+
+        for (window, bank) in banks.iter().enumerate() {
+            self.base.banks[window] = bank % self.base.total_banks;
+            self.base.bank_offsets[window] = self.base.banks[window] * self.base.bank_size;
+        }
+
+        info!(
+            "NWC bank switch {:?} => {:?} ({:?})",
+            self.base.banks, self.base.bank_offsets, self.lock
+        );
+    }
+}
+
+save_state_fields!(NwcPrgChip { base, registers, lock });
+
+impl CpuCartridgeAddressBus for NwcPrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram_enabled() => 0x0,
+            _ => self.base.read_byte(address),
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8, cycles: CpuCycle) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.base.write_byte(address, value),
+            0x8000..=0xFFFF if self.registers.write(address, value, cycles) => self.update_banks(),
+            _ => (),
+        }
+    }
+}
+
+struct NwcChrChip {
+    base: ChrBaseData,
+    registers: NwcRegisters,
+    /// The CPU cycle the timer was released from reset on
+    timer_start: Option<CpuCycle>,
+    dip_switches: u8,
+}
+
+impl NwcChrChip {
+    fn new(chr_data: ChrData) -> Self {
+        let registers = NwcRegisters::new();
+
+        NwcChrChip {
+            base: ChrBaseData::new(mirroring_mode(registers.control), chr_data, 0x2000, vec![0], vec![0]),
+            registers,
+            timer_start: None,
+            dip_switches: DEFAULT_DIP_SWITCHES,
+        }
+    }
+
+    fn timer_cycles(&self) -> CpuCycle {
+        TIMER_BASE_CYCLES | (self.dip_switches as CpuCycle) << TIMER_DIP_SWITCH_SHIFT
+    }
+}
+
+save_state_fields!(NwcChrChip {
+    base,
+    registers,
+    timer_start
+});
+
+impl PpuCartridgeAddressBus for NwcChrChip {
+    /// The IRQ is only acknowledged by putting the timer back into reset
+    fn check_trigger_irq(&mut self, _: bool, cycles: CpuCycle) -> bool {
+        matches!(self.timer_start, Some(start) if cycles - start >= self.timer_cycles())
+    }
+
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
+
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
+        self.base.read_byte(address)
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
+        self.base.write_byte(address, value);
+    }
+
+    fn cpu_write_byte(&mut self, address: u16, value: u8, cycles: CpuCycle) {
+        if address < 0x8000 || !self.registers.write(address, value, cycles) {
+            return;
+        }
+
+        self.base.mirroring_mode = mirroring_mode(self.registers.control);
+        self.timer_start = match self.registers.chr_bank_0 & TIMER_RESET_FLAG {
+            0 => self.timer_start.or(Some(cycles)),
+            _ => None,
+        };
+    }
+
+    fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.dip_switches = dip_switches & 0b1111;
+    }
+}
+
+pub(crate) fn from_header(
+    prg_rom: Vec<u8>,
+    chr_rom: Option<Vec<u8>>,
+    header: CartridgeHeader,
+) -> (
+    Box<dyn CpuCartridgeAddressBus>,
+    Box<dyn PpuCartridgeAddressBus>,
+    CartridgeHeader,
+) {
+    info!("Creating Mapper 105 (NWC) for cartridge {:?}", header);
+    (
+        Box::new(NwcPrgChip::new(
+            prg_rom,
+            header.prg_ram(true),
+            header.prg_rom_16kb_units as usize,
+        )),
+        Box::new(NwcChrChip::new(ChrData::from(chr_rom))),
+        header,
+    )
+}
+
+#[cfg(test)]
+mod mapper_105_tests {
+    use super::*;
+
+    /// Writes each bit on alternate cycles so that none are skipped
+    fn shift_in(bus: &mut dyn FnMut(u16, u8, CpuCycle), address: u16, value: u8, cycles: &mut CpuCycle) {
+        for bit in 0..5 {
+            bus(address, value >> bit, *cycles);
+            *cycles += 2;
+        }
+    }
+
+    #[test]
+    fn test_first_chip_locked_until_timer_reset_toggled() {
+        let prg_rom = (0..16).flat_map(|bank| vec![bank as u8; 0x4000]).collect();
+        let mut chip = NwcPrgChip::new(prg_rom, Some(vec![0; 0x2000]), 16);
+        let mut cycles = 0;
+
+        shift_in(&mut |a, v, c| chip.write_byte(a, v, c), 0xA000, 0b1_0100, &mut cycles);
+        assert_eq!(chip.read_byte(0x8000), 0);
+        shift_in(&mut |a, v, c| chip.write_byte(a, v, c), 0xA000, 0b0_0100, &mut cycles);
+        assert_eq!(chip.read_byte(0x8000), 0);
+        shift_in(&mut |a, v, c| chip.write_byte(a, v, c), 0xA000, 0b1_0100, &mut cycles);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0xC000)), (4, 5));
+
+        // The second chip switches as an MMC1 with the last bank fixed
+        shift_in(&mut |a, v, c| chip.write_byte(a, v, c), 0xE000, 0b0011, &mut cycles);
+        shift_in(&mut |a, v, c| chip.write_byte(a, v, c), 0xA000, 0b1_1000, &mut cycles);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0xC000)), (11, 15));
+    }
+
+    #[test]
+    fn test_timer_length_set_by_dip_switches() {
+        let mut chip = NwcChrChip::new(ChrData::from(None));
+        chip.set_dip_switches(0b0011);
+        let mut cycles = 100;
+
+        shift_in(&mut |a, v, c| chip.cpu_write_byte(a, v, c), 0xA000, 0, &mut cycles);
+        let start = cycles - 2;
+        let timer_cycles = 0x2000_0000 + 3 * 0x200_0000;
+        assert!(!chip.check_trigger_irq(true, start + timer_cycles - 1));
+        assert!(chip.check_trigger_irq(true, start + timer_cycles));
+        assert!(chip.check_trigger_irq(true, start + timer_cycles + 1));
+
+        shift_in(
+            &mut |a, v, c| chip.cpu_write_byte(a, v, c),
+            0xA000,
+            TIMER_RESET_FLAG,
+            &mut cycles,
+        );
+        assert!(!chip.check_trigger_irq(true, start + timer_cycles + 1));
+    }
+}
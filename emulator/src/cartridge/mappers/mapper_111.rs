@@ -0,0 +1,300 @@
+use cartridge::mappers::flash::FlashChip;
+use cartridge::mappers::PrgBaseData;
+use cartridge::CartridgeHeader;
+use cartridge::CpuCartridgeAddressBus;
+use cartridge::PpuCartridgeAddressBus;
+use log::info;
+use savestate::{SaveState, SaveStateError, StateReader, StateWriter};
+
+/// The board's only register, written at 5000-5FFF and 7000-7FFF
+fn is_register(address: u16) -> bool {
+    matches!(address, 0x5000..=0x5FFF | 0x7000..=0x7FFF)
+}
+
+/// Mapper 111 (GTROM/Cheapocabra), a homebrew board with 32KB PRG banks and 32KB of CHR RAM
+/// holding two pattern table pages and two pages of four screen nametables, c.f.
+/// https://wiki.nesdev.com/w/index.php/GTROM
+///
+/// The register is `GRNC PPPP`, the 32KB PRG bank (P), the pattern table page (C), the
+/// nametable page (N) and the red (R) and green (G) LEDs on the cartridge, which are lit when
+/// their bit is clear.
+///
+/// The PRG ROM is an SST39SF040 flash chip which games save to by writing commands to 8000-FFFF,
+/// it's exposed as PRG RAM so that battery saves persist it.
+struct GtRomPrgChip {
+    base: PrgBaseData,
+    /// The LED bits of the register, the LEDs aren't shown anywhere but are kept for debugging
+    leds: u8,
+    flash: FlashChip,
+}
+
+impl GtRomPrgChip {
+    fn new(prg_rom: Vec<u8>, total_banks: usize) -> Self {
+        GtRomPrgChip {
+            base: PrgBaseData::new(prg_rom, None, total_banks, 0x8000, vec![0], vec![0]),
+            leds: 0,
+            flash: FlashChip::new(),
+        }
+    }
+}
+
+/// The flash is saved along with the registers as games can write to it
+impl SaveState for GtRomPrgChip {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.base.save_state(writer);
+        self.leds.save_state(writer);
+        self.base.prg_rom[..].save_state(writer);
+        self.flash.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.base.load_state(reader)?;
+        self.leds.load_state(reader)?;
+        self.base.prg_rom[..].load_state(reader)?;
+        self.flash.load_state(reader)
+    }
+}
+
+impl CpuCartridgeAddressBus for GtRomPrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.base.prg_rom)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.base.prg_rom)
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.flash.take_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
+    fn read_byte(&self, address: u16) -> u8 {
+        let flash_address = self.base.prg_rom_offset(address);
+        flash_address
+            .and_then(|flash_address| self.flash.read_id(flash_address, self.base.prg_rom.len()))
+            .unwrap_or_else(|| self.base.read_byte(address))
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
+        if let Some(flash_address) = self.base.prg_rom_offset(address) {
+            self.flash.write(&mut self.base.prg_rom, flash_address, value);
+            return;
+        }
+        if !is_register(address) {
+            return;
+        }
+
+        self.base.banks[0] = (value & 0b1111) as usize % self.base.total_banks;
+        self.base.bank_offsets[0] = self.base.banks[0] * self.base.bank_size;
+        info!(
+            "GTROM bank switch {:?} => {:?}",
+            self.base.banks, self.base.bank_offsets
+        );
+
+        if value & 0b1100_0000 != self.leds {
+            self.leds = value & 0b1100_0000;
+            let lit = |bit: u8| if self.leds & bit == 0 { "on" } else { "off" };
+            info!("GTROM LEDs red {} green {}", lit(0b0100_0000), lit(0b1000_0000));
+        }
+    }
+}
+
+struct GtRomChrChip {
+    /// The two 8KB pattern table pages followed by the two 8KB nametable pages
+    ram: Vec<u8>,
+    chr_page: usize,
+    nametable_page: usize,
+}
+
+impl GtRomChrChip {
+    /// The board only has CHR RAM, CHR ROM in the rom file (if any) is copied into the first page
+    fn new(chr_rom: Option<Vec<u8>>) -> Self {
+        let mut ram = vec![0; 0x8000];
+        if let Some(chr_rom) = chr_rom {
+            let length = chr_rom.len().min(0x4000);
+            ram[..length].copy_from_slice(&chr_rom[..length]);
+        }
+
+        GtRomChrChip {
+            ram,
+            chr_page: 0,
+            nametable_page: 0,
+        }
+    }
+
+    /// The nametables aren't mirrored, 2000-3EFF maps straight onto the nametable page
+    fn ram_index(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x1FFF => self.chr_page * 0x2000 + address as usize,
+            0x2000..=0x3EFF => 0x4000 + self.nametable_page * 0x2000 + (address as usize & 0x1FFF),
+            _ => panic!("Access to {:04X} invalid for CHR address bus", address),
+        }
+    }
+}
+
+save_state_fields!(GtRomChrChip {
+    ram,
+    chr_page,
+    nametable_page
+});
+
+impl PpuCartridgeAddressBus for GtRomChrChip {
+    fn check_trigger_irq(&mut self, _: bool, _: u64) -> bool {
+        false
+    }
+
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.ram[self.ram_index(address)]
+    }
+
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
+        self.ram[self.ram_index(address)]
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
+        let index = self.ram_index(address);
+        self.ram[index] = value;
+    }
+
+    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u64) {
+        if is_register(address) {
+            self.chr_page = ((value >> 4) & 1) as usize;
+            self.nametable_page = ((value >> 5) & 1) as usize;
+        }
+    }
+}
+
+pub(crate) fn from_header(
+    prg_rom: Vec<u8>,
+    chr_rom: Option<Vec<u8>>,
+    header: CartridgeHeader,
+) -> (
+    Box<dyn CpuCartridgeAddressBus>,
+    Box<dyn PpuCartridgeAddressBus>,
+    CartridgeHeader,
+) {
+    info!("Creating GTROM mapper for cartridge {:?}", header);
+    // A single 16KB unit of PRG ROM is mirrored by `PrgBaseData` to fill one 32KB bank
+    let total_banks = (header.prg_rom_16kb_units as usize).max(2) / 2;
+    (
+        Box::new(GtRomPrgChip::new(prg_rom, total_banks)),
+        Box::new(GtRomChrChip::new(chr_rom)),
+        header,
+    )
+}
+
+#[cfg(test)]
+mod gtrom_tests {
+    use super::*;
+
+    #[test]
+    fn test_register_switches_prg_bank() {
+        // 512KB of PRG ROM where every byte of each 32KB bank holds the bank number
+        let prg_rom = (0..16).flat_map(|bank| vec![bank as u8; 0x8000]).collect();
+        let mut chip = GtRomPrgChip::new(prg_rom, 16);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0xFFFF)), (0, 0));
+
+        chip.write_byte(0x5000, 0b1100_0101, 0);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0xFFFF)), (5, 5));
+        assert_eq!(chip.leds, 0b1100_0000);
+        chip.write_byte(0x7FFF, 0b0000_1111, 0);
+        assert_eq!(chip.read_byte(0x8000), 15);
+        assert_eq!(chip.leds, 0);
+
+        // Neither the gap between the registers nor the ROM switch banks
+        chip.write_byte(0x6000, 3, 0);
+        chip.write_byte(0x8000, 3, 0);
+        assert_eq!(chip.read_byte(0x8000), 15);
+    }
+
+    #[test]
+    fn test_16kb_prg_rom_mirrored() {
+        let prg_rom = (0..0x4000).map(|offset| offset as u8).collect();
+        let mut chip = GtRomPrgChip::new(prg_rom, 1);
+        assert_eq!((chip.read_byte(0x8001), chip.read_byte(0xC001)), (1, 1));
+        assert_eq!(chip.read_byte(0xFFFF), 0xFF);
+
+        chip.write_byte(0x5000, 0b0000_0011, 0);
+        assert_eq!(chip.read_byte(0xC001), 1);
+    }
+
+    #[test]
+    fn test_state_from_larger_rom_rejected() {
+        let mut chip = GtRomPrgChip::new(vec![0; 0x80000], 16);
+        chip.write_byte(0x5000, 15, 0);
+        let mut writer = StateWriter::new();
+        chip.save_state(&mut writer);
+        let bytes = writer.into_bytes();
+
+        let mut smaller = GtRomPrgChip::new(vec![0; 0x8000], 1);
+        let mut reader = StateReader::new(&bytes).unwrap();
+        assert!(smaller.load_state(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_flash_programs_and_erases() {
+        let prg_rom = (0..16).flat_map(|bank| vec![bank as u8; 0x8000]).collect();
+        let mut chip = GtRomPrgChip::new(prg_rom, 16);
+        chip.write_byte(0x5000, 3, 0);
+
+        // The command addresses are offsets into the chip with A15 and up ignored, so they're
+        // the same in every bank
+        let unlock = |chip: &mut GtRomPrgChip, command: u8| {
+            chip.write_byte(0xD555, 0xAA, 0);
+            chip.write_byte(0xAAAA, 0x55, 0);
+            chip.write_byte(0xD555, command, 0);
+        };
+
+        chip.write_byte(0x9234, 0, 0);
+        assert_eq!(chip.read_byte(0x9234), 3);
+        assert!(!chip.take_prg_ram_written());
+
+        unlock(&mut chip, 0xA0);
+        chip.write_byte(0x9234, 0b0000_0001, 0);
+        assert_eq!((chip.read_byte(0x9234), chip.read_byte(0x9235)), (1, 3));
+        assert!(chip.take_prg_ram_written());
+        assert_eq!(chip.prg_ram().unwrap()[3 * 0x8000 + 0x1234], 1);
+
+        unlock(&mut chip, 0x80);
+        chip.write_byte(0xD555, 0xAA, 0);
+        chip.write_byte(0xAAAA, 0x55, 0);
+        chip.write_byte(0x9000, 0x30, 0);
+        assert_eq!((chip.read_byte(0x8FFF), chip.read_byte(0x9234)), (3, 0xFF));
+        assert_eq!(chip.read_byte(0xA000), 3);
+
+        unlock(&mut chip, 0x90);
+        assert_eq!((chip.read_byte(0x8000), chip.read_byte(0x8001)), (0xBF, 0xB7));
+        chip.write_byte(0x8000, 0xF0, 0);
+        assert_eq!(chip.read_byte(0x8000), 3);
+    }
+
+    #[test]
+    fn test_register_switches_pattern_and_nametable_pages() {
+        let mut chip = GtRomChrChip::new(None);
+        chip.write_byte(0x0000, 0x12, 0);
+        chip.write_byte(0x2000, 0x34, 0);
+        chip.write_byte(0x3000, 0x56, 0);
+
+        chip.cpu_write_byte(0x5000, 0b0011_0000, 0);
+        assert_eq!((chip.read_byte(0x0000, 0), chip.read_byte(0x2000, 0)), (0, 0));
+        chip.write_byte(0x0000, 0x78, 0);
+        chip.write_byte(0x2000, 0x9A, 0);
+
+        chip.cpu_write_byte(0x7000, 0, 0);
+        assert_eq!(chip.read_byte(0x0000, 0), 0x12);
+        assert_eq!(chip.read_byte(0x2000, 0), 0x34);
+        assert_eq!(chip.read_byte(0x3000, 0), 0x56);
+
+        // Only the register is decoded, not the ROM it shares a bus with
+        chip.cpu_write_byte(0x8000, 0b0011_0000, 0);
+        assert_eq!(chip.peek_byte(0x0000), 0x12);
+        chip.cpu_write_byte(0x5000, 0b0001_0000, 0);
+        assert_eq!((chip.peek_byte(0x0000), chip.peek_byte(0x2000)), (0x78, 0x34));
+    }
+}
@@ -14,12 +14,20 @@ enum PRGBankMode {
     FixLast16KB,
 }
 
+save_state_enum!(PRGBankMode {
+    Switch32KB,
+    FixFirst16KB,
+    FixLast16KB
+});
+
 #[derive(Debug, PartialEq)]
 enum CHRBankMode {
     Switch8KB,
     Switch4KB,
 }
 
+save_state_enum!(CHRBankMode { Switch8KB, Switch4KB });
+
 #[derive(Debug, PartialEq)]
 enum MMC1Variant {
     MMC1,
@@ -27,14 +35,20 @@ enum MMC1Variant {
     MMC1A,
 }
 
-struct LoadRegister {
-    shift_writes: u8,
-    value: u8,
-    last_write_cycle: PpuCycle,
+/// SUROM and SXROM carry 512KB of PRG ROM, the extra address line comes from the CHR bank
+/// register and each 256KB half is then banked as a regular MMC1
+const PRG_OUTER_BANK_FLAG: u8 = 0b1_0000;
+
+/// The serial port which all MMC1 registers are written through, also used by boards built
+/// around an MMC1 (e.g. NWC)
+pub(super) struct LoadRegister {
+    pub(super) shift_writes: u8,
+    pub(super) value: u8,
+    pub(super) last_write_cycle: PpuCycle,
 }
 
 impl LoadRegister {
-    fn new() -> Self {
+    pub(super) fn new() -> Self {
         LoadRegister {
             last_write_cycle: 0,
             value: 0,
@@ -43,22 +57,33 @@ impl LoadRegister {
     }
 }
 
+save_state_fields!(LoadRegister {
+    shift_writes,
+    value,
+    last_write_cycle,
+});
+
 pub(crate) struct MMC1PrgChip {
     base: PrgBaseData,
     prg_ram_enabled: bool,
     prg_bank_mode: PRGBankMode,
+    prg_bank: u8,
+    /// Boards with more PRG ROM or RAM than the MMC1 can address use the upper bits of the CHR
+    /// bank register for it. In 4KB CHR mode the register used depends on which pattern table
+    /// the PPU is reading, games write the same value to both so only the first is tracked.
+    chr_bank_0: u8,
     load_register: LoadRegister,
     variant: MMC1Variant,
 }
 
 impl MMC1PrgChip {
-    fn new(prg_rom: Vec<u8>, total_banks: usize, variant: MMC1Variant) -> Self {
+    fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>, total_banks: usize, variant: MMC1Variant) -> Self {
         debug_assert!(prg_rom.len() >= 0x4000);
 
         let mut chip = MMC1PrgChip {
             base: PrgBaseData::new(
                 prg_rom,
-                Some([0; 0x2000]), // TODO - I think this should be optional
+                prg_ram,
                 total_banks,
                 0x4000,
                 vec![0, total_banks - 1],
@@ -66,6 +91,8 @@ impl MMC1PrgChip {
             ),
             prg_ram_enabled: true,
             prg_bank_mode: PRGBankMode::FixLast16KB,
+            prg_bank: 0,
+            chr_bank_0: 0,
             load_register: LoadRegister::new(),
             variant,
         };
@@ -90,49 +117,87 @@ impl MMC1PrgChip {
 
     fn update_prg_bank(&mut self, value: u8) {
         self.prg_ram_enabled = value & 0b1_0000 == 0;
+        self.prg_bank = value & 0b1111;
 
-        self.base.banks[0] = match self.prg_bank_mode {
-            PRGBankMode::Switch32KB => (value as usize & 0b1110) >> 1,
-            _ => value as usize & 0b1111,
-        } % self.base.total_banks;
+        self.update_bank_offsets();
+    }
 
-        info!("PRG Banks updated to {:?}", self.base.banks);
+    fn update_chr_bank_0(&mut self, value: u8) {
+        self.chr_bank_0 = value;
 
         self.update_bank_offsets();
     }
 
     fn update_bank_offsets(&mut self) {
-        match self.prg_bank_mode {
-            PRGBankMode::FixFirst16KB => {
-                self.base.bank_offsets[0] = 0;
-                self.base.bank_offsets[1] = self.base.banks[0] * 0x4000;
-            }
-            PRGBankMode::FixLast16KB => {
-                self.base.bank_offsets[0] = self.base.banks[0] * 0x4000;
-                self.base.bank_offsets[1] = self.base.prg_rom.len() as usize - 0x4000;
-            }
-            PRGBankMode::Switch32KB => {
-                self.base.bank_offsets[0] = self.base.banks[0] as usize * 0x8000;
-                self.base.bank_offsets[1] = self.base.bank_offsets[0] + 0x4000;
-            }
+        let inner_banks = self.base.total_banks.min(16);
+        let outer_bank = match self.base.total_banks > 16 && self.chr_bank_0 & PRG_OUTER_BANK_FLAG != 0 {
+            true => 16,
+            false => 0,
         };
+        let bank = self.prg_bank as usize;
+        let banks = match self.prg_bank_mode {
+            PRGBankMode::FixFirst16KB => [0, bank],
+            PRGBankMode::FixLast16KB => [bank, inner_banks - 1],
+            PRGBankMode::Switch32KB => [bank & !1, bank | 1],
+        };
+
+        for (window, bank) in banks.iter().enumerate() {
+            self.base.banks[window] = (outer_bank + bank % inner_banks) % self.base.total_banks;
+            self.base.bank_offsets[window] = self.base.banks[window] * 0x4000;
+        }
 
-        info!("Bank offsets updated: {:?}", self.base.bank_offsets);
+        info!(
+            "PRG banks updated to {:?}, offsets to {:?}",
+            self.base.banks, self.base.bank_offsets
+        );
+    }
+
+    /// SOROM (16KB) and SXROM (32KB) select the PRG RAM bank with bits 3 and 2-3 of the CHR bank
+    /// register respectively
+    fn prg_ram_index(&self, address: u16) -> usize {
+        let bank = match self.base.prg_ram.as_ref().map_or(0, |ram| ram.len() / 0x2000) {
+            4 => (self.chr_bank_0 as usize >> 2) & 0b11,
+            2 => (self.chr_bank_0 as usize >> 3) & 0b1,
+            _ => 0,
+        };
+
+        bank * 0x2000 + (address - 0x6000) as usize
     }
 }
 
+save_state_fields!(MMC1PrgChip {
+    base,
+    prg_ram_enabled,
+    prg_bank_mode,
+    prg_bank,
+    chr_bank_0,
+    load_register,
+});
+
 impl CpuCartridgeAddressBus for MMC1PrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         match address {
-            0x6000..=0x7FFF => match self.base.prg_ram {
-                Some(ram) => {
-                    if self.prg_ram_enabled || self.variant == MMC1Variant::MMC1A {
-                        ram[(address - 0x6000) as usize]
-                    } else {
-                        0x0
-                    }
+            0x6000..=0x7FFF => match &self.base.prg_ram {
+                Some(ram) if self.prg_ram_enabled || self.variant == MMC1Variant::MMC1A => {
+                    ram[self.prg_ram_index(address)]
                 }
-                None => 0x0,
+                _ => 0x0,
             },
             0x8000..=0xBFFF => {
                 let adj_addr = address as usize - 0x8000;
@@ -156,14 +221,11 @@ impl CpuCartridgeAddressBus for MMC1PrgChip {
         self.load_register.last_write_cycle = cycles;
 
         match address {
-            0x6000..=0x7FFF => match &mut self.base.prg_ram {
-                Some(ram) => {
-                    if self.prg_ram_enabled || self.variant == MMC1Variant::MMC1A {
-                        ram[(address - 0x6000) as usize] = value;
-                    }
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled || self.variant == MMC1Variant::MMC1A {
+                    self.base.write_prg_ram(self.prg_ram_index(address), value);
                 }
-                None => {}
-            },
+            }
             0x8000..=0xFFFF => {
                 if value & 0b1000_0000 != 0 {
                     self.load_register.value = 0;
@@ -176,7 +238,7 @@ impl CpuCartridgeAddressBus for MMC1PrgChip {
                     if self.load_register.shift_writes == 5 {
                         match address {
                             0x8000..=0x9FFF => self.update_control_register(self.load_register.value),
-                            0xA000..=0xBFFF => (),
+                            0xA000..=0xBFFF => self.update_chr_bank_0(self.load_register.value),
                             0xC000..=0xDFFF => (),
                             0xE000..=0xFFFF => self.update_prg_bank(self.load_register.value),
                             _ => panic!("Invalid MMC1 address {:04X}={:02X}", address, value),
@@ -266,13 +328,23 @@ impl MMC1ChrChip {
     }
 }
 
+save_state_fields!(MMC1ChrChip {
+    base,
+    load_register,
+    chr_bank_mode,
+});
+
 impl PpuCartridgeAddressBus for MMC1ChrChip {
-    fn check_trigger_irq(&mut self, _: bool) -> bool {
+    fn check_trigger_irq(&mut self, _: bool, _: CpuCycle) -> bool {
         false
     }
 
     fn update_vram_address(&mut self, _: u16, _: PpuCycle) {}
 
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
+
     fn read_byte(&mut self, address: u16, _: PpuCycle) -> u8 {
         self.base.read_byte(address)
     }
@@ -330,6 +402,7 @@ pub(crate) fn from_header(
     (
         Box::new(MMC1PrgChip::new(
             prg_rom,
+            header.banked_prg_ram(true, 4),
             header.prg_rom_16kb_units as usize,
             match header.mapper {
                 1 => MMC1Variant::MMC1,
@@ -347,10 +420,24 @@ mod mmc1_tests {
     use super::{MMC1PrgChip, PRGBankMode};
     use cartridge::mappers::mmc1::MMC1Variant;
     use cartridge::CpuCartridgeAddressBus;
+    use cpu::CpuCycle;
+
+    /// Shift a value into an MMC1 register on alternate cycles so that no writes are skipped
+    fn write_register(mmc1: &mut MMC1PrgChip, address: u16, value: u8, cycles: &mut CpuCycle) {
+        for bit in 0..5 {
+            mmc1.write_byte(address, value >> bit, *cycles);
+            *cycles += 2;
+        }
+    }
+
+    /// PRG ROM where every byte of each 16KB bank holds the bank number
+    fn numbered_prg_rom(banks: usize) -> Vec<u8> {
+        (0..banks).flat_map(|bank| vec![bank as u8; 0x4000]).collect()
+    }
 
     #[test]
     fn test_change_bank() {
-        let mut mmc1 = MMC1PrgChip::new(vec![0; 0x4000 * 16], 16, MMC1Variant::MMC1);
+        let mut mmc1 = MMC1PrgChip::new(vec![0; 0x4000 * 16], Some(vec![0; 0x2000]), 16, MMC1Variant::MMC1);
         mmc1.write_byte(0xE000, 0b0001, 0);
         mmc1.write_byte(0xE000, 0b0000, 0);
         mmc1.write_byte(0xE000, 0b0000, 0);
@@ -362,7 +449,7 @@ mod mmc1_tests {
 
     #[test]
     fn test_change_bank_needs_wrap() {
-        let mut mmc1 = MMC1PrgChip::new(vec![0; 0x4000 * 2], 2, MMC1Variant::MMC1);
+        let mut mmc1 = MMC1PrgChip::new(vec![0; 0x4000 * 2], Some(vec![0; 0x2000]), 2, MMC1Variant::MMC1);
         mmc1.write_byte(0xE000, 0b0011, 0);
         mmc1.write_byte(0xE000, 0b0001, 0);
         mmc1.write_byte(0xE000, 0b0000, 0);
@@ -374,7 +461,7 @@ mod mmc1_tests {
 
     #[test]
     fn test_ignore_sequential_writes() {
-        let mut mmc1 = MMC1PrgChip::new(vec![0; 0x4000 * 16], 16, MMC1Variant::MMC1);
+        let mut mmc1 = MMC1PrgChip::new(vec![0; 0x4000 * 16], Some(vec![0; 0x2000]), 16, MMC1Variant::MMC1);
         mmc1.write_byte(0xE000, 0b0001, 0);
         mmc1.write_byte(0xE000, 0b0000, 2);
         mmc1.write_byte(0xE000, 0b0000, 4);
@@ -389,7 +476,7 @@ mod mmc1_tests {
     #[test]
     fn test_set_control_register() {
         let value = 0b1111;
-        let mut mmc1 = MMC1PrgChip::new(vec![0; 0x4000 * 16], 16, MMC1Variant::MMC1);
+        let mut mmc1 = MMC1PrgChip::new(vec![0; 0x4000 * 16], Some(vec![0; 0x2000]), 16, MMC1Variant::MMC1);
         mmc1.write_byte(0x8000, 0, 0);
         mmc1.write_byte(0x8000, 0, 2);
         mmc1.write_byte(0x8000, 0, 4);
@@ -403,4 +490,51 @@ mod mmc1_tests {
         mmc1.write_byte(0x8000, value >> 4, 8);
         assert_eq!(mmc1.prg_bank_mode, PRGBankMode::FixLast16KB);
     }
+
+    #[test]
+    fn test_surom_512kb_prg_selected_by_chr_register() {
+        let mut mmc1 = MMC1PrgChip::new(numbered_prg_rom(32), Some(vec![0; 0x2000]), 32, MMC1Variant::MMC1);
+        let mut cycles = 0;
+        assert_eq!((mmc1.read_byte(0x8000), mmc1.read_byte(0xC000)), (0, 15));
+
+        write_register(&mut mmc1, 0xE000, 3, &mut cycles);
+        write_register(&mut mmc1, 0xA000, 0b1_0000, &mut cycles);
+        assert_eq!((mmc1.read_byte(0x8000), mmc1.read_byte(0xC000)), (19, 31));
+
+        // 32KB mode switches within the selected 256KB
+        write_register(&mut mmc1, 0x8000, 0b0_0000, &mut cycles);
+        write_register(&mut mmc1, 0xE000, 0b1111, &mut cycles);
+        assert_eq!((mmc1.read_byte(0x8000), mmc1.read_byte(0xC000)), (30, 31));
+        write_register(&mut mmc1, 0xA000, 0, &mut cycles);
+        assert_eq!((mmc1.read_byte(0x8000), mmc1.read_byte(0xC000)), (14, 15));
+    }
+
+    #[test]
+    fn test_sxrom_prg_ram_banked_by_chr_register() {
+        let mut mmc1 = MMC1PrgChip::new(numbered_prg_rom(32), Some(vec![0; 0x8000]), 32, MMC1Variant::MMC1);
+        let mut cycles = 0;
+
+        for bank in 0..4 {
+            write_register(&mut mmc1, 0xA000, bank << 2, &mut cycles);
+            mmc1.write_byte(0x6000, 0x10 + bank, cycles);
+            cycles += 2;
+        }
+        for bank in 0..4 {
+            write_register(&mut mmc1, 0xA000, bank << 2, &mut cycles);
+            assert_eq!(mmc1.read_byte(0x6000), 0x10 + bank);
+        }
+        assert_eq!(mmc1.prg_ram().unwrap()[0x6000], 0x13);
+    }
+
+    #[test]
+    fn test_sorom_prg_ram_banked_by_chr_register() {
+        let mut mmc1 = MMC1PrgChip::new(numbered_prg_rom(16), Some(vec![0; 0x4000]), 16, MMC1Variant::MMC1);
+        let mut cycles = 0;
+
+        write_register(&mut mmc1, 0xA000, 0b0_1000, &mut cycles);
+        mmc1.write_byte(0x7FFF, 0x42, cycles);
+        assert_eq!(mmc1.prg_ram().unwrap()[0x3FFF], 0x42);
+        write_register(&mut mmc1, 0xA000, 0, &mut cycles);
+        assert_eq!(mmc1.read_byte(0x7FFF), 0);
+    }
 }
@@ -6,19 +6,21 @@ use cartridge::PpuCartridgeAddressBus;
 use cpu::CpuCycle;
 use log::{debug, info};
 use ppu::PpuCycle;
+use savestate::{SaveState, SaveStateError, StateReader, StateWriter};
 
 struct Mmc2PrgChip {
     base: PrgBaseData,
 }
 
 impl Mmc2PrgChip {
-    fn new(prg_rom: Vec<u8>, total_banks: usize) -> Self {
+    fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>, total_banks: usize) -> Self {
         debug_assert!(total_banks >= 4);
 
         Mmc2PrgChip {
             base: PrgBaseData {
                 prg_rom,
-                prg_ram: None,
+                prg_ram,
+                prg_ram_written: false,
                 total_banks,
                 bank_size: 0x2000,
                 banks: vec![0, total_banks - 3, total_banks - 2, total_banks - 1],
@@ -33,12 +35,30 @@ impl Mmc2PrgChip {
     }
 }
 
+save_state_fields!(Mmc2PrgChip { base });
+
 impl CpuCartridgeAddressBus for Mmc2PrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
 
         // MMC2 has four banks, switched by 0xA000-0xFFFF where only the first is switchable
@@ -76,13 +96,37 @@ impl Mmc2Mmc4ChrChip {
     }
 }
 
+impl SaveState for Mmc2Mmc4ChrChip {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.base.save_state(writer);
+        for latch in 0..2 {
+            self.chr_banks[latch].save_state(writer);
+            self.chr_bank_offsets[latch].save_state(writer);
+        }
+        self.latches.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.base.load_state(reader)?;
+        for latch in 0..2 {
+            self.chr_banks[latch].load_state(reader)?;
+            self.chr_bank_offsets[latch].load_state(reader)?;
+        }
+        self.latches.load_state(reader)
+    }
+}
+
 impl PpuCartridgeAddressBus for Mmc2Mmc4ChrChip {
-    fn check_trigger_irq(&mut self, _: bool) -> bool {
+    fn check_trigger_irq(&mut self, _: bool, _: CpuCycle) -> bool {
         false
     }
 
     fn update_vram_address(&mut self, _: u16, _: PpuCycle) {}
 
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
+
     fn read_byte(&mut self, address: u16, _: PpuCycle) -> u8 {
         let value = self.base.read_byte(address);
 
@@ -173,7 +217,11 @@ pub(crate) fn from_header(
     info!("Creating MMC2 mapper for cartridge {:?}", header);
 
     (
-        Box::new(Mmc2PrgChip::new(prg_rom, header.prg_rom_16kb_units as usize * 2)),
+        Box::new(Mmc2PrgChip::new(
+            prg_rom,
+            header.prg_ram(false),
+            header.prg_rom_16kb_units as usize * 2,
+        )),
         Box::new(Mmc2Mmc4ChrChip::new(
             ChrData::from(chr_rom),
             MirroringMode::Vertical,
@@ -6,6 +6,7 @@ use cartridge::PpuCartridgeAddressBus;
 use cpu::CpuCycle;
 use log::{debug, info};
 use ppu::PpuCycle;
+use scheduler::Scheduler;
 
 #[derive(Debug)]
 enum PRGBankMode {
@@ -15,6 +16,11 @@ enum PRGBankMode {
     HighBankSwappable,
 }
 
+save_state_enum!(PRGBankMode {
+    LowBankSwappable,
+    HighBankSwappable
+});
+
 pub(crate) struct MMC3PrgChip {
     base: PrgBaseData,
     prg_ram_readonly: bool,
@@ -25,11 +31,11 @@ pub(crate) struct MMC3PrgChip {
 }
 
 impl MMC3PrgChip {
-    fn new(prg_rom: Vec<u8>, total_banks: usize) -> Self {
+    fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>, total_banks: usize) -> Self {
         MMC3PrgChip {
             base: PrgBaseData::new(
                 prg_rom,
-                Some([0; 0x2000]),
+                prg_ram,
                 total_banks,
                 0x2000,
                 vec![0, 1, total_banks - 2, total_banks - 1],
@@ -63,7 +69,31 @@ impl MMC3PrgChip {
     }
 }
 
+save_state_fields!(MMC3PrgChip {
+    base,
+    prg_ram_readonly,
+    prg_ram_disabled,
+    bank_mode,
+    bank_select,
+});
+
 impl CpuCartridgeAddressBus for MMC3PrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         match address {
             0x6000..=0x7FFF => match &self.base.prg_ram {
@@ -85,14 +115,11 @@ impl CpuCartridgeAddressBus for MMC3PrgChip {
         info!("CPU write to MMC3 PRG bus {:04X}={:02X}", address, value);
 
         match address {
-            0x6000..=0x7FFF => match &mut self.base.prg_ram {
-                Some(ram) => {
-                    if !self.prg_ram_disabled && !self.prg_ram_readonly {
-                        ram[(address - 0x6000) as usize] = value
-                    }
+            0x6000..=0x7FFF => {
+                if !self.prg_ram_disabled && !self.prg_ram_readonly {
+                    self.base.write_prg_ram((address - 0x6000) as usize, value);
                 }
-                None => {}
-            },
+            }
             // Bank select and Bank data registers
             0x8000..=0x9FFF => match address & 1 {
                 // Even addresses => Bank select register
@@ -134,6 +161,17 @@ impl CpuCartridgeAddressBus for MMC3PrgChip {
     }
 }
 
+/// A12 must have been low for this many PPU cycles for a rising edge to clock the IRQ counter
+const A12_FILTER_CYCLES: PpuCycle = 6;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum MMC3Event {
+    /// A12 has been low for long enough that the next rising edge will be counted
+    A12Filtered,
+}
+
+save_state_enum!(MMC3Event { A12Filtered }, default = A12Filtered);
+
 #[derive(Debug)]
 enum CHRBankMode {
     /// Two 2KB banks at 0000-0FFF and four 1KB banks at 1000-1FFF  
@@ -142,15 +180,19 @@ enum CHRBankMode {
     HighBank2KB,
 }
 
+save_state_enum!(CHRBankMode {
+    LowBank2KB,
+    HighBank2KB
+});
+
 pub(crate) struct MMC3ChrChip {
     base: ChrBaseData,
     bank_mode: CHRBankMode,
     /// 0b000-0b111 -> The register to be written to on next write to BankData
     bank_select: u8,
-    /// Track the cycle on which we last noticed an A12 change to low
-    /// It takes 6 cycles at low voltage before a high voltage causes a counter decrement
-    /// This is set to 0 whenever we see A12 high, if it was >=6 then we trigger a count
-    a12_cycles_at_last_low: Option<PpuCycle>,
+    /// It takes 6 cycles at low voltage before a high voltage causes a counter decrement so
+    /// every access with A12 low (re)schedules the point at which the filter has elapsed
+    scheduler: Scheduler<PpuCycle, MMC3Event>,
     /// IRQ register holding the value to load into the counter on the next reload
     irq_latch: u8,
     /// Set on reload to note that on the next rising edge the counter will get reloaded with the IRQ latch
@@ -175,7 +217,7 @@ impl MMC3ChrChip {
             ),
             bank_mode: CHRBankMode::LowBank2KB,
             bank_select: 0,
-            a12_cycles_at_last_low: None,
+            scheduler: Scheduler::new(),
             irq_latch: 0,
             reload_irq_next_rising_edge: false,
             irq_counter: 0,
@@ -224,8 +266,20 @@ impl MMC3ChrChip {
     }
 }
 
+save_state_fields!(MMC3ChrChip {
+    base,
+    bank_mode,
+    bank_select,
+    scheduler,
+    irq_latch,
+    reload_irq_next_rising_edge,
+    irq_counter,
+    irq_enabled,
+    irq_triggered,
+});
+
 impl PpuCartridgeAddressBus for MMC3ChrChip {
-    fn check_trigger_irq(&mut self, clear: bool) -> bool {
+    fn check_trigger_irq(&mut self, clear: bool, _: CpuCycle) -> bool {
         let val = self.irq_triggered;
 
         if clear {
@@ -237,24 +291,21 @@ impl PpuCartridgeAddressBus for MMC3ChrChip {
 
     fn update_vram_address(&mut self, address: u16, cycles: PpuCycle) {
         if address < 0x2000 {
-            let cycle_diff = match self.a12_cycles_at_last_low {
-                None => None,
-                Some(c) => Some(cycles - c),
-            };
-
             info!("MMC3 notified of PPU ADDR change {:04X} at cycle {}", address, cycles);
 
-            self.a12_cycles_at_last_low = match (address & 0x1000 == 0x1000, cycle_diff) {
-                (false, _) => Some(cycles),
-                (true, Some(6..=PpuCycle::MAX)) => {
-                    self.clock_irq_counter();
-                    None
-                }
-                (true, _) => self.a12_cycles_at_last_low,
-            };
+            if address & 0x1000 == 0 {
+                self.scheduler
+                    .schedule(MMC3Event::A12Filtered, cycles, A12_FILTER_CYCLES);
+            } else if self.scheduler.take_due(MMC3Event::A12Filtered, cycles) {
+                self.clock_irq_counter();
+            }
         }
     }
 
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
+
     fn read_byte(&mut self, address: u16, _: PpuCycle) -> u8 {
         self.base.read_byte(address)
     }
@@ -347,9 +398,13 @@ pub(crate) fn from_header(
     CartridgeHeader,
 ) {
     (
-        Box::new(MMC3PrgChip::new(prg_rom, header.prg_rom_16kb_units as usize * 2)),
+        Box::new(MMC3PrgChip::new(
+            prg_rom,
+            header.prg_ram(true),
+            header.prg_rom_16kb_units as usize * 2,
+        )),
         Box::new(match chr_rom {
-            None => MMC3ChrChip::new(ChrData::Ram(Box::new([0; 0x2000])), header.mirroring),
+            None => MMC3ChrChip::new(ChrData::Ram(vec![0; 0x2000]), header.mirroring),
             Some(rom) => MMC3ChrChip::new(ChrData::Rom(rom), header.mirroring),
         }),
         header,
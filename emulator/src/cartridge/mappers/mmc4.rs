@@ -11,11 +11,11 @@ struct Mmc4PrgChip {
 }
 
 impl Mmc4PrgChip {
-    fn new(prg_rom: Vec<u8>, total_banks: usize) -> Self {
+    fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>, total_banks: usize) -> Self {
         Mmc4PrgChip {
             base: PrgBaseData::new(
                 prg_rom,
-                None,
+                prg_ram,
                 total_banks,
                 0x4000,
                 vec![0, total_banks - 1],
@@ -25,12 +25,30 @@ impl Mmc4PrgChip {
     }
 }
 
+save_state_fields!(Mmc4PrgChip { base });
+
 impl CpuCartridgeAddressBus for Mmc4PrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
 
         // MMC4 has two banks, switched by 0xA000-0xFFFF where only the first is switchable
@@ -58,7 +76,11 @@ pub(crate) fn from_header(
 ) {
     info!("Creating MMC4 mapper for cartridge {:?}", header);
     (
-        Box::new(Mmc4PrgChip::new(prg_rom, header.prg_rom_16kb_units as usize)),
+        Box::new(Mmc4PrgChip::new(
+            prg_rom,
+            header.prg_ram(false),
+            header.prg_rom_16kb_units as usize,
+        )),
         Box::new(Mmc2Mmc4ChrChip::new(
             ChrData::from(chr_rom),
             MirroringMode::Vertical,
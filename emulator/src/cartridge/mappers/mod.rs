@@ -1,13 +1,19 @@
 use cartridge::mirroring::MirroringMode;
 use cartridge::{CpuCartridgeAddressBus, PpuCartridgeAddressBus};
 use log::{debug, info};
+use savestate::{invalid_state, SaveState, SaveStateError, StateReader, StateWriter};
 
 pub(super) mod axrom; // Mapper 7
 pub(super) mod bxrom; // Mapper 34 (note this is both BxROM and NINA-001 boards)
 pub(super) mod cnrom; // Mapper 3
 pub(super) mod color_dreams; // Mapper 11
+mod flash; // SST39SF0x0 flash used as PRG ROM by homebrew boards
 pub(super) mod gxrom; // Mapper 66
+pub(super) mod mapper_028; // Mapper 28
+pub(super) mod mapper_030; // Mapper 30 (UNROM-512)
 pub(super) mod mapper_071; // Mapper 71
+pub(super) mod mapper_105; // Mapper 105 (Nintendo World Championships)
+pub(super) mod mapper_111; // Mapper 111 (GTROM/Cheapocabra)
 pub(super) mod mmc1; // Mapper 1
 pub(super) mod mmc2; // Mapper 9
 pub(super) mod mmc3; // Mapper 4
@@ -19,14 +25,24 @@ pub(super) mod uxrom; // Mapper 2, 94, 180
 #[derive(Debug)]
 pub(crate) enum ChrData {
     Rom(Vec<u8>),
-    Ram(Box<[u8; 0x2000]>),
+    Ram(Vec<u8>),
+}
+
+impl ChrData {
+    /// CHR RAM for boards which carry more than 8KB
+    fn with_ram_8kb_units(chr_rom: Option<Vec<u8>>, ram_8kb_units: u8) -> Self {
+        match chr_rom {
+            Some(rom) => ChrData::Rom(rom),
+            None => ChrData::Ram(vec![0; ram_8kb_units.max(1) as usize * 0x2000]),
+        }
+    }
 }
 
 impl From<Option<Vec<u8>>> for ChrData {
     fn from(chr_rom: Option<Vec<u8>>) -> Self {
         match chr_rom {
             Some(rom) => ChrData::Rom(rom),
-            None => ChrData::Ram(Box::new([0; 0x2000])),
+            None => ChrData::Ram(vec![0; 0x2000]),
         }
     }
 }
@@ -54,7 +70,7 @@ impl ChrBaseData {
         debug_assert!(banks.len() == bank_offsets.len());
 
         let total_banks = match &chr_data {
-            ChrData::Ram(_) => 0x2000 / bank_size,
+            ChrData::Ram(ram) => ram.len() / bank_size,
             ChrData::Rom(rom) => rom.len() / bank_size,
         };
 
@@ -114,9 +130,43 @@ impl ChrBaseData {
     }
 }
 
+/// CHR ROM never changes so only CHR RAM is saved
+impl SaveState for ChrBaseData {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.mirroring_mode.save_state(writer);
+        if let ChrData::Ram(ram) = &self.chr_data {
+            ram[..].save_state(writer);
+        }
+        self.ppu_vram.save_state(writer);
+        self.banks.save_state(writer);
+        self.bank_offsets.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.mirroring_mode.load_state(reader)?;
+        if let ChrData::Ram(ram) = &mut self.chr_data {
+            ram[..].load_state(reader)?;
+        }
+        self.ppu_vram.load_state(reader)?;
+        let chr_size = match &self.chr_data {
+            ChrData::Rom(rom) => rom.len(),
+            ChrData::Ram(ram) => ram.len(),
+        };
+        load_banks(
+            &mut self.banks,
+            &mut self.bank_offsets,
+            self.bank_size,
+            chr_size,
+            reader,
+        )
+    }
+}
+
 pub(crate) struct PrgBaseData {
     prg_rom: Vec<u8>,
-    prg_ram: Option<[u8; 0x2000]>,
+    prg_ram: Option<Vec<u8>>,
+    /// Set when a write changes PRG RAM so that battery saves know to flush it, not saved in states
+    prg_ram_written: bool,
     total_banks: usize,
     bank_size: usize,
     banks: Vec<usize>,
@@ -126,7 +176,7 @@ pub(crate) struct PrgBaseData {
 impl PrgBaseData {
     pub(super) fn new(
         prg_rom: Vec<u8>,
-        prg_ram: Option<[u8; 0x2000]>,
+        prg_ram: Option<Vec<u8>>,
         total_banks: usize,
         bank_size: usize,
         banks: Vec<usize>,
@@ -154,6 +204,7 @@ impl PrgBaseData {
         PrgBaseData {
             prg_rom: full_prg_rom,
             prg_ram,
+            prg_ram_written: false,
             total_banks,
             bank_size,
             banks,
@@ -177,36 +228,132 @@ impl PrgBaseData {
         }
     }
 
+    pub(crate) fn prg_ram(&self) -> Option<&[u8]> {
+        self.prg_ram.as_ref().map(|ram| &ram[..])
+    }
+
+    pub(crate) fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.prg_ram.as_mut().map(|ram| &mut ram[..])
+    }
+
+    pub(crate) fn take_prg_ram_written(&mut self) -> bool {
+        std::mem::replace(&mut self.prg_ram_written, false)
+    }
+
+    /// Write to PRG RAM for mappers which have already checked it's enabled, the index is into
+    /// the whole of PRG RAM so that mappers with more than 8KB can bank it
+    pub(crate) fn write_prg_ram(&mut self, index: usize, value: u8) {
+        if let Some(ram) = &mut self.prg_ram {
+            let byte = &mut ram[index];
+            self.prg_ram_written |= *byte != value;
+            *byte = value;
+        }
+    }
+
+    pub(crate) fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        match address {
+            0x8000..=0xFFFF => {
+                let bank = (address as usize - 0x8000) / self.bank_size;
+                let offset = bank * self.bank_size;
+
+                Some(self.bank_offsets[bank] + (address as usize) - offset - 0x8000)
+            }
+            _ => None,
+        }
+    }
+
     pub(crate) fn write_byte(&mut self, address: u16, value: u8) {
         debug!("Mapper write {:04X}={:02X}", address, value);
 
         if let 0x6000..=0x7FFF = address {
-            match &mut self.prg_ram {
-                None => (),
-                Some(ram) => ram[(address - 0x6000) as usize] = value,
-            }
+            self.write_prg_ram((address - 0x6000) as usize, value);
         };
     }
 }
 
+impl SaveState for PrgBaseData {
+    fn save_state(&self, writer: &mut StateWriter) {
+        if let Some(ram) = &self.prg_ram {
+            ram[..].save_state(writer);
+        }
+        self.banks.save_state(writer);
+        self.bank_offsets.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        if let Some(ram) = &mut self.prg_ram {
+            ram[..].load_state(reader)?;
+        }
+        load_banks(
+            &mut self.banks,
+            &mut self.bank_offsets,
+            self.bank_size,
+            self.prg_rom.len(),
+            reader,
+        )
+    }
+}
+
+/// Load the banks and their offsets, a state saved from a different rom could otherwise have
+/// more banks than the mapper or offsets past the end of its ROM and RAM
+fn load_banks(
+    banks: &mut Vec<usize>,
+    bank_offsets: &mut Vec<usize>,
+    bank_size: usize,
+    memory_size: usize,
+    reader: &mut StateReader,
+) -> Result<(), SaveStateError> {
+    let (bank_count, offset_count) = (banks.len(), bank_offsets.len());
+    banks.load_state(reader)?;
+    bank_offsets.load_state(reader)?;
+
+    if banks.len() != bank_count || bank_offsets.len() != offset_count {
+        return Err(invalid_state("the number of banks doesn't match the mapper"));
+    }
+    let outside = |offset: &usize| offset.checked_add(bank_size).is_none_or(|end| end > memory_size);
+    if bank_offsets.iter().any(outside) {
+        return Err(invalid_state("a bank is outside of the cartridge's memory"));
+    }
+
+    Ok(())
+}
+
 pub(crate) struct NoBankPrgChip {
     base: PrgBaseData,
 }
 
 impl NoBankPrgChip {
-    pub(super) fn new(prg_rom: Vec<u8>) -> Self {
+    pub(super) fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>) -> Self {
         NoBankPrgChip {
-            base: PrgBaseData::new(prg_rom, Some([0; 0x2000]), 1, 0x8000, vec![0], vec![0]),
+            base: PrgBaseData::new(prg_rom, prg_ram, 1, 0x8000, vec![0], vec![0]),
         }
     }
 }
 
+save_state_fields!(NoBankPrgChip { base });
+
 impl CpuCartridgeAddressBus for NoBankPrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value)
     }
 }
@@ -224,22 +371,28 @@ impl NoBankChrChip {
     }
 }
 
+save_state_fields!(NoBankChrChip { base });
+
 impl PpuCartridgeAddressBus for NoBankChrChip {
-    fn check_trigger_irq(&mut self, _: bool) -> bool {
+    fn check_trigger_irq(&mut self, _: bool, _: u64) -> bool {
         false
     }
 
-    fn update_vram_address(&mut self, _: u16, _: u32) {}
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
 
-    fn read_byte(&mut self, address: u16, _: u32) -> u8 {
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
     }
 
-    fn cpu_write_byte(&mut self, _: u16, _: u8, _: u32) {}
+    fn cpu_write_byte(&mut self, _: u16, _: u8, _: u64) {}
 }
 
 /// Used to represent all mappers which just use a single register write to map a single 32KB bank
@@ -256,7 +409,7 @@ struct SingleBankedPrgChip {
 impl SingleBankedPrgChip {
     fn new(
         prg_rom: Vec<u8>,
-        prg_ram: Option<[u8; 0x2000]>,
+        prg_ram: Option<Vec<u8>>,
         total_banks: usize,
         mask: u8,
         shift: u8,
@@ -271,12 +424,30 @@ impl SingleBankedPrgChip {
     }
 }
 
+save_state_fields!(SingleBankedPrgChip { base });
+
 impl CpuCartridgeAddressBus for SingleBankedPrgChip {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
 
         if address == 0x7460 {
@@ -321,22 +492,28 @@ impl SingleBankedChrChip {
     }
 }
 
+save_state_fields!(SingleBankedChrChip { base });
+
 impl PpuCartridgeAddressBus for SingleBankedChrChip {
-    fn check_trigger_irq(&mut self, _: bool) -> bool {
+    fn check_trigger_irq(&mut self, _: bool, _: u64) -> bool {
         false
     }
 
-    fn update_vram_address(&mut self, _: u16, _: u32) {}
+    fn update_vram_address(&mut self, _: u16, _: u64) {}
+
+    fn peek_byte(&self, address: u16) -> u8 {
+        self.base.read_byte(address)
+    }
 
-    fn read_byte(&mut self, address: u16, _: u32) -> u8 {
+    fn read_byte(&mut self, address: u16, _: u64) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
     }
 
-    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn cpu_write_byte(&mut self, address: u16, value: u8, _: u64) {
         if (self.control_register_check)(address) {
             self.base.banks[0] = ((value & self.mask) >> self.shift) as usize % self.base.total_banks;
             self.base.bank_offsets[0] = self.base.banks[0] as usize * 0x2000;
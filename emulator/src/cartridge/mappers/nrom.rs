@@ -15,7 +15,7 @@ pub(crate) fn from_header(
 ) {
     info!("Creating NROM mapper for cartridge");
     (
-        Box::new(NoBankPrgChip::new(prg_rom)),
+        Box::new(NoBankPrgChip::new(prg_rom, header.prg_ram(true))),
         Box::new(NoBankChrChip::new(ChrData::from(chr_rom), header.mirroring)),
         header,
     )
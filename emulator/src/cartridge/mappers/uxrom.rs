@@ -4,57 +4,127 @@ use cartridge::CpuCartridgeAddressBus;
 use cartridge::PpuCartridgeAddressBus;
 use log::info;
 
+/// Which 16KB bank is fixed in place, the other window is switched by writes to 8000-FFFF
+#[derive(Debug, PartialEq)]
+enum FixedBank {
+    First,
+    Last,
+}
+
 /// UxRom board comes in a variety of variants which subtly change how
 /// banking is achieved
-#[derive(Debug, PartialEq)]
-enum UxRomVariant {
-    Unrom,        // Mapper 002
-    UnromReverse, // Mapper 180
-    HvcUn1Rom,    // Mapper 094
+#[derive(Debug)]
+struct UxRomConfig {
+    name: &'static str,
+    /// The CPU window (0 => 8000-BFFF, 1 => C000-FFFF) selected by the bank register
+    switchable_window: usize,
+    fixed_bank: FixedBank,
+    /// Where the bank number sits in the value written to the bank register
+    bank_shift: u8,
+    bank_mask: u8,
 }
 
+impl UxRomConfig {
+    fn bank(&self, value: u8) -> usize {
+        ((value >> self.bank_shift) & self.bank_mask) as usize
+    }
+}
+
+/// Mapper 002, 8000-BFFF switchable with the last bank fixed at C000-FFFF
+///
+/// TODO - According to https://wiki.nesdev.com/w/index.php/UxROM UOROM uses 4 bits to describe the bank and UNROM
+/// uses 3 bits, I mask here with 4 bits because I'm not sure how to tell the two apart.
+const UNROM: UxRomConfig = UxRomConfig {
+    name: "UNROM",
+    switchable_window: 0,
+    fixed_bank: FixedBank::Last,
+    bank_shift: 0,
+    bank_mask: 0b1111,
+};
+
+/// Mapper 094 (Senjou no Ookami), laid out as UNROM but the bank is taken from bits 2-4
+const HVC_UN1ROM: UxRomConfig = UxRomConfig {
+    name: "HVC-UN1ROM",
+    switchable_window: 0,
+    fixed_bank: FixedBank::Last,
+    bank_shift: 2,
+    bank_mask: 0b111,
+};
+
+/// Mapper 180 (Crazy Climber), the first bank is fixed at 8000-BFFF and C000-FFFF is switchable
+const UNROM_REVERSE: UxRomConfig = UxRomConfig {
+    name: "UNROM (reverse)",
+    switchable_window: 1,
+    fixed_bank: FixedBank::First,
+    bank_shift: 0,
+    bank_mask: 0b111,
+};
+
 struct UxRom {
     base: PrgBaseData,
-    variant: UxRomVariant,
+    config: &'static UxRomConfig,
 }
 
 impl UxRom {
-    fn new(prg_rom: Vec<u8>, total_banks: usize, variant: UxRomVariant) -> Self {
+    fn new(prg_rom: Vec<u8>, prg_ram: Option<Vec<u8>>, total_banks: usize, config: &'static UxRomConfig) -> Self {
+        let fixed_bank = match config.fixed_bank {
+            FixedBank::First => 0,
+            FixedBank::Last => total_banks - 1,
+        };
+        // At power on the switchable window holds the bank at the opposite end to the fixed one
+        let mut banks = vec![0; 2];
+        banks[1 - config.switchable_window] = fixed_bank;
+        banks[config.switchable_window] = total_banks - 1 - fixed_bank;
+        let bank_offsets = banks.iter().map(|bank| bank * 0x4000).collect();
+
         UxRom {
-            variant,
+            config,
             base: PrgBaseData {
                 prg_rom,
-                prg_ram: None,
+                prg_ram,
+                prg_ram_written: false,
                 bank_size: 0x4000,
                 total_banks,
-                banks: vec![0, total_banks - 1],
-                bank_offsets: vec![0, (total_banks - 1) * 0x4000],
+                banks,
+                bank_offsets,
             },
         }
     }
 }
 
+save_state_fields!(UxRom { base });
+
 impl CpuCartridgeAddressBus for UxRom {
+    fn prg_ram(&self) -> Option<&[u8]> {
+        self.base.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.base.prg_ram_mut()
+    }
+
+    fn take_prg_ram_written(&mut self) -> bool {
+        self.base.take_prg_ram_written()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.base.prg_rom_offset(address)
+    }
+
     fn read_byte(&self, address: u16) -> u8 {
         self.base.read_byte(address)
     }
 
-    fn write_byte(&mut self, address: u16, value: u8, _: u32) {
+    fn write_byte(&mut self, address: u16, value: u8, _: u64) {
         self.base.write_byte(address, value);
 
         if let 0x8000..=0xFFFF = address {
-            // TODO - According to https://wiki.nesdev.com/w/index.php/UxROM UOROM uses 4 bits to describe the bank and UNROM uses 3 bits, I mask here with 4 bits because I'm not sure how to tell the two apart.
-            let (switchable_bank, value) = match self.variant {
-                UxRomVariant::Unrom => (0, (value as usize & 0b1111) % self.base.total_banks),
-                UxRomVariant::UnromReverse => (1, (value as usize & 0b1111) % self.base.total_banks),
-                UxRomVariant::HvcUn1Rom => (0, ((value as usize & 0b1_1100) >> 2) % self.base.total_banks),
-            };
-
-            self.base.banks[switchable_bank] = value;
-            self.base.bank_offsets[switchable_bank] = self.base.banks[switchable_bank] * 0x4000;
+            let window = self.config.switchable_window;
+            self.base.banks[window] = self.config.bank(value) % self.base.total_banks;
+            self.base.bank_offsets[window] = self.base.banks[window] * 0x4000;
             info!(
-                "UxROM ({:?}) bank switch {:?} => {:?}",
-                self.variant, self.base.banks, self.base.bank_offsets
+                "{} bank switch {:?} => {:?}",
+                self.config.name, self.base.banks, self.base.bank_offsets
             );
         }
     }
@@ -73,11 +143,12 @@ pub(crate) fn from_header(
     (
         Box::new(UxRom::new(
             prg_rom,
+            header.prg_ram(false),
             header.prg_rom_16kb_units as usize,
             match header.mapper {
-                2 => UxRomVariant::Unrom,
-                94 => UxRomVariant::HvcUn1Rom,
-                180 => UxRomVariant::UnromReverse,
+                2 => &UNROM,
+                94 => &HVC_UN1ROM,
+                180 => &UNROM_REVERSE,
                 _ => panic!("Can't create UxROM from mapper {}", header.mapper),
             },
         )),
@@ -85,3 +156,53 @@ pub(crate) fn from_header(
         header,
     )
 }
+
+#[cfg(test)]
+mod uxrom_tests {
+    use super::*;
+
+    /// 128KB of PRG ROM where every byte of each bank holds the bank number
+    fn uxrom(config: &'static UxRomConfig) -> UxRom {
+        let prg_rom = (0..8).flat_map(|bank| vec![bank as u8; 0x4000]).collect();
+        UxRom::new(prg_rom, None, 8, config)
+    }
+
+    fn windows(uxrom: &UxRom) -> (u8, u8) {
+        (uxrom.read_byte(0x8000), uxrom.read_byte(0xC000))
+    }
+
+    #[test]
+    fn test_unrom_fixes_last_bank() {
+        let mut uxrom = uxrom(&UNROM);
+        assert_eq!(windows(&uxrom), (0, 7));
+
+        uxrom.write_byte(0x8000, 3, 0);
+        assert_eq!(windows(&uxrom), (3, 7));
+        uxrom.write_byte(0xFFFF, 0b1111, 0);
+        assert_eq!(windows(&uxrom), (7, 7));
+    }
+
+    #[test]
+    fn test_hvc_un1rom_selects_bank_from_bits_2_to_4() {
+        // Senjou no Ookami
+        let mut uxrom = uxrom(&HVC_UN1ROM);
+        assert_eq!(windows(&uxrom), (0, 7));
+
+        uxrom.write_byte(0x8000, 3, 0);
+        assert_eq!(windows(&uxrom), (0, 7));
+        uxrom.write_byte(0x8000, 5 << 2 | 0b1110_0011, 0);
+        assert_eq!(windows(&uxrom), (5, 7));
+    }
+
+    #[test]
+    fn test_unrom_reverse_fixes_first_bank() {
+        // Crazy Climber
+        let mut uxrom = uxrom(&UNROM_REVERSE);
+        assert_eq!(windows(&uxrom), (0, 7));
+
+        uxrom.write_byte(0x8000, 3, 0);
+        assert_eq!(windows(&uxrom), (0, 3));
+        uxrom.write_byte(0xC000, 0b1111_1110, 0);
+        assert_eq!(windows(&uxrom), (0, 6));
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt::{Display, Formatter, Result};
+use std::str::FromStr;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MirroringMode {
     OneScreenLowerBank,
@@ -7,6 +10,44 @@ pub enum MirroringMode {
     FourScreen,
 }
 
+save_state_enum!(MirroringMode {
+    OneScreenLowerBank,
+    OneScreenUpperBank,
+    Vertical,
+    Horizontal,
+    FourScreen,
+});
+
+impl Display for MirroringMode {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            MirroringMode::OneScreenLowerBank => write!(f, "one_screen_lower"),
+            MirroringMode::OneScreenUpperBank => write!(f, "one_screen_upper"),
+            MirroringMode::Vertical => write!(f, "vertical"),
+            MirroringMode::Horizontal => write!(f, "horizontal"),
+            MirroringMode::FourScreen => write!(f, "four_screen"),
+        }
+    }
+}
+
+impl FromStr for MirroringMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "one_screen_lower" => Ok(MirroringMode::OneScreenLowerBank),
+            "one_screen_upper" => Ok(MirroringMode::OneScreenUpperBank),
+            "vertical" => Ok(MirroringMode::Vertical),
+            "horizontal" => Ok(MirroringMode::Horizontal),
+            "four_screen" => Ok(MirroringMode::FourScreen),
+            _ => Err(format!(
+                "Unknown mirroring mode {}, expected horizontal, vertical, four_screen, one_screen_lower or one_screen_upper",
+                s
+            )),
+        }
+    }
+}
+
 impl MirroringMode {
     pub(crate) fn get_mirrored_address(&self, address: u16) -> u16 {
         let adjusted_address = address - 0x2000;
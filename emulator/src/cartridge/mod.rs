@@ -1,10 +1,21 @@
+mod compression;
+mod info;
+mod mapper_info;
 mod mappers;
 mod mirroring;
+mod patch;
+mod region;
 
-use cartridge::mirroring::MirroringMode;
+pub use cartridge::info::CartridgeInfo;
+pub use cartridge::mapper_info::{mapper_implementation, mapper_info, supported_mappers, CompatLevel, MapperInfo};
+pub use cartridge::mirroring::MirroringMode;
+pub use cartridge::patch::RomPatch;
+pub use cartridge::region::Region;
 use cpu::CpuCycle;
-use log::info;
+use crc32fast::Hasher;
+use log::{info, warn};
 use ppu::PpuCycle;
+use savestate::SaveState;
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fmt;
@@ -46,102 +57,432 @@ impl From<ZipError> for CartridgeError {
 }
 
 /// A trait representing the CPU address bus into the cartridge
-pub trait CpuCartridgeAddressBus {
+/// Mappers save their bank registers and any RAM (PRG RAM, CHR RAM and nametables) as part of a savestate
+/// Mappers are Send so that consoles can run on their own threads, they mustn't share state with
+/// other cartridges
+pub trait CpuCartridgeAddressBus: SaveState + Send {
     /// Read from the 16 bit CPU address bus
     fn read_byte(&self, address: u16) -> u8;
     /// Write to the 16 bit CPU address bus
     fn write_byte(&mut self, address: u16, value: u8, cycles: PpuCycle);
+    /// Debug information, the offset into PRG ROM currently mapped at this address (if any)
+    fn prg_rom_offset(&self, address: u16) -> Option<usize>;
+    /// The PRG RAM mapped at 0x6000-0x7FFF (if any), for saving and restoring it. Boards which
+    /// save to flash rather than battery backed RAM return the flash.
+    fn prg_ram(&self) -> Option<&[u8]>;
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]>;
+    /// Whether PRG RAM has changed since the last call, so battery backed RAM can be saved once
+    /// the game has finished writing to it
+    fn take_prg_ram_written(&mut self) -> bool;
+    /// Boards with DIP switches (e.g. the NWC competition timer) read them from here, the value
+    /// is configuration rather than state so isn't included in savestates
+    fn set_dip_switches(&mut self, _: u8) {}
 }
 
 /// A trait representing the PPU address bus into the cartridge
-pub trait PpuCartridgeAddressBus {
-    /// Certain mappers can trigger an IRQ based on scanline counting (MMC3)
+pub trait PpuCartridgeAddressBus: SaveState + Send {
+    /// Certain mappers can trigger an IRQ based on scanline counting (MMC3) or a cycle timer (NWC)
     /// This function allows the CPU to poll and request state on whether an IRQ is ready to fire.
-    fn check_trigger_irq(&mut self, clear: bool) -> bool;
+    fn check_trigger_irq(&mut self, clear: bool, cycles: CpuCycle) -> bool;
     /// Certain mappers can trigger an IRQ based on scanline counting (MMC3)
     /// This function allows the mapper to listen on address bus changes
     fn update_vram_address(&mut self, address: u16, cycles: PpuCycle);
+    /// Read from the 14 bit PPU address bus without triggering any mapper side effects (e.g. MMC2 latches)
+    fn peek_byte(&self, address: u16) -> u8;
     /// Read from the 14 bit PPU address bus
     fn read_byte(&mut self, address: u16, cycles: PpuCycle) -> u8;
     /// Write to the 14 bit PPU address bus
     fn write_byte(&mut self, address: u16, value: u8, cycles: PpuCycle);
     /// Write to the 16 bit CPU address bus, required to set mapper registers
     fn cpu_write_byte(&mut self, address: u16, value: u8, cycles: CpuCycle);
+    /// As `CpuCartridgeAddressBus::set_dip_switches`
+    fn set_dip_switches(&mut self, _: u8) {}
+    /// Called after the nametable fetch of each background tile, mappers which replace parts of
+    /// the background (e.g. MMC5 extended attributes and split screen) return the substitutions
+    fn background_tile_fetch(&mut self, _: &BackgroundTileFetch) -> BackgroundTileOverride {
+        BackgroundTileOverride::default()
+    }
+    /// Read the pattern bytes of a background tile, mappers can serve these from a different
+    /// bank to the one sprites and $2007 see
+    fn read_background_pattern(&mut self, address: u16, cycles: PpuCycle) -> u8 {
+        self.read_byte(address, cycles)
+    }
+}
+
+/// A background tile which the PPU has just fetched the nametable byte for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackgroundTileFetch {
+    /// Which of the 34 tiles fetched for a scanline this is, 0 and 1 are fetched at the end of
+    /// the line before (or the pre-render line)
+    pub column: u8,
+    /// The scanline the tile is drawn on
+    pub scanline: u16,
+    pub nametable_address: u16,
+    pub nametable_byte: u8,
+}
+
+/// Parts of a background tile fetch for the mapper to replace, None keeps what the PPU fetched
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BackgroundTileOverride {
+    /// Used to pick the pattern instead of the nametable byte
+    pub nametable_byte: Option<u8>,
+    /// The 2 bit palette, instead of the one picked from the attribute byte
+    pub palette: Option<u8>,
+    /// The row of the pattern to fetch, instead of the fine y scroll
+    pub fine_y: Option<u8>,
 }
 
 /// Represents flags/details about the rom from the header
-/// c.f. http://wiki.nesdev.com/w/index.php/INES for details
+/// c.f. http://wiki.nesdev.com/w/index.php/INES and http://wiki.nesdev.com/w/index.php/NES_2.0 for details
 #[derive(Debug)]
 pub struct CartridgeHeader {
-    pub prg_rom_16kb_units: u8,
-    pub chr_rom_8kb_units: u8,
+    pub prg_rom_16kb_units: u16,
+    pub chr_rom_8kb_units: u16,
     pub mapper: u8,
     pub mirroring: MirroringMode,
+    /// Bit 0 of flags 6, boards with their own nametable layouts (e.g. UNROM-512) still read it
+    /// when the four screen bit is set
+    pub vertical_mirroring_bit: bool,
     pub ram_is_battery_backed: bool,
+    /// The amount of PRG RAM at 0x6000-0x7FFF from an NES 2.0 header (volatile and battery backed
+    /// combined), None leaves it to the mapper as iNES 1.0 headers rarely fill this in correctly
+    pub prg_ram_8kb_units: Option<u8>,
+    /// The amount of CHR RAM from an NES 2.0 header (rounded up to 8KB), None leaves it to the mapper
+    pub chr_ram_8kb_units: Option<u8>,
+    /// From the NES 2.0 timing byte, or failing that a region tag in the filename, NTSC otherwise
+    pub region: Region,
+    /// The implementation chosen for `mapper`, filled in when the mapper is created
+    pub mapper_info: Option<&'static MapperInfo>,
+    /// Whether the header is in the NES 2.0 format rather than iNES 1.0
+    pub nes_2: bool,
+    /// The NES 2.0 submapper, None for iNES 1.0 headers
+    pub submapper: Option<u8>,
+    /// CRC32s of the ROM as loaded (i.e. after any patch), filled in once it's been split out
+    pub prg_rom_crc32: u32,
+    pub chr_rom_crc32: Option<u32>,
+    /// CRC32 of PRG and CHR ROM together
+    pub rom_crc32: u32,
     // TODO - Lots more flags and possible options
 }
 
 impl CartridgeHeader {
-    fn new(prg_rom_16kb_units: u8, chr_rom_8kb_units: u8, flags_6: u8, flags_7: u8) -> Self {
-        CartridgeHeader {
-            prg_rom_16kb_units,
-            chr_rom_8kb_units,
+    fn new(header: &[u8]) -> Result<Self, CartridgeError> {
+        let (flags_6, flags_7) = (header[6], header[7]);
+        let mut cartridge_header = CartridgeHeader {
+            prg_rom_16kb_units: header[4] as u16,
+            chr_rom_8kb_units: header[5] as u16,
             mapper: (flags_6 >> 4) | (flags_7 & 0b1111_0000),
             mirroring: match (flags_6 & 1 == 0, flags_6 & 0b1000 == 0) {
                 (true, true) => MirroringMode::Horizontal,
                 (false, true) => MirroringMode::Vertical,
                 (_, false) => MirroringMode::FourScreen,
             },
+            vertical_mirroring_bit: flags_6 & 1 == 1,
             ram_is_battery_backed: flags_6 & 0b10 == 0b10,
+            prg_ram_8kb_units: None,
+            chr_ram_8kb_units: None,
+            region: Region::from_header(header).unwrap_or(Region::Ntsc),
+            mapper_info: None,
+            nes_2: flags_7 & 0b1100 == 0b1000,
+            submapper: None,
+            prg_rom_crc32: 0,
+            chr_rom_crc32: None,
+            rom_crc32: 0,
+        };
+
+        if cartridge_header.nes_2 {
+            cartridge_header.submapper = Some(header[8] >> 4);
+            // NES 2.0 extends the ROM sizes with a high nibble in byte 9 or, for sizes which
+            // aren't a multiple of the unit, an exponent-multiplier format
+            cartridge_header.prg_rom_16kb_units = nes_2_rom_units(header[4], header[9] & 0xF, 0x4000, "PRG")?;
+            cartridge_header.chr_rom_8kb_units = nes_2_rom_units(header[5], header[9] >> 4, 0x2000, "CHR")?;
+            // Volatile and battery backed PRG RAM are given separately, the mappers don't
+            // distinguish them so they're added together
+            let prg_ram_bytes: usize = [header[10] & 0xF, header[10] >> 4]
+                .iter()
+                .map(|&shift| if shift == 0 { 0 } else { 64 << shift })
+                .sum();
+            if prg_ram_bytes > 0 {
+                // Anything smaller than 8KB is still mapped as a full 8KB
+                cartridge_header.prg_ram_8kb_units =
+                    Some((prg_ram_bytes.max(0x2000) / 0x2000).min(u8::MAX as usize) as u8);
+            }
+            cartridge_header.chr_ram_8kb_units = match header[11] & 0xF {
+                0 => None,
+                shift => Some(((64usize << shift).max(0x2000) / 0x2000).min(u8::MAX as usize) as u8),
+            };
+        }
+
+        Ok(cartridge_header)
+    }
+
+    /// The PRG RAM for a mapper which has RAM (or not) unless the header says otherwise
+    pub(crate) fn prg_ram(&self, mapper_has_ram: bool) -> Option<Vec<u8>> {
+        self.banked_prg_ram(mapper_has_ram, 1)
+    }
+
+    /// As `prg_ram` for mappers which can bank up to `max_8kb_units` of PRG RAM, they get 8KB
+    /// unless the header asks for more
+    pub(crate) fn banked_prg_ram(&self, mapper_has_ram: bool, max_8kb_units: u8) -> Option<Vec<u8>> {
+        match self.prg_ram_8kb_units {
+            None if mapper_has_ram => Some(vec![0; 0x2000]),
+            None | Some(0) => None,
+            Some(units) => Some(vec![0; units.min(max_8kb_units) as usize * 0x2000]),
+        }
+    }
+
+    /// Sizes in KB, the likely board and checksums, for frontends to describe the rom
+    pub fn info(&self) -> CartridgeInfo {
+        CartridgeInfo::from_header(self)
+    }
+
+    fn set_crc32s(&mut self, prg_rom: &[u8], chr_rom: Option<&[u8]>) {
+        let mut hasher = Hasher::new();
+        hasher.update(prg_rom);
+        self.prg_rom_crc32 = hasher.clone().finalize();
+        self.chr_rom_crc32 = chr_rom.map(|chr_rom| {
+            hasher.update(chr_rom);
+            let mut chr_hasher = Hasher::new();
+            chr_hasher.update(chr_rom);
+            chr_hasher.finalize()
+        });
+        self.rom_crc32 = hasher.finalize();
+    }
+}
+
+/// Corrections applied to a rom as it's loaded, for dumps with bad headers or to soft patch it
+#[derive(Debug, Default, Clone)]
+pub struct CartridgeOverrides {
+    /// Applied to the whole file before the header is parsed
+    pub patch: Option<RomPatch>,
+    pub mapper: Option<u8>,
+    /// Use this implementation rather than the default for the mapper, see `supported_mappers`
+    pub mapper_id: Option<String>,
+    pub mirroring: Option<MirroringMode>,
+    /// 0 removes PRG RAM, only MMC1 (up to 4 banks) supports more than a single 8KB bank
+    pub prg_ram_8kb_units: Option<u8>,
+    pub battery: Option<bool>,
+    pub region: Option<Region>,
+}
+
+impl CartridgeOverrides {
+    fn apply(&self, header: &mut CartridgeHeader) {
+        if let Some(mapper) = self.mapper {
+            warn!("Overriding mapper {} from header with {}", header.mapper, mapper);
+            header.mapper = mapper;
+        }
+        if let Some(mirroring) = self.mirroring {
+            warn!(
+                "Overriding mirroring {} from header with {}",
+                header.mirroring, mirroring
+            );
+            header.mirroring = mirroring;
+        }
+        if let Some(prg_ram_8kb_units) = self.prg_ram_8kb_units {
+            warn!("Overriding PRG RAM with {} 8KB units", prg_ram_8kb_units);
+            header.prg_ram_8kb_units = Some(prg_ram_8kb_units);
+        }
+        if let Some(battery) = self.battery {
+            warn!(
+                "Overriding battery backed RAM {} from header with {}",
+                header.ram_is_battery_backed, battery
+            );
+            header.ram_is_battery_backed = battery;
+        }
+        if let Some(region) = self.region {
+            warn!("Overriding region {} with {}", header.region, region);
+            header.region = region;
         }
     }
 }
 
+/// The number of units of ROM given the NES 2.0 size LSB byte and MSB nibble
+fn nes_2_rom_units(lsb: u8, msb: u8, unit_size: u64, name: &str) -> Result<u16, CartridgeError> {
+    if msb != 0xF {
+        return Ok((msb as u16) << 8 | lsb as u16);
+    }
+
+    let exponent = (lsb >> 2) as u32;
+    let multiplier = (lsb & 0b11) as u64 * 2 + 1;
+    match 1u64.checked_shl(exponent).and_then(|size| size.checked_mul(multiplier)) {
+        Some(size) if size % unit_size == 0 && size / unit_size <= u16::MAX as u64 => Ok((size / unit_size) as u16),
+        _ => Err(CartridgeError {
+            message: format!(
+                "{} ROM of 2^{} * {} bytes isn't a supported size",
+                name, exponent, multiplier
+            ),
+            mapper: None,
+        }),
+    }
+}
+
 impl fmt::Display for CartridgeHeader {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "PRG Units {}, CHR Units {}, Mapper {}",
-            self.prg_rom_16kb_units, self.chr_rom_8kb_units, self.mapper
+            "Mapper {}, PRG ROM {}KB, CHR ROM {}KB, {} mirroring, Region {}",
+            self.mapper,
+            self.prg_rom_16kb_units as u32 * 16,
+            self.chr_rom_8kb_units as u32 * 8,
+            self.mirroring,
+            self.region
         )
     }
 }
 
-pub(crate) fn from_file(file_path: &str) -> Result<Cartridge, CartridgeError> {
-    let file_extension = Path::new(file_path).extension().and_then(OsStr::to_str);
-    let file = File::open(file_path)?;
+pub(crate) fn from_file(file_path: &str, overrides: &CartridgeOverrides) -> Result<Cartridge, CartridgeError> {
+    let bytes = read_file(file_path)?;
+
+    from_bytes(&bytes, file_path, overrides)
+}
+
+/// Describe the rom in a file from its header and contents, without creating the mapper so that
+/// roms for unsupported mappers can still be described
+pub(crate) fn info_from_file(file_path: &str) -> Result<CartridgeInfo, CartridgeError> {
+    let bytes = read_file(file_path)?;
+    let mut rom = parse(&bytes, file_path, &CartridgeOverrides::default())?;
+    rom.header.mapper_info = mapper_info(rom.header.mapper);
+
+    Ok(rom.header.info())
+}
+
+/// Describe the rom in a file as `info_from_file` does and then try to create the mapper from the
+/// same parse, returning why the rom couldn't be loaded if it can still be described
+pub(crate) fn info_and_load_error_from_file(
+    file_path: &str,
+) -> Result<(CartridgeInfo, Option<CartridgeError>), CartridgeError> {
+    let bytes = read_file(file_path)?;
+    let mut rom = parse(&bytes, file_path, &CartridgeOverrides::default())?;
+    rom.header.mapper_info = mapper_info(rom.header.mapper);
+    let info = rom.header.info();
+
+    Ok((info, create_mapper(rom.prg_rom, rom.chr_rom, rom.header, None).err()))
+}
+
+/// The bytes of a rom file, decompressed or extracted from an archive containing only that rom
+fn read_file(file_path: &str) -> Result<Vec<u8>, CartridgeError> {
+    let file_extension = Path::new(file_path)
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase);
 
-    let mut bytes = Vec::<u8>::new();
-    match file_extension {
+    let bytes = match file_extension.as_deref() {
         Some("zip") => {
-            let mut zip = ZipArchive::new(file)?;
-
-            let nes_files = (0..zip.len())
-                .filter_map(|ix| {
-                    let zfile = zip.by_index(ix).unwrap();
-                    let extension = Path::new(zfile.name()).extension().and_then(OsStr::to_str);
-
-                    match extension {
-                        Some("nes") => Some(ix),
-                        _ => None,
-                    }
-                })
-                .collect::<Vec<_>>();
-
-            match nes_files.first() {
-                None => {
+            let entries = list_archive_entries(file_path)?;
+            match entries.len() {
+                0 => {
                     return Err(CartridgeError {
-                        message: "The zip file must contain only one file with the .nes extension".to_string(),
+                        message: "The zip file must contain a file with the .nes extension".to_string(),
                         mapper: None,
                     });
                 }
-                Some(zip_file_index) => {
-                    let mut zfile = zip.by_index(*zip_file_index).unwrap();
-                    zfile.read_to_end(&mut bytes)?;
+                1 => read_archive_entry(file_path, 0)?,
+                _ => {
+                    return Err(CartridgeError {
+                        message: format!(
+                            "The zip file contains {} roms ({}), select one with from_archive_entry",
+                            entries.len(),
+                            entries.join(", ")
+                        ),
+                        mapper: None,
+                    });
                 }
             }
         }
-        _ => bytes = std::fs::read(file_path)?,
+        Some("gz") => compression::read_gzip(file_path)?,
+        Some("7z") => compression::read_7z(file_path)?,
+        _ => std::fs::read(file_path)?,
+    };
+
+    Ok(bytes)
+}
+
+/// List the names (including any directories) of the roms contained in a zip archive, in the
+/// order they appear in the archive. The position in this list is the index passed to
+/// `from_archive_entry`.
+pub(crate) fn list_archive_entries(file_path: &str) -> Result<Vec<String>, CartridgeError> {
+    let mut zip = ZipArchive::new(File::open(file_path)?)?;
+    let indices = rom_indices(&mut zip)?;
+
+    indices
+        .into_iter()
+        .map(|ix| Ok(zip.by_index(ix)?.name().to_string()))
+        .collect()
+}
+
+/// Load a single rom out of a zip archive which may contain several
+pub(crate) fn from_archive_entry(
+    file_path: &str,
+    index: usize,
+    overrides: &CartridgeOverrides,
+) -> Result<Cartridge, CartridgeError> {
+    let bytes = read_archive_entry(file_path, index)?;
+
+    from_bytes(&bytes, &format!("{} [{}]", file_path, index), overrides)
+}
+
+fn read_archive_entry(file_path: &str, index: usize) -> Result<Vec<u8>, CartridgeError> {
+    let mut zip = ZipArchive::new(File::open(file_path)?)?;
+    let indices = rom_indices(&mut zip)?;
+
+    match indices.get(index) {
+        None => Err(CartridgeError {
+            message: format!(
+                "The zip file {} contains {} roms, no rom at index {}",
+                file_path,
+                indices.len(),
+                index
+            ),
+            mapper: None,
+        }),
+        Some(zip_file_index) => {
+            let mut bytes = Vec::<u8>::new();
+            zip.by_index(*zip_file_index)?.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// The indices into the zip archive of all files with the .nes extension, at any depth
+fn rom_indices(zip: &mut ZipArchive<File>) -> Result<Vec<usize>, CartridgeError> {
+    let mut indices = vec![];
+    for ix in 0..zip.len() {
+        let zfile = zip.by_index(ix)?;
+        let extension = Path::new(zfile.name())
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+
+        if !zfile.is_dir() && extension.as_deref() == Some("nes") {
+            indices.push(ix);
+        }
+    }
+
+    Ok(indices)
+}
+
+fn from_bytes(bytes: &[u8], file_path: &str, overrides: &CartridgeOverrides) -> Result<Cartridge, CartridgeError> {
+    let rom = parse(bytes, file_path, overrides)?;
+
+    create_mapper(rom.prg_rom, rom.chr_rom, rom.header, overrides.mapper_id.as_deref())
+}
+
+/// A rom file split into its parts, the ROM banks are handed to the mapper
+struct ParsedRom {
+    header: CartridgeHeader,
+    prg_rom: Vec<u8>,
+    chr_rom: Option<Vec<u8>>,
+}
+
+/// Patch the rom and apply the overrides, then split it into the header, PRG ROM and CHR ROM
+fn parse(bytes: &[u8], file_path: &str, overrides: &CartridgeOverrides) -> Result<ParsedRom, CartridgeError> {
+    let patched_bytes;
+    let bytes = match &overrides.patch {
+        None => bytes,
+        Some(patch) => {
+            patched_bytes = patch.apply(bytes)?;
+            &patched_bytes[..]
+        }
     };
 
     if bytes.len() < 0x10 {
@@ -151,10 +492,18 @@ pub(crate) fn from_file(file_path: &str) -> Result<Cartridge, CartridgeError> {
         });
     }
 
-    let header = CartridgeHeader::new(bytes[4], bytes[5], bytes[6], bytes[7]);
+    let mut header = CartridgeHeader::new(&bytes[..0x10])?;
+    if Region::from_header(&bytes[..0x10]).is_none() {
+        if let Some(region) = Region::from_filename(file_path) {
+            info!("Region {} from filename {}", region, file_path);
+            header.region = region;
+        }
+    }
 
     info!("{}: {:08b} {:08b}", header, bytes[6], bytes[7]);
 
+    overrides.apply(&mut header);
+
     let prg_rom_start = 0x10 as usize;
     let prg_rom_end = prg_rom_start + (header.prg_rom_16kb_units as usize * 0x4000);
     let chr_rom_end = prg_rom_end + (header.chr_rom_8kb_units as usize * 0x2000);
@@ -175,24 +524,255 @@ pub(crate) fn from_file(file_path: &str) -> Result<Cartridge, CartridgeError> {
         0 => None,
         _ => Some(bytes[prg_rom_end..chr_rom_end].to_vec()),
     };
+    header.set_crc32s(&prg_rom, chr_rom.as_deref());
 
-    match header.mapper {
-        0 => Ok(mappers::nrom::from_header(prg_rom, chr_rom, header)),
-        1 | 155 => Ok(mappers::mmc1::from_header(prg_rom, chr_rom, header)),
-        2 | 94 | 180 => Ok(mappers::uxrom::from_header(prg_rom, chr_rom, header)),
-        3 => Ok(mappers::cnrom::from_header(prg_rom, chr_rom, header)),
-        4 => Ok(mappers::mmc3::from_header(prg_rom, chr_rom, header)),
-        7 => Ok(mappers::axrom::from_header(prg_rom, chr_rom, header)),
-        9 => Ok(mappers::mmc2::from_header(prg_rom, chr_rom, header)),
-        10 => Ok(mappers::mmc4::from_header(prg_rom, chr_rom, header)),
-        11 => Ok(mappers::color_dreams::from_header(prg_rom, chr_rom, header)),
-        34 => Ok(mappers::bxrom::from_header(prg_rom, chr_rom, header)),
-        66 => Ok(mappers::gxrom::from_header(prg_rom, chr_rom, header)),
-        71 => Ok(mappers::mapper_071::from_header(prg_rom, chr_rom, header)),
-        79 => Ok(mappers::nina_003_006::from_header(prg_rom, chr_rom, header)),
-        _ => Err(CartridgeError {
+    Ok(ParsedRom {
+        header,
+        prg_rom,
+        chr_rom,
+    })
+}
+
+/// Build a cartridge from ROM already in memory, for tests and fuzzers which want to run a small
+/// program without writing an iNES file. No CHR ROM gives the board CHR RAM.
+pub(crate) fn from_prg_chr(
+    prg_rom: Vec<u8>,
+    chr_rom: Option<Vec<u8>>,
+    mapper: u8,
+    mirroring: MirroringMode,
+) -> Result<Cartridge, CartridgeError> {
+    let chr_length = chr_rom.as_ref().map_or(0, Vec::len);
+    if prg_rom.is_empty() || prg_rom.len() & 0x3FFF != 0 || chr_length & 0x1FFF != 0 {
+        return Err(CartridgeError {
+            message: format!(
+                "PRG ROM must be a non zero multiple of 16KB and CHR ROM a multiple of 8KB, got {:x} and {:x} bytes",
+                prg_rom.len(),
+                chr_length
+            ),
+            mapper: Some(mapper),
+        });
+    }
+
+    let mut header = CartridgeHeader {
+        prg_rom_16kb_units: (prg_rom.len() / 0x4000) as u16,
+        chr_rom_8kb_units: (chr_length / 0x2000) as u16,
+        mapper,
+        mirroring,
+        vertical_mirroring_bit: mirroring == MirroringMode::Vertical,
+        ram_is_battery_backed: false,
+        prg_ram_8kb_units: None,
+        chr_ram_8kb_units: None,
+        region: Region::Ntsc,
+        mapper_info: None,
+        nes_2: false,
+        submapper: None,
+        prg_rom_crc32: 0,
+        chr_rom_crc32: None,
+        rom_crc32: 0,
+    };
+    let chr_rom = chr_rom.filter(|chr_rom| !chr_rom.is_empty());
+    header.set_crc32s(&prg_rom, chr_rom.as_deref());
+
+    create_mapper(prg_rom, chr_rom, header, None)
+}
+
+fn create_mapper(
+    prg_rom: Vec<u8>,
+    chr_rom: Option<Vec<u8>>,
+    mut header: CartridgeHeader,
+    mapper_id: Option<&str>,
+) -> Result<Cartridge, CartridgeError> {
+    let info = match mapper_id {
+        None => mapper_info(header.mapper).ok_or_else(|| CartridgeError {
             message: format!("Mapper {} not yet implemented", header.mapper),
             mapper: Some(header.mapper),
-        }),
+        })?,
+        Some(id) => mapper_implementation(id)
+            .filter(|info| info.number == header.mapper)
+            .ok_or_else(|| CartridgeError {
+                message: format!("Mapper {} has no implementation {}", header.mapper, id),
+                mapper: Some(header.mapper),
+            })?,
+    };
+    header.mapper_info = Some(info);
+
+    // `test_supported_mappers_all_load` checks every registered implementation is created here
+    Ok(match info.id {
+        "nrom" => mappers::nrom::from_header(prg_rom, chr_rom, header),
+        "mmc1" | "mmc1a" => mappers::mmc1::from_header(prg_rom, chr_rom, header),
+        "uxrom" | "un1rom" | "unrom_180" => mappers::uxrom::from_header(prg_rom, chr_rom, header),
+        "cnrom" => mappers::cnrom::from_header(prg_rom, chr_rom, header),
+        "mmc3" => mappers::mmc3::from_header(prg_rom, chr_rom, header),
+        "axrom" => mappers::axrom::from_header(prg_rom, chr_rom, header),
+        "mmc2" => mappers::mmc2::from_header(prg_rom, chr_rom, header),
+        "mmc4" => mappers::mmc4::from_header(prg_rom, chr_rom, header),
+        "color_dreams" => mappers::color_dreams::from_header(prg_rom, chr_rom, header),
+        "action53" => mappers::mapper_028::from_header(prg_rom, chr_rom, header),
+        "bxrom" => mappers::bxrom::from_header(prg_rom, chr_rom, header),
+        "gxrom" => mappers::gxrom::from_header(prg_rom, chr_rom, header),
+        "unrom_512" => mappers::mapper_030::from_header(prg_rom, chr_rom, header),
+        "camerica" => mappers::mapper_071::from_header(prg_rom, chr_rom, header),
+        "nina_003_006" => mappers::nina_003_006::from_header(prg_rom, chr_rom, header),
+        "gtrom" => mappers::mapper_111::from_header(prg_rom, chr_rom, header),
+        "nwc" => mappers::mapper_105::from_header(prg_rom, chr_rom, header),
+        id => {
+            return Err(CartridgeError {
+                message: format!("Mapper implementation {} is registered but not yet implemented", id),
+                mapper: Some(header.mapper),
+            })
+        }
+    })
+}
+
+#[cfg(test)]
+mod cartridge_tests {
+    use super::*;
+
+    fn nes_2_header(prg_lsb: u8, chr_lsb: u8, byte_9: u8, byte_11: u8) -> [u8; 0x10] {
+        let mut header = [0; 0x10];
+        header[..4].copy_from_slice(b"NES\x1A");
+        header[4] = prg_lsb;
+        header[5] = chr_lsb;
+        header[6] = 0b1100_0000;
+        header[7] = 0b0001_1000;
+        header[9] = byte_9;
+        header[11] = byte_11;
+        header
+    }
+
+    #[test]
+    fn test_nes_2_rom_sizes() {
+        let header = CartridgeHeader::new(&nes_2_header(0x00, 0x20, 0x01, 0x00)).unwrap();
+        assert_eq!(header.mapper, 28);
+        assert_eq!(header.prg_rom_16kb_units, 0x100);
+        assert_eq!(header.chr_rom_8kb_units, 0x20);
+        assert_eq!(header.chr_ram_8kb_units, None);
+        assert_eq!(header.prg_ram_8kb_units, None);
+
+        // 2^22 * 1 bytes of PRG (4MB) and 2^15 * 3 bytes of CHR with 32KB of CHR RAM
+        let header = CartridgeHeader::new(&nes_2_header(22 << 2, (15 << 2) | 1, 0xFF, 0x09)).unwrap();
+        assert_eq!(header.prg_rom_16kb_units, 0x100);
+        assert_eq!(header.chr_rom_8kb_units, 12);
+        assert_eq!(header.chr_ram_8kb_units, Some(4));
+
+        // Exponent sizes which aren't whole units can't be loaded
+        assert!(CartridgeHeader::new(&nes_2_header(12 << 2, 0, 0x0F, 0x00)).is_err());
+
+        // SOROM has 8KB of volatile PRG RAM and 8KB battery backed
+        let mut header = nes_2_header(0x10, 0x00, 0x00, 0x07);
+        header[10] = 0x77;
+        let header = CartridgeHeader::new(&header).unwrap();
+        assert_eq!(header.prg_ram_8kb_units, Some(2));
+        assert_eq!(header.banked_prg_ram(true, 4).map(|ram| ram.len()), Some(0x4000));
+        assert_eq!(header.prg_ram(true).map(|ram| ram.len()), Some(0x2000));
+
+        // iNES 1.0 headers ignore byte 9
+        let mut header = nes_2_header(0x20, 0x00, 0xFF, 0x09);
+        header[7] = 0;
+        let header = CartridgeHeader::new(&header).unwrap();
+        assert_eq!(header.prg_rom_16kb_units, 0x20);
+        assert_eq!(header.chr_ram_8kb_units, None);
+    }
+
+    #[test]
+    fn test_nes_2_info() {
+        let mut rom = vec![0; 0x10 + 0x80000];
+        rom[..0x10].copy_from_slice(&nes_2_header(0x20, 0x00, 0x00, 0x07));
+        rom[6] = 0b0001_0010;
+        rom[7] = 0b0000_1000;
+        rom[8] = 0x50;
+        rom[10] = 0x70;
+        let mut header = parse(&rom, "test.nes", &CartridgeOverrides::default()).unwrap().header;
+        header.mapper_info = mapper_info(header.mapper);
+
+        let info = header.info();
+        assert!(info.nes_2);
+        assert_eq!(info.submapper, Some(5));
+        assert_eq!((info.prg_rom_kb, info.chr_rom_kb), (512, 0));
+        assert_eq!((info.prg_ram_kb, info.chr_ram_kb), (Some(8), Some(8)));
+        assert_eq!(info.board, Some("SUROM"));
+        assert_eq!(info.chr_rom_crc32, None);
+        assert_eq!(info.rom_crc32, info.prg_rom_crc32);
+        assert_eq!(info.to_string(), "MMC1 (SUROM), 512KB PRG, CHR RAM, battery, ntsc");
+
+        // iNES 1.0 headers have no submapper and unsupported mappers are still described
+        rom[6] = 5 << 4;
+        rom[7] = 0;
+        let header = parse(&rom, "test.nes", &CartridgeOverrides::default()).unwrap().header;
+        let info = header.info();
+        assert!(!info.nes_2);
+        assert_eq!(info.submapper, None);
+        assert_eq!(info.to_string(), "Mapper 5, 512KB PRG, CHR RAM, ntsc");
+    }
+
+    #[test]
+    fn test_supported_mappers_all_load() {
+        // 32KB PRG ROM and 8KB CHR ROM is the one size that every board can take
+        let mut rom = vec![0; 0x10 + 0x8000 + 0x2000];
+        rom[..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 2;
+        rom[5] = 1;
+        for info in supported_mappers() {
+            rom[6] = info.number << 4;
+            rom[7] = info.number & 0xF0;
+            let overrides = CartridgeOverrides {
+                mapper_id: Some(info.id.to_string()),
+                ..CartridgeOverrides::default()
+            };
+            let result = from_bytes(&rom, "test.nes", &overrides);
+            assert!(result.is_ok(), "{} ({})", info.number, info.name);
+            assert_eq!(result.unwrap().2.mapper_info, Some(info));
+            assert_eq!(mapper_implementation(info.id), Some(info));
+        }
+
+        for info in supported_mappers() {
+            let result = from_prg_chr(
+                vec![0; 0x8000],
+                Some(vec![0; 0x2000]),
+                info.number,
+                MirroringMode::Vertical,
+            );
+            assert!(result.is_ok(), "{} ({})", info.number, info.name);
+        }
+
+        rom[6] = 5 << 4;
+        rom[7] = 0;
+        assert_eq!(mapper_info(5), None);
+        assert_eq!(
+            from_bytes(&rom, "test.nes", &CartridgeOverrides::default())
+                .err()
+                .unwrap()
+                .mapper,
+            Some(5)
+        );
+
+        // An implementation for a different mapper can't be chosen
+        rom[6] = 0;
+        let overrides = CartridgeOverrides {
+            mapper_id: Some("mmc3".to_string()),
+            ..CartridgeOverrides::default()
+        };
+        assert!(from_bytes(&rom, "test.nes", &overrides).is_err());
+    }
+
+    #[test]
+    fn test_from_prg_chr() {
+        let (_, _, header) = from_prg_chr(vec![0; 0x4000], None, 0, MirroringMode::Horizontal).unwrap();
+        assert_eq!((header.prg_rom_16kb_units, header.chr_rom_8kb_units), (1, 0));
+        assert_eq!(header.mirroring, MirroringMode::Horizontal);
+
+        let (_, _, header) = from_prg_chr(vec![0; 0x20000], Some(vec![0; 0x4000]), 4, MirroringMode::Vertical).unwrap();
+        assert_eq!((header.prg_rom_16kb_units, header.chr_rom_8kb_units), (8, 2));
+
+        assert!(from_prg_chr(vec![], None, 0, MirroringMode::Vertical).is_err());
+        assert!(from_prg_chr(vec![0; 0x3000], None, 0, MirroringMode::Vertical).is_err());
+        assert!(from_prg_chr(vec![0; 0x4000], Some(vec![0; 0x100]), 0, MirroringMode::Vertical).is_err());
+        assert_eq!(
+            from_prg_chr(vec![0; 0x4000], None, 5, MirroringMode::Vertical)
+                .err()
+                .unwrap()
+                .mapper,
+            Some(5)
+        );
     }
 }
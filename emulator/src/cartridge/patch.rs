@@ -0,0 +1,355 @@
+use cartridge::CartridgeError;
+use crc32fast::Hasher;
+use std::ffi::OsStr;
+use std::path::Path;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Source, target and patch CRC32s
+const BPS_FOOTER_LENGTH: usize = 12;
+
+/// A soft patch (e.g. a translation or rom hack) applied to the rom image when it's loaded so
+/// that the original file is left untouched. Offsets in the patch include the iNES header.
+#[derive(Debug, Clone)]
+pub enum RomPatch {
+    /// c.f. https://zerosoft.zophar.net/ips.php
+    Ips(Vec<u8>),
+    /// c.f. https://www.romhacking.net/documents/746/
+    Bps(Vec<u8>),
+}
+
+impl RomPatch {
+    /// Load a patch, the format is chosen by the file extension
+    pub fn load(file_path: &str) -> Result<Self, CartridgeError> {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+        let bytes = std::fs::read(file_path)?;
+
+        match extension.as_deref() {
+            Some("ips") => Ok(RomPatch::Ips(bytes)),
+            Some("bps") => Ok(RomPatch::Bps(bytes)),
+            _ => Err(patch_error(format!(
+                "Unknown patch format for {}, expected .ips or .bps",
+                file_path
+            ))),
+        }
+    }
+
+    pub fn apply(&self, rom: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+        match self {
+            RomPatch::Ips(patch) => apply_ips(rom, patch),
+            RomPatch::Bps(patch) => apply_bps(rom, patch),
+        }
+    }
+}
+
+fn patch_error(message: String) -> CartridgeError {
+    CartridgeError { message, mapper: None }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let truncated = || patch_error("IPS patch is truncated".to_string());
+
+    if !patch.starts_with(IPS_MAGIC) {
+        return Err(patch_error("IPS patch doesn't start with PATCH".to_string()));
+    }
+
+    let mut target = rom.to_vec();
+    let mut position = IPS_MAGIC.len();
+    loop {
+        let record = patch.get(position..position + 3).ok_or_else(truncated)?;
+        position += 3;
+        if record == IPS_EOF {
+            break;
+        }
+
+        let offset = (record[0] as usize) << 16 | (record[1] as usize) << 8 | record[2] as usize;
+        let size = patch.get(position..position + 2).ok_or_else(truncated)?;
+        let size = (size[0] as usize) << 8 | size[1] as usize;
+        position += 2;
+
+        // A zero size record is run length encoded, a count followed by the byte to repeat
+        let data = if size == 0 {
+            let run = patch.get(position..position + 3).ok_or_else(truncated)?;
+            position += 3;
+            vec![run[2]; (run[0] as usize) << 8 | run[1] as usize]
+        } else {
+            let data = patch.get(position..position + size).ok_or_else(truncated)?.to_vec();
+            position += size;
+            data
+        };
+
+        if target.len() < offset + data.len() {
+            target.resize(offset + data.len(), 0);
+        }
+        target[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    // Some patches have a 24 bit length to truncate the rom to after the EOF marker
+    if let Some(length) = patch.get(position..position + 3) {
+        target.truncate((length[0] as usize) << 16 | (length[1] as usize) << 8 | length[2] as usize);
+    }
+
+    Ok(target)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) | u32::from(bytes[1]) << 8 | u32::from(bytes[2]) << 16 | u32::from(bytes[3]) << 24
+}
+
+/// Reads the BPS variable length integer encoding, 7 bits per byte with the top bit marking
+/// the final byte
+fn read_number(patch: &[u8], position: &mut usize) -> Result<usize, CartridgeError> {
+    let overflow = || patch_error("BPS patch has a number too large to be an offset".to_string());
+
+    let mut value = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = *patch
+            .get(*position)
+            .ok_or_else(|| patch_error("BPS patch is truncated".to_string()))?;
+        *position += 1;
+        value = ((byte & 0x7F) as usize)
+            .checked_mul(shift)
+            .and_then(|data| value.checked_add(data))
+            .ok_or_else(overflow)?;
+        if byte & 0x80 != 0 {
+            return Ok(value);
+        }
+        shift = shift.checked_mul(0x80).ok_or_else(overflow)?;
+        value = value.checked_add(shift).ok_or_else(overflow)?;
+    }
+}
+
+/// Relative offsets in copy commands store the sign in the lowest bit
+fn apply_relative_offset(offset: usize, data: usize) -> Option<usize> {
+    if data & 1 == 1 {
+        offset.checked_sub(data >> 1)
+    } else {
+        offset.checked_add(data >> 1)
+    }
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    if !patch.starts_with(BPS_MAGIC) || patch.len() < BPS_MAGIC.len() + BPS_FOOTER_LENGTH {
+        return Err(patch_error("BPS patch doesn't start with BPS1".to_string()));
+    }
+
+    let footer = &patch[patch.len() - BPS_FOOTER_LENGTH..];
+    let (source_crc, target_crc, patch_crc) = (read_u32(&footer[0..]), read_u32(&footer[4..]), read_u32(&footer[8..]));
+    if crc32(&patch[..patch.len() - 4]) != patch_crc {
+        return Err(patch_error("BPS patch is corrupt, CRC32 doesn't match".to_string()));
+    }
+    if crc32(rom) != source_crc {
+        return Err(patch_error(format!(
+            "BPS patch is for a different rom, expected CRC32 {:08X} but was {:08X}",
+            source_crc,
+            crc32(rom)
+        )));
+    }
+
+    let mut position = BPS_MAGIC.len();
+    let source_size = read_number(patch, &mut position)?;
+    let target_size = read_number(patch, &mut position)?;
+    let metadata_size = read_number(patch, &mut position)?;
+    position = position
+        .checked_add(metadata_size)
+        .filter(|&end| end <= patch.len() - BPS_FOOTER_LENGTH)
+        .ok_or_else(|| patch_error("BPS patch metadata runs past the end of the patch".to_string()))?;
+    if source_size != rom.len() {
+        return Err(patch_error(format!(
+            "BPS patch expects a rom of {} bytes but was {}",
+            source_size,
+            rom.len()
+        )));
+    }
+
+    let invalid = || patch_error("BPS patch contains an invalid command".to_string());
+    // The header's target size isn't trusted for the allocation, commands are checked against it as they run
+    let mut target = Vec::with_capacity(target_size.min(patch.len() + rom.len()));
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    while position < patch.len() - BPS_FOOTER_LENGTH {
+        let command = read_number(patch, &mut position)?;
+        let length = (command >> 2) + 1;
+        if target.len().checked_add(length).map_or(true, |end| end > target_size) {
+            return Err(invalid());
+        }
+
+        match command & 0b11 {
+            // Source read, copy from the same position in the source
+            0 => {
+                let start = target.len();
+                target.extend_from_slice(rom.get(start..start + length).ok_or_else(invalid)?);
+            }
+            // Target read, copy from the patch itself
+            1 => {
+                let end = position.checked_add(length).ok_or_else(invalid)?;
+                target.extend_from_slice(patch.get(position..end).ok_or_else(invalid)?);
+                position = end;
+            }
+            // Source copy, copy from anywhere in the source
+            2 => {
+                let data = read_number(patch, &mut position)?;
+                source_offset = apply_relative_offset(source_offset, data).ok_or_else(invalid)?;
+                let end = source_offset.checked_add(length).ok_or_else(invalid)?;
+                target.extend_from_slice(rom.get(source_offset..end).ok_or_else(invalid)?);
+                source_offset = end;
+            }
+            // Target copy, copy from earlier in the output one byte at a time as the ranges may overlap
+            _ => {
+                let data = read_number(patch, &mut position)?;
+                target_offset = apply_relative_offset(target_offset, data).ok_or_else(invalid)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or_else(invalid)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size || crc32(&target) != target_crc {
+        return Err(patch_error(
+            "BPS patch produced an incorrect rom, CRC32 doesn't match".to_string(),
+        ));
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod patch_tests {
+    use super::*;
+
+    fn bps_number(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let data = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | data);
+                return bytes;
+            }
+            bytes.push(data);
+            value -= 1;
+        }
+    }
+
+    fn bps_patch(source: &[u8], target: &[u8], body: &[u8]) -> Vec<u8> {
+        bps_patch_with_sizes(source, target, target.len(), 0, body)
+    }
+
+    fn bps_patch_with_sizes(
+        source: &[u8],
+        target: &[u8],
+        target_size: usize,
+        metadata_size: usize,
+        body: &[u8],
+    ) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend(bps_number(source.len()));
+        patch.extend(bps_number(target_size));
+        patch.extend(bps_number(metadata_size));
+        patch.extend_from_slice(body);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+
+        patch
+    }
+
+    #[test]
+    fn test_ips_records_rle_and_truncation() {
+        let rom = vec![0u8; 8];
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0xAA, 0xBB]);
+        patch.extend_from_slice(&[0, 0, 4, 0, 0, 0, 6, 0xCC]);
+        patch.extend_from_slice(IPS_EOF);
+
+        let patched = RomPatch::Ips(patch.clone()).apply(&rom).unwrap();
+        assert_eq!(patched, vec![0, 0xAA, 0xBB, 0, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC]);
+
+        patch.extend_from_slice(&[0, 0, 3]);
+        let patched = RomPatch::Ips(patch).apply(&rom).unwrap();
+        assert_eq!(patched, vec![0, 0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn test_bps_commands() {
+        let source = b"ABCDEF";
+        let target = b"ABxyCDCDCD";
+        let body = [
+            0x80 | (1 << 2),     // Source read of 2 bytes "AB"
+            0x80 | (1 << 2) | 1, // Target read of 2 bytes "xy"
+            b'x',
+            b'y',
+            0x80 | (1 << 2) | 2, // Source copy of 2 bytes from offset 2 "CD"
+            0x80 | (2 << 1),
+            0x80 | (3 << 2) | 3, // Target copy of 4 bytes from offset 4 "CDCD"
+            0x80 | (4 << 1),
+        ];
+
+        let patched = RomPatch::Bps(bps_patch(source, target, &body)).apply(source).unwrap();
+        assert_eq!(patched, target.to_vec());
+    }
+
+    #[test]
+    fn test_bps_crc_validated() {
+        let source = b"ABCD";
+        let mut patch = bps_patch(source, source, &[0x80 | (3 << 2)]);
+
+        assert!(RomPatch::Bps(patch.clone()).apply(b"ABCE").is_err());
+
+        let last = patch.len() - 1;
+        patch[last] ^= 0xFF;
+        assert!(RomPatch::Bps(patch).apply(source).is_err());
+    }
+
+    #[test]
+    fn test_bps_number_overflow_rejected() {
+        let mut position = 0;
+        assert_eq!(read_number(&[0x01, 0x82], &mut position).unwrap(), 1 + 0x80 + 2 * 0x80);
+        assert_eq!(position, 2);
+
+        let source = b"ABCD";
+        let mut body = vec![0x7F; 10];
+        body.push(0xFF);
+        let error = RomPatch::Bps(bps_patch(source, source, &body))
+            .apply(source)
+            .unwrap_err();
+        assert_eq!(error.message, "BPS patch has a number too large to be an offset");
+    }
+
+    #[test]
+    fn test_bps_oversized_header_fields_rejected() {
+        let source = b"ABCD";
+        let body = [0x80 | (3 << 2)];
+
+        let patch = bps_patch_with_sizes(source, source, source.len(), usize::MAX - 8, &body);
+        let error = RomPatch::Bps(patch).apply(source).unwrap_err();
+        assert_eq!(error.message, "BPS patch metadata runs past the end of the patch");
+
+        let patch = bps_patch_with_sizes(source, source, usize::MAX - 8, 0, &body);
+        let error = RomPatch::Bps(patch).apply(source).unwrap_err();
+        assert_eq!(
+            error.message,
+            "BPS patch produced an incorrect rom, CRC32 doesn't match"
+        );
+
+        // A command longer than the target can't run away allocating
+        let patch = bps_patch_with_sizes(source, source, source.len(), 0, &bps_number((usize::MAX >> 2) << 2 | 3));
+        let error = RomPatch::Bps(patch).apply(source).unwrap_err();
+        assert_eq!(error.message, "BPS patch contains an invalid command");
+    }
+}
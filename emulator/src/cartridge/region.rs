@@ -0,0 +1,119 @@
+use clock::{NTSC_FRAME_RATE, PAL_FRAME_RATE};
+use std::fmt::{Display, Formatter, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The console a rom was made for, see `CartridgeHeader::region`
+///
+/// The console is always emulated with NTSC timing, the region is for frontends to pace frames
+/// at the rate the game expects.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    /// The Russian famiclone, PAL frame rate with NTSC-like CPU timing
+    Dendy,
+}
+
+impl Region {
+    /// Frames per second on this region's console, Dendy runs at the same 50Hz as PAL
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => NTSC_FRAME_RATE,
+            Region::Pal | Region::Dendy => PAL_FRAME_RATE,
+        }
+    }
+
+    /// The CPU/PPU timing byte from an NES 2.0 header, None for iNES 1.0 headers and roms which
+    /// run on any region
+    pub(super) fn from_header(header: &[u8]) -> Option<Region> {
+        if header[7] & 0b1100 != 0b1000 {
+            return None;
+        }
+
+        match header[12] & 0b11 {
+            0 => Some(Region::Ntsc),
+            1 => Some(Region::Pal),
+            3 => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    /// Region tags in the filename as used by GoodNES and No-Intro, e.g. "Elite (E).nes" or
+    /// "Smurfs (Europe) (En,Fr,De).nes"
+    pub(super) fn from_filename(file_path: &str) -> Option<Region> {
+        let file_name = Path::new(file_path).file_name()?.to_str()?;
+
+        file_name
+            .split(&['(', ')'][..])
+            .skip(1)
+            .step_by(2)
+            .flat_map(|tag| tag.split(','))
+            .filter_map(|tag| match tag.trim() {
+                "E" | "A" | "Europe" | "Australia" | "PAL" => Some(Region::Pal),
+                "U" | "J" | "USA" | "Japan" | "NTSC" => Some(Region::Ntsc),
+                "Dendy" => Some(Region::Dendy),
+                _ => None,
+            })
+            .next()
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            Region::Ntsc => write!(f, "ntsc"),
+            Region::Pal => write!(f, "pal"),
+            Region::Dendy => write!(f, "dendy"),
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!("Unknown region {}, expected ntsc, pal or dendy", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod region_tests {
+    use super::*;
+
+    #[test]
+    fn test_region_from_filename() {
+        assert_eq!(Region::from_filename("roms/Elite (E).nes"), Some(Region::Pal));
+        assert_eq!(
+            Region::from_filename("Smurfs (Europe) (En,Fr,De).nes"),
+            Some(Region::Pal)
+        );
+        assert_eq!(Region::from_filename("Mega Man (USA).nes"), Some(Region::Ntsc));
+        assert_eq!(
+            Region::from_filename("Tetris (Europe, Australia).zip"),
+            Some(Region::Pal)
+        );
+        assert_eq!(Region::from_filename("nestest.nes"), None);
+        // Tags in the directory aren't about this rom
+        assert_eq!(Region::from_filename("Roms (E)/nestest.nes"), None);
+    }
+
+    #[test]
+    fn test_region_from_header() {
+        let mut header = [0u8; 0x10];
+        header[12] = 1;
+        assert_eq!(Region::from_header(&header), None);
+
+        header[7] = 0b1000;
+        assert_eq!(Region::from_header(&header), Some(Region::Pal));
+        header[12] = 2;
+        assert_eq!(Region::from_header(&header), None);
+        header[12] = 3;
+        assert_eq!(Region::from_header(&header), Some(Region::Dendy));
+    }
+}
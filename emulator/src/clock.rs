@@ -0,0 +1,71 @@
+//! A single timebase for the whole console.
+//!
+//! The CPU, PPU and APU each count cycles in their own units for comparing against events they've
+//! scheduled. `Clock` is a snapshot of all of them at once, giving tools a consistent set of
+//! timestamps along with conversions to wall clock time.
+
+use std::time::Duration;
+
+/// CPU cycles per second on an NTSC console (the master clock of 21.477272MHz divided by 12)
+pub const NTSC_CPU_CLOCK_RATE: f64 = 1_789_773.0;
+
+/// Frames per second on an NTSC console, 341 dots on each of 262 scanlines with one dot skipped
+/// on odd frames when rendering
+pub const NTSC_FRAME_RATE: f64 = NTSC_CPU_CLOCK_RATE * 3.0 / (341.0 * 262.0 - 0.5);
+
+/// CPU cycles per second on a PAL console (the master clock of 26.601712MHz divided by 16)
+pub const PAL_CPU_CLOCK_RATE: f64 = 1_662_607.0;
+
+/// Frames per second on a PAL console, 341 dots on each of 312 scanlines at 3.2 dots per CPU cycle
+pub const PAL_FRAME_RATE: f64 = PAL_CPU_CLOCK_RATE * 3.2 / (341.0 * 312.0);
+
+/// Emulated time since power on, see `Nes::clock`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Clock {
+    pub cpu_cycles: u64,
+    pub ppu_dots: u64,
+    /// One APU cycle occurs every two CPU cycles
+    pub apu_cycles: u64,
+    /// The frame the PPU is currently rendering, as `Nes::frame_number`
+    pub frame_number: u64,
+    /// CPU cycles per second for the region being emulated
+    pub cpu_clock_rate: f64,
+    /// Frames per second for the region being emulated
+    pub frame_rate: f64,
+}
+
+impl Clock {
+    /// The APU and region timings all follow from the CPU so only the CPU and PPU counters are needed
+    pub(crate) fn new(cpu_cycles: u64, ppu_dots: u64, frame_number: u64) -> Self {
+        Clock {
+            cpu_cycles,
+            ppu_dots,
+            apu_cycles: cpu_cycles / 2,
+            frame_number,
+            cpu_clock_rate: NTSC_CPU_CLOCK_RATE,
+            frame_rate: NTSC_FRAME_RATE,
+        }
+    }
+
+    /// The emulated time since power on
+    pub fn seconds(&self) -> f64 {
+        self.cpu_cycles_to_seconds(self.cpu_cycles)
+    }
+
+    /// As `seconds` but as a `Duration`, e.g. for comparing against real time
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.seconds())
+    }
+
+    pub fn cpu_cycles_to_seconds(&self, cycles: u64) -> f64 {
+        cycles as f64 / self.cpu_clock_rate
+    }
+
+    pub fn seconds_to_cpu_cycles(&self, seconds: f64) -> u64 {
+        (seconds * self.cpu_clock_rate).round() as u64
+    }
+
+    pub fn frames_to_seconds(&self, frames: u64) -> f64 {
+        frames as f64 / self.frame_rate
+    }
+}
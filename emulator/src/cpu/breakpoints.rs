@@ -0,0 +1,220 @@
+use cpu::condition::Condition;
+use cpu::CpuCycle;
+use log::info;
+use std::collections::HashMap;
+
+/// A condition on which emulation should stop so the host can inspect the state of the console
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Breakpoint {
+    /// Break before executing the instruction at this address
+    Execute(u16),
+    /// Break on any read of this address, PPU registers also match through their mirrors
+    Read(u16),
+    /// Break on any write to this address, PPU registers also match through their mirrors
+    Write(u16),
+    /// Break on any write to mapper registers ($4020-$5FFF & $8000-$FFFF). Note that PRG RAM
+    /// ($6000-$7FFF) is excluded so the few boards with registers there need `Write` instead.
+    MapperWrite,
+}
+
+/// Details of the access which triggered a breakpoint
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BreakpointHit {
+    pub breakpoint: Breakpoint,
+    pub address: u16,
+    /// The value read or written, not present for execute breakpoints
+    pub value: Option<u8>,
+    pub cycles: CpuCycle,
+}
+
+/// One bit per address in the 16 bit CPU address space so that checking an access is a single lookup
+struct AddressBitmap(Box<[u64; 0x400]>);
+
+impl AddressBitmap {
+    fn new() -> Self {
+        AddressBitmap(Box::new([0; 0x400]))
+    }
+
+    fn set(&mut self, address: u16, enabled: bool) {
+        let bit = 1 << (address & 0x3F);
+        if enabled {
+            self.0[address as usize >> 6] |= bit;
+        } else {
+            self.0[address as usize >> 6] &= !bit;
+        }
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        self.0[address as usize >> 6] & (1 << (address & 0x3F)) != 0
+    }
+}
+
+fn canonical_breakpoint(breakpoint: Breakpoint) -> Breakpoint {
+    match breakpoint {
+        Breakpoint::Read(address) => Breakpoint::Read(canonical_address(address)),
+        Breakpoint::Write(address) => Breakpoint::Write(canonical_address(address)),
+        _ => breakpoint,
+    }
+}
+
+/// PPU registers are mirrored every 8 bytes from $2000-$3FFF, breakpoints are stored
+/// against the canonical address so that they also match on the mirrors.
+fn canonical_address(address: u16) -> u16 {
+    match address {
+        0x2000..=0x3FFF => 0x2000 | (address & 7),
+        _ => address,
+    }
+}
+
+pub(super) struct Breakpoints {
+    execute: AddressBitmap,
+    read: AddressBitmap,
+    write: AddressBitmap,
+    mapper_writes: bool,
+    conditions: HashMap<Breakpoint, Condition>,
+    /// Only the first hit is kept until the host collects it
+    hit: Option<BreakpointHit>,
+}
+
+impl Breakpoints {
+    pub(super) fn new() -> Self {
+        Breakpoints {
+            execute: AddressBitmap::new(),
+            read: AddressBitmap::new(),
+            write: AddressBitmap::new(),
+            mapper_writes: false,
+            conditions: HashMap::new(),
+            hit: None,
+        }
+    }
+
+    pub(super) fn set(&mut self, breakpoint: Breakpoint, enabled: bool) {
+        match breakpoint {
+            Breakpoint::Execute(address) => self.execute.set(address, enabled),
+            Breakpoint::Read(address) => self.read.set(canonical_address(address), enabled),
+            Breakpoint::Write(address) => self.write.set(canonical_address(address), enabled),
+            Breakpoint::MapperWrite => self.mapper_writes = enabled,
+        }
+
+        if !enabled {
+            self.conditions.remove(&canonical_breakpoint(breakpoint));
+        }
+    }
+
+    /// Only trigger the breakpoint when the condition is met
+    pub(super) fn set_condition(&mut self, breakpoint: Breakpoint, condition: Condition) {
+        self.conditions.insert(canonical_breakpoint(breakpoint), condition);
+    }
+
+    pub(super) fn condition(&self, breakpoint: Breakpoint) -> Option<&Condition> {
+        self.conditions.get(&breakpoint)
+    }
+
+    // The check functions return the breakpoint (and the address to report) which matches
+    // an access, it's up to the caller to evaluate any condition before triggering it.
+
+    pub(super) fn check_execute(&self, address: u16) -> Option<(Breakpoint, u16)> {
+        if self.execute.contains(address) {
+            Some((Breakpoint::Execute(address), address))
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn check_read(&self, address: u16) -> Option<(Breakpoint, u16)> {
+        let address = canonical_address(address);
+        if self.read.contains(address) {
+            Some((Breakpoint::Read(address), address))
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn check_write(&self, address: u16) -> Option<(Breakpoint, u16)> {
+        let canonical = canonical_address(address);
+        if self.write.contains(canonical) {
+            Some((Breakpoint::Write(canonical), canonical))
+        } else if self.mapper_writes {
+            match address {
+                0x4020..=0x5FFF | 0x8000..=0xFFFF => Some((Breakpoint::MapperWrite, address)),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn trigger(&mut self, breakpoint: Breakpoint, address: u16, value: Option<u8>, cycles: CpuCycle) {
+        info!("Breakpoint {:?} hit at {:04X}", breakpoint, address);
+
+        if self.hit.is_none() {
+            self.hit = Some(BreakpointHit {
+                breakpoint,
+                address,
+                value,
+                cycles,
+            });
+        }
+    }
+
+    pub(super) fn take_hit(&mut self) -> Option<BreakpointHit> {
+        self.hit.take()
+    }
+}
+
+#[cfg(test)]
+mod breakpoints_tests {
+    use super::*;
+
+    #[test]
+    fn test_ppu_register_breakpoints_match_mirrors() {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.set(Breakpoint::Write(0x2001), true);
+        assert_eq!(breakpoints.check_write(0x2002), None);
+        assert_eq!(
+            breakpoints.check_write(0x3FF9),
+            Some((Breakpoint::Write(0x2001), 0x2001))
+        );
+
+        breakpoints.set(Breakpoint::Write(0x2001), false);
+        assert_eq!(breakpoints.check_write(0x2001), None);
+    }
+
+    #[test]
+    fn test_mapper_write_breakpoint_ignores_prg_ram() {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.set(Breakpoint::MapperWrite, true);
+        assert_eq!(breakpoints.check_write(0x6000), None);
+        assert_eq!(breakpoints.check_write(0x0300), None);
+        assert_eq!(breakpoints.check_write(0x8000), Some((Breakpoint::MapperWrite, 0x8000)));
+    }
+
+    #[test]
+    fn test_only_first_hit_is_kept() {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.trigger(Breakpoint::Read(0x4015), 0x4015, Some(0x40), 10);
+        breakpoints.trigger(Breakpoint::Write(0x4015), 0x4015, Some(0x0F), 11);
+
+        assert_eq!(
+            breakpoints.take_hit(),
+            Some(BreakpointHit {
+                breakpoint: Breakpoint::Read(0x4015),
+                address: 0x4015,
+                value: Some(0x40),
+                cycles: 10
+            })
+        );
+        assert_eq!(breakpoints.take_hit(), None);
+    }
+
+    #[test]
+    fn test_removing_breakpoint_removes_condition() {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.set(Breakpoint::Write(0x2001), true);
+        breakpoints.set_condition(Breakpoint::Write(0x2009), "value == 0".parse().unwrap());
+        assert!(breakpoints.condition(Breakpoint::Write(0x2001)).is_some());
+
+        breakpoints.set(Breakpoint::Write(0x2001), false);
+        assert!(breakpoints.condition(Breakpoint::Write(0x2001)).is_none());
+    }
+}
@@ -0,0 +1,385 @@
+use cpu::trace::CpuRegisters;
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::FromStr;
+
+/// Represents any error which occurs parsing a breakpoint condition
+#[derive(Debug)]
+pub struct ConditionError {
+    pub message: String,
+}
+impl Error for ConditionError {}
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid breakpoint condition: {}", self.message)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Variable {
+    A,
+    X,
+    Y,
+    Status,
+    StackPointer,
+    ProgramCounter,
+    Scanline,
+    Dot,
+    /// The value read or written by the access which triggered the breakpoint
+    Value,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    BitwiseOr,
+    BitwiseAnd,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Literal(i64),
+    Variable(Variable),
+    /// A byte read from the CPU address space, written as [address]
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Operator(&'static str),
+}
+
+/// The state of the console that a condition is evaluated against
+pub(super) struct ConditionState {
+    pub(super) registers: CpuRegisters,
+    pub(super) scanline: u16,
+    pub(super) dot: u16,
+    pub(super) value: Option<u8>,
+}
+
+/// An expression which must evaluate to non zero for a breakpoint to trigger, e.g.
+/// `A == 0x3F && scanline > 200` or `[$0300] & 0x80 && value != 0`.
+///
+/// Variables are the registers (a, x, y, p, sp, pc), the PPU position (scanline, dot) and
+/// the value read/written by the access. Memory is read with [address] and numbers can be
+/// decimal, 0x or $ prefixed hex.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    expression: Expression,
+}
+
+impl FromStr for Condition {
+    type Err = ConditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expression = parser.parse_or()?;
+
+        match parser.tokens.get(parser.position) {
+            None => Ok(Condition { expression }),
+            Some(token) => Err(ConditionError {
+                message: format!("Unexpected {:?} in {}", token, s),
+            }),
+        }
+    }
+}
+
+impl Condition {
+    pub(super) fn is_met(&self, state: &ConditionState, memory: &dyn Fn(u16) -> u8) -> bool {
+        evaluate(&self.expression, state, memory) != 0
+    }
+}
+
+fn evaluate(expression: &Expression, state: &ConditionState, memory: &dyn Fn(u16) -> u8) -> i64 {
+    match expression {
+        Expression::Literal(value) => *value,
+        Expression::Variable(variable) => match variable {
+            Variable::A => state.registers.a as i64,
+            Variable::X => state.registers.x as i64,
+            Variable::Y => state.registers.y as i64,
+            Variable::Status => state.registers.status as i64,
+            Variable::StackPointer => state.registers.stack_pointer as i64,
+            Variable::ProgramCounter => state.registers.program_counter as i64,
+            Variable::Scanline => state.scanline as i64,
+            Variable::Dot => state.dot as i64,
+            Variable::Value => state.value.map_or(-1, |value| value as i64),
+        },
+        Expression::Memory(address) => memory(evaluate(address, state, memory) as u16) as i64,
+        Expression::Not(operand) => (evaluate(operand, state, memory) == 0) as i64,
+        Expression::Binary(operator, left, right) => {
+            let left = evaluate(left, state, memory);
+
+            // Short circuit so that memory isn't read unnecessarily
+            match operator {
+                BinaryOperator::Or if left != 0 => return 1,
+                BinaryOperator::And if left == 0 => return 0,
+                _ => (),
+            }
+
+            let right = evaluate(right, state, memory);
+            match operator {
+                BinaryOperator::Or | BinaryOperator::And => (right != 0) as i64,
+                BinaryOperator::Equal => (left == right) as i64,
+                BinaryOperator::NotEqual => (left != right) as i64,
+                BinaryOperator::LessThan => (left < right) as i64,
+                BinaryOperator::LessThanOrEqual => (left <= right) as i64,
+                BinaryOperator::GreaterThan => (left > right) as i64,
+                BinaryOperator::GreaterThanOrEqual => (left >= right) as i64,
+                BinaryOperator::BitwiseOr => left | right,
+                BinaryOperator::BitwiseAnd => left & right,
+            }
+        }
+    }
+}
+
+/// Longer operators first so that e.g. "<=" isn't tokenized as "<" followed by "="
+const OPERATORS: [&str; 15] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "&", "!", "(", ")", "[", "]",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ConditionError> {
+    let mut tokens = vec![];
+    let mut remaining = input.trim_start();
+
+    while !remaining.is_empty() {
+        let mut chars = remaining.chars().peekable();
+        let (token, length) = match chars.peek().copied() {
+            Some('$') => {
+                chars.next();
+                let digits = take_while(&mut chars, |c| c.is_ascii_hexdigit());
+                (parse_number(&digits, 16, input)?, digits.len() + 1)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                if remaining.starts_with("0x") || remaining.starts_with("0X") {
+                    let digits = take_while(&mut chars.skip(2).peekable(), |c| c.is_ascii_hexdigit());
+                    (parse_number(&digits, 16, input)?, digits.len() + 2)
+                } else {
+                    let digits = take_while(&mut chars, |c| c.is_ascii_digit());
+                    (parse_number(&digits, 10, input)?, digits.len())
+                }
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let identifier = take_while(&mut chars, |c| c.is_ascii_alphanumeric() || c == '_');
+                let length = identifier.len();
+                (Token::Identifier(identifier.to_ascii_lowercase()), length)
+            }
+            _ => match OPERATORS.iter().find(|operator| remaining.starts_with(*operator)) {
+                Some(operator) => (Token::Operator(operator), operator.len()),
+                None => {
+                    return Err(ConditionError {
+                        message: format!("Unexpected character at {} in {}", remaining, input),
+                    })
+                }
+            },
+        };
+
+        tokens.push(token);
+        remaining = remaining[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn take_while<F: Fn(char) -> bool>(chars: &mut Peekable<impl Iterator<Item = char>>, predicate: F) -> String {
+    let mut result = String::new();
+    while let Some(c) = chars.peek() {
+        if !predicate(*c) {
+            break;
+        }
+        result.push(*c);
+        chars.next();
+    }
+
+    result
+}
+
+fn parse_number(digits: &str, radix: u32, input: &str) -> Result<Token, ConditionError> {
+    i64::from_str_radix(digits, radix)
+        .map(Token::Number)
+        .map_err(|_| ConditionError {
+            message: format!("Invalid number in {}", input),
+        })
+}
+
+/// Recursive descent parser, in order of increasing precedence: ||, &&, comparisons, |, &, !
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next_operator_in(&mut self, operators: &[(&str, BinaryOperator)]) -> Option<BinaryOperator> {
+        if let Some(Token::Operator(token)) = self.tokens.get(self.position) {
+            if let Some((_, operator)) = operators.iter().find(|(symbol, _)| symbol == token) {
+                self.position += 1;
+                return Some(*operator);
+            }
+        }
+
+        None
+    }
+
+    fn parse_binary(
+        &mut self,
+        operators: &[(&str, BinaryOperator)],
+        operand: fn(&mut Parser) -> Result<Expression, ConditionError>,
+    ) -> Result<Expression, ConditionError> {
+        let mut expression = operand(self)?;
+        while let Some(operator) = self.next_operator_in(operators) {
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(operand(self)?));
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, ConditionError> {
+        self.parse_binary(&[("||", BinaryOperator::Or)], Parser::parse_and)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, ConditionError> {
+        self.parse_binary(&[("&&", BinaryOperator::And)], Parser::parse_comparison)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, ConditionError> {
+        self.parse_binary(
+            &[
+                ("==", BinaryOperator::Equal),
+                ("!=", BinaryOperator::NotEqual),
+                ("<", BinaryOperator::LessThan),
+                ("<=", BinaryOperator::LessThanOrEqual),
+                (">", BinaryOperator::GreaterThan),
+                (">=", BinaryOperator::GreaterThanOrEqual),
+            ],
+            Parser::parse_bitwise_or,
+        )
+    }
+
+    fn parse_bitwise_or(&mut self) -> Result<Expression, ConditionError> {
+        self.parse_binary(&[("|", BinaryOperator::BitwiseOr)], Parser::parse_bitwise_and)
+    }
+
+    fn parse_bitwise_and(&mut self) -> Result<Expression, ConditionError> {
+        self.parse_binary(&[("&", BinaryOperator::BitwiseAnd)], Parser::parse_unary)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, ConditionError> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        match token {
+            Some(Token::Operator("!")) => Ok(Expression::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Operator("(")) => {
+                let expression = self.parse_or()?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Some(Token::Operator("[")) => {
+                let address = self.parse_or()?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(address)))
+            }
+            Some(Token::Number(value)) => Ok(Expression::Literal(value)),
+            Some(Token::Identifier(identifier)) => {
+                let variable = match identifier.as_str() {
+                    "a" => Variable::A,
+                    "x" => Variable::X,
+                    "y" => Variable::Y,
+                    "p" | "status" => Variable::Status,
+                    "sp" => Variable::StackPointer,
+                    "pc" => Variable::ProgramCounter,
+                    "scanline" => Variable::Scanline,
+                    "dot" | "cycle" => Variable::Dot,
+                    "value" => Variable::Value,
+                    _ => {
+                        return Err(ConditionError {
+                            message: format!("Unknown variable {}", identifier),
+                        })
+                    }
+                };
+                Ok(Expression::Variable(variable))
+            }
+            _ => Err(ConditionError {
+                message: format!("Expected a value but found {:?}", token),
+            }),
+        }
+    }
+
+    fn expect(&mut self, operator: &str) -> Result<(), ConditionError> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(token)) if *token == operator => {
+                self.position += 1;
+                Ok(())
+            }
+            token => Err(ConditionError {
+                message: format!("Expected {} but found {:?}", operator, token),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod condition_tests {
+    use super::*;
+
+    fn state(a: u8, scanline: u16) -> ConditionState {
+        ConditionState {
+            registers: CpuRegisters {
+                a,
+                x: 0,
+                y: 0,
+                status: 0x24,
+                stack_pointer: 0xFD,
+                program_counter: 0xC000,
+            },
+            scanline,
+            dot: 0,
+            value: Some(0x80),
+        }
+    }
+
+    fn memory(address: u16) -> u8 {
+        (address & 0xFF) as u8
+    }
+
+    #[test]
+    fn test_register_and_scanline_condition() {
+        let condition = "A == 0x3F && scanline > 200".parse::<Condition>().unwrap();
+
+        assert!(condition.is_met(&state(0x3F, 201), &memory));
+        assert!(!condition.is_met(&state(0x3F, 200), &memory));
+        assert!(!condition.is_met(&state(0x3E, 201), &memory));
+    }
+
+    #[test]
+    fn test_memory_precedence_and_hex_formats() {
+        let condition = "[$0310] == 16 || !(value & $80) && pc >= 0xC000"
+            .parse::<Condition>()
+            .unwrap();
+        assert!(condition.is_met(&state(0, 0), &memory));
+
+        let condition = "[0x0311] == 16 || !(value & $80)".parse::<Condition>().unwrap();
+        assert!(!condition.is_met(&state(0, 0), &memory));
+    }
+
+    #[test]
+    fn test_invalid_conditions() {
+        assert!("A ==".parse::<Condition>().is_err());
+        assert!("(A == 1".parse::<Condition>().is_err());
+        assert!("B == 1".parse::<Condition>().is_err());
+        assert!("A == 1 2".parse::<Condition>().is_err());
+        assert!("A = 1".parse::<Condition>().is_err());
+    }
+}
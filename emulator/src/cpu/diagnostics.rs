@@ -0,0 +1,194 @@
+use cpu::trace::ExecutedInstruction;
+use cpu::CpuCycle;
+use log::warn;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter, Result};
+
+/// Events are buffered until the application collects them, if it never does
+/// then further events are discarded rather than growing without bound.
+const MAX_PENDING_EVENTS: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// The program counter has moved outside of cartridge ROM ($8000-$FFFF) and
+    /// is now executing from RAM, PRG RAM or unmapped space.
+    ExecutingFromNonRom { pc: u16 },
+    /// A push occurred with the stack pointer at $00 so it wrapped to $FF
+    StackOverflow,
+    /// A pop occurred with the stack pointer at $FF so it wrapped to $00
+    StackUnderflow,
+    /// A KIL opcode at this address locked up the CPU
+    Jammed { pc: u16 },
+    /// PPUDATA was written with a nametable address while the PPU was rendering, which
+    /// corrupts both the nametable and the scroll position. Only reported when enabled
+    /// with `Nes::set_nametable_write_checks`.
+    NametableWriteDuringRendering {
+        pc: u16,
+        address: u16,
+        scanline: u16,
+        dot: u16,
+    },
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            DiagnosticKind::ExecutingFromNonRom { pc } => write!(f, "Executing from non-ROM address {:04X}", pc),
+            DiagnosticKind::StackOverflow => write!(f, "Stack pointer wrapped from 00 to FF on push"),
+            DiagnosticKind::StackUnderflow => write!(f, "Stack pointer wrapped from FF to 00 on pop"),
+            DiagnosticKind::Jammed { pc } => write!(f, "CPU jammed by KIL opcode at {:04X}", pc),
+            DiagnosticKind::NametableWriteDuringRendering {
+                pc,
+                address,
+                scanline,
+                dot,
+            } => write!(
+                f,
+                "Nametable write to {:04X} from {:04X} while rendering on scanline {} dot {}",
+                address, pc, scanline, dot
+            ),
+        }
+    }
+}
+
+/// A single suspicious event along with the instructions which led up to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticEvent {
+    pub kind: DiagnosticKind,
+    pub cycles: CpuCycle,
+    /// The most recently executed instructions, oldest first. The last entry is
+    /// the instruction which was executing when the event occurred.
+    pub history: Vec<ExecutedInstruction>,
+}
+
+impl Display for DiagnosticEvent {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        writeln!(f, "{} at cycle {}", self.kind, self.cycles)?;
+        for instruction in &self.history {
+            write!(f, "  {:04X}  {:02X}", instruction.pc, instruction.opcode)?;
+            for operand in &instruction.operands {
+                write!(f, " {:02X}", operand)?;
+            }
+            write!(
+                f,
+                "  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                instruction.registers.a,
+                instruction.registers.x,
+                instruction.registers.y,
+                instruction.registers.status,
+                instruction.registers.stack_pointer,
+                instruction.cycles
+            )?;
+            if let Some(label) = &instruction.label {
+                write!(f, "  ; {}", label)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) struct Diagnostics {
+    history: VecDeque<ExecutedInstruction>,
+    history_length: usize,
+    events: Vec<DiagnosticEvent>,
+    /// Only the transition into non-ROM space is reported, not every instruction executed there
+    executing_from_non_rom: bool,
+    /// The address of the instruction currently executing
+    pub(super) instruction_pc: u16,
+    pub(super) nametable_write_checks: bool,
+}
+
+impl Diagnostics {
+    pub(super) fn new(history_length: usize) -> Self {
+        Diagnostics {
+            history: VecDeque::with_capacity(history_length),
+            history_length,
+            events: vec![],
+            executing_from_non_rom: false,
+            instruction_pc: 0,
+            nametable_write_checks: false,
+        }
+    }
+
+    pub(super) fn record_instruction(&mut self, instruction: ExecutedInstruction) {
+        let pc = instruction.pc;
+        let cycles = instruction.cycles;
+        self.instruction_pc = pc;
+
+        if self.history_length > 0 {
+            if self.history.len() == self.history_length {
+                self.history.pop_front();
+            }
+            self.history.push_back(instruction);
+        }
+
+        if pc < 0x8000 {
+            if !self.executing_from_non_rom {
+                self.executing_from_non_rom = true;
+                self.raise(DiagnosticKind::ExecutingFromNonRom { pc }, cycles);
+            }
+        } else {
+            self.executing_from_non_rom = false;
+        }
+    }
+
+    pub(super) fn raise(&mut self, kind: DiagnosticKind, cycles: CpuCycle) {
+        warn!("Diagnostic raised: {} at cycle {}", kind, cycles);
+
+        if self.events.len() < MAX_PENDING_EVENTS {
+            self.events.push(DiagnosticEvent {
+                kind,
+                cycles,
+                history: self.history.iter().cloned().collect(),
+            });
+        }
+    }
+
+    pub(super) fn take_events(&mut self) -> Vec<DiagnosticEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use super::*;
+    use cpu::trace::CpuRegisters;
+
+    fn instruction(pc: u16) -> ExecutedInstruction {
+        ExecutedInstruction {
+            pc,
+            opcode: 0xEA,
+            operands: vec![],
+            registers: CpuRegisters {
+                a: 0,
+                x: 0,
+                y: 0,
+                status: 0x24,
+                stack_pointer: 0xFD,
+                program_counter: pc,
+            },
+            cycles: pc as CpuCycle,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn test_non_rom_execution_only_reported_on_entry() {
+        let mut diagnostics = Diagnostics::new(2);
+        for pc in &[0x8000, 0x8001, 0x0300, 0x0301, 0x8002, 0x6000] {
+            diagnostics.record_instruction(instruction(*pc));
+        }
+
+        let events = diagnostics.take_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, DiagnosticKind::ExecutingFromNonRom { pc: 0x0300 });
+        assert_eq!(
+            events[0].history.iter().map(|i| i.pc).collect::<Vec<u16>>(),
+            vec![0x8001, 0x0300]
+        );
+        assert_eq!(events[1].kind, DiagnosticKind::ExecutingFromNonRom { pc: 0x6000 });
+        assert!(diagnostics.take_events().is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::{Display, Formatter, Result};
+
+/// A CPU address along with where it currently maps to in the cartridge
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedAddress {
+    pub address: u16,
+    /// The offset into PRG ROM mapped at `address` with the current banks, if any
+    pub prg_rom_offset: Option<usize>,
+    /// The label for `address` if symbols have been loaded
+    pub label: Option<String>,
+}
+
+impl Display for ResolvedAddress {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{:04X}", self.address)?;
+        if let Some(offset) = self.prg_rom_offset {
+            write!(f, " (PRG {:05X})", offset)?;
+        }
+        if let Some(label) = &self.label {
+            write!(f, " {}", label)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The handlers the CPU would jump to for each interrupt given the banks mapped right now
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterruptVectors {
+    pub nmi: ResolvedAddress,
+    pub reset: ResolvedAddress,
+    pub irq: ResolvedAddress,
+}
+
+impl Display for InterruptVectors {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        writeln!(f, "NMI   {}", self.nmi)?;
+        writeln!(f, "RESET {}", self.reset)?;
+        writeln!(f, "IRQ   {}", self.irq)
+    }
+}
+
+/// A byte in use on the stack page, from the top of the stack down to $01FF
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackEntry {
+    pub address: u16,
+    pub value: u8,
+    /// Set when this byte and the one above it look like an address pushed by JSR, i.e. there's
+    /// a JSR opcode just before it in the banks currently mapped. This is where RTS would return
+    /// to, which won't be the caller if the bank has been switched since the call.
+    pub return_address: Option<ResolvedAddress>,
+}
+
+impl Display for StackEntry {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{:04X}: {:02X}", self.address, self.value)?;
+        if let Some(return_address) = &self.return_address {
+            write!(f, "  returns to {}", return_address)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,28 +1,53 @@
+mod breakpoints;
+mod condition;
+mod diagnostics;
+mod inspect;
 pub(crate) mod interrupts;
 mod opcodes;
 mod registers;
 mod status_flags;
-
-use apu::Apu;
+mod symbols;
+mod trace;
+
+pub use cpu::breakpoints::{Breakpoint, BreakpointHit};
+pub use cpu::condition::{Condition, ConditionError};
+pub use cpu::diagnostics::{DiagnosticEvent, DiagnosticKind};
+pub use cpu::inspect::{InterruptVectors, ResolvedAddress, StackEntry};
+pub use cpu::symbols::{SymbolError, SymbolTable};
+pub use cpu::trace::{CpuRegisters, ExecutedInstruction};
+
+use accuracy::AccuracyProfile;
+use apu::{Apu, ChannelSamples};
 use cartridge::CpuCartridgeAddressBus;
+use clock::Clock;
+use cpu::breakpoints::Breakpoints;
+use cpu::condition::ConditionState;
+use cpu::diagnostics::Diagnostics;
 use cpu::interrupts::Interrupt;
 use cpu::opcodes::Opcode;
-use cpu::opcodes::{AddressingMode, InstructionType, Operation, OPCODE_TABLE};
+use cpu::opcodes::{AddressingMode, InstructionLength, InstructionType, Operation, OPCODE_TABLE};
 use cpu::registers::Registers;
 use cpu::status_flags::StatusFlags;
 use io::Button;
 use io::Controller;
 use io::Io;
-use log::{debug, info};
+use log::{debug, error, info};
+use memory_region::MemoryRegion;
+use overclock::Overclock;
+use ppu::HdPack;
 use ppu::SCREEN_HEIGHT;
 use ppu::SCREEN_WIDTH;
-use ppu::{Ppu, PpuIteratorState};
+use ppu::{FrameInfo, PaletteTint, Ppu, PpuBusAccess, PpuIteratorState, SpriteStats};
+use savestate::{invalid_state, SaveState, SaveStateError, StateReader, StateWriter};
+use std::sync::mpsc::{SyncSender, TrySendError};
 
 #[derive(Debug, Copy, Clone)]
 enum State {
     Interrupt(InterruptState),
     Cpu(CpuState),
     Dma(DmaState),
+    /// A KIL opcode has locked up the CPU, only a reset recovers from this
+    Jammed,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -103,33 +128,47 @@ enum CpuState {
     WritingResult {
         address: u16,
         value: u8,
-        dummy: bool,
+        /// The unmodified value read by a read-modify-write instruction, which is written back
+        /// on the cycle before the result
+        dummy: Option<u8>,
     },
 }
 
-pub(crate) type CpuCycle = u32;
+pub(crate) type CpuCycle = u64;
 
-pub struct Cpu<'a> {
+pub struct Cpu {
     state: State,
     registers: Registers,
     pub cycles: CpuCycle,
     cpu_cycle_counter: u8,
     ram: [u8; 0x800],
-    apu: &'a mut Apu,
-    io: &'a mut Io,
-    ppu: &'a mut Ppu,
+    apu: Apu,
+    io: Io,
+    ppu: Ppu,
     prg_address_bus: Box<dyn CpuCartridgeAddressBus>,
     trigger_dma: bool,
     dma_address: u16,
     polled_interrupt: Option<Interrupt>,
+    instruction_sender: Option<SyncSender<ExecutedInstruction>>,
+    diagnostics: Option<Diagnostics>,
+    accuracy: AccuracyProfile,
+    symbols: Option<SymbolTable>,
+    breakpoints: Option<Breakpoints>,
+    /// Subroutines and interrupt handlers entered less those returned from, only meaningful
+    /// relative to an earlier value so it isn't saved
+    call_depth: i64,
+    overclock: Overclock,
+    /// PPU dots left for which the PPU is held while the CPU runs on, see `Overclock`
+    overclock_dots: u32,
 }
 
-impl<'a> Cpu<'a> {
+impl Cpu {
     pub fn new(
         prg_address_bus: Box<dyn CpuCartridgeAddressBus>,
-        apu: &'a mut Apu,
-        io: &'a mut Io,
-        ppu: &'a mut Ppu,
+        apu: Apu,
+        io: Io,
+        ppu: Ppu,
+        accuracy: AccuracyProfile,
     ) -> Self {
         // The processor starts at the RESET interrupt handler address
         let pc = prg_address_bus.read_byte(Interrupt::RESET(0).offset()) as u16
@@ -148,13 +187,31 @@ impl<'a> Cpu<'a> {
             trigger_dma: false,
             dma_address: 0x0000,
             polled_interrupt: None,
+            instruction_sender: None,
+            diagnostics: None,
+            accuracy,
+            symbols: None,
+            breakpoints: None,
+            call_depth: 0,
+            overclock: Overclock::default(),
+            overclock_dots: 0,
+        }
+    }
+
+    /// Read from the CPU address space without triggering any side effects, anything
+    /// outside of RAM & the cartridge (e.g. PPU registers) reads as 0
+    pub(crate) fn peek_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram[(address & 0x7FF) as usize],
+            0x4020..=0xFFFF => self.prg_address_bus.read_byte(address),
+            _ => 0x0,
         }
     }
 
     fn read_byte(&mut self, address: u16) -> u8 {
         debug!("CPU address space read {:04X}", address);
 
-        match address {
+        let value = match address {
             0x0000..=0x1FFF => self.ram[(address & 0x7FF) as usize],
             0x2000..=0x2007 => self.ppu.read_register(address),
             0x2008..=0x3FFF => self.ppu.read_register((address & 7) + 0x2000),
@@ -163,12 +220,28 @@ impl<'a> Cpu<'a> {
             0x4016..=0x4017 => self.io.read_byte(address), // Controller registers
             0x4018..=0x401F => 0x00, // TODO - Unused APU & IO registers
             0x4020..=0xFFFF => self.prg_address_bus.read_byte(address),
+        };
+
+        if let Some(breakpoints) = &self.breakpoints {
+            let candidate = breakpoints.check_read(address);
+            self.check_breakpoint(candidate, Some(value));
         }
+
+        value
     }
 
     fn write_byte(&mut self, address: u16, value: u8) {
         debug!("CPU address space write {:04X} = {:02X}", address, value);
 
+        if let Some(breakpoints) = &self.breakpoints {
+            let candidate = breakpoints.check_write(address);
+            self.check_breakpoint(candidate, Some(value));
+        }
+
+        if address & 0xE007 == 0x2007 {
+            self.check_nametable_write();
+        }
+
         match address {
             0x0000..=0x1FFF => self.ram[(address & 0x7FF) as usize] = value,
             0x2000..=0x2007 => self.ppu.write_register(address, value),
@@ -189,11 +262,40 @@ impl<'a> Cpu<'a> {
         }
     }
 
+    /// Trigger a breakpoint which matched the current access if it has no condition or its condition is met
+    fn check_breakpoint(&mut self, candidate: Option<(Breakpoint, u16)>, value: Option<u8>) {
+        if let (Some((breakpoint, address)), Some(breakpoints)) = (candidate, &self.breakpoints) {
+            let condition_met = match breakpoints.condition(breakpoint) {
+                None => true,
+                Some(condition) => {
+                    let state = ConditionState {
+                        registers: self.registers(),
+                        scanline: self.ppu.current_scanline(),
+                        dot: self.ppu.current_scanline_cycle(),
+                        value,
+                    };
+                    condition.is_met(&state, &|address| self.peek_byte(address))
+                }
+            };
+
+            if condition_met {
+                let cycles = self.cycles;
+                if let Some(breakpoints) = &mut self.breakpoints {
+                    breakpoints.trigger(breakpoint, address, value, cycles);
+                }
+            }
+        }
+    }
+
     fn nes_test_log(&mut self, opcode: &Opcode) -> String {
-        let pc_1 = self.read_byte(self.registers.program_counter);
-        let pc_2 = self.read_byte(self.registers.program_counter + 1);
+        let pc_1 = self.peek_byte(self.registers.program_counter);
+        let pc_2 = self.peek_byte(self.registers.program_counter + 1);
+        let label = match self.label(self.registers.program_counter - 1) {
+            Some(label) => format!(" ; {}", label),
+            None => String::new(),
+        };
         format!(
-            "{:04X}  {:} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{:}",
+            "{:04X}  {:} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{:}{}",
             self.registers.program_counter - 1,
             opcode.nes_test_log(pc_1, pc_2),
             self.registers.a,
@@ -203,10 +305,102 @@ impl<'a> Cpu<'a> {
             self.registers.stack_pointer,
             self.ppu.current_scanline_cycle(),
             self.ppu.current_scanline(),
-            self.cycles
+            self.cycles,
+            label
         )
     }
 
+    /// Find the label (if any) for an address, taking into account which PRG bank is mapped there
+    pub(crate) fn label(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .as_ref()
+            .and_then(|symbols| symbols.label(address, self.prg_address_bus.prg_rom_offset(address)))
+    }
+
+    fn resolve_address(&self, address: u16) -> ResolvedAddress {
+        ResolvedAddress {
+            address,
+            prg_rom_offset: self.prg_address_bus.prg_rom_offset(address),
+            label: self.label(address).map(str::to_string),
+        }
+    }
+
+    fn peek_word(&self, address: u16) -> u16 {
+        self.peek_byte(address) as u16 | (self.peek_byte(address.wrapping_add(1)) as u16) << 8
+    }
+
+    pub(crate) fn interrupt_vectors(&self) -> InterruptVectors {
+        InterruptVectors {
+            nmi: self.resolve_address(self.peek_word(Interrupt::NMI(0).offset())),
+            reset: self.resolve_address(self.peek_word(Interrupt::RESET(0).offset())),
+            irq: self.resolve_address(self.peek_word(Interrupt::IRQ(0).offset())),
+        }
+    }
+
+    pub(crate) fn stack(&self) -> Vec<StackEntry> {
+        let top = self.registers.stack_pointer as u16 + 1;
+
+        (top..=0xFF)
+            .map(|offset| {
+                let address = 0x0100 | offset;
+                // JSR pushes the address of its last byte, high byte first
+                let pushed = match offset {
+                    0xFF => None,
+                    _ => Some(self.peek_word(address)),
+                };
+                let return_address = pushed
+                    .filter(|&pushed| pushed >= 0x8000 && self.peek_byte(pushed.wrapping_sub(2)) == 0x20)
+                    .map(|pushed| self.resolve_address(pushed.wrapping_add(1)));
+
+                StackEntry {
+                    address,
+                    value: self.peek_byte(address),
+                    return_address,
+                }
+            })
+            .collect()
+    }
+
+    /// Build a record of the instruction which has just been fetched
+    fn executed_instruction(&self, opcode: &Opcode) -> ExecutedInstruction {
+        let pc = self.registers.program_counter.wrapping_sub(1);
+        let operand_count = match opcode.address_mode.instruction_length() {
+            InstructionLength::One => 0,
+            InstructionLength::Two => 1,
+            InstructionLength::Three => 2,
+        };
+        let mut registers = self.registers();
+        registers.program_counter = pc;
+
+        ExecutedInstruction {
+            pc,
+            opcode: opcode.opcode,
+            operands: (1..=operand_count)
+                .map(|ix| self.peek_byte(pc.wrapping_add(ix)))
+                .collect(),
+            registers,
+            cycles: self.cycles,
+            label: self.label(pc).map(str::to_string),
+        }
+    }
+
+    /// Publish an executed instruction to any listener on the instruction stream.
+    /// The stream never blocks emulation, if the consumer isn't keeping up then instructions are dropped.
+    fn send_executed_instruction(&mut self, instruction: ExecutedInstruction) {
+        let pc = instruction.pc;
+
+        if let Some(sender) = &self.instruction_sender {
+            match sender.try_send(instruction) {
+                Ok(()) => (),
+                Err(TrySendError::Full(_)) => debug!("Instruction stream full, dropping instruction at {:04X}", pc),
+                Err(TrySendError::Disconnected(_)) => {
+                    info!("Instruction stream receiver dropped, no longer publishing instructions");
+                    self.instruction_sender = None;
+                }
+            }
+        }
+    }
+
     /// This routine simulates checking for IRQ/NMI and happens during the last
     /// cycle of an instruction based on the state of the registers at the
     /// _start_ of that instruction
@@ -220,7 +414,7 @@ impl<'a> Cpu<'a> {
             .registers
             .status_register
             .contains(StatusFlags::INTERRUPT_DISABLE_FLAG)
-            && (self.ppu.check_trigger_irq(clear_lines) || self.apu.check_trigger_irq())
+            && (self.ppu.check_trigger_irq(clear_lines, self.cycles) || self.apu.check_trigger_irq())
         {
             self.polled_interrupt = Some(Interrupt::IRQ(self.cycles * 3));
 
@@ -229,15 +423,62 @@ impl<'a> Cpu<'a> {
     }
 
     fn push_to_stack(&mut self, value: u8) {
+        if self.registers.stack_pointer == 0x00 {
+            self.raise_diagnostic(DiagnosticKind::StackOverflow);
+        }
         self.write_byte(self.registers.stack_pointer as u16 | 0x0100, value);
         self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1);
     }
 
+    /// Reset runs the same sequence as the other interrupts but with the writes suppressed, so
+    /// only the stack pointer moves
+    fn push_interrupt_byte(&mut self, interrupt: Interrupt, value: u8) {
+        match interrupt {
+            Interrupt::RESET(_) => self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1),
+            _ => self.push_to_stack(value),
+        }
+    }
+
     fn pop_from_stack(&mut self) -> u8 {
+        if self.registers.stack_pointer == 0xFF {
+            self.raise_diagnostic(DiagnosticKind::StackUnderflow);
+        }
         self.registers.stack_pointer = self.registers.stack_pointer.wrapping_add(1);
         self.read_byte(self.registers.stack_pointer as u16 | 0x0100)
     }
 
+    /// Report PPUDATA writes which land in the nametables while the PPU is rendering
+    fn check_nametable_write(&mut self) {
+        let pc = match &self.diagnostics {
+            Some(diagnostics) if diagnostics.nametable_write_checks => diagnostics.instruction_pc,
+            _ => return,
+        };
+
+        if let Some(address) = self.ppu.nametable_write_during_rendering() {
+            self.raise_diagnostic(DiagnosticKind::NametableWriteDuringRendering {
+                pc,
+                address,
+                scanline: self.ppu.current_scanline(),
+                dot: self.ppu.current_scanline_cycle(),
+            });
+        }
+    }
+
+    fn raise_diagnostic(&mut self, kind: DiagnosticKind) {
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.raise(kind, self.cycles);
+        }
+    }
+
+    /// Lock up the CPU on a KIL opcode, the PPU and APU carry on without it
+    fn jam(&mut self) -> State {
+        let pc = self.registers.program_counter.wrapping_sub(1);
+        error!("KIL opcode at {:04X}, CPU jammed", pc);
+        self.raise_diagnostic(DiagnosticKind::Jammed { pc });
+
+        State::Jammed
+    }
+
     fn read_and_inc_program_counter(&mut self) -> u8 {
         let value = self.read_byte(self.registers.program_counter);
         self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
@@ -433,12 +674,15 @@ impl<'a> Cpu<'a> {
             InterruptState::InternalOps1(i) => State::Interrupt(InterruptState::InternalOps2(i)),
             InterruptState::InternalOps2(i) => State::Interrupt(InterruptState::PushPCH(i)),
             InterruptState::PushPCH(i) => {
-                self.push_to_stack((self.registers.program_counter >> 8) as u8);
+                if !matches!(i, Interrupt::RESET(_)) {
+                    self.call_depth += 1;
+                }
+                self.push_interrupt_byte(i, (self.registers.program_counter >> 8) as u8);
 
                 State::Interrupt(InterruptState::PushPCL(i))
             }
             InterruptState::PushPCL(i) => {
-                self.push_to_stack(self.registers.program_counter as u8);
+                self.push_interrupt_byte(i, self.registers.program_counter as u8);
                 State::Interrupt(InterruptState::PushStatusRegister(i))
             }
             InterruptState::PushStatusRegister(i) => {
@@ -462,10 +706,11 @@ impl<'a> Cpu<'a> {
                 };
                 self.polled_interrupt = None;
 
-                self.push_to_stack(match i {
+                let status = match i {
                     Interrupt::IRQ_BRK(_) => self.registers.status_register.bits() | 0b0011_0000,
                     _ => (self.registers.status_register.bits() | 0b0010_0000) & 0b1110_1111,
-                });
+                };
+                self.push_interrupt_byte(i, status);
 
                 // Set interrupt disable at this point, whether this is NMI, BRK or normal IRQ
                 self.registers
@@ -495,6 +740,21 @@ impl<'a> Cpu<'a> {
 
                 info!("{}", self.nes_test_log(opcode));
 
+                match opcode.operation {
+                    Operation::JSR => self.call_depth += 1,
+                    Operation::RTS | Operation::RTI => self.call_depth -= 1,
+                    _ => (),
+                }
+
+                if self.instruction_sender.is_some() || self.diagnostics.is_some() {
+                    let instruction = self.executed_instruction(opcode);
+
+                    if let Some(diagnostics) = &mut self.diagnostics {
+                        diagnostics.record_instruction(instruction.clone());
+                    }
+                    self.send_executed_instruction(instruction);
+                }
+
                 match opcode.address_mode {
                     AddressingMode::Accumulator => State::Cpu(CpuState::ThrowawayRead {
                         opcode,
@@ -993,16 +1253,24 @@ impl<'a> Cpu<'a> {
             CpuState::WritingResult {
                 value,
                 address,
-                dummy: true,
-            } => State::Cpu(CpuState::WritingResult {
-                value,
-                address,
-                dummy: false,
-            }),
+                dummy: Some(original),
+            } => {
+                // Mapper registers and $2007 see both writes, e.g. MMC1 ignores the result as
+                // it's written on the very next cycle
+                if self.accuracy.rmw_dummy_writes() {
+                    self.write_byte(address, original);
+                }
+
+                State::Cpu(CpuState::WritingResult {
+                    value,
+                    address,
+                    dummy: None,
+                })
+            }
             CpuState::WritingResult {
                 value,
                 address,
-                dummy: false,
+                dummy: None,
             } => {
                 // Crucially this _must_ happen before the write_byte.
                 self.poll_for_interrupts(true);
@@ -1028,12 +1296,13 @@ impl<'a> Cpu<'a> {
             DmaState::OddCpuCycle => State::Dma(DmaState::ReadCycle),
             DmaState::ReadCycle => {
                 let value = self.read_byte(self.dma_address);
-                self.dma_address += 1;
+                // A DMA from page $FF finishes when the address wraps to $0000
+                self.dma_address = self.dma_address.wrapping_add(1);
 
                 State::Dma(DmaState::WriteCycle(value))
             }
             DmaState::WriteCycle(value) => {
-                self.ppu.write_dma_byte(value, (self.dma_address - 1) as u8);
+                self.ppu.write_dma_byte(value);
 
                 if self.dma_address.trailing_zeros() >= 8 {
                     info!("Finished DMA on cycle {}", self.cycles);
@@ -1051,6 +1320,7 @@ impl<'a> Cpu<'a> {
             State::Cpu(state) => self.step_cpu(state),
             State::Interrupt(state) => self.step_interrupt_handler(state),
             State::Dma(state) => self.step_dma_handler(state),
+            State::Jammed => State::Jammed,
         };
 
         if let State::Cpu(CpuState::FetchOpcode) = self.state {
@@ -1064,12 +1334,182 @@ impl<'a> Cpu<'a> {
                 self.state = State::Dma(DmaState::DummyCycle);
 
                 info!("Starting DMA transfer from {:04X}", self.dma_address);
+            } else if let Some(breakpoints) = &self.breakpoints {
+                let candidate = breakpoints.check_execute(self.registers.program_counter);
+                self.check_breakpoint(candidate, None);
             }
         }
 
         self.cycles += 1;
     }
 
+    /// True when the previous instruction has completed and the next opcode is about to be fetched
+    pub(crate) fn at_instruction_boundary(&self) -> bool {
+        matches!(self.state, State::Cpu(CpuState::FetchOpcode))
+    }
+
+    /// Incremented by JSR and interrupts (including BRK) and decremented by RTS and RTI, so code
+    /// which manipulates the stack to return (e.g. RTS as a jump table) throws it off
+    pub(crate) fn call_depth(&self) -> i64 {
+        self.call_depth
+    }
+
+    pub(crate) fn is_jammed(&self) -> bool {
+        matches!(self.state, State::Jammed)
+    }
+
+    /// Soft reset as with the console's reset button, RAM and the cartridge keep their contents
+    /// while the CPU runs the reset sequence and the APU and PPU are silenced and blanked
+    pub(crate) fn reset(&mut self) {
+        info!("Resetting at cycle {}", self.cycles);
+        self.state = State::Interrupt(InterruptState::InternalOps1(Interrupt::RESET(self.cycles)));
+        self.polled_interrupt = None;
+        self.trigger_dma = false;
+        self.apu.reset();
+        self.ppu.write_register(0x2000, 0);
+        self.ppu.write_register(0x2001, 0);
+    }
+
+    /// True on the first cycle of the CPU handling an NMI
+    pub(crate) fn is_starting_nmi(&self) -> bool {
+        matches!(
+            self.state,
+            State::Interrupt(InterruptState::InternalOps1(Interrupt::NMI(_)))
+        )
+    }
+
+    /// Get a copy of the current state of the CPU registers
+    pub fn registers(&self) -> CpuRegisters {
+        CpuRegisters {
+            a: self.registers.a,
+            x: self.registers.x,
+            y: self.registers.y,
+            status: self.registers.status_register.bits() | 0b0010_0000,
+            stack_pointer: self.registers.stack_pointer,
+            program_counter: self.registers.program_counter,
+        }
+    }
+
+    pub(crate) fn set_program_counter(&mut self, pc: u16) {
+        self.registers.program_counter = pc;
+    }
+
+    pub(crate) fn set_instruction_sender(&mut self, sender: SyncSender<ExecutedInstruction>) {
+        self.instruction_sender = Some(sender);
+    }
+
+    /// Start watching for signs that the running program has crashed, keeping the last
+    /// `history_length` instructions to report alongside each event
+    pub(crate) fn enable_diagnostics(&mut self, history_length: usize) {
+        self.diagnostics = Some(Diagnostics::new(history_length));
+    }
+
+    pub(crate) fn set_nametable_write_checks(&mut self, enabled: bool) {
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.nametable_write_checks = enabled;
+        }
+    }
+
+    /// Emulated time since power on in every unit
+    pub(crate) fn elapsed_clock(&self) -> Clock {
+        Clock::new(self.cycles, self.ppu.total_cycles, self.ppu.frame_number() as u64)
+    }
+
+    pub(crate) fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.prg_address_bus.set_dip_switches(dip_switches);
+        self.ppu.set_dip_switches(dip_switches);
+    }
+
+    pub(crate) fn take_prg_ram_written(&mut self) -> bool {
+        self.prg_address_bus.take_prg_ram_written()
+    }
+
+    pub(crate) fn take_diagnostic_events(&mut self) -> Vec<DiagnosticEvent> {
+        self.diagnostics
+            .as_mut()
+            .map_or_else(Vec::new, |diagnostics| diagnostics.take_events())
+    }
+
+    pub(crate) fn set_breakpoint(&mut self, breakpoint: Breakpoint, enabled: bool) {
+        self.breakpoints
+            .get_or_insert_with(Breakpoints::new)
+            .set(breakpoint, enabled);
+    }
+
+    pub(crate) fn set_breakpoint_condition(&mut self, breakpoint: Breakpoint, condition: Condition) {
+        self.breakpoints
+            .get_or_insert_with(Breakpoints::new)
+            .set_condition(breakpoint, condition);
+    }
+
+    pub(crate) fn clear_breakpoints(&mut self) {
+        self.breakpoints = None;
+    }
+
+    pub(crate) fn take_breakpoint_hit(&mut self) -> Option<BreakpointHit> {
+        self.breakpoints.as_mut().and_then(|breakpoints| breakpoints.take_hit())
+    }
+
+    pub(crate) fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = Some(symbols);
+    }
+
+    pub(crate) fn set_hd_pack(&mut self, pack: HdPack) {
+        self.ppu.set_hd_pack(pack);
+    }
+
+    pub(crate) fn set_palette(&mut self, palette: [u32; 0x40]) {
+        self.ppu.set_palette(palette);
+    }
+
+    pub(crate) fn set_palette_tint(&mut self, tint: Option<PaletteTint>) {
+        self.ppu.set_palette_tint(tint);
+    }
+
+    pub(crate) fn set_overclock(&mut self, overclock: Overclock) {
+        self.overclock = overclock;
+    }
+
+    pub(crate) fn set_sprite_limit(&mut self, enabled: bool) {
+        self.ppu.set_sprite_limit(enabled);
+    }
+
+    pub(crate) fn controller_state(&self, controller: Controller) -> u8 {
+        self.io.controller_state(controller)
+    }
+
+    pub(crate) fn set_controller_state(&mut self, controller: Controller, buttons: u8) {
+        self.io.set_controller_state(controller, buttons);
+    }
+
+    pub(crate) fn take_controller_read(&mut self, controller: Controller) -> Option<u8> {
+        self.io.take_controller_read(controller)
+    }
+
+    pub(crate) fn record_ppu_bus_activity(&mut self, frame: u32) {
+        self.ppu.record_bus_activity(frame);
+    }
+
+    pub(crate) fn ppu_bus_activity(&self) -> Option<&[PpuBusAccess]> {
+        self.ppu.bus_activity()
+    }
+
+    pub(crate) fn frame_number(&self) -> u32 {
+        self.ppu.frame_number()
+    }
+
+    pub(crate) fn record_sprite_stats(&mut self, enabled: bool) {
+        self.ppu.record_sprite_stats(enabled);
+    }
+
+    pub(crate) fn sprite_stats(&self) -> Option<&SpriteStats> {
+        self.ppu.sprite_stats()
+    }
+
+    pub(crate) fn frame_info(&self) -> Option<FrameInfo> {
+        self.ppu.frame_info()
+    }
+
     pub fn button_down(&mut self, controller: Controller, button: Button) {
         self.io.button_down(controller, button);
     }
@@ -1079,20 +1519,125 @@ impl<'a> Cpu<'a> {
     }
 
     pub fn get_framebuffer(&self) -> &[u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize] {
-        &self.ppu.frame_buffer
+        self.ppu.frame_buffer()
+    }
+
+    pub(crate) fn resolve_framebuffer(&mut self) {
+        self.ppu.resolve_frame_buffer();
+    }
+
+    pub fn get_index_buffer(&self) -> &[u16; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize] {
+        &self.ppu.index_buffer
+    }
+
+    /// Returns the upscaled framebuffer and scale factor when an HD pack is in use
+    pub fn get_hd_framebuffer(&self) -> Option<(&[u8], u32)> {
+        self.ppu.hd_frame_buffer()
+    }
+
+    pub(crate) fn apu_channel_samples(&self) -> ChannelSamples {
+        self.apu.channel_samples()
+    }
+
+    pub fn dump_memory(&self, region: MemoryRegion) -> Vec<u8> {
+        match region {
+            MemoryRegion::CpuRam => self.ram.to_vec(),
+            MemoryRegion::PrgRam => self.prg_address_bus.prg_ram().map_or(vec![], |ram| ram.to_vec()),
+            _ => self.ppu.dump_memory(region),
+        }
+    }
+
+    pub fn load_memory(&mut self, region: MemoryRegion, data: &[u8]) {
+        match region {
+            MemoryRegion::CpuRam => self.ram.copy_from_slice(data),
+            MemoryRegion::PrgRam => {
+                if let Some(ram) = self.prg_address_bus.prg_ram_mut() {
+                    ram.copy_from_slice(data);
+                }
+            }
+            _ => self.ppu.load_memory(region, data),
+        }
     }
+}
 
-    pub fn dump_ppu_state(&mut self, vram_clone: &mut [u8; 0x4000]) -> &[u8; 0x100] {
-        self.ppu.dump_state(vram_clone)
+/// Only the state between instructions is saved, see `Nes::save_state`. Debugging aids like
+/// breakpoints and symbols belong to the host rather than the console so aren't saved either.
+impl SaveState for Cpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        debug_assert!(self.at_instruction_boundary() || self.is_jammed());
+
+        self.is_jammed().save_state(writer);
+        self.registers.save_state(writer);
+        self.cycles.save_state(writer);
+        self.cpu_cycle_counter.save_state(writer);
+        self.overclock_dots.save_state(writer);
+        self.ram.save_state(writer);
+        self.trigger_dma.save_state(writer);
+        self.dma_address.save_state(writer);
+        let (interrupt, cycle) = match self.polled_interrupt {
+            None => (0u8, 0),
+            Some(Interrupt::NMI(cycle)) => (1, cycle),
+            Some(Interrupt::IRQ(cycle)) => (2, cycle),
+            Some(Interrupt::IRQ_BRK(cycle)) => (3, cycle),
+            Some(Interrupt::RESET(cycle)) => (4, cycle),
+        };
+        interrupt.save_state(writer);
+        cycle.save_state(writer);
+        self.apu.save_state(writer);
+        self.io.save_state(writer);
+        self.ppu.save_state(writer);
+        self.prg_address_bus.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut jammed = false;
+        jammed.load_state(reader)?;
+        self.state = if jammed {
+            State::Jammed
+        } else {
+            State::Cpu(CpuState::FetchOpcode)
+        };
+        self.registers.load_state(reader)?;
+        self.cycles.load_state(reader)?;
+        self.cpu_cycle_counter.load_state(reader)?;
+        self.overclock_dots.load_state(reader)?;
+        self.ram.load_state(reader)?;
+        self.trigger_dma.load_state(reader)?;
+        self.dma_address.load_state(reader)?;
+        let (mut interrupt, mut cycle) = (0u8, 0);
+        interrupt.load_state(reader)?;
+        cycle.load_state(reader)?;
+        self.polled_interrupt = match interrupt {
+            0 => None,
+            1 => Some(Interrupt::NMI(cycle)),
+            2 => Some(Interrupt::IRQ(cycle)),
+            3 => Some(Interrupt::IRQ_BRK(cycle)),
+            4 => Some(Interrupt::RESET(cycle)),
+            _ => return Err(invalid_state("unknown interrupt")),
+        };
+        self.apu.load_state(reader)?;
+        self.io.load_state(reader)?;
+        self.ppu.load_state(reader)?;
+        self.prg_address_bus.load_state(reader)
     }
 }
 
-impl<'a> Iterator for Cpu<'a> {
+impl Iterator for Cpu {
     type Item = (Option<PpuIteratorState>, Option<f32>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Always clock the PPU
-        let ppu_state = self.ppu.next();
+        // Clock the PPU unless it's being held at the end of vblank to overclock the CPU
+        let overclocking = self.overclock_dots > 0;
+        let ppu_state = if overclocking {
+            self.overclock_dots -= 1;
+            None
+        } else {
+            let ppu_state = self.ppu.next();
+            if self.ppu.current_scanline() == 260 && self.ppu.current_scanline_cycle() == 340 {
+                self.overclock_dots = self.overclock.extra_dots();
+            }
+            ppu_state
+        };
         let mut sample: Option<f32> = None;
 
         // Check if we need to clock the CPU
@@ -1101,8 +1646,11 @@ impl<'a> Iterator for Cpu<'a> {
             self.cpu_cycle_counter = 3;
             self.clock();
 
-            // Clock the APU once every CPU cycle, it decides internally which things to clock at what speed
-            sample = self.apu.next();
+            // Clock the APU once every CPU cycle, it decides internally which things to clock at what speed.
+            // The extra cycles from overclocking are skipped so that the APU keeps to its stock rate.
+            if !overclocking || self.overclock.clock_apu {
+                sample = self.apu.next();
+            }
         }
 
         // Does the cpu ever halt? If no return None, otherwise this is just an
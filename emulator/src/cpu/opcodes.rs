@@ -4,7 +4,6 @@ use cpu::Cpu;
 use cpu::CpuState;
 use cpu::InterruptState;
 use cpu::State;
-use log::error;
 
 #[derive(Debug, PartialEq)]
 pub(super) struct Opcode {
@@ -45,15 +44,6 @@ impl Opcode {
     }
 
     pub(super) fn execute(&self, cpu: &mut Cpu, operand: Option<u8>, address: Option<u16>) -> State {
-        // All read modify write instructions do a double write, one on this cycle and
-        // one on the actual write cycle with the proper new value
-        if let (InstructionType::ReadModifyWrite, Some(o), Some(a)) =
-            (self.operation.instruction_type(), operand, address)
-        {
-            // Dummy write, first write the original value
-            cpu.write_byte(a, o);
-        };
-
         match self.operation {
             Operation::ADC => {
                 cpu.poll_for_interrupts(true);
@@ -86,7 +76,7 @@ impl Opcode {
                     _ => State::Cpu(CpuState::WritingResult {
                         address: address.unwrap(),
                         value: result,
-                        dummy: true,
+                        dummy: operand,
                     }),
                 }
             }
@@ -158,7 +148,7 @@ impl Opcode {
                 State::Cpu(CpuState::WritingResult {
                     value: result,
                     address: address.unwrap(),
-                    dummy: true,
+                    dummy: operand,
                 })
             }
             Operation::DEC => {
@@ -173,7 +163,7 @@ impl Opcode {
                     _ => State::Cpu(CpuState::WritingResult {
                         address: address.unwrap(),
                         value: result,
-                        dummy: true,
+                        dummy: operand,
                     }),
                 }
             }
@@ -205,7 +195,7 @@ impl Opcode {
                     _ => State::Cpu(CpuState::WritingResult {
                         address: address.unwrap(),
                         value: result,
-                        dummy: true,
+                        dummy: operand,
                     }),
                 }
             }
@@ -226,7 +216,7 @@ impl Opcode {
                 State::Cpu(CpuState::WritingResult {
                     value: result,
                     address: address.unwrap(),
-                    dummy: true,
+                    dummy: operand,
                 })
             }
             Operation::JMP => {
@@ -237,11 +227,7 @@ impl Opcode {
             Operation::JSR => State::Cpu(CpuState::WritePCHToStack {
                 address: address.unwrap(),
             }),
-            Operation::KIL => {
-                // Illegal opcode - KIL
-                error!("KIL opcode");
-                panic!();
-            }
+            Operation::KIL => cpu.jam(),
             Operation::LAS => todo!(),
             Operation::LAX => {
                 cpu.poll_for_interrupts(true);
@@ -284,7 +270,7 @@ impl Opcode {
                     _ => State::Cpu(CpuState::WritingResult {
                         address: address.unwrap(),
                         value: result,
-                        dummy: true,
+                        dummy: operand,
                     }),
                 }
             }
@@ -335,7 +321,7 @@ impl Opcode {
                     _ => State::Cpu(CpuState::WritingResult {
                         address: address.unwrap(),
                         value: result,
-                        dummy: true,
+                        dummy: operand,
                     }),
                 }
             }
@@ -358,7 +344,7 @@ impl Opcode {
                     _ => State::Cpu(CpuState::WritingResult {
                         address: address.unwrap(),
                         value: result,
-                        dummy: true,
+                        dummy: operand,
                     }),
                 }
             }
@@ -381,7 +367,7 @@ impl Opcode {
                     _ => State::Cpu(CpuState::WritingResult {
                         address: address.unwrap(),
                         value: result,
-                        dummy: true,
+                        dummy: operand,
                     }),
                 }
             }
@@ -404,7 +390,7 @@ impl Opcode {
                     _ => State::Cpu(CpuState::WritingResult {
                         address: address.unwrap(),
                         value: result,
-                        dummy: true,
+                        dummy: operand,
                     }),
                 }
             }
@@ -417,7 +403,7 @@ impl Opcode {
             Operation::SAX => State::Cpu(CpuState::WritingResult {
                 value: cpu.registers.a & cpu.registers.x,
                 address: address.unwrap(),
-                dummy: false,
+                dummy: None,
             }),
             Operation::SBC => {
                 cpu.poll_for_interrupts(true);
@@ -454,7 +440,7 @@ impl Opcode {
                 State::Cpu(CpuState::WritingResult {
                     value: result,
                     address: address.unwrap(),
-                    dummy: true,
+                    dummy: operand,
                 })
             }
             Operation::SRE => {
@@ -468,23 +454,23 @@ impl Opcode {
                 State::Cpu(CpuState::WritingResult {
                     address: address.unwrap(),
                     value: result,
-                    dummy: true,
+                    dummy: operand,
                 })
             }
             Operation::STA => State::Cpu(CpuState::WritingResult {
                 value: cpu.registers.a,
                 address: address.unwrap(),
-                dummy: false,
+                dummy: None,
             }),
             Operation::STX => State::Cpu(CpuState::WritingResult {
                 value: cpu.registers.x,
                 address: address.unwrap(),
-                dummy: false,
+                dummy: None,
             }),
             Operation::STY => State::Cpu(CpuState::WritingResult {
                 value: cpu.registers.y,
                 address: address.unwrap(),
-                dummy: false,
+                dummy: None,
             }),
             Operation::TAS => todo!(),
             Operation::TAX => {
@@ -726,7 +712,8 @@ impl Operation {
             | Operation::TSX
             | Operation::TXA
             | Operation::TXS
-            | Operation::TYA => InstructionType::NoMemoryAccess,
+            | Operation::TYA
+            | Operation::KIL => InstructionType::NoMemoryAccess,
             _ => todo!("Not yet defined instruction type for {:?}", self),
         }
     }
@@ -26,3 +26,12 @@ impl Registers {
         }
     }
 }
+
+save_state_fields!(Registers {
+    a,
+    x,
+    y,
+    stack_pointer,
+    program_counter,
+    status_register,
+});
@@ -1,3 +1,5 @@
+use savestate::{SaveState, SaveStateError, StateReader, StateWriter};
+
 bitflags! {
   #[derive(Default)]
   pub(crate) struct StatusFlags: u8 {
@@ -10,6 +12,19 @@ bitflags! {
   }
 }
 
+impl SaveState for StatusFlags {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.bits().save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut bits = 0u8;
+        bits.load_state(reader)?;
+        *self = StatusFlags::from_bits_truncate(bits);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod status_flag_tests {
     use super::StatusFlags;
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// FCEUX symbol files are split per 16KB PRG bank
+const FCEUX_BANK_SIZE: usize = 0x4000;
+
+/// The size of the iNES header, ca65 segment offsets are relative to the start of the file
+const INES_HEADER_SIZE: usize = 0x10;
+
+/// Represents any error which occurs loading a symbol file
+#[derive(Debug)]
+pub struct SymbolError {
+    pub message: String,
+}
+impl Error for SymbolError {}
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error loading symbols: {}", self.message)
+    }
+}
+impl From<io::Error> for SymbolError {
+    fn from(error: io::Error) -> Self {
+        SymbolError {
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Labels for addresses in the running program, loaded from assembler output.
+///
+/// Labels in PRG ROM are keyed on their offset into PRG ROM rather than CPU address so
+/// that the same address can resolve to different labels depending on the mapped bank.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    cpu_labels: HashMap<u16, String>,
+    prg_labels: HashMap<usize, String>,
+}
+
+impl SymbolTable {
+    /// Load symbols from either a ca65 debug file (.dbg) or the FCEUX name list
+    /// files (<rom>.ram.nl, <rom>.0.nl, <rom>.1.nl...) which sit alongside a rom.
+    pub fn load(path: &str) -> Result<Self, SymbolError> {
+        match Path::new(path).extension().and_then(OsStr::to_str) {
+            Some("dbg") => SymbolTable::parse_ca65_dbg(&fs::read_to_string(path)?),
+            _ => SymbolTable::load_fceux_nl(path),
+        }
+    }
+
+    fn load_fceux_nl(rom_file: &str) -> Result<Self, SymbolError> {
+        let mut symbols = SymbolTable::default();
+
+        let ram_file = format!("{}.ram.nl", rom_file);
+        if Path::new(&ram_file).exists() {
+            symbols.parse_fceux_nl(&fs::read_to_string(ram_file)?, None)?;
+        }
+
+        let mut bank = 0;
+        loop {
+            let bank_file = format!("{}.{:X}.nl", rom_file, bank);
+            if !Path::new(&bank_file).exists() {
+                break;
+            }
+
+            symbols.parse_fceux_nl(&fs::read_to_string(bank_file)?, Some(bank))?;
+            bank += 1;
+        }
+
+        if symbols.is_empty() {
+            return Err(SymbolError {
+                message: format!("No FCEUX symbol files (.nl) found for {}", rom_file),
+            });
+        }
+
+        Ok(symbols)
+    }
+
+    /// Parse a single FCEUX name list file, lines are of the form `$C000#Label#Comment`
+    fn parse_fceux_nl(&mut self, contents: &str, bank: Option<usize>) -> Result<(), SymbolError> {
+        for line in contents.lines().filter(|line| line.starts_with('$')) {
+            let mut fields = line.splitn(3, '#');
+            // Array labels are written as $0200/10 which we treat as a label at the start only
+            let address = fields.next().unwrap()[1..].split('/').next().unwrap();
+            let address = u16::from_str_radix(address, 16).map_err(|_| SymbolError {
+                message: format!("Invalid address in symbol file line {}", line),
+            })?;
+            let name = match fields.next() {
+                Some(name) if !name.is_empty() => name.to_string(),
+                _ => continue,
+            };
+
+            match bank {
+                Some(bank) if address >= 0x8000 => {
+                    let offset = bank * FCEUX_BANK_SIZE + (address as usize % FCEUX_BANK_SIZE);
+                    self.prg_labels.insert(offset, name);
+                }
+                _ => {
+                    self.cpu_labels.insert(address, name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse the debug file written by ld65 with --dbgfile. Only the (tab separated) segment
+    /// and label lines are used, e.g.
+    ///
+    /// seg id=0,name="CODE",start=0x008000,size=0x0123,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+    /// sym id=0,name="reset",addrsize=absolute,scope=0,def=1,ref=2,val=0x8000,seg=0,type=lab
+    fn parse_ca65_dbg(contents: &str) -> Result<Self, SymbolError> {
+        let mut symbols = SymbolTable::default();
+        let mut segments = HashMap::<usize, (usize, Option<usize>)>::new();
+        let mut labels = vec![];
+
+        for line in contents.lines() {
+            let mut parts = line.splitn(2, '\t');
+            let kind = parts.next().unwrap();
+            let attributes = match parts.next() {
+                Some(attributes) => parse_dbg_attributes(attributes),
+                None => continue,
+            };
+
+            match kind {
+                "seg" => {
+                    let id = dbg_number(&attributes, "id")?;
+                    let start = dbg_number(&attributes, "start")?;
+                    let file_offset = attributes
+                        .get("ooffs")
+                        .map(|_| dbg_number(&attributes, "ooffs"))
+                        .transpose()?;
+                    segments.insert(id, (start, file_offset));
+                }
+                "sym" if attributes.get("type").map(String::as_str) == Some("lab") => {
+                    let name = attributes.get("name").cloned().unwrap_or_default();
+                    let value = dbg_number(&attributes, "val")?;
+                    let segment = attributes
+                        .get("seg")
+                        .map(|_| dbg_number(&attributes, "seg"))
+                        .transpose()?;
+                    labels.push((name, value, segment));
+                }
+                _ => (),
+            }
+        }
+
+        for (name, value, segment) in labels {
+            match segment.and_then(|segment| segments.get(&segment)) {
+                Some((start, Some(file_offset))) if value >= 0x8000 && *file_offset >= INES_HEADER_SIZE => {
+                    let offset = file_offset - INES_HEADER_SIZE + value - start;
+                    symbols.prg_labels.insert(offset, name);
+                }
+                _ => {
+                    symbols.cpu_labels.insert(value as u16, name);
+                }
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    /// Find the label for an address, `prg_rom_offset` is the offset into PRG ROM which
+    /// the address currently maps to (if any) as reported by the mapper.
+    pub fn label(&self, address: u16, prg_rom_offset: Option<usize>) -> Option<&str> {
+        prg_rom_offset
+            .and_then(|offset| self.prg_labels.get(&offset))
+            .or_else(|| self.cpu_labels.get(&address))
+            .map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cpu_labels.len() + self.prg_labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Split a comma separated list of key=value pairs, values may be quoted strings containing commas
+fn parse_dbg_attributes(attributes: &str) -> HashMap<&str, String> {
+    let mut result = HashMap::new();
+    let mut remaining = attributes;
+
+    while let Some(equals) = remaining.find('=') {
+        let key = &remaining[..equals];
+        remaining = &remaining[equals + 1..];
+
+        let value = if remaining.starts_with('"') {
+            let end = remaining[1..].find('"').map_or(remaining.len(), |end| end + 1);
+            let value = remaining[1..end].to_string();
+            remaining = remaining.get(end + 1..).unwrap_or("");
+            value
+        } else {
+            let end = remaining.find(',').unwrap_or(remaining.len());
+            let value = remaining[..end].to_string();
+            remaining = &remaining[end..];
+            value
+        };
+
+        result.insert(key, value);
+        remaining = remaining.trim_start_matches(',');
+    }
+
+    result
+}
+
+fn dbg_number(attributes: &HashMap<&str, String>, key: &str) -> Result<usize, SymbolError> {
+    let value = attributes.get(key).ok_or_else(|| SymbolError {
+        message: format!("Missing {} in debug file", key),
+    })?;
+
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed.map_err(|_| SymbolError {
+        message: format!("Invalid number {} for {} in debug file", value, key),
+    })
+}
+
+#[cfg(test)]
+mod symbols_tests {
+    use super::*;
+
+    #[test]
+    fn test_fceux_labels_are_bank_aware() {
+        let mut symbols = SymbolTable::default();
+        symbols
+            .parse_fceux_nl("$0300#buffer#scratch space\n$0400/10#table#\n", None)
+            .unwrap();
+        symbols.parse_fceux_nl("$8010#bank0_routine#\n", Some(0)).unwrap();
+        symbols.parse_fceux_nl("$8010#bank1_routine#\n", Some(1)).unwrap();
+
+        assert_eq!(symbols.label(0x0300, None), Some("buffer"));
+        assert_eq!(symbols.label(0x0400, None), Some("table"));
+        assert_eq!(symbols.label(0x8010, Some(0x0010)), Some("bank0_routine"));
+        assert_eq!(symbols.label(0x8010, Some(0x4010)), Some("bank1_routine"));
+        assert_eq!(symbols.label(0x8010, Some(0x8010)), None);
+    }
+
+    #[test]
+    fn test_ca65_labels_use_segment_file_offsets() {
+        let symbols = SymbolTable::parse_ca65_dbg(
+            "version\tmajor=2,minor=0\n\
+             seg\tid=0,name=\"ZEROPAGE\",start=0x000000,size=0x0010,addrsize=zeropage,type=rw\n\
+             seg\tid=1,name=\"BANK1\",start=0x008000,size=0x4000,addrsize=absolute,type=ro,oname=\"a,b.nes\",ooffs=16400\n\
+             sym\tid=0,name=\"temp\",addrsize=zeropage,scope=0,def=1,val=0x4,seg=0,type=lab\n\
+             sym\tid=1,name=\"draw\",addrsize=absolute,scope=0,def=2,val=0x8020,seg=1,type=lab\n\
+             sym\tid=2,name=\"SPEED\",addrsize=zeropage,scope=0,def=3,val=0x3,type=equ\n",
+        )
+        .unwrap();
+
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.label(0x0004, None), Some("temp"));
+        assert_eq!(symbols.label(0x8020, Some(0x4020)), Some("draw"));
+        assert_eq!(symbols.label(0x8020, Some(0x0020)), None);
+    }
+}
@@ -0,0 +1,28 @@
+use cpu::CpuCycle;
+
+/// A copy of the CPU registers at a point in time
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+    pub stack_pointer: u8,
+    pub program_counter: u16,
+}
+
+/// A structured equivalent of a single line of the nestest style trace log.
+///
+/// Note that the registers and cycle count are those at the _start_ of the
+/// instruction (i.e. before it has had any effect).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutedInstruction {
+    pub pc: u16,
+    pub opcode: u8,
+    /// The 0-2 bytes following the opcode which make up the rest of the instruction
+    pub operands: Vec<u8>,
+    pub registers: CpuRegisters,
+    pub cycles: CpuCycle,
+    /// The label for `pc` if symbols have been loaded
+    pub label: Option<String>,
+}
@@ -0,0 +1,134 @@
+//! An environment for reinforcement learning agents in the style of OpenAI Gym. Each step holds
+//! an action on controller one for a few frames and scores the result with a `RewardProbe`
+//! which reads the game's RAM, e.g. the score and number of lives.
+//!
+//! ```no_run
+//! # let nes = rust_nes::Nes::new(rust_nes::get_cartridge("../roms/test/nestest.nes").unwrap());
+//! use rust_nes::{Environment, Nes, RewardProbe};
+//!
+//! /// Rewards the score going up until the player runs out of lives
+//! struct ScoreProbe {
+//!     last_score: u8,
+//! }
+//!
+//! impl RewardProbe for ScoreProbe {
+//!     fn reset(&mut self, nes: &Nes) {
+//!         self.last_score = nes.peek_achievement_memory(0x07DE);
+//!     }
+//!
+//!     fn reward(&mut self, nes: &Nes) -> f32 {
+//!         let score = nes.peek_achievement_memory(0x07DE);
+//!         let reward = score.wrapping_sub(self.last_score) as f32;
+//!         self.last_score = score;
+//!         reward
+//!     }
+//!
+//!     fn done(&mut self, nes: &Nes) -> bool {
+//!         nes.peek_achievement_memory(0x075A) == 0xFF
+//!     }
+//! }
+//!
+//! let mut environment = Environment::new(nes, Box::new(ScoreProbe { last_score: 0 }));
+//! environment.reset();
+//! loop {
+//!     let step = environment.step(0b1000_0001); // Right + A
+//!     if step.done {
+//!         break;
+//!     }
+//! }
+//! ```
+
+use io::Controller;
+use nes::{Event, Nes};
+
+/// Scores an agent's progress through a game by reading its memory, written for each game
+pub trait RewardProbe {
+    /// Called at the start of each episode, e.g. to remember the starting score
+    fn reset(&mut self, _nes: &Nes) {}
+
+    /// The reward for the frame just run, a step's reward is the total over its frames
+    fn reward(&mut self, nes: &Nes) -> f32;
+
+    /// Whether the episode is over, e.g. the player has run out of lives
+    fn done(&mut self, nes: &Nes) -> bool;
+}
+
+/// The result of `Environment::step`
+#[derive(Debug)]
+pub struct StepResult<'a> {
+    /// The last frame, as `Nes::get_framebuffer`
+    pub observation: &'a [u8],
+    pub reward: f32,
+    /// Set if the probe ended the episode or the CPU jammed, `reset` to start another
+    pub done: bool,
+}
+
+/// A console which restarts from the same state at the start of every episode, as emulation is
+/// deterministic the same actions always produce the same observations and rewards.
+pub struct Environment {
+    nes: Nes,
+    probe: Box<dyn RewardProbe>,
+    /// Saved when the environment is created, e.g. after getting past the title screen
+    start_state: Vec<u8>,
+    /// How many frames each action is held for
+    frame_skip: u32,
+}
+
+impl Environment {
+    /// Every episode starts from the console's state as it is now
+    pub fn new(mut nes: Nes, probe: Box<dyn RewardProbe>) -> Self {
+        Environment {
+            start_state: nes.save_state(),
+            nes,
+            probe,
+            frame_skip: 4,
+        }
+    }
+
+    /// Hold each action for this many frames (4 by default), agents rarely need to act on
+    /// every frame and it cuts down the number of steps in an episode
+    pub fn set_frame_skip(&mut self, frames: u32) {
+        self.frame_skip = frames.max(1);
+    }
+
+    /// Start a new episode, returning the first observation
+    pub fn reset(&mut self) -> &[u8] {
+        self.nes
+            .load_state(&self.start_state)
+            .expect("A state saved by this console always loads");
+        self.probe.reset(&self.nes);
+
+        self.nes.get_framebuffer()
+    }
+
+    /// Hold `action` on controller one for the frame skip and score the result, the action is
+    /// buttons as bitflags as `Nes::set_controller_state`. The step ends early if the episode does.
+    pub fn step(&mut self, action: u8) -> StepResult<'_> {
+        self.nes.set_controller_state(Controller::One, action);
+
+        let mut reward = 0.0;
+        let mut done = false;
+        for _ in 0..self.frame_skip {
+            let run = self.nes.run_until(Event::Frame);
+            reward += self.probe.reward(&self.nes);
+            done = run.jammed || self.probe.done(&self.nes);
+            if done {
+                break;
+            }
+        }
+
+        StepResult {
+            observation: self.nes.get_framebuffer(),
+            reward,
+            done,
+        }
+    }
+
+    pub fn nes(&self) -> &Nes {
+        &self.nes
+    }
+
+    pub fn nes_mut(&mut self) -> &mut Nes {
+        &mut self.nes
+    }
+}
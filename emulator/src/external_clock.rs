@@ -0,0 +1,139 @@
+//! Drives emulation from a timer owned by the host rather than by sleeping, for servers which run
+//! consoles without a window (cloud play, checking achievements) and tick them from their own loop.
+
+use clock::{NTSC_FRAME_RATE, PAL_FRAME_RATE};
+use nes::{CyclesRun, Event, Nes};
+use std::time::Duration;
+
+/// Converts the real time reported by the host on each tick into whole frames to emulate,
+/// carrying part frames over to the next tick so the console keeps pace with real time however
+/// often it's ticked.
+///
+/// If the host falls behind (e.g. the server was busy) then at most `max_frames_per_tick` frames
+/// are run on a tick and the rest are dropped rather than building up an ever growing backlog.
+///
+/// ```no_run
+/// # let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge("../roms/test/nestest.nes").unwrap());
+/// # let mut last_tick = std::time::Instant::now();
+/// let mut clock = rust_nes::ExternalClock::ntsc(4);
+/// loop {
+///     let now = std::time::Instant::now();
+///     let run = clock.tick(&mut nes, now - last_tick);
+///     last_tick = now;
+///     // Send run.samples and the framebuffer (if run.frames > 0) to the client
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalClock {
+    frame_rate: f64,
+    max_frames_per_tick: u32,
+    /// Real time which has passed but hasn't yet been emulated, in frames so that it's always
+    /// less than one between ticks
+    owed_frames: f64,
+    frames_run: u64,
+    frames_dropped: u64,
+}
+
+impl ExternalClock {
+    pub fn new(frame_rate: f64, max_frames_per_tick: u32) -> Self {
+        ExternalClock {
+            frame_rate,
+            max_frames_per_tick,
+            owed_frames: 0.0,
+            frames_run: 0,
+            frames_dropped: 0,
+        }
+    }
+
+    pub fn ntsc(max_frames_per_tick: u32) -> Self {
+        ExternalClock::new(NTSC_FRAME_RATE, max_frames_per_tick)
+    }
+
+    pub fn pal(max_frames_per_tick: u32) -> Self {
+        ExternalClock::new(PAL_FRAME_RATE, max_frames_per_tick)
+    }
+
+    /// Add the real time since the last tick and return the number of frames which should be
+    /// emulated to catch up with it, for hosts which run the frames themselves
+    pub fn frames_due(&mut self, elapsed: Duration) -> u32 {
+        self.owed_frames += elapsed.as_secs_f64() * self.frame_rate;
+        let due = self.owed_frames.floor();
+        let frames = due.min(self.max_frames_per_tick as f64) as u32;
+
+        self.frames_dropped += (due - frames as f64) as u64;
+        self.frames_run += frames as u64;
+        self.owed_frames -= due;
+
+        frames
+    }
+
+    /// Run the console for the frames due after `elapsed` real time. As with `Nes::run_until` this
+    /// stops early if a breakpoint is hit or the CPU jams with `JamPolicy::Halt`.
+    pub fn tick(&mut self, nes: &mut Nes, elapsed: Duration) -> CyclesRun {
+        let mut run = CyclesRun::default();
+        for _ in 0..self.frames_due(elapsed) {
+            run.append(nes.run_until(Event::Frame));
+            if run.breakpoint.is_some() || run.jammed {
+                break;
+            }
+        }
+
+        run
+    }
+
+    /// Forget any part frame owed, e.g. after the host stops ticking for a while on purpose
+    pub fn reset(&mut self) {
+        self.owed_frames = 0.0;
+    }
+
+    /// Emulated time according to this clock, the frames run so far at the console's frame rate.
+    /// Frames dropped to catch up aren't included, see `Nes::clock` for time since power on.
+    pub fn emulated_seconds(&self) -> f64 {
+        self.frames_run as f64 / self.frame_rate
+    }
+
+    pub fn frames_run(&self) -> u64 {
+        self.frames_run
+    }
+
+    /// Frames skipped because a tick was due more than `max_frames_per_tick`
+    pub fn frames_dropped(&self) -> u64 {
+        self.frames_dropped
+    }
+}
+
+#[cfg(test)]
+mod external_clock_tests {
+    use super::*;
+
+    /// A frame rate where frames are a whole number of microseconds to avoid rounding in tests
+    const FRAME_RATE: f64 = 64.0;
+    const FRAME_MICROS: u64 = 15_625;
+
+    fn frames(count: u64) -> Duration {
+        Duration::from_micros(FRAME_MICROS * count)
+    }
+
+    #[test]
+    fn test_part_frames_carry_over() {
+        let mut clock = ExternalClock::new(FRAME_RATE, 4);
+        assert_eq!(clock.frames_due(frames(3) / 2), 1);
+        assert_eq!(clock.frames_due(frames(1) / 4), 0);
+        assert_eq!(clock.frames_due(frames(1) / 4), 1);
+        assert_eq!(clock.frames_run(), 2);
+        assert_eq!(clock.emulated_seconds(), 2.0 / FRAME_RATE);
+    }
+
+    #[test]
+    fn test_catch_up_is_capped() {
+        let mut clock = ExternalClock::new(FRAME_RATE, 4);
+        assert_eq!(clock.frames_due(frames(21) / 2), 4);
+        assert_eq!(clock.frames_dropped(), 6);
+        // The half frame left over is still owed
+        assert_eq!(clock.frames_due(frames(1) / 2), 1);
+
+        clock.frames_due(frames(1) / 2);
+        clock.reset();
+        assert_eq!(clock.frames_due(frames(1) / 2), 0);
+    }
+}
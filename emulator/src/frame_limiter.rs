@@ -0,0 +1,117 @@
+//! Paces emulation to the console's real frame rate, for frontends and for headless realtime runs
+//! (e.g. netplay testing without a window).
+
+use clock::{NTSC_FRAME_RATE, PAL_FRAME_RATE};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Sleeping is only accurate to around a millisecond so the end of each wait is spun instead
+const SPIN_DURATION: Duration = Duration::from_millis(2);
+
+/// If a frame is this far behind schedule (e.g. after being paused or stopped at a breakpoint)
+/// then the schedule restarts from now rather than running frames back to back to catch up
+const MAX_FRAMES_BEHIND: u32 = 3;
+
+/// Schedules frames against absolute deadlines rather than sleeping a fixed amount after each one,
+/// so time spent emulating and any oversleep is corrected on the next frame instead of drifting.
+///
+/// ```no_run
+/// # let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge("../roms/test/nestest.nes").unwrap());
+/// let mut limiter = rust_nes::FrameLimiter::ntsc();
+/// loop {
+///     nes.run_until(rust_nes::Event::Frame);
+///     limiter.wait();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FrameLimiter {
+    frame_duration: Duration,
+    next_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new(frame_rate: f64) -> Self {
+        FrameLimiter {
+            frame_duration: Duration::from_secs_f64(1.0 / frame_rate),
+            next_frame: None,
+        }
+    }
+
+    /// 60.0988 frames per second
+    pub fn ntsc() -> Self {
+        FrameLimiter::new(NTSC_FRAME_RATE)
+    }
+
+    /// 50.0070 frames per second
+    pub fn pal() -> Self {
+        FrameLimiter::new(PAL_FRAME_RATE)
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        self.frame_duration
+    }
+
+    /// Start the schedule again from the next call to `wait`, e.g. after unpausing
+    pub fn reset(&mut self) {
+        self.next_frame = None;
+    }
+
+    /// Block until the current frame's time is up, returns how long was spent waiting
+    pub fn wait(&mut self) -> Duration {
+        let start = Instant::now();
+        let deadline = self.next_deadline(start);
+
+        if let Some(sleep) = deadline.checked_duration_since(start + SPIN_DURATION) {
+            thread::sleep(sleep);
+        }
+        while Instant::now() < deadline {
+            thread::yield_now();
+        }
+
+        Instant::now() - start
+    }
+
+    /// The time at which the current frame ends, advancing the schedule by a frame
+    fn next_deadline(&mut self, now: Instant) -> Instant {
+        let deadline = match self.next_frame {
+            Some(deadline) if now < deadline + self.frame_duration * MAX_FRAMES_BEHIND => deadline,
+            _ => now,
+        };
+        self.next_frame = Some(deadline + self.frame_duration);
+
+        deadline
+    }
+}
+
+#[cfg(test)]
+mod frame_limiter_tests {
+    use super::*;
+
+    #[test]
+    fn test_deadlines_absorb_variation_in_frame_time() {
+        let mut limiter = FrameLimiter::new(50.0);
+        let frame = Duration::from_millis(20);
+        let start = Instant::now();
+
+        assert_eq!(limiter.next_deadline(start), start);
+        // Finishing a frame late or early doesn't move later deadlines
+        assert_eq!(limiter.next_deadline(start + frame * 3 / 2), start + frame);
+        assert_eq!(limiter.next_deadline(start + frame), start + frame * 2);
+        assert_eq!(limiter.next_deadline(start + frame * 3), start + frame * 3);
+    }
+
+    #[test]
+    fn test_restarts_schedule_when_far_behind() {
+        let mut limiter = FrameLimiter::new(50.0);
+        let frame = Duration::from_millis(20);
+        let start = Instant::now();
+
+        limiter.next_deadline(start);
+        let resumed = start + frame * 10;
+        assert_eq!(limiter.next_deadline(resumed), resumed);
+        assert_eq!(limiter.next_deadline(resumed), resumed + frame);
+
+        limiter.reset();
+        assert_eq!(limiter.next_deadline(start), start);
+    }
+}
@@ -0,0 +1,82 @@
+//! The glue between the console and a frontend's window (or terminal, or image files), so that
+//! each frontend only has to implement how a frame is shown and where input comes from.
+
+use io::{Button, Controller};
+use ppu::{FrameInfo, PpuIteratorState, SCREEN_HEIGHT, SCREEN_WIDTH};
+use Nes;
+
+/// A completed frame to show, as the framebuffer is `SCREEN_WIDTH` x `SCREEN_HEIGHT` (256x240)
+/// unless an HD pack is loaded
+#[derive(Debug)]
+pub struct Frame<'a> {
+    /// BGRA, 4 bytes per pixel in rows of `width` pixels
+    pub pixels: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    pub info: Option<FrameInfo>,
+}
+
+/// Somewhere to show frames, see `run_frame`
+pub trait VideoSink {
+    fn present(&mut self, frame: &Frame);
+}
+
+/// A change to the controllers from the keyboard (or any other input device)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputEvent {
+    ButtonDown(Controller, Button),
+    ButtonUp(Controller, Button),
+    /// Stop running, e.g. the window was closed
+    Quit,
+}
+
+/// Somewhere to get input from between frames, see `apply_input`
+pub trait InputProvider {
+    /// The events since the last call, in the order they happened
+    fn poll(&mut self) -> Vec<InputEvent>;
+}
+
+/// Press and release buttons for each event from `input`, returns false if it asked to quit
+pub fn apply_input(nes: &mut Nes, input: &mut dyn InputProvider) -> bool {
+    for event in input.poll() {
+        match event {
+            InputEvent::ButtonDown(controller, button) => nes.button_down(controller, button),
+            InputEvent::ButtonUp(controller, button) => nes.button_up(controller, button),
+            InputEvent::Quit => return false,
+        }
+    }
+
+    true
+}
+
+/// Run the console to the end of the next frame and present it, adding the audio samples
+/// produced along the way to `samples`.
+///
+/// Unlike `Nes::run_until` the PPU keeps running if the CPU jams, so that a frontend still gets
+/// frames (and a chance to handle input) from a jammed console.
+pub fn run_frame(nes: &mut Nes, video: &mut dyn VideoSink, samples: &mut Vec<f32>) {
+    for (ppu_state, sample) in nes.by_ref() {
+        if let Some(sample) = sample {
+            samples.push(sample);
+        }
+        if let Some(PpuIteratorState::ReadyToRender) = ppu_state {
+            break;
+        }
+    }
+
+    let info = nes.frame_info();
+    match nes.get_hd_framebuffer() {
+        Some((pixels, scale)) => video.present(&Frame {
+            pixels,
+            width: SCREEN_WIDTH * scale,
+            height: SCREEN_HEIGHT * scale,
+            info,
+        }),
+        None => video.present(&Frame {
+            pixels: nes.get_framebuffer(),
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            info,
+        }),
+    }
+}
@@ -0,0 +1,151 @@
+//! Scripted controller input for driving a game headless, e.g. to get past a title screen in
+//! a test before checking a frame of gameplay.
+//!
+//! ```no_run
+//! # let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge("../roms/test/nestest.nes").unwrap());
+//! use rust_nes::io::Button;
+//! use rust_nes::InputScript;
+//!
+//! InputScript::new()
+//!     .wait(120)
+//!     .press(Button::Start)
+//!     .for_frames(2)
+//!     .wait(60)
+//!     .press(Button::Right)
+//!     .and(Button::B)
+//!     .for_frames(30)
+//!     .play(&mut nes);
+//! ```
+
+use io::{Button, Controller};
+use nes::{Event, Nes};
+
+/// Buttons held on both controllers for a number of frames
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Step {
+    controllers: [u8; 2],
+    frames: u32,
+}
+
+/// A sequence of steps, each holding some buttons for a number of frames. Consecutive steps
+/// which share a button keep it held, so leave a `wait` between them for the game to see it
+/// pressed twice.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+    steps: Vec<Step>,
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        InputScript { steps: vec![] }
+    }
+
+    /// Hold nothing for a number of frames
+    pub fn wait(mut self, frames: u32) -> Self {
+        self.steps.push(Step {
+            controllers: [0, 0],
+            frames,
+        });
+        self
+    }
+
+    /// Start a step which holds a button on controller one for a single frame, add more
+    /// buttons with `and` and hold them for longer with `for_frames`
+    pub fn press(self, button: Button) -> Self {
+        self.press_on(Controller::One, button)
+    }
+
+    /// As `press` for either controller
+    pub fn press_on(mut self, controller: Controller, button: Button) -> Self {
+        let mut controllers = [0, 0];
+        controllers[controller as usize] = button.bitflag();
+        self.steps.push(Step { controllers, frames: 1 });
+        self
+    }
+
+    /// Reads as `press(A).for_frames(2).then(Start)`
+    pub fn then(self, button: Button) -> Self {
+        self.press(button)
+    }
+
+    /// Hold another button on controller one during the last step
+    pub fn and(self, button: Button) -> Self {
+        self.and_on(Controller::One, button)
+    }
+
+    /// As `and` for either controller
+    pub fn and_on(mut self, controller: Controller, button: Button) -> Self {
+        if let Some(step) = self.steps.last_mut() {
+            step.controllers[controller as usize] |= button.bitflag();
+        }
+        self
+    }
+
+    /// Set how many frames the last step lasts for
+    pub fn for_frames(mut self, frames: u32) -> Self {
+        if let Some(step) = self.steps.last_mut() {
+            step.frames = frames;
+        }
+        self
+    }
+
+    /// The number of frames the script runs for
+    pub fn frames(&self) -> u32 {
+        self.steps.iter().map(|step| step.frames).sum()
+    }
+
+    /// Run the console for every step with its buttons held, then release everything
+    pub fn play(&self, nes: &mut Nes) {
+        for step in &self.steps {
+            nes.set_controller_state(Controller::One, step.controllers[0]);
+            nes.set_controller_state(Controller::Two, step.controllers[1]);
+            for _ in 0..step.frames {
+                nes.run_until(Event::Frame);
+            }
+        }
+
+        nes.set_controller_state(Controller::One, 0);
+        nes.set_controller_state(Controller::Two, 0);
+    }
+}
+
+#[cfg(test)]
+mod input_script_tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_built_from_presses() {
+        let script = InputScript::new()
+            .press(Button::A)
+            .for_frames(2)
+            .then(Button::Start)
+            .wait(10)
+            .press(Button::Right)
+            .and(Button::B)
+            .and_on(Controller::Two, Button::Select)
+            .for_frames(30);
+
+        assert_eq!(
+            script.steps,
+            vec![
+                Step {
+                    controllers: [0b0000_0001, 0],
+                    frames: 2
+                },
+                Step {
+                    controllers: [0b0000_1000, 0],
+                    frames: 1
+                },
+                Step {
+                    controllers: [0, 0],
+                    frames: 10
+                },
+                Step {
+                    controllers: [0b1000_0010, 0b0000_0100],
+                    frames: 30
+                },
+            ]
+        );
+        assert_eq!(script.frames(), 43);
+    }
+}
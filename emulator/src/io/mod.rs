@@ -1,14 +1,14 @@
 use log::debug;
 
 #[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Controller {
     One,
     Two,
 }
 
 #[repr(u8)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Button {
     A,
     B,
@@ -21,7 +21,7 @@ pub enum Button {
 }
 
 impl Button {
-    fn bitflag(&self) -> u8 {
+    pub(crate) fn bitflag(&self) -> u8 {
         match self {
             Button::A => 0b0000_0001,
             Button::B => 0b0000_0010,
@@ -33,39 +33,66 @@ impl Button {
             Button::Right => 0b1000_0000,
         }
     }
+}
 
-    fn read_bit(&self, state: u8) -> u8 {
-        match self {
-            Button::A => self.bitflag() & state,
-            Button::B => (self.bitflag() & state) >> 1,
-            Button::Select => (self.bitflag() & state) >> 2,
-            Button::Start => (self.bitflag() & state) >> 3,
-            Button::Up => (self.bitflag() & state) >> 4,
-            Button::Down => (self.bitflag() & state) >> 5,
-            Button::Left => (self.bitflag() & state) >> 6,
-            Button::Right => (self.bitflag() & state) >> 7,
+#[derive(Debug)]
+struct ControllerState {
+    all_data: u8,
+    /// The controller's shift register, reloaded from the buttons for as long as the strobe is
+    /// high so buttons changing after the strobe falls aren't seen until the next strobe
+    shift_register: u8,
+    /// The number of buttons shifted out since the strobe fell, up to 8
+    bits_read: u8,
+    /// The buttons shifted out to the game since the last strobe
+    read_data: u8,
+    /// The buttons from the last time the game shifted out all 8, for input displays
+    last_read: Option<u8>,
+}
+
+impl ControllerState {
+    fn new() -> Self {
+        ControllerState {
+            all_data: 0,
+            shift_register: 0,
+            bits_read: 0,
+            read_data: 0,
+            last_read: None,
         }
     }
 
-    fn next(&self) -> Option<Self> {
-        match self {
-            Button::A => Some(Button::B),
-            Button::B => Some(Button::Select),
-            Button::Select => Some(Button::Start),
-            Button::Start => Some(Button::Up),
-            Button::Up => Some(Button::Down),
-            Button::Down => Some(Button::Left),
-            Button::Left => Some(Button::Right),
-            Button::Right => None,
+    fn latch(&mut self) {
+        self.shift_register = self.all_data;
+        self.bits_read = 0;
+        self.read_data = 0;
+    }
+
+    fn read(&mut self, strobing: bool) -> u8 {
+        // While strobing the register is continuously reloaded so only ever shows A as it is now
+        if strobing {
+            return self.all_data & Button::A.bitflag();
+        }
+
+        // Standard controllers shift in 1s so every read after the 8th returns 1
+        let result = self.shift_register & 1;
+        self.shift_register = (self.shift_register >> 1) | 0x80;
+        if self.bits_read < 8 {
+            self.read_data |= result << self.bits_read;
+            self.bits_read += 1;
+            if self.bits_read == 8 {
+                self.last_read = Some(self.read_data);
+            }
         }
+
+        result
     }
 }
 
-#[derive(Debug)]
-struct ControllerState {
-    all_data: u8,
-    reading_button: Option<Button>,
-}
+// The buttons read so far are for input displays so aren't saved
+save_state_fields!(ControllerState {
+    all_data,
+    shift_register,
+    bits_read,
+});
 
 #[derive(Debug)]
 pub struct Io {
@@ -83,14 +110,8 @@ impl Default for Io {
 impl Io {
     pub fn new() -> Self {
         Io {
-            controller_1_state: ControllerState {
-                all_data: 0,
-                reading_button: Some(Button::A),
-            },
-            controller_2_state: ControllerState {
-                all_data: 0,
-                reading_button: Some(Button::A),
-            },
+            controller_1_state: ControllerState::new(),
+            controller_2_state: ControllerState::new(),
             strobe_register: false, // TODO - What is the starting state of the strobe register?
         }
     }
@@ -109,30 +130,39 @@ impl Io {
         }
     }
 
+    /// The buttons held on a controller as bitflags, A in the lowest bit through to Right in the highest
+    pub(crate) fn controller_state(&self, controller: Controller) -> u8 {
+        match controller {
+            Controller::One => self.controller_1_state.all_data,
+            Controller::Two => self.controller_2_state.all_data,
+        }
+    }
+
+    pub(crate) fn set_controller_state(&mut self, controller: Controller, buttons: u8) {
+        match controller {
+            Controller::One => self.controller_1_state.all_data = buttons,
+            Controller::Two => self.controller_2_state.all_data = buttons,
+        }
+    }
+
+    /// The buttons the game read from the controller the last time it shifted out all 8, as
+    /// opposed to the buttons currently held. None if it hasn't done so since the last call.
+    pub(crate) fn take_controller_read(&mut self, controller: Controller) -> Option<u8> {
+        match controller {
+            Controller::One => self.controller_1_state.last_read.take(),
+            Controller::Two => self.controller_2_state.last_read.take(),
+        }
+    }
+
     pub(crate) fn read_byte(&mut self, address: u16) -> u8 {
         debug!(
             "Reading from controller register {:04X}, strobing {:}",
             address, self.strobe_register
         );
 
-        fn read_controller_state(state: &mut ControllerState, strobing: bool) -> u8 {
-            0x40 | if strobing {
-                state.all_data & Button::A.bitflag()
-            } else {
-                match &state.reading_button {
-                    Some(nes_button) => {
-                        let result = nes_button.read_bit(state.all_data);
-                        state.reading_button = nes_button.next();
-                        result
-                    }
-                    None => 0b0000_0001,
-                }
-            }
-        }
-
         match address {
-            0x4016 => read_controller_state(&mut self.controller_1_state, self.strobe_register),
-            0x4017 => read_controller_state(&mut self.controller_2_state, self.strobe_register),
+            0x4016 => 0x40 | self.controller_1_state.read(self.strobe_register),
+            0x4017 => 0x40 | self.controller_2_state.read(self.strobe_register),
             _ => panic!("Invalid read from io registers {:04X}", address),
         }
     }
@@ -142,11 +172,111 @@ impl Io {
 
         match address {
             0x4016 => {
-                self.strobe_register = value & 1 == 1;
-                self.controller_1_state.reading_button = Some(Button::A);
-                self.controller_2_state.reading_button = Some(Button::A);
+                // The buttons are held in the shift registers once the strobe falls, writing 0
+                // again without raising it first doesn't reload them
+                let strobe = value & 1 == 1;
+                if self.strobe_register && !strobe {
+                    self.controller_1_state.latch();
+                    self.controller_2_state.latch();
+                }
+                self.strobe_register = strobe;
             }
             _ => panic!("Write to invalid IO register {:04X}={:02X}", address, value),
         }
     }
 }
+
+save_state_fields!(Io {
+    controller_1_state,
+    controller_2_state,
+    strobe_register,
+});
+
+#[cfg(test)]
+mod io_tests {
+    use super::*;
+
+    fn read_all(io: &mut Io) -> u8 {
+        io.write_byte(0x4016, 1);
+        io.write_byte(0x4016, 0);
+        (0..8).fold(0, |buttons, bit| buttons | (io.read_byte(0x4016) & 1) << bit)
+    }
+
+    #[test]
+    fn test_controller_read_echoes_buttons_shifted_out() {
+        let mut io = Io::new();
+        io.button_down(Controller::One, Button::Start);
+        io.button_down(Controller::One, Button::Left);
+        assert_eq!(io.take_controller_read(Controller::One), None);
+
+        assert_eq!(read_all(&mut io), 0b0100_1000);
+        io.button_up(Controller::One, Button::Left);
+        assert_eq!(io.take_controller_read(Controller::One), Some(0b0100_1000));
+        assert_eq!(io.take_controller_read(Controller::One), None);
+        assert_eq!(io.take_controller_read(Controller::Two), None);
+
+        // Buttons changing part way through a read aren't seen until the next strobe
+        io.write_byte(0x4016, 1);
+        io.write_byte(0x4016, 0);
+        for _ in 0..4 {
+            io.read_byte(0x4016);
+        }
+        io.button_down(Controller::One, Button::B);
+        io.button_down(Controller::One, Button::Right);
+        for _ in 0..4 {
+            io.read_byte(0x4016);
+        }
+        assert_eq!(io.take_controller_read(Controller::One), Some(0b0000_1000));
+
+        // A partial read followed by a new strobe isn't reported
+        io.write_byte(0x4016, 1);
+        io.write_byte(0x4016, 0);
+        io.read_byte(0x4016);
+        assert_eq!(read_all(&mut io), 0b1000_1010);
+        assert_eq!(io.take_controller_read(Controller::One), Some(0b1000_1010));
+    }
+
+    #[test]
+    fn test_strobe_high_reads_a_continuously() {
+        let mut io = Io::new();
+        io.write_byte(0x4016, 1);
+        assert_eq!(io.read_byte(0x4016), 0x40);
+        io.button_down(Controller::One, Button::A);
+        assert_eq!(io.read_byte(0x4016), 0x41);
+        assert_eq!(io.read_byte(0x4016), 0x41);
+        io.button_up(Controller::One, Button::A);
+        assert_eq!(io.read_byte(0x4016), 0x40);
+
+        // Buttons pressed while the strobe is high are latched when it falls
+        io.button_down(Controller::One, Button::B);
+        io.write_byte(0x4016, 0);
+        io.button_up(Controller::One, Button::B);
+        assert_eq!(io.read_byte(0x4016), 0x40);
+        assert_eq!(io.read_byte(0x4016), 0x41);
+    }
+
+    #[test]
+    fn test_strobe_falling_edge_latches() {
+        let mut io = Io::new();
+        io.button_down(Controller::One, Button::A);
+        io.button_down(Controller::Two, Button::Right);
+        io.write_byte(0x4016, 1);
+        io.write_byte(0x4016, 0);
+        io.button_up(Controller::One, Button::A);
+
+        // Writing 0 again isn't a falling edge so doesn't reload the shift register
+        io.write_byte(0x4016, 0);
+        assert_eq!(io.read_byte(0x4016), 0x41);
+        io.write_byte(0x4016, 0);
+        assert_eq!(io.read_byte(0x4016), 0x40);
+
+        let controller_2 = (0..8).map(|_| io.read_byte(0x4017) & 1).collect::<Vec<_>>();
+        assert_eq!(controller_2, vec![0, 0, 0, 0, 0, 0, 0, 1]);
+        // Then 1s once all the buttons have been shifted out
+        assert_eq!(io.read_byte(0x4017), 0x41);
+        for _ in 0..6 {
+            io.read_byte(0x4016);
+        }
+        assert_eq!(io.read_byte(0x4016), 0x41);
+    }
+}
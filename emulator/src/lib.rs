@@ -1,20 +1,60 @@
 #[macro_use]
 extern crate bitflags;
+extern crate crc32fast;
+#[cfg(feature = "gzip")]
+extern crate flate2;
 extern crate log;
 extern crate log4rs;
+extern crate png;
+#[cfg(test)]
+extern crate proptest;
+#[cfg(feature = "sevenz")]
+extern crate sevenz_rust;
 extern crate zip;
 
+#[macro_use]
+mod savestate;
+
+mod accuracy;
+pub mod achievements;
 pub mod apu;
+mod battery_save;
 pub mod cartridge;
+mod clock;
 pub mod cpu;
+mod environment;
+mod external_clock;
+mod frame_limiter;
+mod frontend;
+mod input_script;
 pub mod io;
+mod memory_region;
+mod nes;
+mod overclock;
 pub mod ppu;
+mod repro;
+mod resume_state;
+mod scheduler;
+
+pub use accuracy::AccuracyProfile;
+pub use battery_save::BatterySave;
+pub use clock::{Clock, NTSC_CPU_CLOCK_RATE, NTSC_FRAME_RATE, PAL_CPU_CLOCK_RATE, PAL_FRAME_RATE};
+pub use environment::{Environment, RewardProbe, StepResult};
+pub use external_clock::ExternalClock;
+pub use frame_limiter::FrameLimiter;
+pub use frontend::{apply_input, run_frame, Frame, InputEvent, InputProvider, VideoSink};
+pub use input_script::InputScript;
+pub use memory_region::{MemoryRegion, MemoryRegionError};
+pub use nes::{CyclesRun, Event, JamPolicy, Nes};
+pub use overclock::Overclock;
+pub use repro::{Repro, ReproInput};
+pub use resume_state::ResumeState;
+pub use savestate::SaveStateError;
 
-use apu::Apu;
-use cartridge::{CartridgeError, CartridgeHeader, CpuCartridgeAddressBus, PpuCartridgeAddressBus};
-use cpu::Cpu;
-use io::Io;
-use ppu::Ppu;
+use cartridge::{
+    CartridgeError, CartridgeHeader, CartridgeInfo, CartridgeOverrides, CpuCartridgeAddressBus, MirroringMode,
+    PpuCartridgeAddressBus,
+};
 use ppu::SCREEN_HEIGHT;
 use ppu::SCREEN_WIDTH;
 
@@ -26,19 +66,83 @@ pub type Cartridge = (
 
 /// Load a cartridge
 pub fn get_cartridge(rom_file: &str) -> Result<Cartridge, CartridgeError> {
-    cartridge::from_file(rom_file)
+    cartridge::from_file(rom_file, &CartridgeOverrides::default())
+}
+
+/// Load a cartridge, correcting the header with any overrides before the mapper is created
+pub fn get_cartridge_with_overrides(
+    rom_file: &str,
+    overrides: &CartridgeOverrides,
+) -> Result<Cartridge, CartridgeError> {
+    cartridge::from_file(rom_file, overrides)
+}
+
+/// Describe a rom from its header and contents without loading it, this succeeds for roms whose
+/// mapper isn't supported
+pub fn get_cartridge_info(rom_file: &str) -> Result<CartridgeInfo, CartridgeError> {
+    cartridge::info_from_file(rom_file)
+}
+
+/// Describe a rom as `get_cartridge_info` does along with the reason it can't be loaded (if any),
+/// reading and parsing the file once
+pub fn get_cartridge_info_and_load_error(
+    rom_file: &str,
+) -> Result<(CartridgeInfo, Option<CartridgeError>), CartridgeError> {
+    cartridge::info_and_load_error_from_file(rom_file)
+}
+
+/// List the roms contained in a zip archive, including those in nested directories
+pub fn list_archive_entries(archive_file: &str) -> Result<Vec<String>, CartridgeError> {
+    cartridge::list_archive_entries(archive_file)
+}
+
+/// Load the rom at `index` in the listing returned by `list_archive_entries`
+pub fn from_archive_entry(archive_file: &str, index: usize) -> Result<Cartridge, CartridgeError> {
+    cartridge::from_archive_entry(archive_file, index, &CartridgeOverrides::default())
+}
+
+/// Load the rom at `index` in an archive, correcting the header with any overrides
+pub fn from_archive_entry_with_overrides(
+    archive_file: &str,
+    index: usize,
+    overrides: &CartridgeOverrides,
+) -> Result<Cartridge, CartridgeError> {
+    cartridge::from_archive_entry(archive_file, index, overrides)
+}
+
+/// Build a cartridge from PRG ROM and (optionally) CHR ROM in memory rather than an iNES file,
+/// e.g. to run a short test program. PRG ROM is in 16KB units and CHR ROM in 8KB units, without
+/// CHR ROM the board gets CHR RAM.
+pub fn from_prg_chr(
+    prg_rom: Vec<u8>,
+    chr_rom: Option<Vec<u8>>,
+    mapper: u8,
+    mirroring: MirroringMode,
+) -> Result<Cartridge, CartridgeError> {
+    cartridge::from_prg_chr(prg_rom, chr_rom, mapper, mirroring)
 }
 
 /// Run a rom for N cycles and return the CRC32 checksum of the framebuffer
 pub fn run_headless_cycles(cartridge: Cartridge, cycles: usize) -> [u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize] {
-    let mut apu = Apu::new();
-    let mut io = Io::new();
-    let mut ppu = Ppu::new(cartridge.1);
-    let mut cpu = Cpu::new(cartridge.0, &mut apu, &mut io, &mut ppu);
+    run_headless_cycles_with_resets(cartridge, cycles, &[])
+}
+
+/// As `run_headless_cycles` but pressing reset at each of the given cycles, for test roms which
+/// check the console's state after a reset (e.g. blargg's apu_reset tests)
+pub fn run_headless_cycles_with_resets(
+    cartridge: Cartridge,
+    cycles: usize,
+    resets: &[usize],
+) -> [u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize] {
+    let mut nes = Nes::new(cartridge);
 
-    for _ in 0..cycles {
-        cpu.next();
+    for cycle in 0..cycles {
+        if resets.contains(&cycle) {
+            nes.reset();
+        }
+        nes.next();
     }
 
-    *cpu.get_framebuffer()
+    nes.resolve_framebuffer();
+    *nes.get_framebuffer()
 }
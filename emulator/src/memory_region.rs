@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The blocks of memory which can be dumped from and loaded back into a running console
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryRegion {
+    /// The 2KB of internal RAM at 0x0000-0x07FF
+    CpuRam,
+    /// The whole PPU address space, pattern tables, nametables and palettes, as the PPU sees it
+    Vram,
+    /// Sprite attribute memory
+    Oam,
+    /// The 32 palette entries at 0x3F00-0x3F1F
+    Palette,
+    /// The cartridge RAM at 0x6000-0x7FFF, empty if the cartridge doesn't have any. Boards which
+    /// save to their PRG flash instead (UNROM-512) return the flash.
+    PrgRam,
+}
+
+impl MemoryRegion {
+    pub const ALL: [MemoryRegion; 5] = [
+        MemoryRegion::CpuRam,
+        MemoryRegion::Vram,
+        MemoryRegion::Oam,
+        MemoryRegion::Palette,
+        MemoryRegion::PrgRam,
+    ];
+}
+
+impl Display for MemoryRegion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MemoryRegion::CpuRam => write!(f, "cpu_ram"),
+            MemoryRegion::Vram => write!(f, "vram"),
+            MemoryRegion::Oam => write!(f, "oam"),
+            MemoryRegion::Palette => write!(f, "palette"),
+            MemoryRegion::PrgRam => write!(f, "prg_ram"),
+        }
+    }
+}
+
+impl FromStr for MemoryRegion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cpu_ram" => Ok(MemoryRegion::CpuRam),
+            "vram" => Ok(MemoryRegion::Vram),
+            "oam" => Ok(MemoryRegion::Oam),
+            "palette" => Ok(MemoryRegion::Palette),
+            "prg_ram" => Ok(MemoryRegion::PrgRam),
+            _ => Err(format!(
+                "Unknown memory region {}, expected cpu_ram, vram, oam, palette or prg_ram",
+                s
+            )),
+        }
+    }
+}
+
+/// Returned when data can't be loaded into a memory region
+#[derive(Debug)]
+pub struct MemoryRegionError {
+    pub message: String,
+}
+impl Error for MemoryRegionError {}
+impl Display for MemoryRegionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
@@ -0,0 +1,626 @@
+use accuracy::AccuracyProfile;
+use achievements::FrameCallback;
+use apu::{Apu, AudioEnhancements, ChannelSamples};
+use clock::Clock;
+use cpu::{
+    Breakpoint, BreakpointHit, Condition, Cpu, CpuCycle, CpuRegisters, DiagnosticEvent, ExecutedInstruction,
+    InterruptVectors, StackEntry, SymbolTable,
+};
+use io::{Button, Controller, Io};
+use memory_region::{MemoryRegion, MemoryRegionError};
+use overclock::Overclock;
+use ppu::{
+    FrameInfo, HdPack, PaletteTint, Ppu, PpuBusAccess, PpuIteratorState, SpriteStats, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use savestate::{SaveState, SaveStateError, StateReader, StateWriter};
+use std::sync::mpsc::{sync_channel, Receiver};
+use Cartridge;
+
+/// Conditions on which `Nes::run_until` returns control to the host
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// The CPU has started handling an NMI
+    Nmi,
+    /// The PPU has completed a frame and the framebuffer is ready to render
+    Frame,
+    /// The CPU is about to execute the instruction at this address
+    ProgramCounter(u16),
+    /// Any breakpoint added with `Nes::add_breakpoint` has been hit
+    Breakpoint,
+}
+
+/// What to do when the CPU executes one of the KIL opcodes, which lock it up until the console
+/// is reset. Either way a `DiagnosticKind::Jammed` event is raised if diagnostics are enabled.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JamPolicy {
+    /// Stop emulating, `run_for_cpu_cycles` and `run_until` return immediately with `jammed` set
+    Halt,
+    /// Keep running the PPU and APU and reset the console after this many frames, as a player
+    /// would press the reset button
+    ResetAfterFrames(u32),
+}
+
+/// Summary of what happened during a call to `run_for_cpu_cycles` or `run_until`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CyclesRun {
+    pub cpu_cycles: CpuCycle,
+    /// The number of frames completed, if non-zero the framebuffer contains the most recent
+    pub frames: u32,
+    /// Describes the most recent frame completed during the run
+    pub frame_info: Option<FrameInfo>,
+    /// APU samples generated, one per CPU cycle
+    pub samples: Vec<f32>,
+    /// The samples broken down by channel, only recorded once `Nes::record_channel_samples` is set
+    pub channel_samples: Vec<ChannelSamples>,
+    /// Set if the run stopped early because a breakpoint was hit
+    pub breakpoint: Option<BreakpointHit>,
+    /// Set if the CPU is jammed at the end of the run, see `JamPolicy`
+    pub jammed: bool,
+}
+
+impl CyclesRun {
+    /// Add a following run onto this one, as if they had been a single run
+    pub(crate) fn append(&mut self, run: CyclesRun) {
+        self.cpu_cycles += run.cpu_cycles;
+        self.frames += run.frames;
+        self.frame_info = run.frame_info.or(self.frame_info);
+        self.samples.extend(run.samples);
+        self.channel_samples.extend(run.channel_samples);
+        self.breakpoint = self.breakpoint.or(run.breakpoint);
+        self.jammed = run.jammed;
+    }
+}
+
+/// The console itself, this owns the CPU (which in turn owns the other components)
+/// and is the entry point for any application embedding the emulator.
+pub struct Nes {
+    cpu: Cpu,
+    record_channel_samples: bool,
+    jam_policy: JamPolicy,
+    /// Frames completed since the CPU jammed, for `JamPolicy::ResetAfterFrames`
+    jammed_frames: u32,
+    frame_callback: Option<FrameCallback>,
+    /// Recorded in savestates so that they're only loaded into the implementation which saved them
+    mapper: (&'static str, u16),
+}
+
+impl Nes {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Nes::with_accuracy(cartridge, AccuracyProfile::Balanced)
+    }
+
+    /// Create a console which trades emulation fidelity for speed according to the given profile
+    pub fn with_accuracy(cartridge: Cartridge, accuracy: AccuracyProfile) -> Self {
+        Nes::with_options(cartridge, accuracy, AudioEnhancements::default())
+    }
+
+    /// Create a console with an accuracy profile and non hardware accurate audio improvements
+    pub fn with_options(cartridge: Cartridge, accuracy: AccuracyProfile, audio: AudioEnhancements) -> Self {
+        let (prg_address_bus, chr_address_bus, header) = cartridge;
+
+        Nes {
+            cpu: Cpu::new(
+                prg_address_bus,
+                Apu::new(accuracy, audio),
+                Io::new(),
+                Ppu::new(chr_address_bus, accuracy),
+                accuracy,
+            ),
+            record_channel_samples: false,
+            jam_policy: JamPolicy::Halt,
+            jammed_frames: 0,
+            frame_callback: None,
+            mapper: header.mapper_info.map_or(("", 0), |info| (info.id, info.version)),
+        }
+    }
+
+    /// Returns a receiver which is sent every instruction executed from this point onwards.
+    ///
+    /// The channel holds at most `capacity` instructions, if the consumer falls behind then
+    /// further instructions are dropped rather than blocking emulation. Only a single stream
+    /// is supported, calling this again replaces the previous one.
+    pub fn instruction_stream(&mut self, capacity: usize) -> Receiver<ExecutedInstruction> {
+        let (sender, receiver) = sync_channel(capacity);
+        self.cpu.set_instruction_sender(sender);
+
+        receiver
+    }
+
+    /// Watch for the program counter leaving cartridge ROM or the stack pointer wrapping,
+    /// both of which usually mean that the running program has crashed.
+    ///
+    /// Each event records the last `history_length` instructions executed before it.
+    pub fn enable_diagnostics(&mut self, history_length: usize) {
+        self.cpu.enable_diagnostics(history_length);
+    }
+
+    /// Also raise a diagnostic event for each PPUDATA write to nametable RAM while the PPU is
+    /// rendering, with the PC, scanline and dot of the write. Games shouldn't do this so these
+    /// usually point at either a game bug or incorrect timing in the emulator.
+    ///
+    /// Has no effect unless diagnostics have been enabled first.
+    pub fn set_nametable_write_checks(&mut self, enabled: bool) {
+        self.cpu.set_nametable_write_checks(enabled);
+    }
+
+    /// Set the DIP switches on boards which have them, e.g. the 4 switches on the Nintendo
+    /// World Championships cartridge (mapper 105) which set the length of the competition timer.
+    /// Boards without DIP switches ignore this.
+    pub fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.cpu.set_dip_switches(dip_switches);
+    }
+
+    /// Choose what happens when the CPU jams, by default emulation halts
+    pub fn set_jam_policy(&mut self, policy: JamPolicy) {
+        self.jam_policy = policy;
+    }
+
+    /// Whether the CPU has executed a KIL opcode and is locked up until reset, so that harnesses
+    /// can tell a jammed rom apart from one which is waiting in a loop
+    pub fn is_jammed(&self) -> bool {
+        self.cpu.is_jammed()
+    }
+
+    /// Press the reset button, the game restarts from its reset vector with RAM intact
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.jammed_frames = 0;
+    }
+
+    /// Returns any diagnostic events raised since the last call
+    pub fn take_diagnostic_events(&mut self) -> Vec<DiagnosticEvent> {
+        self.cpu.take_diagnostic_events()
+    }
+
+    /// Stop `run_for_cpu_cycles` and `run_until` when the breakpoint is hit
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.cpu.set_breakpoint(breakpoint, true);
+    }
+
+    /// As `add_breakpoint` but only stops when the condition is met, e.g.
+    /// `"A == 0x3F && scanline > 200".parse::<Condition>()`
+    pub fn add_conditional_breakpoint(&mut self, breakpoint: Breakpoint, condition: Condition) {
+        self.cpu.set_breakpoint(breakpoint, true);
+        self.cpu.set_breakpoint_condition(breakpoint, condition);
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.cpu.set_breakpoint(breakpoint, false);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.cpu.clear_breakpoints();
+    }
+
+    /// Returns the first breakpoint hit since the last call, for hosts stepping
+    /// the console manually rather than through `run_until`
+    pub fn take_breakpoint_hit(&mut self) -> Option<BreakpointHit> {
+        self.cpu.take_breakpoint_hit()
+    }
+
+    /// Use labels from a symbol file in the trace log and executed instructions
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.cpu.set_symbols(symbols);
+    }
+
+    /// The label for an address given the banks currently mapped in, if symbols are loaded
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.cpu.label(address)
+    }
+
+    /// Render using replacement textures from an HD pack alongside the native framebuffer
+    pub fn set_hd_pack(&mut self, pack: HdPack) {
+        self.cpu.set_hd_pack(pack);
+    }
+
+    /// Replace the colours used for the 64 palette entries, colours are 0xRRGGBB. Use
+    /// `PaletteSettings::generate` to produce a palette from adjustable signal decoding.
+    pub fn set_palette(&mut self, palette: [u32; 0x40]) {
+        self.cpu.set_palette(palette);
+    }
+
+    /// Tint each pixel by the palette it was drawn with, see `PaletteTint`. None turns it off.
+    pub fn set_palette_tint(&mut self, tint: Option<PaletteTint>) {
+        self.cpu.set_palette_tint(tint);
+    }
+
+    /// Disable the limit of 8 sprites per scanline to remove flicker, this isn't hardware
+    /// accurate but the sprite overflow flag is unaffected
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.cpu.set_sprite_limit(enabled);
+    }
+
+    /// Run the CPU for extra cycles at the end of each vblank, see `Overclock`. Emulated time
+    /// from `clock` includes the extra cycles so runs ahead of the frames produced.
+    pub fn set_overclock(&mut self, overclock: Overclock) {
+        self.cpu.set_overclock(overclock);
+    }
+
+    /// Record every PPU address bus access (dot, scanline, address, read/write) made during
+    /// the given frame, replacing any previous recording. See `frame_number` for the current frame.
+    pub fn record_ppu_bus_activity(&mut self, frame: u32) {
+        self.cpu.record_ppu_bus_activity(frame);
+    }
+
+    /// The PPU address bus accesses from the recorded frame, None until that frame has completed
+    pub fn ppu_bus_activity(&self) -> Option<&[PpuBusAccess]> {
+        self.cpu.ppu_bus_activity()
+    }
+
+    /// Collect the sprites in range and overflow flag for each scanline, and the position of
+    /// sprite zero hit, for every frame from now on
+    pub fn record_sprite_stats(&mut self, enabled: bool) {
+        self.cpu.record_sprite_stats(enabled);
+    }
+
+    /// The sprite statistics from the last complete frame, None if not recording or no frame
+    /// has completed since recording started
+    pub fn sprite_stats(&self) -> Option<&SpriteStats> {
+        self.cpu.sprite_stats()
+    }
+
+    /// Describes the last complete frame (the one in the framebuffer), None before the first
+    pub fn frame_info(&self) -> Option<FrameInfo> {
+        self.cpu.frame_info()
+    }
+
+    /// The number of the frame the PPU is currently rendering
+    pub fn frame_number(&self) -> u32 {
+        self.cpu.frame_number()
+    }
+
+    pub fn button_down(&mut self, controller: Controller, button: Button) {
+        self.cpu.button_down(controller, button);
+    }
+
+    pub fn button_up(&mut self, controller: Controller, button: Button) {
+        self.cpu.button_up(controller, button);
+    }
+
+    /// The buttons held on a controller as bitflags, A in the lowest bit then B, Select, Start,
+    /// Up, Down, Left and Right
+    pub fn controller_state(&self, controller: Controller) -> u8 {
+        self.cpu.controller_state(controller)
+    }
+
+    /// Set every button on a controller at once from bitflags as returned by `controller_state`
+    pub fn set_controller_state(&mut self, controller: Controller, buttons: u8) {
+        self.cpu.set_controller_state(controller, buttons);
+    }
+
+    /// The buttons the game actually read from a controller through $4016/$4017, as bitflags like
+    /// `controller_state`, from the last time it shifted out all 8. Calling this once per frame
+    /// gives an input display of what the game saw rather than what was sent to it, None means
+    /// the game didn't read the controller since the last call.
+    pub fn take_controller_read(&mut self, controller: Controller) -> Option<u8> {
+        self.cpu.take_controller_read(controller)
+    }
+
+    /// The total number of CPU cycles executed since power on
+    pub fn cycles(&self) -> CpuCycle {
+        self.cpu.cycles
+    }
+
+    /// Emulated time since power on as CPU cycles, PPU dots, APU cycles and frames, along with
+    /// conversions to seconds
+    pub fn clock(&self) -> Clock {
+        self.cpu.elapsed_clock()
+    }
+
+    pub fn registers(&self) -> CpuRegisters {
+        self.cpu.registers()
+    }
+
+    /// Jump to an address, only meaningful at an instruction boundary such as before the console
+    /// has run, e.g. to start nestest in its automated mode at $C000
+    pub fn set_program_counter(&mut self, pc: u16) {
+        self.cpu.set_program_counter(pc);
+    }
+
+    /// The NMI, RESET and IRQ handlers as read through the mapper's current banks, useful when a
+    /// game crashes after a bad bank switch leaves garbage where its vectors should be
+    pub fn interrupt_vectors(&self) -> InterruptVectors {
+        self.cpu.interrupt_vectors()
+    }
+
+    /// The bytes in use on the stack page with any JSR return addresses decoded
+    pub fn stack(&self) -> Vec<StackEntry> {
+        self.cpu.stack()
+    }
+
+    /// The last completed frame as BGRA, converted from the index buffer with the current palette
+    /// as the frame completed
+    pub fn get_framebuffer(&self) -> &[u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize] {
+        self.cpu.get_framebuffer()
+    }
+
+    /// Convert the frame drawn so far into the framebuffer, for callers which stop part way through
+    /// a frame and want to see it. Otherwise this happens once as each frame completes.
+    pub fn resolve_framebuffer(&mut self) {
+        self.cpu.resolve_framebuffer();
+    }
+
+    /// The frame so far as the colours the PPU output, cheaper to hash than the framebuffer and the
+    /// input to filters which need them. Each pixel is laid out as:
+    ///
+    /// - bits 0-5, the palette index
+    /// - bits 6-8, the PPUMASK emphasis bits (red, green, blue)
+    /// - bits 9-13, the palette RAM address (0-0x1F) the index was read from
+    ///
+    /// or `ppu::BLANK_PIXEL` for pixels which haven't been drawn this frame. Mask with 0x3F to
+    /// compare palette indices alone.
+    pub fn get_index_buffer(&self) -> &[u16; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize] {
+        self.cpu.get_index_buffer()
+    }
+
+    pub fn get_hd_framebuffer(&self) -> Option<(&[u8], u32)> {
+        self.cpu.get_hd_framebuffer()
+    }
+
+    /// Include each channel's output in `CyclesRun::channel_samples` alongside the mixed samples
+    pub fn record_channel_samples(&mut self, enabled: bool) {
+        self.record_channel_samples = enabled;
+    }
+
+    /// Copy the contents of a memory region, e.g. to write it to a file
+    pub fn dump_memory(&self, region: MemoryRegion) -> Vec<u8> {
+        self.cpu.dump_memory(region)
+    }
+
+    /// Read a byte of the flat address space used by achievement runtimes, see `achievements` for
+    /// the address map. This has no side effects so can be called at any time.
+    pub fn peek_achievement_memory(&self, address: u32) -> u8 {
+        if address > 0xFFFF {
+            0
+        } else {
+            self.cpu.peek_byte(address as u16)
+        }
+    }
+
+    /// As `peek_achievement_memory` for consecutive addresses starting at `address`, e.g. to
+    /// copy a whole region in one call
+    pub fn read_achievement_memory(&self, address: u32, buffer: &mut [u8]) {
+        for (offset, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.peek_achievement_memory(address.saturating_add(offset as u32));
+        }
+    }
+
+    /// Call `callback` each time the PPU completes a frame, for integrations which check memory
+    /// once per frame such as achievements. Replaces any previous callback, None removes it.
+    pub fn set_frame_callback(&mut self, callback: Option<FrameCallback>) {
+        self.frame_callback = callback;
+    }
+
+    /// Replace the contents of a memory region, the data must be the same length as
+    /// `dump_memory` returns for that region
+    pub fn load_memory(&mut self, region: MemoryRegion, data: &[u8]) -> Result<(), MemoryRegionError> {
+        let expected_length = self.cpu.dump_memory(region).len();
+        if data.len() != expected_length {
+            return Err(MemoryRegionError {
+                message: format!(
+                    "Memory region {} is {} bytes but {} bytes were provided",
+                    region,
+                    expected_length,
+                    data.len()
+                ),
+            });
+        }
+
+        self.cpu.load_memory(region, data);
+        Ok(())
+    }
+
+    /// Whether the game has changed PRG RAM since the last call, see `BatterySave` which uses this
+    /// to write battery backed RAM out shortly after the game stops writing to it
+    pub fn take_prg_ram_written(&mut self) -> bool {
+        self.cpu.take_prg_ram_written()
+    }
+
+    /// Save the state of the whole console so that it can be restored with `load_state`.
+    ///
+    /// States are only saved between instructions so the console first runs to the end of
+    /// the current instruction (a handful of cycles), any audio produced meanwhile is dropped.
+    pub fn save_state(&mut self) -> Vec<u8> {
+        while !self.cpu.at_instruction_boundary() && !self.cpu.is_jammed() {
+            self.cpu.next();
+        }
+
+        let (mapper_id, mapper_version) = self.mapper;
+        let mut writer = StateWriter::new();
+        mapper_id.as_bytes().to_vec().save_state(&mut writer);
+        mapper_version.save_state(&mut writer);
+        self.cpu.save_state(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Restore a state from `save_state`, the console must be running the same rom. If the state
+    /// can't be loaded the console carries on from where it was, run to the end of the current
+    /// instruction as for `save_state`.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        // Each component is restored in turn, so one failing part way through (e.g. a truncated
+        // state) has to put back the ones restored before it
+        let previous = self.save_state();
+        match self.restore_state(state) {
+            Ok(()) => Ok(()),
+            Err(why) => {
+                self.restore_state(&previous)
+                    .expect("A state saved by this console must load into it");
+                Err(why)
+            }
+        }
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(state)?;
+        let (mut mapper_id, mut mapper_version) = (Vec::<u8>::new(), 0u16);
+        mapper_id.load_state(&mut reader)?;
+        mapper_version.load_state(&mut reader)?;
+        let (expected_id, expected_version) = self.mapper;
+        if mapper_id != expected_id.as_bytes() || mapper_version != expected_version {
+            return Err(SaveStateError {
+                message: format!(
+                    "Savestate was made by mapper implementation {} version {} but this rom is running {} version {}",
+                    String::from_utf8_lossy(&mapper_id),
+                    mapper_version,
+                    expected_id,
+                    expected_version
+                ),
+            });
+        }
+        self.cpu.load_state(&mut reader)?;
+        reader.finish()?;
+        self.cpu.resolve_framebuffer();
+        Ok(())
+    }
+
+    /// Run the console for (at least) the given number of CPU cycles, intended for hosts
+    /// which want to schedule emulation alongside their own work. Stops early if a
+    /// breakpoint is hit or the CPU jams with `JamPolicy::Halt`.
+    pub fn run_for_cpu_cycles(&mut self, cycles: CpuCycle) -> CyclesRun {
+        let mut run = CyclesRun::default();
+        while run.cpu_cycles < cycles && run.breakpoint.is_none() && !self.halted() {
+            self.step(&mut run);
+        }
+        run.jammed = self.cpu.is_jammed();
+
+        run
+    }
+
+    /// Run the console until the given event occurs. Note that this will never return
+    /// if the event never happens (e.g. a program counter which is never reached), unless the CPU
+    /// jams with `JamPolicy::Halt`.
+    pub fn run_until(&mut self, event: Event) -> CyclesRun {
+        let mut run = CyclesRun::default();
+        loop {
+            if self.halted() {
+                run.jammed = true;
+                return run;
+            }
+
+            let (ppu_state, cpu_clocked) = self.step(&mut run);
+
+            let occurred = match event {
+                Event::Nmi => cpu_clocked && self.cpu.is_starting_nmi(),
+                Event::Frame => matches!(ppu_state, Some(PpuIteratorState::ReadyToRender)),
+                Event::ProgramCounter(address) => {
+                    cpu_clocked && self.cpu.at_instruction_boundary() && self.registers().program_counter == address
+                }
+                Event::Breakpoint => run.breakpoint.is_some(),
+            };
+
+            if occurred {
+                run.jammed = self.cpu.is_jammed();
+                return run;
+            }
+        }
+    }
+
+    /// Run the current instruction (or the interrupt about to be handled) and stop before the
+    /// next one, or early if a breakpoint is hit or the CPU jams with `JamPolicy::Halt`.
+    pub fn step_instruction(&mut self) -> CyclesRun {
+        self.run_to_instruction(|_| true)
+    }
+
+    /// As `step_instruction` but a JSR runs until the subroutine returns, and any interrupts
+    /// taken meanwhile run until their handlers return.
+    ///
+    /// Calls are tracked by counting JSR, RTS, RTI and interrupts so this is thrown off by code
+    /// which returns by other means, e.g. pushing an address and using RTS as a jump.
+    pub fn step_over(&mut self) -> CyclesRun {
+        let depth = self.cpu.call_depth();
+        self.run_to_instruction(|call_depth| call_depth <= depth)
+    }
+
+    /// Run until the current subroutine or interrupt handler returns with RTS or RTI, stopping
+    /// before the instruction it returns to. Subject to the same caveats as `step_over`.
+    pub fn step_out(&mut self) -> CyclesRun {
+        let depth = self.cpu.call_depth();
+        self.run_to_instruction(|call_depth| call_depth < depth)
+    }
+
+    /// Run until an instruction is about to be fetched with a call depth for which `stop` is true
+    fn run_to_instruction<F: Fn(i64) -> bool>(&mut self, stop: F) -> CyclesRun {
+        let mut run = CyclesRun::default();
+        loop {
+            if self.halted() {
+                run.jammed = true;
+                return run;
+            }
+
+            let (_, cpu_clocked) = self.step(&mut run);
+
+            let at_stop = cpu_clocked && self.cpu.at_instruction_boundary() && stop(self.cpu.call_depth());
+            if at_stop || run.breakpoint.is_some() {
+                run.jammed = self.cpu.is_jammed();
+                return run;
+            }
+        }
+    }
+
+    fn halted(&self) -> bool {
+        self.jam_policy == JamPolicy::Halt && self.cpu.is_jammed()
+    }
+
+    /// Count frames while jammed and reset once the policy says to
+    fn apply_jam_policy(&mut self, ppu_state: &Option<PpuIteratorState>) {
+        let frame_completed = matches!(ppu_state, Some(PpuIteratorState::ReadyToRender));
+        if let JamPolicy::ResetAfterFrames(frames) = self.jam_policy {
+            if frame_completed && self.cpu.is_jammed() {
+                self.jammed_frames += 1;
+                if self.jammed_frames >= frames {
+                    self.reset();
+                }
+            }
+        }
+    }
+
+    fn call_frame_callback(&mut self, ppu_state: &Option<PpuIteratorState>) {
+        if matches!(ppu_state, Some(PpuIteratorState::ReadyToRender)) {
+            // Taken out for the call as the callback can't borrow the console while it's borrowed
+            if let Some(mut callback) = self.frame_callback.take() {
+                callback(self);
+                self.frame_callback = Some(callback);
+            }
+        }
+    }
+
+    /// Step a single PPU cycle, accumulating into the run summary and returning the PPU
+    /// state along with whether the CPU was clocked on this cycle
+    fn step(&mut self, run: &mut CyclesRun) -> (Option<PpuIteratorState>, bool) {
+        let cycles_before = self.cpu.cycles;
+        let (ppu_state, sample) = self.cpu.next().unwrap();
+        let cpu_cycles = self.cpu.cycles - cycles_before;
+        self.apply_jam_policy(&ppu_state);
+        self.call_frame_callback(&ppu_state);
+
+        run.cpu_cycles += cpu_cycles;
+        if let Some(sample) = sample {
+            run.samples.push(sample);
+            if self.record_channel_samples {
+                run.channel_samples.push(self.cpu.apu_channel_samples());
+            }
+        }
+        if matches!(ppu_state, Some(PpuIteratorState::ReadyToRender)) {
+            run.frames += 1;
+            run.frame_info = self.cpu.frame_info();
+        }
+        if cpu_cycles > 0 && run.breakpoint.is_none() {
+            run.breakpoint = self.cpu.take_breakpoint_hit();
+        }
+
+        (ppu_state, cpu_cycles > 0)
+    }
+}
+
+impl Iterator for Nes {
+    type Item = (Option<PpuIteratorState>, Option<f32>);
+
+    /// Step the console by a single PPU cycle
+    fn next(&mut self) -> Option<Self::Item> {
+        let (ppu_state, sample) = self.cpu.next().unwrap();
+        self.apply_jam_policy(&ppu_state);
+        self.call_frame_callback(&ppu_state);
+
+        Some((ppu_state, sample))
+    }
+}
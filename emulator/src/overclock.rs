@@ -0,0 +1,20 @@
+/// Gives the CPU more time each frame by holding the PPU at the end of vblank while the CPU runs
+/// on, as if the frame had extra vblank scanlines. Reduces slowdown in games which drop frames
+/// when there's a lot on screen but games which count cycles (or rely on the APU frame IRQ to
+/// time their frame) can misbehave, so it's off by default. See `Nes::set_overclock`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Overclock {
+    /// Scanlines' worth of CPU cycles (341 PPU dots each) to add to the end of each vblank
+    pub extra_scanlines: u16,
+    /// Also clock the APU during the extra cycles, as happens on an overclocked console. This
+    /// produces more samples per frame so music plays faster and higher pitched, by default the
+    /// APU runs at the stock rate so audio sounds the same as without overclocking.
+    pub clock_apu: bool,
+}
+
+impl Overclock {
+    /// PPU dots for which the PPU is held each frame
+    pub(crate) fn extra_dots(&self) -> u32 {
+        self.extra_scanlines as u32 * 341
+    }
+}
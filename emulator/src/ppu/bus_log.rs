@@ -0,0 +1,46 @@
+/// A single read or write on the PPU address bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PpuBusAccess {
+    pub dot: u16,
+    pub scanline: u16,
+    pub address: u16,
+    pub is_read: bool,
+}
+
+/// Records every access on the PPU address bus for a single frame, intended to validate
+/// mapper implementations which watch the bus (e.g. MMC2 latches, MMC3 scanline IRQs)
+/// against known fetch patterns.
+pub(super) struct BusRecorder {
+    frame: u32,
+    accesses: Vec<PpuBusAccess>,
+}
+
+impl BusRecorder {
+    pub(super) fn new(frame: u32) -> Self {
+        BusRecorder {
+            frame,
+            // Roughly the number of fetches in a frame with rendering enabled
+            accesses: Vec::with_capacity(45_000),
+        }
+    }
+
+    pub(super) fn record(&mut self, frame: u32, dot: u16, scanline: u16, address: u16, is_read: bool) {
+        if frame == self.frame {
+            self.accesses.push(PpuBusAccess {
+                dot,
+                scanline,
+                address,
+                is_read,
+            });
+        }
+    }
+
+    /// The recorded accesses, only available once the selected frame has finished
+    pub(super) fn accesses(&self, current_frame: u32) -> Option<&[PpuBusAccess]> {
+        if current_frame > self.frame {
+            Some(&self.accesses)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,72 @@
+/// Blends each frame with those before it, imitating the phosphor persistence of a CRT. Games
+/// which flicker sprites on alternate frames to get around the 8 sprites per scanline limit show
+/// them as steady, semi transparent sprites instead of strobing.
+///
+/// This is a post process on the framebuffer (or an HD framebuffer), it doesn't change emulation.
+#[derive(Debug, Clone)]
+pub struct FrameBlender {
+    /// Weight of the previous output in each new output out of 256
+    persistence: u16,
+    previous: Vec<u8>,
+}
+
+impl FrameBlender {
+    /// `persistence` is how much of the previous output remains in each frame from 0 (none, so
+    /// no blending) to 1, 0.5 averages each frame with the one before
+    pub fn new(persistence: f32) -> Self {
+        FrameBlender {
+            persistence: ((persistence * 256.0) as u16).min(256),
+            previous: vec![],
+        }
+    }
+
+    /// Blend a frame with the previous output, returning the frame to display. The first frame,
+    /// and any frame of a different size to the previous one, is returned unblended.
+    pub fn blend(&mut self, frame: &[u8]) -> &[u8] {
+        if self.previous.len() != frame.len() {
+            self.previous = frame.to_vec();
+        } else {
+            let persistence = self.persistence;
+            for (previous, &current) in self.previous.iter_mut().zip(frame.iter()) {
+                *previous = ((current as u16 * (256 - persistence) + *previous as u16 * persistence) >> 8) as u8;
+            }
+        }
+
+        &self.previous
+    }
+
+    /// Forget the previous frame, e.g. after loading a state so that it isn't blended with
+    /// an unrelated frame
+    pub fn reset(&mut self) {
+        self.previous.clear();
+    }
+}
+
+#[cfg(test)]
+mod frame_blend_tests {
+    use super::*;
+
+    #[test]
+    fn test_blends_with_previous_output() {
+        let mut blender = FrameBlender::new(0.5);
+        assert_eq!(blender.blend(&[200, 0]), &[200, 0]);
+        assert_eq!(blender.blend(&[0, 200]), &[100, 100]);
+        assert_eq!(blender.blend(&[0, 200]), &[50, 150]);
+
+        // A frame of a different size (e.g. an HD pack loaded) starts again
+        assert_eq!(blender.blend(&[10]), &[10]);
+        blender.reset();
+        assert_eq!(blender.blend(&[20]), &[20]);
+    }
+
+    #[test]
+    fn test_persistence_limits() {
+        let mut blender = FrameBlender::new(0.0);
+        blender.blend(&[255, 255]);
+        assert_eq!(blender.blend(&[1, 2]), &[1, 2]);
+
+        let mut blender = FrameBlender::new(1.0);
+        blender.blend(&[255, 255]);
+        assert_eq!(blender.blend(&[1, 2]), &[255, 255]);
+    }
+}
@@ -0,0 +1,21 @@
+use ppu::PpuCycle;
+
+/// A summary of how a frame was produced, for frontends and tests which want to know about a
+/// frame without looking at PPU internals, see `Nes::frame_info`.
+///
+/// A frame runs from the end of the previous frame's visible scanlines, so it includes the
+/// vblank (and NMI) in which the game prepared it.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct FrameInfo {
+    /// As `Nes::frame_number`
+    pub frame_number: u32,
+    /// Odd frames skip a dot on the pre-render scanline when rendering is enabled
+    pub odd_frame: bool,
+    /// Whether background or sprite rendering was enabled at any point on the visible scanlines
+    pub rendering_enabled: bool,
+    /// Writes to the scroll or address registers (or the nametable select bits of PPUCTRL)
+    /// while rendering the visible scanlines, e.g. for a status bar or parallax split
+    pub mid_frame_scroll_changes: u32,
+    /// The PPU cycle on which the CPU took the NMI, None if NMIs were disabled
+    pub nmi_cycle: Option<PpuCycle>,
+}
@@ -0,0 +1,427 @@
+use log::{info, warn};
+use ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// The file inside the pack directory which describes the replacement tiles
+const DEFINITION_FILE: &str = "hires.txt";
+
+/// Represents any error which occurs whilst loading an HD pack
+#[derive(Debug)]
+pub struct HdPackError {
+    pub message: String,
+}
+impl Error for HdPackError {}
+impl fmt::Display for HdPackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error loading the HD pack: {}", self.message)
+    }
+}
+impl From<io::Error> for HdPackError {
+    fn from(error: io::Error) -> Self {
+        HdPackError {
+            message: error.to_string(),
+        }
+    }
+}
+impl From<png::DecodingError> for HdPackError {
+    fn from(error: png::DecodingError) -> Self {
+        HdPackError {
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Replacement tiles are looked up by the raw CHR data of the 8x8 tile along
+/// with the four colours (as indexes into the system palette) it's drawn with
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct TileKey {
+    chr: [u8; 16],
+    palette: [u8; 4],
+}
+
+/// A single `<tile>` line from the definition file
+#[derive(Debug, PartialEq)]
+struct TileDefinition {
+    image: usize,
+    key: TileKey,
+    x: u32,
+    y: u32,
+}
+
+#[derive(Debug, PartialEq)]
+struct PackDefinition {
+    scale: u32,
+    images: Vec<String>,
+    tiles: Vec<TileDefinition>,
+}
+
+/// An HDNes style texture pack. The pack is a directory containing a `hires.txt`
+/// definition file and a set of PNG images, the definition file is made up of
+/// lines of the form:
+///
+/// ```text
+/// <scale>4
+/// <img>sprites.png
+/// <tile>0,<32 hex digits of CHR data>,<8 hex digits of palette>,<x>,<y>
+/// ```
+///
+/// Where the tile line references an image by its index (in the order the
+/// `<img>` lines appear) and the x/y location of the top left of the
+/// (8 * scale) square replacement texture within it.
+pub struct HdPack {
+    scale: u32,
+    /// Replacement textures stored as 0xAARRGGBB, (8 * scale)^2 pixels each
+    textures: HashMap<TileKey, Vec<u32>>,
+}
+
+impl HdPack {
+    /// Load a pack from a directory on disk
+    pub fn load(directory: &str) -> Result<Self, HdPackError> {
+        let directory = Path::new(directory);
+        let definition = parse_definition(&std::fs::read_to_string(directory.join(DEFINITION_FILE))?)?;
+
+        let images = definition
+            .images
+            .iter()
+            .map(|image| load_png(&directory.join(image)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let texture_size = 8 * definition.scale;
+        let mut textures = HashMap::new();
+        for tile in definition.tiles {
+            let image = match images.get(tile.image) {
+                Some(image) => image,
+                None => {
+                    return Err(HdPackError {
+                        message: format!("Tile references image {} which doesn't exist", tile.image),
+                    })
+                }
+            };
+
+            if tile.x + texture_size > image.width || tile.y + texture_size > image.height {
+                return Err(HdPackError {
+                    message: format!(
+                        "Tile at {},{} doesn't fit within image {}",
+                        tile.x, tile.y, definition.images[tile.image]
+                    ),
+                });
+            }
+
+            let mut texture = Vec::with_capacity((texture_size * texture_size) as usize);
+            for y in tile.y..tile.y + texture_size {
+                let row_start = (y * image.width + tile.x) as usize;
+                texture.extend_from_slice(&image.pixels[row_start..row_start + texture_size as usize]);
+            }
+
+            if textures.insert(tile.key, texture).is_some() {
+                warn!("Duplicate HD pack tile definition {:?}, using the last one", tile.key);
+            }
+        }
+
+        info!(
+            "Loaded HD pack from {:?} with scale {} and {} tiles",
+            directory,
+            definition.scale,
+            textures.len()
+        );
+
+        Ok(HdPack {
+            scale: definition.scale,
+            textures,
+        })
+    }
+
+    /// The factor by which the output framebuffer is larger than the native one
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u32>,
+}
+
+fn load_png(path: &Path) -> Result<Image, HdPackError> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    let pixels = match info.color_type {
+        png::ColorType::RGBA => buffer
+            .chunks(4)
+            .map(|p| (p[3] as u32) << 24 | (p[0] as u32) << 16 | (p[1] as u32) << 8 | p[2] as u32)
+            .collect(),
+        png::ColorType::RGB => buffer
+            .chunks(3)
+            .map(|p| 0xFF00_0000 | (p[0] as u32) << 16 | (p[1] as u32) << 8 | p[2] as u32)
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks(2)
+            .map(|p| (p[1] as u32) << 24 | (p[0] as u32) << 16 | (p[0] as u32) << 8 | p[0] as u32)
+            .collect(),
+        png::ColorType::Grayscale => buffer
+            .iter()
+            .map(|&p| 0xFF00_0000 | (p as u32) << 16 | (p as u32) << 8 | p as u32)
+            .collect(),
+        png::ColorType::Indexed => {
+            return Err(HdPackError {
+                message: format!("Unable to expand indexed image {:?}", path),
+            })
+        }
+    };
+
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+fn parse_hex<T: AsMut<[u8]> + Default>(value: &str, line: usize) -> Result<T, HdPackError> {
+    let mut result = T::default();
+    let bytes = result.as_mut();
+    let value = value.trim();
+
+    if value.len() != bytes.len() * 2 || !value.is_ascii() {
+        return Err(HdPackError {
+            message: format!("Line {}: expected {} hex digits, got {}", line, bytes.len() * 2, value),
+        });
+    }
+
+    for (ix, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[ix * 2..ix * 2 + 2], 16).map_err(|e| HdPackError {
+            message: format!("Line {}: {}", line, e),
+        })?;
+    }
+
+    Ok(result)
+}
+
+fn parse_number(value: &str, line: usize) -> Result<u32, HdPackError> {
+    value.trim().parse::<u32>().map_err(|e| HdPackError {
+        message: format!("Line {}: {}", line, e),
+    })
+}
+
+fn parse_definition(contents: &str) -> Result<PackDefinition, HdPackError> {
+    let mut definition = PackDefinition {
+        scale: 1,
+        images: vec![],
+        tiles: vec![],
+    };
+
+    for (ix, line) in contents.lines().enumerate() {
+        let line_number = ix + 1;
+        let line = line.trim();
+
+        if let Some(scale) = line.strip_prefix("<scale>") {
+            definition.scale = parse_number(scale, line_number)?;
+            if definition.scale == 0 {
+                return Err(HdPackError {
+                    message: format!("Line {}: scale must be at least 1", line_number),
+                });
+            }
+        } else if let Some(image) = line.strip_prefix("<img>") {
+            definition.images.push(image.trim().to_string());
+        } else if let Some(tile) = line.strip_prefix("<tile>") {
+            let parts = tile.split(',').collect::<Vec<_>>();
+            if parts.len() != 5 {
+                return Err(HdPackError {
+                    message: format!(
+                        "Line {}: tile definitions require 5 comma separated values",
+                        line_number
+                    ),
+                });
+            }
+
+            definition.tiles.push(TileDefinition {
+                image: parse_number(parts[0], line_number)? as usize,
+                key: TileKey {
+                    chr: parse_hex(parts[1], line_number)?,
+                    palette: parse_hex(parts[2], line_number)?,
+                },
+                x: parse_number(parts[3], line_number)?,
+                y: parse_number(parts[4], line_number)?,
+            });
+        } else if !line.is_empty() && !line.starts_with('#') {
+            warn!("Ignoring unsupported HD pack line {}: {}", line_number, line);
+        }
+    }
+
+    Ok(definition)
+}
+
+/// Identifies a single row of an 8x8 tile as it was fetched by the PPU
+#[derive(Debug, Copy, Clone)]
+pub(super) struct HdTileRef {
+    pub(super) chr: [u8; 16],
+    pub(super) row: u8,
+    pub(super) flipped_horizontal: bool,
+    pub(super) flipped_vertical: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct HdPixel {
+    tile: HdTileRef,
+    column: u8,
+    palette: [u8; 4],
+}
+
+/// Tracks which tile each pixel on screen came from during the frame and then
+/// builds an upscaled framebuffer from those at the end of the frame.
+pub(super) struct HdRenderer {
+    pack: HdPack,
+    pixels: Vec<Option<HdPixel>>,
+    /// Mirrors the two tiles held in the background shift registers
+    bg_tiles: [Option<HdTileRef>; 2],
+    pub(super) frame_buffer: Vec<u8>,
+}
+
+impl HdRenderer {
+    pub(super) fn new(pack: HdPack) -> Self {
+        let scale = pack.scale;
+        HdRenderer {
+            pack,
+            pixels: vec![None; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+            bg_tiles: [None; 2],
+            frame_buffer: vec![0; (SCREEN_WIDTH * SCREEN_HEIGHT * scale * scale * 4) as usize],
+        }
+    }
+
+    pub(super) fn scale(&self) -> u32 {
+        self.pack.scale
+    }
+
+    /// Called whenever the background shift registers are reloaded
+    pub(super) fn push_bg_tile(&mut self, tile: HdTileRef) {
+        self.bg_tiles[0] = self.bg_tiles[1];
+        self.bg_tiles[1] = Some(tile);
+    }
+
+    /// Get the background tile and column within it for a given offset into
+    /// the shift registers (i.e. fine x + number of shifts since the reload)
+    pub(super) fn bg_tile_at(&self, offset: u8) -> Option<(HdTileRef, u8)> {
+        debug_assert!(offset < 16);
+        self.bg_tiles[(offset >> 3) as usize].map(|tile| (tile, offset & 7))
+    }
+
+    pub(super) fn set_pixel(&mut self, x: u32, y: u32, tile: Option<(HdTileRef, u8)>, palette: [u8; 4]) {
+        self.pixels[(y * SCREEN_WIDTH + x) as usize] = tile.map(|(tile, column)| HdPixel { tile, column, palette });
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|p| *p = None);
+    }
+
+    /// Construct the upscaled framebuffer, any pixel which doesn't come from a
+    /// tile in the pack (or is transparent in the replacement) is taken from the
+    /// native framebuffer.
+    pub(super) fn render(&mut self, native_frame_buffer: &[u8]) {
+        let scale = self.pack.scale;
+        let texture_size = 8 * scale;
+        let output_width = SCREEN_WIDTH * scale;
+        let textures = &self.pack.textures;
+        let frame_buffer = &mut self.frame_buffer;
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let native_offset = ((y * SCREEN_WIDTH + x) * 4) as usize;
+                let native_color = (native_frame_buffer[native_offset + 2] as u32) << 16
+                    | (native_frame_buffer[native_offset + 1] as u32) << 8
+                    | native_frame_buffer[native_offset] as u32;
+
+                let texture = self.pixels[(y * SCREEN_WIDTH + x) as usize].and_then(|pixel| {
+                    textures
+                        .get(&TileKey {
+                            chr: pixel.tile.chr,
+                            palette: pixel.palette,
+                        })
+                        .map(|texture| (pixel, texture))
+                });
+
+                for sub_y in 0..scale {
+                    for sub_x in 0..scale {
+                        let color = match texture {
+                            None => native_color,
+                            Some((pixel, texture)) => {
+                                let column = if pixel.tile.flipped_horizontal {
+                                    7 - pixel.column as u32
+                                } else {
+                                    pixel.column as u32
+                                };
+                                let texture_x = column * scale
+                                    + if pixel.tile.flipped_horizontal {
+                                        scale - 1 - sub_x
+                                    } else {
+                                        sub_x
+                                    };
+                                let texture_y = pixel.tile.row as u32 * scale
+                                    + if pixel.tile.flipped_vertical {
+                                        scale - 1 - sub_y
+                                    } else {
+                                        sub_y
+                                    };
+                                let texel = texture[(texture_y * texture_size + texture_x) as usize];
+
+                                if texel >> 24 == 0 {
+                                    native_color
+                                } else {
+                                    texel & 0xFF_FFFF
+                                }
+                            }
+                        };
+
+                        let offset = (((y * scale + sub_y) * output_width + x * scale + sub_x) * 4) as usize;
+                        frame_buffer[offset] = (color & 0xFF) as u8; // Blue channel
+                        frame_buffer[offset + 1] = ((color >> 8) & 0xFF) as u8; // Green channel
+                        frame_buffer[offset + 2] = (color >> 16) as u8; // Red channel
+                        frame_buffer[offset + 3] = 0x00; // Alpha channel
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod hd_pack_tests {
+    use ppu::hd_pack::{parse_definition, TileKey};
+
+    #[test]
+    fn test_parse_definition() {
+        let definition = parse_definition(
+            "# Comment\n\
+             <scale>2\n\
+             <img>tiles.png\n\
+             <tile>0,000102030405060708090A0B0C0D0E0F,0F162A30,16,32\n",
+        )
+        .unwrap();
+
+        assert_eq!(definition.scale, 2);
+        assert_eq!(definition.images, vec!["tiles.png".to_string()]);
+        assert_eq!(definition.tiles.len(), 1);
+        assert_eq!(
+            definition.tiles[0].key,
+            TileKey {
+                chr: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+                palette: [0x0F, 0x16, 0x2A, 0x30],
+            }
+        );
+        assert_eq!((definition.tiles[0].x, definition.tiles[0].y), (16, 32));
+    }
+
+    #[test]
+    fn test_parse_definition_invalid_tile() {
+        assert!(parse_definition("<tile>0,0001,0F162A30,0,0").is_err());
+        assert!(parse_definition("<scale>0").is_err());
+    }
+}
@@ -1,22 +1,114 @@
+mod bus_log;
+mod frame_blend;
+mod frame_info;
+mod hd_pack;
 mod palette;
+mod palette_generator;
+mod palette_tint;
 mod registers;
+mod sprite_stats;
 mod sprites;
 
-use cartridge::PpuCartridgeAddressBus;
+pub use ppu::bus_log::PpuBusAccess;
+pub use ppu::frame_blend::FrameBlender;
+pub use ppu::frame_info::FrameInfo;
+pub use ppu::hd_pack::{HdPack, HdPackError};
+pub use ppu::palette_generator::{PaletteRegion, PaletteSettings};
+pub use ppu::palette_tint::PaletteTint;
+pub use ppu::sprite_stats::SpriteStats;
+
+use accuracy::AccuracyProfile;
+use cartridge::{BackgroundTileFetch, PpuCartridgeAddressBus};
 use cpu::interrupts::Interrupt;
+use cpu::CpuCycle;
 use log::{debug, info};
+use memory_region::MemoryRegion;
+use ppu::bus_log::BusRecorder;
+use ppu::hd_pack::{HdRenderer, HdTileRef};
 use ppu::palette::PaletteRam;
 use ppu::registers::ppuctrl::{IncrementMode, PpuCtrl};
 use ppu::registers::ppumask::PpuMask;
 use ppu::registers::ppustatus::PpuStatus;
+use ppu::sprite_stats::SpriteStatsRecorder;
 use ppu::sprites::SpriteData;
+use scheduler::Scheduler;
+
+pub const SCREEN_WIDTH: u32 = 256;
+pub const SCREEN_HEIGHT: u32 = 240;
+
+/// Pixels in the index buffer are the 6 bit palette index with the PPUMASK emphasis bits
+/// (red, green, blue) above it and the palette RAM address it was read from above those, or this
+/// for pixels which haven't been drawn this frame and are shown as black
+pub const BLANK_PIXEL: u16 = 0x8000;
+
+/// Where the 5 bit palette RAM address sits in each index buffer pixel
+const PALETTE_ADDRESS_SHIFT: u16 = 9;
+
+/// How much of each colour channel is left when emphasis is set for one of the other channels
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+/// Darken the channels not emphasised by the PPUMASK bits stored with an index buffer pixel,
+/// colours are 0xRRGGBB
+fn apply_emphasis(color: u32, pixel: u16) -> u32 {
+    let emphasis = (pixel >> 6) & 0b111;
+    if emphasis == 0 {
+        return color;
+    }
 
-pub(crate) const SCREEN_WIDTH: u32 = 256;
-pub(crate) const SCREEN_HEIGHT: u32 = 240;
+    // Emphasis bits are red, green, blue from the lowest bit, the colour has blue in the lowest byte
+    (0..3).fold(0, |emphasised, channel| {
+        let shift = channel * 8;
+        let mut value = (color >> shift) & 0xFF;
+        if emphasis & !(0b100 >> channel) != 0 {
+            value = (value as f32 * EMPHASIS_ATTENUATION) as u32;
+        }
+        emphasised | value << shift
+    })
+}
 
 /// This type is used to represent a PPU cycle to make it clearer when
 /// we're talking about cycles which type (PPU, CPU, APU) we mean
-pub(crate) type PpuCycle = u32;
+pub(crate) type PpuCycle = u64;
+
+/// The NMI line is raised this many PPU cycles after the event which triggers it, until then
+/// it can still be suppressed by reading PPUSTATUS or clearing the NMI enable flag
+const NMI_DELAY: PpuCycle = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PpuEvent {
+    Nmi,
+}
+
+save_state_enum!(PpuEvent { Nmi }, default = Nmi);
+
+/// Roughly 600ms worth of PPU cycles, after which the I/O latch has decayed to 0
+const OPEN_BUS_DECAY_CYCLES: PpuCycle = 3_200_000;
+
+/// Enough background tiles to cover a line at any fine x scroll
+const LINE_TILES: usize = 33;
+
+/// A background tile as loaded into the shift registers
+#[derive(Debug, Copy, Clone, Default)]
+struct BackgroundTile {
+    low_byte: u8,
+    high_byte: u8,
+    palette: u8,
+}
+
+impl BackgroundTile {
+    /// Returns the index into the palette memory of the given column (0 on the left)
+    fn pixel_palette(&self, column: usize) -> u8 {
+        let color_index = ((self.low_byte >> (7 - column)) & 1) | (((self.high_byte >> (7 - column)) & 1) << 1);
+
+        (self.palette << 2) | color_index
+    }
+}
+
+save_state_fields!(BackgroundTile {
+    low_byte,
+    high_byte,
+    palette,
+});
 
 #[derive(Debug)]
 struct ScanlineState {
@@ -32,6 +124,14 @@ struct ScanlineState {
     at_shift_register_low: u8,
     at_shift_latch_high: u8,
     at_shift_latch_low: u8,
+    /// Substitutions the mapper made for the tile being fetched
+    palette_override: Option<u8>,
+    fine_y_override: Option<u8>,
+    /// Each tile loaded for the current line, so that it can be drawn in one pass when scanline
+    /// rendering. The first two are loaded at the end of the previous line
+    line_tiles: [BackgroundTile; LINE_TILES],
+    /// Whether the current line is drawn a dot at a time rather than in one pass
+    draw_by_dot: bool,
 }
 
 impl ScanlineState {
@@ -57,16 +157,31 @@ impl ScanlineState {
         self.bg_shift_register_high |= self.bg_high_byte as u16;
         self.bg_shift_register_low |= self.bg_low_byte as u16;
 
-        let at_bits = match (coarse_x & 0b10, coarse_y & 0b10) {
-            (0, 0) => self.attribute_table_byte & 0b11,
-            (2, 0) => (self.attribute_table_byte >> 2) & 0b11,
-            (0, 2) => (self.attribute_table_byte >> 4) & 0b11,
-            (2, 2) => (self.attribute_table_byte >> 6) & 0b11,
+        let at_bits = match (self.palette_override, coarse_x & 0b10, coarse_y & 0b10) {
+            (Some(palette), _, _) => palette & 0b11,
+            (None, 0, 0) => self.attribute_table_byte & 0b11,
+            (None, 2, 0) => (self.attribute_table_byte >> 2) & 0b11,
+            (None, 0, 2) => (self.attribute_table_byte >> 4) & 0b11,
+            (None, 2, 2) => (self.attribute_table_byte >> 6) & 0b11,
             _ => panic!(),
         };
 
         self.at_shift_latch_low = at_bits & 1;
         self.at_shift_latch_high = (at_bits >> 1) & 1;
+
+        let slot = match self.dot {
+            329 => Some(0),
+            337 => Some(1),
+            9..=249 => Some((self.dot as usize - 9) / 8 + 2),
+            _ => None,
+        };
+        if let Some(slot) = slot {
+            self.line_tiles[slot] = BackgroundTile {
+                low_byte: self.bg_low_byte,
+                high_byte: self.bg_high_byte,
+                palette: at_bits,
+            };
+        }
     }
 
     /// Returns the index into the palette memory (0x00-0x3F) based on the
@@ -84,8 +199,34 @@ impl ScanlineState {
 
         (palette_index << 2) | color_index as u8
     }
+
+    /// As `bg_pixel_palette` but for any pixel on the line from the tiles loaded for it
+    fn line_pixel_palette(&self, x: u32, fine_x_scroll: u8) -> u8 {
+        let offset = x as usize + fine_x_scroll as usize;
+
+        self.line_tiles[offset / 8].pixel_palette(offset & 7)
+    }
 }
 
+save_state_fields!(ScanlineState {
+    nametable_byte,
+    attribute_table_byte,
+    bg_low_byte,
+    bg_high_byte,
+    scanline,
+    dot,
+    bg_shift_register_high,
+    bg_shift_register_low,
+    at_shift_register_high,
+    at_shift_register_low,
+    at_shift_latch_high,
+    at_shift_latch_low,
+    palette_override,
+    fine_y_override,
+    line_tiles,
+    draw_by_dot,
+});
+
 #[derive(Debug)]
 struct InternalRegisters {
     vram_addr: u16,
@@ -151,6 +292,14 @@ impl InternalRegisters {
     }
 }
 
+save_state_fields!(InternalRegisters {
+    vram_addr,
+    temp_vram_addr,
+    fine_x_scroll,
+    write_toggle,
+    next_address,
+});
+
 pub struct Ppu {
     pub(crate) total_cycles: PpuCycle,
     frame_number: u32,
@@ -163,15 +312,30 @@ pub struct Ppu {
     last_ppu_status_read_cycle: PpuCycle,
     internal_registers: InternalRegisters,
     ppu_data_buffer: u8,   // Internal buffer returned on PPUDATA reads
-    last_written_byte: u8, // Stores the value last written onto the latch - TODO implement decay over time
-    nmi_interrupt: Option<Interrupt>,
-    pub(crate) frame_buffer: [u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize],
-    priorities: [u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize],
+    last_written_byte: u8, // Stores the value last written onto the latch
+    last_written_byte_cycle: PpuCycle,
+    accuracy: AccuracyProfile,
+    /// The RGB colour for each of the 64 palette entries
+    palette: [u32; 0x40],
+    scheduler: Scheduler<PpuCycle, PpuEvent>,
+    /// The palette index (with emphasis and palette address) of each pixel drawn, converted to
+    /// colours in `frame_buffer` with `resolve_frame_buffer` so the palette can change without
+    /// re-rendering
+    pub(crate) index_buffer: Box<[u16; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize]>,
+    frame_buffer: Box<[u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize]>,
+    palette_tint: Option<PaletteTint>,
     pub(crate) chr_address_bus: Box<dyn PpuCartridgeAddressBus>,
+    hd_renderer: Option<HdRenderer>,
+    bus_recorder: Option<BusRecorder>,
+    sprite_stats: Option<SpriteStatsRecorder>,
+    /// Describes the frame being rendered and the last one completed, these aren't saved as a
+    /// loaded state starts a new frame as far as the frontend is concerned
+    frame_info: FrameInfo,
+    completed_frame_info: Option<FrameInfo>,
 }
 
 impl Ppu {
-    pub fn new(chr_address_bus: Box<dyn PpuCartridgeAddressBus>) -> Self {
+    pub fn new(chr_address_bus: Box<dyn PpuCartridgeAddressBus>, accuracy: AccuracyProfile) -> Self {
         Ppu {
             total_cycles: 27,
             frame_number: 1,
@@ -188,6 +352,10 @@ impl Ppu {
                 at_shift_register_low: 0,
                 at_shift_latch_high: 0,
                 at_shift_latch_low: 0,
+                palette_override: None,
+                fine_y_override: None,
+                line_tiles: [BackgroundTile::default(); LINE_TILES],
+                draw_by_dot: true,
             },
             sprite_data: SpriteData::new(),
             palette_ram: PaletteRam { data: [0; 0x20] },
@@ -203,38 +371,186 @@ impl Ppu {
                 next_address: 0,
             },
             last_written_byte: 0x0,
+            last_written_byte_cycle: 0,
+            accuracy,
+            palette: palette::PALETTE_2C02,
             ppu_data_buffer: 0x0,
-            nmi_interrupt: None,
-            frame_buffer: [0; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize],
-            priorities: [0; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize],
+            scheduler: Scheduler::new(),
+            index_buffer: Box::new([BLANK_PIXEL; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize]),
+            frame_buffer: Box::new([0; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize]),
+            palette_tint: None,
             chr_address_bus,
+            hd_renderer: None,
+            bus_recorder: None,
+            sprite_stats: None,
+            frame_info: FrameInfo::default(),
+            completed_frame_info: None,
         }
     }
 
-    pub(crate) fn check_trigger_irq(&mut self, clear: bool) -> bool {
-        self.chr_address_bus.check_trigger_irq(clear)
+    /// Enable rendering with replacement textures from an HD pack, this produces an
+    /// additional upscaled framebuffer alongside the native one.
+    pub fn set_hd_pack(&mut self, pack: HdPack) {
+        self.hd_renderer = Some(HdRenderer::new(pack));
+    }
+
+    /// Render every sprite on a line rather than only the first 8, removing flicker from games
+    /// which multiplex sprites. The overflow flag is still set as it would be on hardware.
+    pub(crate) fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_data.set_sprite_limit(enabled);
+    }
+
+    /// Replace the built in 2C02 palette, e.g. with one from `PaletteSettings::generate`.
+    /// Colours are 0xRRGGBB.
+    pub fn set_palette(&mut self, palette: [u32; 0x40]) {
+        self.palette = palette;
+        self.resolve_frame_buffer();
     }
 
-    pub(crate) fn dump_state(&mut self, vram_copy: &mut [u8; 0x4000]) -> &[u8; 0x100] {
-        for i in 0..=0x3FFF {
-            vram_copy[i] = self.read_byte(i as u16);
+    /// Tint each pixel by the palette it was drawn with when the framebuffer is resolved, None
+    /// turns it off
+    pub(crate) fn set_palette_tint(&mut self, tint: Option<PaletteTint>) {
+        self.palette_tint = tint;
+        self.resolve_frame_buffer();
+    }
+
+    /// Convert the index buffer to BGRA colours with the current palette and emphasis. This
+    /// happens as each frame completes so reading the framebuffer is free.
+    pub(crate) fn resolve_frame_buffer(&mut self) {
+        for (&pixel, bgra) in self.index_buffer.iter().zip(self.frame_buffer.chunks_mut(4)) {
+            let color = match (pixel, self.palette_tint) {
+                (BLANK_PIXEL, _) => 0x0,
+                (_, None) => apply_emphasis(self.palette[pixel as usize & 0x3F], pixel),
+                (_, Some(tint)) => tint.apply(
+                    apply_emphasis(self.palette[pixel as usize & 0x3F], pixel),
+                    (pixel >> PALETTE_ADDRESS_SHIFT) as u8 & 0x1F,
+                ),
+            };
+
+            bgra[0] = (color & 0xFF) as u8; // Blue channel
+            bgra[1] = ((color >> 8) & 0xFF) as u8; // Green channel
+            bgra[2] = (color >> 16) as u8; // Red channel
+            bgra[3] = 0x00; // Alpha channel
         }
+    }
 
-        &self.sprite_data.oam_ram
+    /// The last frame resolved by `resolve_frame_buffer` as BGRA
+    pub(crate) fn frame_buffer(&self) -> &[u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize] {
+        &self.frame_buffer
     }
 
-    pub(crate) fn check_ppu_nmi(&mut self, clear: bool) -> Option<Interrupt> {
-        if let Some(Interrupt::NMI(cycles)) = self.nmi_interrupt {
-            // Due to us checking for interrupts _after_ the last operation we might catch an interrupt
-            // a CPU instruction early (STA 2002 can cause an NMI, last cycle of STA is the write, should have
-            // checked for interrupts first but instead we check whether the interrupt occurred in the last 3 PPU
-            // cycles.
-            if cycles <= self.total_cycles - 3 {
-                if clear {
-                    self.nmi_interrupt = None;
+    /// Returns the upscaled framebuffer and its scale factor if an HD pack is loaded
+    pub(crate) fn hd_frame_buffer(&self) -> Option<(&[u8], u32)> {
+        self.hd_renderer
+            .as_ref()
+            .map(|renderer| (renderer.frame_buffer.as_slice(), renderer.scale()))
+    }
+
+    /// Reads the full 16 bytes of a tile from the pattern tables without side effects
+    fn peek_tile(&self, base_address: u16) -> [u8; 16] {
+        let mut chr = [0; 16];
+        for (ix, byte) in chr.iter_mut().enumerate() {
+            *byte = self.chr_address_bus.peek_byte(base_address + ix as u16);
+        }
+
+        chr
+    }
+
+    /// Record every access on the PPU address bus during the given frame
+    pub(crate) fn record_bus_activity(&mut self, frame: u32) {
+        self.bus_recorder = Some(BusRecorder::new(frame));
+    }
+
+    /// The accesses recorded by `record_bus_activity`, once the frame has completed
+    pub(crate) fn bus_activity(&self) -> Option<&[PpuBusAccess]> {
+        self.bus_recorder
+            .as_ref()
+            .and_then(|recorder| recorder.accesses(self.frame_number))
+    }
+
+    pub(crate) fn frame_number(&self) -> u32 {
+        self.frame_number
+    }
+
+    /// The summary of the last complete frame
+    pub(crate) fn frame_info(&self) -> Option<FrameInfo> {
+        self.completed_frame_info
+    }
+
+    /// Whether the PPU is drawing the visible scanlines, where register writes affect the picture
+    fn is_rendering_visible_scanline(&self) -> bool {
+        self.ppu_mask.is_rendering_enabled() && self.scanline_state.scanline < 240
+    }
+
+    /// Start or stop collecting sprite evaluation statistics for each frame
+    pub(crate) fn record_sprite_stats(&mut self, enabled: bool) {
+        self.sprite_stats = match enabled {
+            true => Some(SpriteStatsRecorder::new()),
+            false => None,
+        };
+    }
+
+    /// The sprite statistics from the last complete frame
+    pub(crate) fn sprite_stats(&self) -> Option<&SpriteStats> {
+        self.sprite_stats.as_ref().and_then(|recorder| recorder.completed())
+    }
+
+    pub(crate) fn check_trigger_irq(&mut self, clear: bool, cycles: CpuCycle) -> bool {
+        self.chr_address_bus.check_trigger_irq(clear, cycles)
+    }
+
+    pub(crate) fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.chr_address_bus.set_dip_switches(dip_switches);
+    }
+
+    /// Copy out one of the PPU's memory regions, reads go around the bus so they don't trigger
+    /// mapper side effects (e.g. MMC2 latches) or show up in the bus recording
+    pub(crate) fn dump_memory(&self, region: MemoryRegion) -> Vec<u8> {
+        match region {
+            MemoryRegion::Vram => (0..=0x3FFF)
+                .map(|address| match address {
+                    0x0000..=0x3EFF => self.chr_address_bus.peek_byte(address),
+                    _ => self.palette_ram.read_byte(address),
+                })
+                .collect(),
+            MemoryRegion::Oam => self.sprite_data.oam_ram.to_vec(),
+            MemoryRegion::Palette => self.palette_ram.data.to_vec(),
+            MemoryRegion::CpuRam | MemoryRegion::PrgRam => vec![],
+        }
+    }
+
+    /// Overwrite one of the PPU's memory regions, the data must be the size returned by `dump_memory`.
+    /// Writes to CHR ROM are ignored.
+    pub(crate) fn load_memory(&mut self, region: MemoryRegion, data: &[u8]) {
+        match region {
+            MemoryRegion::Vram => {
+                // Written backwards so that where addresses are mirrored the lowest one wins, rather
+                // than changes being overwritten by an unchanged mirror
+                for (address, value) in data.iter().enumerate().rev() {
+                    match address {
+                        0x0000..=0x3EFF => self
+                            .chr_address_bus
+                            .write_byte(address as u16, *value, self.total_cycles),
+                        _ => self.palette_ram.write_byte(address as u16, *value),
+                    }
                 }
-                return Some(Interrupt::NMI(cycles));
             }
+            MemoryRegion::Oam => self.sprite_data.oam_ram.copy_from_slice(data),
+            MemoryRegion::Palette => self.palette_ram.data.copy_from_slice(data),
+            MemoryRegion::CpuRam | MemoryRegion::PrgRam => (),
+        }
+    }
+
+    pub(crate) fn check_ppu_nmi(&mut self, clear: bool) -> Option<Interrupt> {
+        // Due to us checking for interrupts _after_ the last operation we might catch an interrupt
+        // a CPU instruction early (STA 2002 can cause an NMI, last cycle of STA is the write, should have
+        // checked for interrupts first but instead the NMI is only raised once it's NMI_DELAY cycles old.
+        if self.scheduler.is_due(PpuEvent::Nmi, self.total_cycles) {
+            if clear {
+                self.scheduler.cancel(PpuEvent::Nmi);
+                self.frame_info.nmi_cycle = Some(self.total_cycles);
+            }
+            return Some(Interrupt::NMI(self.total_cycles));
         }
 
         None
@@ -248,6 +564,19 @@ impl Ppu {
         self.scanline_state.dot
     }
 
+    /// The nametable address a PPUDATA write would go to if it happened now while the PPU is
+    /// fetching background/sprite data, None outside of rendering or for pattern/palette addresses
+    pub(crate) fn nametable_write_during_rendering(&self) -> Option<u16> {
+        let scanline = self.scanline_state.scanline;
+        let address = self.internal_registers.vram_addr & 0x3FFF;
+        let rendering = self.ppu_mask.is_rendering_enabled() && (scanline < 240 || scanline == 261);
+
+        match address {
+            0x2000..=0x3EFF if rendering => Some(address),
+            _ => None,
+        }
+    }
+
     /// Writes to the various PPU registers mapped into the CPU address space.
     pub(crate) fn write_register(&mut self, address: u16, value: u8) {
         // TODO - Handle writes during rendering being off
@@ -255,6 +584,16 @@ impl Ppu {
         debug!("PPU register write {:04X}={:02X}", address, value);
 
         self.last_written_byte = value;
+        self.last_written_byte_cycle = self.total_cycles;
+
+        let changes_scroll = match address {
+            0x2000 => (value as u16 & 0b11) << 10 != self.internal_registers.temp_vram_addr & 0x0C00,
+            0x2005 | 0x2006 => true,
+            _ => false,
+        };
+        if changes_scroll && self.is_rendering_visible_scanline() {
+            self.frame_info.mid_frame_scroll_changes += 1;
+        }
 
         match address {
             0x2000 => {
@@ -262,7 +601,7 @@ impl Ppu {
                 if !self.ppu_ctrl.nmi_enable && value & 0b1000_0000 != 0 && self.ppu_status.vblank_started {
                     // Doesn't affect if vblank about to be turned off
                     if self.scanline_state.scanline != 261 || self.scanline_state.dot != 1 {
-                        self.nmi_interrupt = Some(Interrupt::NMI(self.total_cycles));
+                        self.scheduler.schedule(PpuEvent::Nmi, self.total_cycles, NMI_DELAY);
                         info!("Triggering NMI");
                     }
                 }
@@ -270,10 +609,8 @@ impl Ppu {
                 self.ppu_ctrl.write_byte(value);
 
                 // Setting NMI disabled within 1 cycle of triggering it will suppress as well
-                if let Some(Interrupt::NMI(cycles)) = self.nmi_interrupt {
-                    if !self.ppu_ctrl.nmi_enable && cycles >= self.total_cycles - 2 {
-                        self.nmi_interrupt = None;
-                    }
+                if !self.ppu_ctrl.nmi_enable && self.scheduler.is_pending(PpuEvent::Nmi, self.total_cycles) {
+                    self.scheduler.cancel(PpuEvent::Nmi);
                 }
 
                 self.internal_registers.temp_vram_addr =
@@ -329,6 +666,15 @@ impl Ppu {
         }
     }
 
+    /// The value left on the PPU I/O latch which is returned when reading write only registers
+    fn io_latch(&mut self) -> u8 {
+        if self.accuracy.open_bus_decay() && self.total_cycles - self.last_written_byte_cycle > OPEN_BUS_DECAY_CYCLES {
+            self.last_written_byte = 0;
+        }
+
+        self.last_written_byte
+    }
+
     /// Reads from the various PPU registers mapped into the CPU address space.
     pub(crate) fn read_register(&mut self, address: u16) -> u8 {
         // TODO - Handle behaviour where rendering is off
@@ -336,8 +682,8 @@ impl Ppu {
         //debug!("PPU register read {:04X}", address);
 
         match address {
-            0x2000 => self.last_written_byte,
-            0x2001 => self.last_written_byte,
+            0x2000 => self.io_latch(),
+            0x2001 => self.io_latch(),
             // PPUSTATUS
             0x2002 => {
                 debug!(
@@ -345,33 +691,30 @@ impl Ppu {
                     self.scanline_state.scanline, self.scanline_state.dot
                 );
                 // Suppress NMI if it was triggered within the last 2 PPU cycles
-                match self.nmi_interrupt {
-                    None => (),
-                    Some(Interrupt::NMI(cycles)) => {
-                        if cycles >= self.total_cycles - 2 {
-                            info!("Suppressing NMI due to proximity to PPUSTATUS read");
-                            self.nmi_interrupt = None;
-                        }
-                    }
-                    Some(_) => panic!(),
+                if self.scheduler.is_pending(PpuEvent::Nmi, self.total_cycles) {
+                    info!("Suppressing NMI due to proximity to PPUSTATUS read");
+                    self.scheduler.cancel(PpuEvent::Nmi);
                 }
                 self.internal_registers.write_toggle = false;
                 self.last_ppu_status_read_cycle = self.total_cycles;
-                self.ppu_status.read(self.last_written_byte)
+                let latch = self.io_latch();
+                self.ppu_status.read(latch)
             }
-            0x2003 => self.last_written_byte,
+            0x2003 => self.io_latch(),
             0x2004 => self
                 .sprite_data
                 .read_oam_data(self.scanline_state.dot, self.ppu_mask.is_rendering_enabled()),
-            0x2005 => self.last_written_byte,
-            0x2006 => self.last_written_byte,
+            0x2005 => self.io_latch(),
+            0x2006 => self.io_latch(),
             0x2007 => {
-                // PPUDATA
+                // PPUDATA, reads are delayed through the buffer except for palette RAM which is
+                // returned directly while the nametable byte underneath it goes into the buffer
                 let mut value = self.ppu_data_buffer;
                 self.ppu_data_buffer = match self.internal_registers.vram_addr {
                     0x0000..=0x3EFF => self.read_byte(self.internal_registers.vram_addr),
                     0x3F00..=0x3FFF => {
-                        value = self.palette_ram.read_byte(self.internal_registers.vram_addr);
+                        value =
+                            self.palette_ram.read_byte(self.internal_registers.vram_addr) & self.ppu_mask.colour_mask();
                         self.read_byte(self.internal_registers.vram_addr - 0x1000)
                     }
                     _ => panic!("Invalid address for PPU {:04X}", self.internal_registers.vram_addr),
@@ -394,6 +737,7 @@ impl Ppu {
             address
         );
         //debug!("PPU address space read {:04X}", address);
+        self.record_bus_access(address, true);
 
         match address {
             0x0000..=0x3EFF => self.chr_address_bus.read_byte(address, self.total_cycles),
@@ -402,14 +746,30 @@ impl Ppu {
         }
     }
 
-    pub(crate) fn write_dma_byte(&mut self, value: u8, dma_byte: u8) {
-        self.sprite_data.dma_write(value, dma_byte);
+    /// Reads the pattern bytes of a background tile, which the mapper may bank separately
+    fn read_background_pattern(&mut self, address: u16) -> u8 {
+        debug_assert!(address <= 0x1FFF);
+        self.record_bus_access(address, true);
+
+        self.chr_address_bus.read_background_pattern(address, self.total_cycles)
+    }
+
+    fn record_bus_access(&mut self, address: u16, read: bool) {
+        if let Some(recorder) = &mut self.bus_recorder {
+            let state = &self.scanline_state;
+            recorder.record(self.frame_number, state.dot, state.scanline, address, read);
+        }
+    }
+
+    pub(crate) fn write_dma_byte(&mut self, value: u8) {
+        self.sprite_data.dma_write(value);
     }
 
     /// Writes to the PPU address space
     fn write_byte(&mut self, address: u16, value: u8) {
         debug_assert!(address <= 0x3FFF);
         debug!("PPU address space write: {:04X}={:02X}", address, value);
+        self.record_bus_access(address, false);
 
         match address {
             0x0000..=0x3EFF => {
@@ -422,6 +782,35 @@ impl Ppu {
         }
     }
 
+    /// Lets the mapper substitute parts of the background tile whose nametable byte was just
+    /// fetched, tiles at dots 321-336 are the first two of the next scanline
+    fn apply_background_tile_override(&mut self, cycle: u16) {
+        let (column, scanline) = if cycle >= 321 {
+            ((cycle - 321) / 8, (self.scanline_state.scanline + 1) % 262)
+        } else {
+            ((cycle - 1) / 8 + 2, self.scanline_state.scanline)
+        };
+        let tile_override = self.chr_address_bus.background_tile_fetch(&BackgroundTileFetch {
+            column: column as u8,
+            scanline,
+            nametable_address: self.internal_registers.next_address,
+            nametable_byte: self.scanline_state.nametable_byte,
+        });
+
+        if let Some(nametable_byte) = tile_override.nametable_byte {
+            self.scanline_state.nametable_byte = nametable_byte;
+        }
+        self.scanline_state.palette_override = tile_override.palette;
+        self.scanline_state.fine_y_override = tile_override.fine_y;
+    }
+
+    /// The row of the background tile's pattern to fetch
+    fn background_fine_y(&self) -> u16 {
+        self.scanline_state
+            .fine_y_override
+            .unwrap_or_else(|| self.internal_registers.fine_y()) as u16
+    }
+
     /// Handles the PPU fetch pipeline (ignoring sprites as those are handled by the SpriteData
     /// state machine)
     fn fetch_data(&mut self, cycle: u16) {
@@ -440,7 +829,8 @@ impl Ppu {
         match cycle & 7 {
             0 => {
                 if cycle <= 256 || (cycle >= 321 && cycle <= 336) {
-                    self.scanline_state.bg_high_byte = self.read_byte(self.internal_registers.next_address);
+                    self.scanline_state.bg_high_byte =
+                        self.read_background_pattern(self.internal_registers.next_address);
 
                     // Go to the next tile every 8 dots
                     self.internal_registers.increment_effective_scroll_x();
@@ -461,6 +851,19 @@ impl Ppu {
                         self.internal_registers.coarse_y(),
                     );
 
+                    // The last address fetched was the high byte of the tile just loaded into the shift registers
+                    if self.hd_renderer.is_some() {
+                        let tile = HdTileRef {
+                            chr: self.peek_tile(self.internal_registers.next_address & 0xFFF0),
+                            row: (self.internal_registers.next_address & 7) as u8,
+                            flipped_horizontal: false,
+                            flipped_vertical: false,
+                        };
+                        if let Some(renderer) = &mut self.hd_renderer {
+                            renderer.push_bg_tile(tile);
+                        }
+                    }
+
                     if cycle == 257 {
                         // Copy horizontal data from temporary vram address to vram address at dot 257
                         self.internal_registers.vram_addr = (self.internal_registers.vram_addr & 0b1111_1011_1110_0000)
@@ -473,6 +876,7 @@ impl Ppu {
             2 => {
                 if cycle <= 256 || (cycle >= 321 && cycle <= 336) {
                     self.scanline_state.nametable_byte = self.read_byte(self.internal_registers.next_address);
+                    self.apply_background_tile_override(cycle);
                 } else {
                     self.read_byte(self.internal_registers.next_address); // Garbage nametable byte during sprite read & end of line fetches
                 }
@@ -493,25 +897,23 @@ impl Ppu {
             5 => {
                 if cycle <= 256 || cycle >= 321 {
                     let tile_index = self.scanline_state.nametable_byte as u16 * 16;
-                    self.internal_registers.next_address = self.ppu_ctrl.background_tile_table_select
-                        + tile_index
-                        + self.internal_registers.fine_y() as u16;
+                    self.internal_registers.next_address =
+                        self.ppu_ctrl.background_tile_table_select + tile_index + self.background_fine_y();
                     self.chr_address_bus
                         .update_vram_address(self.internal_registers.next_address, self.total_cycles);
                 }
             }
             6 => {
                 if cycle <= 256 || (cycle >= 321 && cycle <= 336) {
-                    self.scanline_state.bg_low_byte = self.read_byte(self.internal_registers.next_address);
+                    self.scanline_state.bg_low_byte =
+                        self.read_background_pattern(self.internal_registers.next_address);
                 }
             }
             7 => {
                 if cycle <= 256 || cycle >= 321 {
                     let tile_index = self.scanline_state.nametable_byte as u16 * 16;
-                    self.internal_registers.next_address = self.ppu_ctrl.background_tile_table_select
-                        + tile_index
-                        + self.internal_registers.fine_y() as u16
-                        + 8;
+                    self.internal_registers.next_address =
+                        self.ppu_ctrl.background_tile_table_select + tile_index + self.background_fine_y() + 8;
                     self.chr_address_bus
                         .update_vram_address(self.internal_registers.next_address, self.total_cycles);
                 }
@@ -522,11 +924,28 @@ impl Ppu {
 
     /// Perform the dot based rendering for each cycle in a visible scanline
     fn draw_pixel(&mut self, scanline: u16, cycle: u16) {
-        let x = cycle as PpuCycle - 1;
-        let y = scanline as u32;
-        let offset = ((SCREEN_WIDTH * y + x) * 4) as usize;
+        let bg_pixel = self
+            .scanline_state
+            .bg_pixel_palette(self.internal_registers.fine_x_scroll);
+        self.output_pixel(cycle as u32 - 1, scanline as u32, bg_pixel);
+    }
+
+    /// Draw a whole visible scanline in one pass from the tiles loaded for it
+    fn draw_scanline(&mut self, scanline: u16) {
+        for x in 0..SCREEN_WIDTH {
+            let bg_pixel = self
+                .scanline_state
+                .line_pixel_palette(x, self.internal_registers.fine_x_scroll);
+            self.output_pixel(x, scanline as u32, bg_pixel);
+        }
+    }
 
-        let color = if self.ppu_mask.is_rendering_enabled() {
+    /// Multiplex a background pixel with any sprite at this position and store the result in
+    /// the index buffer
+    fn output_pixel(&mut self, x: u32, y: u32, bg_pixel: u8) {
+        let offset = (SCREEN_WIDTH * y + x) as usize;
+
+        let (palette_index, palette_address) = if self.ppu_mask.is_rendering_enabled() {
             // Get background pixel
             let bg_pixel = match (
                 self.ppu_mask.show_background,
@@ -535,18 +954,16 @@ impl Ppu {
             ) {
                 (false, _, _) => 0x0,
                 (true, false, 0..=7) => 0x0,
-                _ => self
-                    .scanline_state
-                    .bg_pixel_palette(self.internal_registers.fine_x_scroll),
+                _ => bg_pixel,
             };
 
             // Get sprite pixel
-            let (sprite_pixel, sprite_priority_over_bg, is_sprite_zero) =
+            let (sprite_pixel, sprite_priority_over_bg, is_sprite_zero, sprite_index) =
                 match (self.ppu_mask.show_sprites, self.ppu_mask.show_sprites_left_side, x) {
-                    (false, _, _) => (0x0, false, false),
+                    (false, _, _) => (0x0, false, false, None),
                     (true, false, 0..=7) => {
                         self.get_sprite_pixel(x); // Throwaway read to force a register shift for relevant sprites even if the left side is masked
-                        (0x0, false, false)
+                        (0x0, false, false, None)
                     }
                     _ => self.get_sprite_pixel(x),
                 };
@@ -562,40 +979,88 @@ impl Ppu {
                     self.total_cycles, self.scanline_state.scanline, self.scanline_state.dot, bg_pixel, sprite_pixel
                 );
                 self.ppu_status.sprite_zero_hit = true;
+                if let Some(recorder) = &mut self.sprite_stats {
+                    recorder.record_sprite_zero_hit(self.scanline_state.dot, self.scanline_state.scanline);
+                }
             }
 
             // Pass the resulting values through a priority multiplexer to get the final pixel value
-            let multiplexed_pixel = match (bg_pixel & 0b11, sprite_pixel & 0b11, sprite_priority_over_bg) {
-                (0, 0, _) => 0x0,
-                (0, _, _) => sprite_pixel,
-                (_, 0, _) => bg_pixel,
-                (_, _, true) => sprite_pixel,
-                (_, _, false) => bg_pixel,
-            };
+            let (multiplexed_pixel, is_sprite_pixel) =
+                match (bg_pixel & 0b11, sprite_pixel & 0b11, sprite_priority_over_bg) {
+                    (0, 0, _) => (0x0, false),
+                    (0, _, _) => (sprite_pixel, true),
+                    (_, 0, _) => (bg_pixel, false),
+                    (_, _, true) => (sprite_pixel, true),
+                    (_, _, false) => (bg_pixel, false),
+                };
 
-            // Read the palette value for the current pixel
-            let palette_index = self.read_byte(0x3F00 | multiplexed_pixel as u16) & 0x3F;
+            if self.hd_renderer.is_some() {
+                let bg_visible = self.ppu_mask.show_background && (self.ppu_mask.show_background_left_side || x > 7);
+                self.record_hd_pixel(x, y, is_sprite_pixel, sprite_index, bg_visible, multiplexed_pixel);
+            }
 
-            palette::PALETTE_2C02[palette_index as usize]
+            // Read the palette value for the current pixel, palette RAM is inside the PPU so this never
+            // reaches the address bus
+            let palette_index = (self.palette_ram.read_byte(0x3F00 | multiplexed_pixel as u16) & 0x3F) as u16;
+            (palette_index, multiplexed_pixel)
         } else if self.internal_registers.vram_addr & 0x3F00 == 0x3F00 {
-            palette::PALETTE_2C02[self.internal_registers.vram_addr as usize & 0x1F]
+            let palette_address = self.internal_registers.vram_addr & 0x1F;
+            (palette_address, palette_address as u8)
         } else {
-            0x0
+            self.index_buffer[offset] = BLANK_PIXEL;
+            return;
+        };
+
+        self.index_buffer[offset] =
+            palette_index | self.ppu_mask.emphasis_bits() | (palette_address as u16) << PALETTE_ADDRESS_SHIFT;
+    }
+
+    /// Track which tile (if any) the pixel just drawn came from so that the
+    /// HD renderer can substitute the replacement texture at the end of the frame
+    fn record_hd_pixel(
+        &mut self,
+        x: u32,
+        y: u32,
+        is_sprite_pixel: bool,
+        sprite_index: Option<usize>,
+        bg_visible: bool,
+        multiplexed_pixel: u8,
+    ) {
+        let (tile, palette_base) = match (is_sprite_pixel, sprite_index) {
+            (true, Some(sprite_index)) => (self.sprite_hd_tile(sprite_index, x), multiplexed_pixel & 0b1_1100),
+            _ if bg_visible => {
+                // Each dot shifts the background registers so the offset into the
+                // registers is the fine x scroll plus the dots since the last reload
+                let offset = self.internal_registers.fine_x_scroll + (x as u8 & 7);
+                let palette_base = multiplexed_pixel & 0b1100;
+                let tile = self.hd_renderer.as_ref().and_then(|r| r.bg_tile_at(offset));
+
+                (tile, palette_base)
+            }
+            _ => (None, 0),
         };
 
-        self.frame_buffer[offset] = (color & 0xFF) as u8; // Blue channel
-        self.frame_buffer[offset + 1] = ((color >> 8) & 0xFF) as u8; // Green channel
-        self.frame_buffer[offset + 2] = (color >> 16) as u8; // Red channel
-        self.frame_buffer[offset + 3] = 0x00; // Alpha channel
+        let palette = [
+            self.palette_ram.read_byte(0x3F00) & 0x3F,
+            self.palette_ram.read_byte(0x3F01 | palette_base as u16) & 0x3F,
+            self.palette_ram.read_byte(0x3F02 | palette_base as u16) & 0x3F,
+            self.palette_ram.read_byte(0x3F03 | palette_base as u16) & 0x3F,
+        ];
+
+        if let Some(renderer) = &mut self.hd_renderer {
+            renderer.set_pixel(x, y, tile, palette);
+        }
     }
 
     fn handle_prerender_scanline_cycle(&mut self, cycle: u16) {
         if cycle == 0 {
             self.ppu_status.sprite_overflow = false;
             self.ppu_status.sprite_zero_hit = false;
-            self.frame_buffer.iter_mut().for_each(|m| *m = 0);
-            self.priorities.iter_mut().for_each(|m| *m = 0);
+            self.index_buffer.iter_mut().for_each(|m| *m = BLANK_PIXEL);
             self.sprite_data.clear_sprites();
+            if let Some(renderer) = &mut self.hd_renderer {
+                renderer.clear();
+            }
         } else if cycle == 1 {
             self.ppu_status.vblank_started = false;
         } else if (cycle >= 280) && (cycle <= 304) && self.ppu_mask.is_rendering_enabled() {
@@ -606,6 +1071,27 @@ impl Ppu {
     }
 }
 
+// The HD renderer and bus recorder are debugging and display aids so aren't saved, the sprite
+// limit, palette and palette tint are display options
+save_state_fields!(Ppu {
+    total_cycles,
+    frame_number,
+    scanline_state,
+    sprite_data,
+    palette_ram,
+    ppu_ctrl,
+    ppu_mask,
+    ppu_status,
+    last_ppu_status_read_cycle,
+    internal_registers,
+    ppu_data_buffer,
+    last_written_byte,
+    last_written_byte_cycle,
+    scheduler,
+    index_buffer,
+    chr_address_bus,
+});
+
 pub enum PpuIteratorState {
     NormalCycle,
     ReadyToRender,
@@ -646,9 +1132,21 @@ impl Iterator for Ppu {
                     }
                 }
 
-                if self.scanline_state.scanline != 261 && self.scanline_state.dot >= 1 && self.scanline_state.dot <= 256
-                {
-                    self.draw_pixel(self.scanline_state.scanline, self.scanline_state.dot);
+                if self.scanline_state.scanline != 261 {
+                    // The HD renderer matches background tiles to pixels as they're shifted out
+                    if self.scanline_state.dot == 1 {
+                        self.scanline_state.draw_by_dot = !self.accuracy.scanline_rendering()
+                            || self.hd_renderer.is_some()
+                            || self.sprite_zero_hit_possible(self.scanline_state.scanline);
+                    }
+
+                    match self.scanline_state.dot {
+                        1..=256 if self.scanline_state.draw_by_dot => {
+                            self.draw_pixel(self.scanline_state.scanline, self.scanline_state.dot)
+                        }
+                        256 => self.draw_scanline(self.scanline_state.scanline),
+                        _ => (),
+                    }
                 }
 
                 if self.scanline_state.scanline == 261 {
@@ -664,7 +1162,7 @@ impl Iterator for Ppu {
 
                         // Trigger a NMI as both vblank flag and nmi enabled are pulled up
                         if self.ppu_ctrl.nmi_enable {
-                            self.nmi_interrupt = Some(Interrupt::NMI(self.total_cycles));
+                            self.scheduler.schedule(PpuEvent::Nmi, self.total_cycles, NMI_DELAY);
                             info!("Triggering NMI");
                         }
                     } else {
@@ -682,9 +1180,10 @@ impl Iterator for Ppu {
 
         // Check for rendering enabled update (delayed by one cycle from write)
         self.ppu_mask.update_rendering_enabled();
+        self.frame_info.rendering_enabled |= self.is_rendering_visible_scanline();
 
-        // Track total PPU cycles for components which need to know. Bit sketchy here that it wraps
-        self.total_cycles = self.total_cycles.wrapping_add(1);
+        // Track total PPU cycles for components which need to know, 64 bits so it never wraps in practice
+        self.total_cycles += 1;
 
         // Track current frame number, partially for debugging and partially to
         // tell whether even or odd frame
@@ -693,6 +1192,19 @@ impl Iterator for Ppu {
         }
 
         if self.scanline_state.scanline == 241 && self.scanline_state.dot == 0 {
+            self.resolve_frame_buffer();
+            if let Some(renderer) = &mut self.hd_renderer {
+                renderer.render(&self.frame_buffer[..]);
+            }
+            if let Some(recorder) = &mut self.sprite_stats {
+                recorder.complete_frame();
+            }
+            self.completed_frame_info = Some(FrameInfo {
+                frame_number: self.frame_number,
+                odd_frame: self.frame_number & 1 == 1,
+                ..std::mem::take(&mut self.frame_info)
+            });
+
             Some(PpuIteratorState::ReadyToRender)
         } else {
             Some(PpuIteratorState::NormalCycle)
@@ -702,22 +1214,32 @@ impl Iterator for Ppu {
 
 #[cfg(test)]
 mod ppu_tests {
-    use cartridge::PpuCartridgeAddressBus;
+    use accuracy::AccuracyProfile;
+    use cartridge::{BackgroundTileFetch, BackgroundTileOverride, PpuCartridgeAddressBus};
     use cpu::CpuCycle;
-    use ppu::Ppu;
     use ppu::PpuCycle;
+    use ppu::{apply_emphasis, InternalRegisters, Ppu};
+    use proptest::prelude::*;
+    use savestate::{SaveState, StateReader, StateWriter};
 
+    /// Every address reads back as its low byte so tests can tell which address was read
     struct FakeCartridge {}
 
+    save_state_fields!(FakeCartridge {});
+
     impl PpuCartridgeAddressBus for FakeCartridge {
-        fn check_trigger_irq(&mut self, _: bool) -> bool {
+        fn check_trigger_irq(&mut self, _: bool, _: CpuCycle) -> bool {
             false
         }
 
         fn update_vram_address(&mut self, _: u16, _: PpuCycle) {}
 
-        fn read_byte(&mut self, _: u16, _: PpuCycle) -> u8 {
-            0x0
+        fn peek_byte(&self, address: u16) -> u8 {
+            address as u8
+        }
+
+        fn read_byte(&mut self, address: u16, _: PpuCycle) -> u8 {
+            address as u8
         }
 
         fn write_byte(&mut self, _: u16, _: u8, _: PpuCycle) {}
@@ -727,7 +1249,7 @@ mod ppu_tests {
 
     #[test]
     fn test_setting_vram_addr() {
-        let mut ppu = Ppu::new(Box::new(FakeCartridge {}));
+        let mut ppu = Ppu::new(Box::new(FakeCartridge {}), AccuracyProfile::Balanced);
         ppu.write_register(0x2000, 0);
         ppu.read_register(0x2002);
         ppu.write_register(0x2005, 0x7D);
@@ -746,7 +1268,7 @@ mod ppu_tests {
 
     #[test]
     fn test_setting_vram_addr_v2() {
-        let mut ppu = Ppu::new(Box::new(FakeCartridge {}));
+        let mut ppu = Ppu::new(Box::new(FakeCartridge {}), AccuracyProfile::Balanced);
         ppu.write_register(0x2006, 0x04);
         assert_eq!(ppu.internal_registers.temp_vram_addr, 0b0000100_00000000);
         ppu.write_register(0x2005, 0x3E);
@@ -760,4 +1282,320 @@ mod ppu_tests {
         assert_eq!(ppu.internal_registers.vram_addr, 0b1100100_11101111);
         assert_eq!(ppu.internal_registers.fine_x_scroll, 0b101);
     }
+
+    #[test]
+    fn test_nametable_writes_during_rendering_detected() {
+        let mut ppu = Ppu::new(Box::new(FakeCartridge {}), AccuracyProfile::Balanced);
+        ppu.write_register(0x2006, 0x24);
+        ppu.write_register(0x2006, 0x10);
+        assert_eq!(ppu.nametable_write_during_rendering(), None);
+
+        ppu.write_register(0x2001, 0b0001_1000);
+        ppu.ppu_mask.update_rendering_enabled();
+        assert_eq!(ppu.nametable_write_during_rendering(), Some(0x2410));
+        ppu.scanline_state.scanline = 241;
+        assert_eq!(ppu.nametable_write_during_rendering(), None);
+        ppu.scanline_state.scanline = 261;
+        assert_eq!(ppu.nametable_write_during_rendering(), Some(0x2410));
+
+        // Pattern tables and palette RAM aren't nametables
+        for address in &[0x0010u16, 0x3F00] {
+            ppu.write_register(0x2006, (address >> 8) as u8);
+            ppu.write_register(0x2006, *address as u8);
+            assert_eq!(ppu.nametable_write_during_rendering(), None);
+        }
+    }
+
+    #[test]
+    fn test_open_bus_decays_only_when_accurate() {
+        for (accuracy, expected) in &[(AccuracyProfile::Accurate, 0x00), (AccuracyProfile::Balanced, 0x5A)] {
+            let mut ppu = Ppu::new(Box::new(FakeCartridge {}), *accuracy);
+            ppu.write_register(0x2003, 0x5A);
+            assert_eq!(ppu.read_register(0x2005), 0x5A);
+            ppu.total_cycles += super::OPEN_BUS_DECAY_CYCLES + 1;
+            assert_eq!(ppu.read_register(0x2005), *expected);
+        }
+    }
+
+    fn set_vram_addr(ppu: &mut Ppu, address: u16) {
+        ppu.write_register(0x2006, (address >> 8) as u8);
+        ppu.write_register(0x2006, address as u8);
+    }
+
+    #[test]
+    fn test_palette_reads_bypass_buffer() {
+        let mut ppu = Ppu::new(Box::new(FakeCartridge {}), AccuracyProfile::Balanced);
+        set_vram_addr(&mut ppu, 0x3F05);
+        ppu.write_register(0x2007, 0x2A);
+
+        set_vram_addr(&mut ppu, 0x2105);
+        ppu.read_register(0x2007);
+        set_vram_addr(&mut ppu, 0x3F05);
+        assert_eq!(ppu.read_register(0x2007), 0x2A);
+
+        // The buffer now holds the nametable byte under the palette (0x2F05) rather than 0x2105
+        set_vram_addr(&mut ppu, 0x2000);
+        assert_eq!(ppu.read_register(0x2007), 0x05);
+        set_vram_addr(&mut ppu, 0x3F1D);
+        ppu.read_register(0x2007);
+        set_vram_addr(&mut ppu, 0x2000);
+        assert_eq!(ppu.read_register(0x2007), 0x1D);
+    }
+
+    #[test]
+    fn test_palette_reads_greyscale() {
+        let mut ppu = Ppu::new(Box::new(FakeCartridge {}), AccuracyProfile::Balanced);
+        set_vram_addr(&mut ppu, 0x3F01);
+        ppu.write_register(0x2007, 0x2A);
+
+        ppu.write_register(0x2001, 0b0000_0001);
+        set_vram_addr(&mut ppu, 0x3F01);
+        assert_eq!(ppu.read_register(0x2007), 0x20);
+
+        // The palette itself is unchanged
+        ppu.write_register(0x2001, 0);
+        set_vram_addr(&mut ppu, 0x3F01);
+        assert_eq!(ppu.read_register(0x2007), 0x2A);
+    }
+
+    #[test]
+    fn test_read_buffer_saved_in_state() {
+        let mut ppu = Ppu::new(Box::new(FakeCartridge {}), AccuracyProfile::Balanced);
+        set_vram_addr(&mut ppu, 0x2142);
+        ppu.read_register(0x2007);
+        let mut writer = StateWriter::new();
+        ppu.save_state(&mut writer);
+        let state = writer.into_bytes();
+
+        let mut loaded = Ppu::new(Box::new(FakeCartridge {}), AccuracyProfile::Balanced);
+        let mut reader = StateReader::new(&state).unwrap();
+        loaded.load_state(&mut reader).unwrap();
+        reader.finish().unwrap();
+
+        assert_eq!(loaded.read_register(0x2007), 0x42);
+        assert_eq!(loaded.internal_registers.vram_addr, 0x2144);
+    }
+
+    /// Replaces every background tile the way a split screen would, with its own tile, palette
+    /// and scroll, and serves the patterns from another bank
+    struct SplitCartridge {}
+
+    save_state_fields!(SplitCartridge {});
+
+    impl PpuCartridgeAddressBus for SplitCartridge {
+        fn check_trigger_irq(&mut self, _: bool, _: CpuCycle) -> bool {
+            false
+        }
+
+        fn update_vram_address(&mut self, _: u16, _: PpuCycle) {}
+
+        fn peek_byte(&self, address: u16) -> u8 {
+            address as u8
+        }
+
+        fn read_byte(&mut self, address: u16, _: PpuCycle) -> u8 {
+            address as u8
+        }
+
+        fn write_byte(&mut self, _: u16, _: u8, _: PpuCycle) {}
+
+        fn cpu_write_byte(&mut self, _: u16, _: u8, _: CpuCycle) {}
+
+        fn background_tile_fetch(&mut self, fetch: &BackgroundTileFetch) -> BackgroundTileOverride {
+            assert_eq!(
+                *fetch,
+                BackgroundTileFetch {
+                    column: 2,
+                    scanline: 0,
+                    nametable_address: 0x2000,
+                    nametable_byte: 0x00,
+                }
+            );
+            BackgroundTileOverride {
+                nametable_byte: Some(0x12),
+                palette: Some(0b11),
+                fine_y: Some(5),
+            }
+        }
+
+        fn read_background_pattern(&mut self, address: u16, _: PpuCycle) -> u8 {
+            !(address as u8)
+        }
+    }
+
+    #[test]
+    fn test_mapper_overrides_background_tile() {
+        let mut ppu = Ppu::new(Box::new(SplitCartridge {}), AccuracyProfile::Balanced);
+        for cycle in 1..=9 {
+            ppu.fetch_data(cycle);
+        }
+
+        // Pattern rows 0x125 and 0x12D come from the mapper's bank rather than the pattern table
+        assert_eq!(ppu.scanline_state.nametable_byte, 0x12);
+        assert_eq!(ppu.scanline_state.bg_low_byte, !0x25);
+        assert_eq!(ppu.scanline_state.bg_high_byte, !0x2D);
+        assert_eq!(ppu.scanline_state.at_shift_latch_low, 1);
+        assert_eq!(ppu.scanline_state.at_shift_latch_high, 1);
+    }
+
+    #[derive(Debug, Clone)]
+    enum RegisterWrite {
+        Ctrl(u8),
+        StatusRead,
+        Scroll(u8),
+        Addr(u8),
+    }
+
+    fn register_write() -> impl Strategy<Value = RegisterWrite> {
+        prop_oneof![
+            // NMI is left disabled so that writes don't schedule one
+            (0u8..0x80).prop_map(RegisterWrite::Ctrl),
+            Just(RegisterWrite::StatusRead),
+            any::<u8>().prop_map(RegisterWrite::Scroll),
+            any::<u8>().prop_map(RegisterWrite::Addr),
+        ]
+    }
+
+    /// The t/v/x/w transfers as written up on the nesdev wiki, kept separate from the PPU so
+    /// that the two can be checked against each other
+    #[derive(Debug, Default, PartialEq)]
+    struct LoopyModel {
+        v: u16,
+        t: u16,
+        x: u8,
+        w: bool,
+    }
+
+    impl LoopyModel {
+        fn apply(&mut self, write: &RegisterWrite) {
+            match *write {
+                RegisterWrite::Ctrl(d) => self.t = (self.t & !0x0C00) | ((d as u16 & 0b11) << 10),
+                RegisterWrite::StatusRead => self.w = false,
+                RegisterWrite::Scroll(d) if !self.w => {
+                    self.t = (self.t & !0x001F) | (d as u16 >> 3);
+                    self.x = d & 0b111;
+                    self.w = true;
+                }
+                RegisterWrite::Scroll(d) => {
+                    self.t = (self.t & !0x73E0) | ((d as u16 & 0b111) << 12) | ((d as u16 & 0xF8) << 2);
+                    self.w = false;
+                }
+                RegisterWrite::Addr(d) if !self.w => {
+                    self.t = (self.t & 0x00FF) | ((d as u16 & 0x3F) << 8);
+                    self.w = true;
+                }
+                RegisterWrite::Addr(d) => {
+                    self.t = (self.t & 0xFF00) | d as u16;
+                    self.v = self.t;
+                    self.w = false;
+                }
+            }
+        }
+    }
+
+    fn registers(vram_addr: u16) -> InternalRegisters {
+        InternalRegisters {
+            vram_addr,
+            temp_vram_addr: 0,
+            fine_x_scroll: 0,
+            write_toggle: false,
+            next_address: 0,
+        }
+    }
+
+    #[test]
+    fn test_emphasis_darkens_the_other_channels() {
+        let color = 0x80_80_80;
+        assert_eq!(apply_emphasis(color, 0x2A), color);
+        // Red emphasis darkens green and blue
+        assert_eq!(apply_emphasis(color, 0x2A | 0b001 << 6), 0x80_68_68);
+        // Green and blue emphasis together darken red, and green and blue by each other
+        assert_eq!(apply_emphasis(color, 0x2A | 0b110 << 6), 0x68_68_68);
+        assert_eq!(apply_emphasis(color, 0x2A | 0b100 << 6), 0x68_68_80);
+    }
+
+    proptest! {
+        #[test]
+        fn test_register_writes_match_model(writes in prop::collection::vec(register_write(), 0..32)) {
+            let mut ppu = Ppu::new(Box::new(FakeCartridge {}), AccuracyProfile::Balanced);
+            let mut model = LoopyModel::default();
+
+            for write in &writes {
+                match *write {
+                    RegisterWrite::Ctrl(value) => ppu.write_register(0x2000, value),
+                    RegisterWrite::StatusRead => {
+                        ppu.read_register(0x2002);
+                    }
+                    RegisterWrite::Scroll(value) => ppu.write_register(0x2005, value),
+                    RegisterWrite::Addr(value) => ppu.write_register(0x2006, value),
+                }
+                model.apply(write);
+
+                let registers = &ppu.internal_registers;
+                prop_assert_eq!(
+                    &LoopyModel {
+                        v: registers.vram_addr,
+                        t: registers.temp_vram_addr,
+                        x: registers.fine_x_scroll,
+                        w: registers.write_toggle,
+                    },
+                    &model
+                );
+            }
+        }
+
+        #[test]
+        fn test_increment_scroll_x_wraps_into_next_nametable(vram_addr in 0u16..0x8000) {
+            let mut registers = registers(vram_addr);
+            registers.increment_effective_scroll_x();
+
+            let coarse_x = vram_addr & 0x1F;
+            prop_assert_eq!(registers.coarse_x() as u16, (coarse_x + 1) % 32);
+            prop_assert_eq!(registers.vram_addr & 0x0400 != vram_addr & 0x0400, coarse_x == 31);
+            // Fine y, coarse y and the vertical nametable are untouched
+            prop_assert_eq!(registers.vram_addr & 0x7BE0, vram_addr & 0x7BE0);
+
+            // Two full nametables widths later it's back where it started
+            for _ in 1..64 {
+                registers.increment_effective_scroll_x();
+            }
+            prop_assert_eq!(registers.vram_addr, vram_addr);
+        }
+
+        #[test]
+        fn test_increment_scroll_y_wraps_into_next_nametable(vram_addr in 0u16..0x8000) {
+            let mut registers = registers(vram_addr);
+            registers.increment_effective_scroll_y();
+
+            let fine_y = (vram_addr >> 12) as u8;
+            let coarse_y = ((vram_addr >> 5) & 0x1F) as u8;
+            let nametable_switched = registers.vram_addr & 0x0800 != vram_addr & 0x0800;
+            // Coarse x and the horizontal nametable are untouched
+            prop_assert_eq!(registers.vram_addr & 0x041F, vram_addr & 0x041F);
+            if fine_y < 7 {
+                prop_assert_eq!(registers.fine_y(), fine_y + 1);
+                prop_assert_eq!(registers.coarse_y(), coarse_y);
+                prop_assert!(!nametable_switched);
+            } else {
+                // Rows 30 and 31 are the attribute table, they wrap without switching nametable
+                prop_assert_eq!(registers.fine_y(), 0);
+                prop_assert_eq!(registers.coarse_y(), match coarse_y {
+                    29 | 31 => 0,
+                    _ => coarse_y + 1,
+                });
+                prop_assert_eq!(nametable_switched, coarse_y == 29);
+            }
+        }
+
+        #[test]
+        fn test_increment_scroll_y_cycles_through_two_nametables(vram_addr in 0u16..0x8000) {
+            // Every row of two nametables, only possible from inside the nametable
+            prop_assume!((vram_addr >> 5) & 0x1F < 30);
+            let mut registers = registers(vram_addr);
+            for _ in 0..30 * 8 * 2 {
+                registers.increment_effective_scroll_y();
+            }
+            prop_assert_eq!(registers.vram_addr, vram_addr);
+        }
+    }
 }
@@ -44,6 +44,8 @@ impl PaletteRam {
     }
 }
 
+save_state_fields!(PaletteRam { data });
+
 #[cfg(test)]
 mod palette_ram_tests {
     use super::PaletteRam;
@@ -0,0 +1,205 @@
+use std::f32::consts::PI;
+use std::fmt::{Display, Formatter, Result};
+use std::str::FromStr;
+
+/// Voltage of the composite signal for each of the 4 luma levels, the first four are the low
+/// half of the square wave and the last four the high half.
+/// Taken from https://wiki.nesdev.com/w/index.php/NTSC_video
+const SIGNAL_LEVELS: [f32; 8] = [0.350, 0.518, 0.962, 1.550, 1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f32 = SIGNAL_LEVELS[1];
+const SIGNAL_WHITE: f32 = SIGNAL_LEVELS[6];
+
+/// The phase (in twelfths of a colour cycle) of the colour burst relative to colour 0, chosen
+/// so that with no hue adjustment the colours line up with the default palette.
+const COLOR_BURST_PHASE: f32 = 4.0;
+
+/// The gamma which the decoded signal is assumed to already be encoded with
+const SOURCE_GAMMA: f32 = 2.2;
+
+/// Which television standard is emulated when decoding the signal into colours
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PaletteRegion {
+    Ntsc,
+    /// PAL inverts the chroma phase on alternating lines so that hue errors cancel out,
+    /// the hue setting reduces saturation rather than rotating colours.
+    Pal,
+}
+
+impl Display for PaletteRegion {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            PaletteRegion::Ntsc => write!(f, "ntsc"),
+            PaletteRegion::Pal => write!(f, "pal"),
+        }
+    }
+}
+
+impl FromStr for PaletteRegion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(PaletteRegion::Ntsc),
+            "pal" => Ok(PaletteRegion::Pal),
+            _ => Err(format!("Unknown palette region {}, expected ntsc or pal", s)),
+        }
+    }
+}
+
+/// Generates the 64 colour palette by decoding the composite video signal that the PPU
+/// outputs for each colour, allowing the picture to be tuned like a television's controls.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaletteSettings {
+    pub region: PaletteRegion,
+    /// Rotation of the decoded colours in degrees
+    pub hue: f32,
+    /// Multiplier applied to the chroma signal, 0 is greyscale
+    pub saturation: f32,
+    /// Offset added to the luma signal, 0 leaves it unchanged
+    pub brightness: f32,
+    /// The gamma of the display, 2.2 leaves the decoded signal unchanged
+    pub gamma: f32,
+}
+
+impl Default for PaletteSettings {
+    fn default() -> Self {
+        PaletteSettings {
+            region: PaletteRegion::Ntsc,
+            hue: 0.0,
+            saturation: 1.0,
+            brightness: 0.0,
+            gamma: SOURCE_GAMMA,
+        }
+    }
+}
+
+impl PaletteSettings {
+    /// Produce the palette in the same 0xRRGGBB form as the built in 2C02 palette
+    pub fn generate(&self) -> [u32; 0x40] {
+        let mut palette = [0; 0x40];
+        for (color, entry) in palette.iter_mut().enumerate() {
+            *entry = self.decode(color as u8);
+        }
+
+        palette
+    }
+
+    fn decode(&self, color: u8) -> u32 {
+        // Sample the signal at 12 points across a single colour cycle, averaging gives the
+        // luma and multiplying by the colour carrier demodulates the chroma
+        let mut y = 0.0;
+        let mut i = 0.0;
+        let mut q = 0.0;
+        for phase in 0..12 {
+            let level = (signal_level(color, phase) - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK);
+            let angle = PI * (phase as f32 + COLOR_BURST_PHASE) / 6.0;
+            y += level;
+            i += level * angle.cos();
+            q += level * angle.sin();
+        }
+        y = y / 12.0 + self.brightness;
+        i = i / 12.0 * self.saturation;
+        q = q / 12.0 * self.saturation;
+
+        let hue = self.hue.to_radians();
+        let (i, q) = match self.region {
+            PaletteRegion::Ntsc => (i * hue.cos() - q * hue.sin(), i * hue.sin() + q * hue.cos()),
+            PaletteRegion::Pal => (i * hue.cos(), q * hue.cos()),
+        };
+
+        let r = y + 0.946_882 * i + 0.623_557 * q;
+        let g = y - 0.274_788 * i - 0.635_691 * q;
+        let b = y - 1.108_545 * i + 1.709_007 * q;
+
+        (self.gamma_corrected_byte(r) << 16) | (self.gamma_corrected_byte(g) << 8) | self.gamma_corrected_byte(b)
+    }
+
+    fn gamma_corrected_byte(&self, value: f32) -> u32 {
+        let corrected = value.max(0.0).powf(SOURCE_GAMMA / self.gamma);
+        (corrected * 255.0).round().min(255.0) as u32
+    }
+}
+
+/// The signal voltage output for a colour at a given point (0-11) in the colour cycle
+fn signal_level(color: u8, phase: u8) -> f32 {
+    let hue = color & 0xF;
+    let luma = if hue > 0xD { 1 } else { (color >> 4) as usize & 3 };
+
+    let mut low = SIGNAL_LEVELS[luma];
+    let mut high = SIGNAL_LEVELS[4 + luma];
+    if hue == 0 {
+        low = high;
+    } else if hue > 0xC {
+        high = low;
+    }
+
+    if (hue + phase) % 12 < 6 {
+        high
+    } else {
+        low
+    }
+}
+
+#[cfg(test)]
+mod palette_generator_tests {
+    use super::*;
+
+    fn channels(color: u32) -> (u32, u32, u32) {
+        (color >> 16, (color >> 8) & 0xFF, color & 0xFF)
+    }
+
+    #[test]
+    fn test_default_palette_has_expected_colours() {
+        let palette = PaletteSettings::default().generate();
+
+        assert_eq!(palette[0x0F], 0x000000);
+        assert_eq!(palette[0x0D], 0x000000);
+        assert_eq!(palette[0x20], 0xFFFFFF);
+
+        let (r, g, b) = channels(palette[0x16]);
+        assert!(r > g && r > b, "0x16 should be red, was {:06X}", palette[0x16]);
+        let (r, g, b) = channels(palette[0x12]);
+        assert!(b > r && b > g, "0x12 should be blue, was {:06X}", palette[0x12]);
+        let (r, g, b) = channels(palette[0x1A]);
+        assert!(g > r && g > b, "0x1A should be green, was {:06X}", palette[0x1A]);
+    }
+
+    #[test]
+    fn test_zero_saturation_is_greyscale() {
+        let settings = PaletteSettings {
+            saturation: 0.0,
+            ..PaletteSettings::default()
+        };
+
+        for color in settings.generate().iter() {
+            let (r, g, b) = channels(*color);
+            assert!(r == g && g == b, "{:06X} isn't grey", color);
+        }
+    }
+
+    #[test]
+    fn test_pal_hue_errors_reduce_saturation() {
+        let pal = PaletteSettings {
+            region: PaletteRegion::Pal,
+            hue: 60.0,
+            ..PaletteSettings::default()
+        };
+        let desaturated = PaletteSettings {
+            saturation: 0.5,
+            ..PaletteSettings::default()
+        };
+
+        for (pal_color, desaturated_color) in pal.generate().iter().zip(desaturated.generate().iter()) {
+            let (r1, g1, b1) = channels(*pal_color);
+            let (r2, g2, b2) = channels(*desaturated_color);
+            for (c1, c2) in &[(r1, r2), (g1, g2), (b1, b2)] {
+                assert!(
+                    (*c1 as i32 - *c2 as i32).abs() <= 1,
+                    "{:06X} != {:06X}",
+                    pal_color,
+                    desaturated_color
+                );
+            }
+        }
+    }
+}
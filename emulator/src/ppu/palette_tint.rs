@@ -0,0 +1,58 @@
+/// Mixes a colour into each pixel according to the palette it was drawn with, 0-3 for the
+/// background and 4-7 for sprites, so that attribute table mistakes stand out when editing a rom's
+/// graphics. The backdrop colour isn't tinted.
+///
+/// This is applied as the framebuffer is resolved from the index buffer, it doesn't change emulation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaletteTint {
+    /// The colour for each palette as 0xRRGGBB
+    pub colors: [u32; 8],
+    /// How much of the pixel's colour is replaced by the tint from 0 (none) to 1 (all of it)
+    pub strength: f32,
+}
+
+impl Default for PaletteTint {
+    fn default() -> Self {
+        PaletteTint {
+            colors: [
+                0xFF0000, 0x00FF00, 0x0000FF, 0xFFFF00, // Background
+                0xFF00FF, 0x00FFFF, 0xFF8000, 0xFFFFFF, // Sprites
+            ],
+            strength: 0.5,
+        }
+    }
+}
+
+impl PaletteTint {
+    /// Tint a colour read from `palette_address` (0-0x1F) in palette RAM
+    pub(super) fn apply(&self, color: u32, palette_address: u8) -> u32 {
+        if palette_address & 0b11 == 0 {
+            return color;
+        }
+
+        let tint = self.colors[(palette_address >> 2) as usize & 0b111];
+        let strength = (self.strength.clamp(0.0, 1.0) * 256.0) as u32;
+        (0..3).fold(0, |tinted, channel| {
+            let shift = channel * 8;
+            let (color, tint) = ((color >> shift) & 0xFF, (tint >> shift) & 0xFF);
+            tinted | ((color * (256 - strength) + tint * strength) >> 8) << shift
+        })
+    }
+}
+
+#[cfg(test)]
+mod palette_tint_tests {
+    use super::*;
+
+    #[test]
+    fn test_tints_by_palette_except_backdrop() {
+        let tint = PaletteTint::default();
+        assert_eq!(tint.apply(0x202020, 0x00), 0x202020);
+        assert_eq!(tint.apply(0x202020, 0x04), 0x202020);
+        assert_eq!(tint.apply(0x202020, 0x01), 0x8F1010);
+        assert_eq!(tint.apply(0x202020, 0x17), 0x108F8F);
+
+        let full = PaletteTint { strength: 1.0, ..tint };
+        assert_eq!(full.apply(0x202020, 0x1E), 0xFFFFFF);
+    }
+}
@@ -5,6 +5,11 @@ pub(crate) enum IncrementMode {
     Add32GoingDown,
 }
 
+save_state_enum!(IncrementMode {
+    Add1GoingAcross,
+    Add32GoingDown
+});
+
 #[derive(Debug)]
 #[repr(u8)]
 pub(crate) enum SpriteSize {
@@ -21,6 +26,8 @@ impl SpriteSize {
     }
 }
 
+save_state_enum!(SpriteSize { X8, X16 });
+
 #[derive(Debug)]
 pub(crate) struct PpuCtrl {
     pub(crate) base_name_table_select: u16,
@@ -69,3 +76,13 @@ impl PpuCtrl {
         self.nmi_enable = value & 0b1000_0000 != 0; // TODO - This should trigger immediate interrupt if in vblank area
     }
 }
+
+save_state_fields!(PpuCtrl {
+    base_name_table_select,
+    increment_mode,
+    sprite_tile_table_select,
+    background_tile_table_select,
+    sprite_size,
+    ppu_master_slave,
+    nmi_enable,
+});
@@ -32,11 +32,25 @@ impl PpuMask {
         self.show_sprites_left_side = value & 0b100 == 0b100;
         self.show_background = value & 0b1000 == 0b1000;
         self.show_sprites = value & 0b1_0000 == 0b1_0000;
-        self.emphasize_red = value & 0b10_0000 == 0b10_0000; // TODO - Actually use these masks!
+        self.emphasize_red = value & 0b10_0000 == 0b10_0000;
         self.emphasize_green = value & 0b100_0000 == 0b100_0000;
         self.emphasize_blue = value & 0b1000_0000 == 0b1000_0000;
     }
 
+    /// Greyscale keeps only the brightness bits of a colour, including palette values read back
+    /// through PPUDATA
+    pub(crate) fn colour_mask(&self) -> u8 {
+        match self.is_grayscale {
+            true => 0x30,
+            false => 0x3F,
+        }
+    }
+
+    /// The emphasis bits as stored above the palette index in the index buffer
+    pub(crate) fn emphasis_bits(&self) -> u16 {
+        (self.emphasize_red as u16) << 6 | (self.emphasize_green as u16) << 7 | (self.emphasize_blue as u16) << 8
+    }
+
     pub(crate) fn update_rendering_enabled(&mut self) {
         self.rendering_enabled = self.show_background || self.show_sprites;
     }
@@ -45,3 +59,15 @@ impl PpuMask {
         self.rendering_enabled
     }
 }
+
+save_state_fields!(PpuMask {
+    is_grayscale,
+    show_background_left_side,
+    show_sprites_left_side,
+    show_background,
+    show_sprites,
+    emphasize_red,
+    emphasize_green,
+    emphasize_blue,
+    rendering_enabled,
+});
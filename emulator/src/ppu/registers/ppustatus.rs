@@ -34,3 +34,9 @@ impl PpuStatus {
         result
     }
 }
+
+save_state_fields!(PpuStatus {
+    sprite_overflow,
+    sprite_zero_hit,
+    vblank_started,
+});
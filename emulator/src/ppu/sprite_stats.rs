@@ -0,0 +1,76 @@
+use ppu::SCREEN_HEIGHT;
+
+/// Sprite evaluation results for a single frame, intended for explaining flicker (e.g. as a
+/// heatmap over the frame) and for asserting on sprite evaluation without comparing pixels.
+///
+/// Evaluation for a line happens during the previous scanline, so the sprites counted at index
+/// N are drawn on scanline N + 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpriteStats {
+    /// The number of sprites in range during evaluation on each visible scanline, including any
+    /// beyond the 8 which the hardware can draw
+    pub sprites_per_line: [u8; SCREEN_HEIGHT as usize],
+    /// Whether the sprite overflow flag was set during evaluation on each visible scanline. This
+    /// follows the hardware (including its bugs) so can differ from `sprites_per_line` > 8.
+    pub overflow_lines: [bool; SCREEN_HEIGHT as usize],
+    /// The (dot, scanline) at which sprite zero hit occurred
+    pub sprite_zero_hit: Option<(u16, u16)>,
+}
+
+impl SpriteStats {
+    fn new() -> Self {
+        SpriteStats {
+            sprites_per_line: [0; SCREEN_HEIGHT as usize],
+            overflow_lines: [false; SCREEN_HEIGHT as usize],
+            sprite_zero_hit: None,
+        }
+    }
+
+    /// The first scanline on which the sprite overflow flag was set
+    pub fn first_overflow_line(&self) -> Option<u16> {
+        self.overflow_lines
+            .iter()
+            .position(|overflow| *overflow)
+            .map(|line| line as u16)
+    }
+}
+
+/// Collects `SpriteStats` for the frame being rendered, keeping those from the last complete frame
+pub(super) struct SpriteStatsRecorder {
+    current: SpriteStats,
+    completed: Option<SpriteStats>,
+}
+
+impl SpriteStatsRecorder {
+    pub(super) fn new() -> Self {
+        SpriteStatsRecorder {
+            current: SpriteStats::new(),
+            completed: None,
+        }
+    }
+
+    pub(super) fn record_sprites_in_range(&mut self, scanline: u16, count: u8) {
+        if let Some(sprites) = self.current.sprites_per_line.get_mut(scanline as usize) {
+            *sprites = count;
+        }
+    }
+
+    pub(super) fn record_overflow(&mut self, scanline: u16) {
+        if let Some(overflow) = self.current.overflow_lines.get_mut(scanline as usize) {
+            *overflow = true;
+        }
+    }
+
+    pub(super) fn record_sprite_zero_hit(&mut self, dot: u16, scanline: u16) {
+        self.current.sprite_zero_hit = Some((dot, scanline));
+    }
+
+    /// Called once the visible scanlines have finished
+    pub(super) fn complete_frame(&mut self) {
+        self.completed = Some(std::mem::replace(&mut self.current, SpriteStats::new()));
+    }
+
+    pub(super) fn completed(&self) -> Option<&SpriteStats> {
+        self.completed.as_ref()
+    }
+}
@@ -1,4 +1,6 @@
 use log::info;
+use ppu::hd_pack::HdTileRef;
+use savestate::{invalid_state, SaveState, SaveStateError, StateReader, StateWriter};
 
 pub(super) const MAX_SPRITES: usize = 64;
 pub(super) const MAX_SPRITES_PER_LINE: usize = 8;
@@ -12,6 +14,38 @@ enum SpriteEvaluation {
     Completed,
 }
 
+impl SaveState for SpriteEvaluation {
+    fn save_state(&self, writer: &mut StateWriter) {
+        let (state, count, value) = match *self {
+            SpriteEvaluation::ReadY => (0u8, 0, 0),
+            SpriteEvaluation::WriteY { y } => (1, 0, y),
+            SpriteEvaluation::ReadByte { count } => (2, count, 0),
+            SpriteEvaluation::WriteByte { count, value } => (3, count, value),
+            SpriteEvaluation::Completed => (4, 0, 0),
+        };
+        state.save_state(writer);
+        count.save_state(writer);
+        value.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let (mut state, mut count, mut value) = (0u8, 0u8, 0u8);
+        state.load_state(reader)?;
+        count.load_state(reader)?;
+        value.load_state(reader)?;
+
+        *self = match state {
+            0 => SpriteEvaluation::ReadY,
+            1 => SpriteEvaluation::WriteY { y: value },
+            2 => SpriteEvaluation::ReadByte { count },
+            3 => SpriteEvaluation::WriteByte { count, value },
+            4 => SpriteEvaluation::Completed,
+            _ => return Err(invalid_state("unknown sprite evaluation state")),
+        };
+        Ok(())
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 enum SpriteFetch {
     ReadY {
@@ -47,7 +81,72 @@ enum SpriteFetch {
     Completed,
 }
 
-#[derive(Debug, Clone)]
+impl SaveState for SpriteFetch {
+    fn save_state(&self, writer: &mut StateWriter) {
+        let (state, sprite_index, y, tile, value, is_high_byte) = match *self {
+            SpriteFetch::ReadY { sprite_index } => (0u8, sprite_index, 0, 0, 0, false),
+            SpriteFetch::ReadTile { sprite_index, y } => (1, sprite_index, y, 0, 0, false),
+            SpriteFetch::ReadAttr { sprite_index, y, tile } => (2, sprite_index, y, tile, 0, false),
+            SpriteFetch::ReadX { sprite_index, y, tile } => (3, sprite_index, y, tile, 0, false),
+            SpriteFetch::FetchByte {
+                sprite_index,
+                y,
+                tile,
+                is_high_byte,
+            } => (4, sprite_index, y, tile, 0, is_high_byte),
+            SpriteFetch::WriteByte {
+                sprite_index,
+                y,
+                tile,
+                value,
+                is_high_byte,
+            } => (5, sprite_index, y, tile, value, is_high_byte),
+            SpriteFetch::Completed => (6, 0, 0, 0, 0, false),
+        };
+        state.save_state(writer);
+        sprite_index.save_state(writer);
+        y.save_state(writer);
+        tile.save_state(writer);
+        value.save_state(writer);
+        is_high_byte.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let (mut state, mut sprite_index, mut y, mut tile, mut value, mut is_high_byte) =
+            (0u8, 0usize, 0u8, 0u8, 0u8, false);
+        state.load_state(reader)?;
+        sprite_index.load_state(reader)?;
+        y.load_state(reader)?;
+        tile.load_state(reader)?;
+        value.load_state(reader)?;
+        is_high_byte.load_state(reader)?;
+
+        *self = match state {
+            0 => SpriteFetch::ReadY { sprite_index },
+            1 => SpriteFetch::ReadTile { sprite_index, y },
+            2 => SpriteFetch::ReadAttr { sprite_index, y, tile },
+            3 => SpriteFetch::ReadX { sprite_index, y, tile },
+            4 => SpriteFetch::FetchByte {
+                sprite_index,
+                y,
+                tile,
+                is_high_byte,
+            },
+            5 => SpriteFetch::WriteByte {
+                sprite_index,
+                y,
+                tile,
+                value,
+                is_high_byte,
+            },
+            6 => SpriteFetch::Completed,
+            _ => return Err(invalid_state("unknown sprite fetch state")),
+        };
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 struct SpriteAttribute {
     palette: u8,
     priority: bool,
@@ -64,7 +163,14 @@ impl SpriteAttribute {
     }
 }
 
-#[derive(Debug, Clone)]
+save_state_fields!(SpriteAttribute {
+    palette,
+    priority,
+    flipped_horizontal,
+    flipped_vertical,
+});
+
+#[derive(Debug, Clone, Default)]
 struct Sprite {
     high_byte_shift_register: u8,
     low_byte_shift_register: u8,
@@ -75,8 +181,19 @@ struct Sprite {
     /// Not sure about this implementation, set on each sprite during fetch to
     /// determine whether to ignore during sprite rendering.
     visible: bool,
+    /// The tile fetched for this sprite, only tracked when an HD pack is loaded
+    hd_tile: Option<HdTileRef>,
 }
 
+// The HD tile is only used for display so isn't saved
+save_state_fields!(Sprite {
+    high_byte_shift_register,
+    low_byte_shift_register,
+    attribute_latch,
+    x_location,
+    visible,
+});
+
 pub(super) struct SpriteData {
     /// PPU register 0x2003
     oam_addr: u8,
@@ -90,34 +207,34 @@ pub(super) struct SpriteData {
     /// We need to know whether sprite zero is loaded into secondary OAM RAM to
     /// know whether a sprite at output unit 0 triggers a sprite zero hit
     sprite_zero_visible: bool,
+    /// Hardware only renders the first 8 sprites on a line, disabling the limit renders the
+    /// rest of them from the slots after the 8 hardware ones
+    sprite_limit: bool,
+    /// The number of sprites beyond 8 being rendered on this line when the limit is disabled
+    extra_sprites: usize,
 }
 
 impl SpriteData {
     pub(super) fn new() -> Self {
-        let default_sprite = Sprite {
-            high_byte_shift_register: 0,
-            low_byte_shift_register: 0,
-            attribute_latch: SpriteAttribute {
-                palette: 0,
-                priority: false,
-                flipped_horizontal: false,
-                flipped_vertical: false,
-            },
-            x_location: 0,
-            visible: false,
-        };
         SpriteData {
             oam_addr: 0,
             oam_ram: [0; MAX_SPRITES * 4],
             secondary_oam_ram: [0xFF; MAX_SPRITES_PER_LINE * 4],
-            sprites: vec![default_sprite; 8],
+            sprites: vec![Sprite::default(); MAX_SPRITES],
             secondary_oam_ram_pointer: 0,
             eval_state: SpriteEvaluation::ReadY,
             fetch_state: SpriteFetch::ReadY { sprite_index: 0 },
             sprite_zero_visible: false,
+            sprite_limit: true,
+            extra_sprites: 0,
         }
     }
 
+    pub(super) fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+        self.extra_sprites = 0;
+    }
+
     pub(super) fn clear_sprites(&mut self) {
         self.sprite_zero_visible = false;
         for sprite in &mut self.sprites {
@@ -148,24 +265,35 @@ impl SpriteData {
         }
     }
 
-    pub(super) fn dma_write(&mut self, value: u8, dma_byte: u8) {
-        // Attribute byte bits always read 0, fix at set time to remove cost of masking on read
-        let masked_value = if dma_byte & 0b11 == 0b10 { value & 0xE3 } else { value };
-
-        // Note that OAM DMA doesn't affect oam_addr
-        self.oam_ram[self.oam_addr.wrapping_add(dma_byte) as usize] = masked_value;
+    /// OAM DMA writes each byte through OAMDATA, so a transfer starting at a non zero OAMADDR
+    /// wraps around within OAM (with the attribute bits masked by where each byte lands rather
+    /// than its position in the page) and leaves OAMADDR back where it started after 256 bytes
+    pub(super) fn dma_write(&mut self, value: u8) {
+        self.write_oam_data(value);
     }
 }
 
+save_state_fields!(SpriteData {
+    oam_addr,
+    oam_ram,
+    secondary_oam_ram,
+    sprites,
+    secondary_oam_ram_pointer,
+    eval_state,
+    fetch_state,
+    sprite_zero_visible,
+    extra_sprites,
+});
+
 impl super::Ppu {
     /// Returns the index into palette RAM based upon the current state of the sprite
     /// shift registers and latches
     /// Note: Also shift the high/low byte shift registers
-    pub(super) fn get_sprite_pixel(&mut self, x: u32) -> (u8, bool, bool) {
+    pub(super) fn get_sprite_pixel(&mut self, x: u32) -> (u8, bool, bool, Option<usize>) {
         let mut found_pixel = false;
-        let mut result = (0x0u8, false, false);
+        let mut result = (0x0u8, false, false, None);
 
-        for sprite_index in 0..MAX_SPRITES_PER_LINE {
+        for sprite_index in 0..MAX_SPRITES_PER_LINE + self.sprite_data.extra_sprites {
             // Skip sprites which aren't yet visible on this line
             if !self.sprite_data.sprites[sprite_index].visible
                 || (self.sprite_data.sprites[sprite_index].x_location as u32 + 8) <= x
@@ -188,6 +316,7 @@ impl super::Ppu {
                         0b10000 | (palette_number << 2) | color_val,
                         self.sprite_data.sprites[sprite_index].attribute_latch.priority,
                         sprite_index == 0 && self.sprite_data.sprite_zero_visible,
+                        Some(sprite_index),
                     );
 
                     found_pixel = true;
@@ -202,6 +331,27 @@ impl super::Ppu {
         result
     }
 
+    /// Whether a sprite 0 hit could be flagged on this line, sprite 0 is visible on the line after
+    /// the one it's evaluated on and once evaluated hits are checked until the end of the frame
+    pub(super) fn sprite_zero_hit_possible(&self, scanline: u16) -> bool {
+        let sprite_zero_y = self.sprite_data.oam_ram[0] as u16;
+
+        self.ppu_mask.show_background
+            && self.ppu_mask.show_sprites
+            && !self.ppu_status.sprite_zero_hit
+            && (self.sprite_data.sprite_zero_visible
+                || (scanline >= sprite_zero_y && scanline <= sprite_zero_y + self.ppu_ctrl.sprite_size.pixels() as u16))
+    }
+
+    /// Returns the tile and column within it drawn by a sprite at a given x coordinate
+    pub(super) fn sprite_hd_tile(&self, sprite_index: usize, x: u32) -> Option<(HdTileRef, u8)> {
+        let sprite = &self.sprite_data.sprites[sprite_index];
+
+        sprite
+            .hd_tile
+            .map(|tile| (tile, (x.saturating_sub(sprite.x_location as u32) & 7) as u8))
+    }
+
     pub(super) fn process_sprite_cycle(
         &mut self,
         scanline: u16,
@@ -220,6 +370,7 @@ impl super::Ppu {
                     if cycle == 65 {
                         self.sprite_data.secondary_oam_ram_pointer = 0;
                         self.sprite_data.eval_state = SpriteEvaluation::ReadY;
+                        self.record_sprites_in_range(scanline, sprite_height);
                     }
                     self.step_sprite_eval_machine(scanline, sprite_height)
                 }
@@ -233,11 +384,30 @@ impl super::Ppu {
                 self.step_sprite_fetch_machine(scanline, sprite_height, pattern_table_base)
             }
             // Read from secondary OAM RAM (but not tracking that read anywhere atm)
-            321..=340 => (),
+            321 => {
+                if !self.sprite_data.sprite_limit {
+                    self.fetch_extra_sprites(scanline, sprite_height, pattern_table_base);
+                }
+            }
+            322..=340 => (),
             _ => panic!("Shouldn't be calling sprite handler at dot {}", cycle),
         };
     }
 
+    /// Count every sprite in range of the line for the sprite stats, unlike evaluation this
+    /// doesn't stop at 8
+    fn record_sprites_in_range(&mut self, scanline: u16, sprite_height: u8) {
+        if let Some(recorder) = &mut self.sprite_stats {
+            let count = self
+                .sprite_data
+                .oam_ram
+                .chunks(4)
+                .filter(|sprite| scanline >= sprite[0] as u16 && scanline < sprite[0] as u16 + sprite_height as u16)
+                .count();
+            recorder.record_sprites_in_range(scanline, count as u8);
+        }
+    }
+
     fn step_sprite_eval_machine(&mut self, scanline: u16, sprite_height: u8) {
         self.sprite_data.eval_state = match self.sprite_data.eval_state {
             SpriteEvaluation::ReadY => {
@@ -266,6 +436,9 @@ impl super::Ppu {
                     // Check for sprite overflow
                     if self.sprite_data.secondary_oam_ram_pointer >= self.sprite_data.secondary_oam_ram.len() {
                         self.ppu_status.sprite_overflow = true;
+                        if let Some(recorder) = &mut self.sprite_stats {
+                            recorder.record_overflow(scanline);
+                        }
                         info!(
                             "Setting sprite overflow flag to true at oam_addr {}, scanline {}, dot {}, cycle {}",
                             self.sprite_data.oam_addr,
@@ -378,6 +551,16 @@ impl super::Ppu {
                 self.chr_address_bus.update_vram_address(address, self.total_cycles);
                 let mut value = self.read_byte(address);
 
+                if self.hd_renderer.is_some() && !is_high_byte {
+                    let attributes = &self.sprite_data.sprites[sprite_index].attribute_latch;
+                    self.sprite_data.sprites[sprite_index].hd_tile = Some(HdTileRef {
+                        chr: self.peek_tile(address & 0xFFF0),
+                        row: (address & 7) as u8,
+                        flipped_horizontal: attributes.flipped_horizontal,
+                        flipped_vertical: attributes.flipped_vertical,
+                    });
+                }
+
                 if scanline >= y as u16 && scanline < y as u16 + sprite_height as u16 {
                     self.sprite_data.sprites[sprite_index].visible = true;
                 } else {
@@ -430,6 +613,71 @@ impl super::Ppu {
     }
 }
 
+impl super::Ppu {
+    /// With the sprite limit disabled, loads every sprite on the line after the first 8 (which
+    /// go through the hardware evaluation so the overflow flag still behaves) into the extra
+    /// slots. These reads bypass the bus so mappers watching it (e.g. MMC3) don't see them.
+    fn fetch_extra_sprites(&mut self, scanline: u16, sprite_height: u8, pattern_table_base: u16) {
+        self.sprite_data.extra_sprites = 0;
+        if scanline == 261 {
+            return;
+        }
+
+        let sprites_on_line = (0..MAX_SPRITES)
+            .filter(|oam_index| {
+                let y = self.sprite_data.oam_ram[oam_index * 4] as u16;
+                scanline >= y && scanline < y + sprite_height as u16
+            })
+            .skip(MAX_SPRITES_PER_LINE)
+            .collect::<Vec<usize>>();
+
+        for (slot, oam_index) in sprites_on_line.into_iter().enumerate() {
+            let sprite_index = MAX_SPRITES_PER_LINE + slot;
+            let y = self.sprite_data.oam_ram[oam_index * 4];
+            let tile = self.sprite_data.oam_ram[oam_index * 4 + 1];
+            let sprite = &mut self.sprite_data.sprites[sprite_index];
+            sprite.attribute_latch.set(self.sprite_data.oam_ram[oam_index * 4 + 2]);
+            sprite.x_location = self.sprite_data.oam_ram[oam_index * 4 + 3];
+            sprite.visible = true;
+
+            let flipped_vertical = sprite.attribute_latch.flipped_vertical;
+            let flipped_horizontal = sprite.attribute_latch.flipped_horizontal;
+            let low_address = get_sprite_address(
+                y as u16,
+                tile,
+                flipped_vertical,
+                sprite_height,
+                scanline,
+                pattern_table_base,
+                false,
+            );
+            let mut low_byte = self.chr_address_bus.peek_byte(low_address);
+            let mut high_byte = self.chr_address_bus.peek_byte(low_address + 8);
+            if flipped_horizontal {
+                low_byte = low_byte.reverse_bits();
+                high_byte = high_byte.reverse_bits();
+            }
+
+            let hd_tile = if self.hd_renderer.is_some() {
+                Some(HdTileRef {
+                    chr: self.peek_tile(low_address & 0xFFF0),
+                    row: (low_address & 7) as u8,
+                    flipped_horizontal,
+                    flipped_vertical,
+                })
+            } else {
+                None
+            };
+
+            let sprite = &mut self.sprite_data.sprites[sprite_index];
+            sprite.low_byte_shift_register = low_byte;
+            sprite.high_byte_shift_register = high_byte;
+            sprite.hd_tile = hd_tile;
+            self.sprite_data.extra_sprites += 1;
+        }
+    }
+}
+
 fn get_sprite_address(
     y: u16,
     tile: u8,
@@ -460,6 +708,37 @@ fn get_sprite_address(
 #[cfg(test)]
 mod sprite_tests {
     use super::get_sprite_address;
+    use super::SpriteData;
+
+    #[test]
+    fn test_dma_wraps_from_oam_addr() {
+        let mut sprite_data = SpriteData::new();
+        sprite_data.write_oam_addr(0x05);
+        for value in 0..=0xFF {
+            sprite_data.dma_write(value);
+        }
+
+        assert_eq!(sprite_data.oam_addr, 0x05);
+        for value in 0..=0xFFu8 {
+            let address = value.wrapping_add(0x05);
+            // The attribute bits are masked where the byte lands, not where it came from
+            let expected = if address & 0b11 == 0b10 { value & 0xE3 } else { value };
+            assert_eq!(sprite_data.oam_ram[address as usize], expected, "OAM {:02X}", address);
+        }
+    }
+
+    #[test]
+    fn test_partial_dma_moves_oam_addr() {
+        let mut sprite_data = SpriteData::new();
+        sprite_data.write_oam_addr(0xFE);
+        for value in 0..4 {
+            sprite_data.dma_write(0x10 | value);
+        }
+
+        assert_eq!(sprite_data.oam_addr, 0x02);
+        assert_eq!(sprite_data.oam_ram[0xFE..], [0x10 & 0xE3, 0x11]);
+        assert_eq!(sprite_data.oam_ram[..2], [0x12, 0x13]);
+    }
 
     #[test]
     fn test_get_sprite_address_x8() {
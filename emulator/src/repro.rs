@@ -0,0 +1,121 @@
+//! Reproductions for bug reports, a savestate along with the controller input for each frame
+//! after it and the number of frames to run. Replaying one is deterministic so the final frame
+//! can be attached to an issue or checked in a regression test.
+
+use io::Controller;
+use nes::{Event, Nes};
+use savestate::{SaveState, SaveStateError, StateReader, StateWriter};
+
+const REPRO_MAGIC: &[u8] = b"RNESREPRO";
+
+/// A change to the buttons held, which lasts until the next change
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ReproInput {
+    /// The frame after the savestate from which these buttons are held
+    pub frame: u32,
+    /// The buttons held on controllers one and two, see `Nes::controller_state`
+    pub controllers: [u8; 2],
+}
+
+save_state_fields!(ReproInput { frame, controllers });
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repro {
+    pub state: Vec<u8>,
+    pub inputs: Vec<ReproInput>,
+    pub frames: u32,
+}
+
+save_state_fields!(Repro { state, inputs, frames });
+
+impl Repro {
+    /// Start recording a reproduction from the current state of the console, call
+    /// `record_frame` before running each frame
+    pub fn record(nes: &mut Nes) -> Self {
+        Repro {
+            state: nes.save_state(),
+            inputs: vec![],
+            frames: 0,
+        }
+    }
+
+    /// Record the buttons held for the frame which is about to run
+    pub fn record_frame(&mut self, nes: &Nes) {
+        let controllers = [
+            nes.controller_state(Controller::One),
+            nes.controller_state(Controller::Two),
+        ];
+        if self.inputs.last().map(|input| input.controllers) != Some(controllers) {
+            self.inputs.push(ReproInput {
+                frame: self.frames,
+                controllers,
+            });
+        }
+        self.frames += 1;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveStateError> {
+        let mut repro = Repro {
+            state: vec![],
+            inputs: vec![],
+            frames: 0,
+        };
+        let mut reader = StateReader::with_magic(bytes, REPRO_MAGIC)?;
+        repro.load_state(&mut reader)?;
+        reader.finish()?;
+
+        Ok(repro)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::with_magic(REPRO_MAGIC);
+        self.save_state(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Load the savestate into a console running the same rom and run it for the recorded
+    /// frames, after which the framebuffer holds the final frame
+    pub fn replay(&self, nes: &mut Nes) -> Result<(), SaveStateError> {
+        nes.load_state(&self.state)?;
+
+        let mut inputs = self.inputs.iter().peekable();
+        for frame in 0..self.frames {
+            if let Some(input) = inputs.peek().filter(|input| input.frame == frame) {
+                nes.set_controller_state(Controller::One, input.controllers[0]);
+                nes.set_controller_state(Controller::Two, input.controllers[1]);
+                inputs.next();
+            }
+            nes.run_until(Event::Frame);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod repro_tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let repro = Repro {
+            state: vec![1, 2, 3],
+            inputs: vec![
+                ReproInput {
+                    frame: 0,
+                    controllers: [0, 0],
+                },
+                ReproInput {
+                    frame: 12,
+                    controllers: [0b1000, 0b1],
+                },
+            ],
+            frames: 60,
+        };
+
+        let bytes = repro.to_bytes();
+        assert!(bytes.starts_with(REPRO_MAGIC));
+        assert_eq!(Repro::from_bytes(&bytes).unwrap(), repro);
+        assert!(Repro::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! Keeps a savestate of where the player left a game so that the frontend can carry on from
+//! there the next time the same rom is loaded.
+//!
+//! States are kept in a single directory with one file per rom, named after the CRC32 of its PRG
+//! and CHR ROM (e.g. `states/1A2B3C4D.state`) so that renaming or moving the rom doesn't lose it.
+
+use log::info;
+use nes::Nes;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// ```no_run
+/// # let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge("../roms/test/nestest.nes").unwrap());
+/// # let info = rust_nes::get_cartridge_info("../roms/test/nestest.nes").unwrap();
+/// let resume_state = rust_nes::ResumeState::for_rom("states", info.rom_crc32);
+/// resume_state.load(&mut nes).unwrap();
+/// // Run until the player quits
+/// resume_state.save(&mut nes).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ResumeState {
+    path: PathBuf,
+}
+
+impl ResumeState {
+    pub fn for_rom<P: AsRef<Path>>(state_dir: P, rom_crc32: u32) -> Self {
+        ResumeState {
+            path: state_dir.as_ref().join(format!("{:08X}.state", rom_crc32)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Restore the state left by the last `save`, returns false if there isn't one yet. States
+    /// from an older version of the emulator can't be loaded and are an `InvalidData` error.
+    pub fn load(&self, nes: &mut Nes) -> io::Result<bool> {
+        let state = match fs::read(&self.path) {
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => return Ok(false),
+            result => result?,
+        };
+
+        nes.load_state(&state)
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why.message))?;
+        info!("Resumed from {}", self.path.display());
+
+        Ok(true)
+    }
+
+    /// Save the console's state to resume from, creating the state directory if needed
+    ///
+    /// As with battery saves the file is written alongside and then renamed over the old state.
+    pub fn save(&self, nes: &mut Nes) -> io::Result<()> {
+        if let Some(state_dir) = self.path.parent() {
+            fs::create_dir_all(state_dir)?;
+        }
+
+        let temp_path = self.path.with_extension("state.tmp");
+        fs::write(&temp_path, nes.save_state())?;
+        fs::rename(&temp_path, &self.path)?;
+        info!("Wrote resume state to {}", self.path.display());
+
+        Ok(())
+    }
+}
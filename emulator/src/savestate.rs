@@ -0,0 +1,412 @@
+//! Serialization of the complete console state so that it can be restored later.
+//!
+//! Each component implements `SaveState` by writing its fields in a fixed order, the format
+//! has no field names or tags so states can only be loaded by the same build of the emulator
+//! that saved them (checked with `SAVE_STATE_VERSION`) and into a console running the same rom.
+//! `Nes::save_state` records the mapper implementation first so that states from a different
+//! implementation, or an older version of the same one, are refused with a clear error.
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+const SAVE_STATE_MAGIC: &[u8] = b"RNES";
+
+/// Bump whenever any component changes the fields it saves
+const SAVE_STATE_VERSION: u16 = 12;
+
+/// Returned when a savestate (or a file containing one) can't be loaded
+#[derive(Debug)]
+pub struct SaveStateError {
+    pub message: String,
+}
+impl Error for SaveStateError {}
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<std::io::Error> for SaveStateError {
+    fn from(error: std::io::Error) -> Self {
+        SaveStateError {
+            message: error.to_string(),
+        }
+    }
+}
+
+pub(crate) fn invalid_state(message: &str) -> SaveStateError {
+    SaveStateError {
+        message: format!("Invalid savestate, {}", message),
+    }
+}
+
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> Self {
+        StateWriter::with_magic(SAVE_STATE_MAGIC)
+    }
+
+    /// Start a file other than a savestate which uses the same format, e.g. one which embeds a savestate
+    pub(crate) fn with_magic(magic: &[u8]) -> Self {
+        let mut bytes = magic.to_vec();
+        bytes.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+
+        StateWriter { bytes }
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Result<Self, SaveStateError> {
+        StateReader::with_magic(bytes, SAVE_STATE_MAGIC)
+    }
+
+    pub(crate) fn with_magic(bytes: &'a [u8], magic: &[u8]) -> Result<Self, SaveStateError> {
+        let mut reader = StateReader { bytes, position: 0 };
+        if reader.read(magic.len()).ok() != Some(magic) {
+            return Err(invalid_state("the file isn't the expected type"));
+        }
+
+        let mut version = 0u16;
+        version.load_state(&mut reader)?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError {
+                message: format!(
+                    "Savestate is version {} but this emulator only loads version {}",
+                    version, SAVE_STATE_VERSION
+                ),
+            });
+        }
+
+        Ok(reader)
+    }
+
+    /// Checks that the whole state was consumed, anything left over means it came from a
+    /// different rom or mapper
+    pub(crate) fn finish(self) -> Result<(), SaveStateError> {
+        match self.position == self.bytes.len() {
+            true => Ok(()),
+            false => Err(invalid_state("it contains more data than expected")),
+        }
+    }
+
+    fn read(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or_else(|| invalid_state("it's truncated"))?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+}
+
+/// Implemented by every component holding state which affects emulation
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+/// Implement `SaveState` for a struct by saving the listed fields in order, fields which
+/// aren't listed (configuration, debugging aids) are left alone on load
+macro_rules! save_state_fields {
+    ($type:ty { $($field:ident),* $(,)* }) => {
+        impl ::savestate::SaveState for $type {
+            #[allow(unused_variables)]
+            fn save_state(&self, writer: &mut ::savestate::StateWriter) {
+                #[allow(unused_imports)]
+                use ::savestate::SaveState;
+                $(self.$field.save_state(writer);)*
+            }
+
+            #[allow(unused_variables)]
+            fn load_state(
+                &mut self,
+                reader: &mut ::savestate::StateReader,
+            ) -> ::std::result::Result<(), ::savestate::SaveStateError> {
+                #[allow(unused_imports)]
+                use ::savestate::SaveState;
+                $(self.$field.load_state(reader)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Implement `SaveState` for an enum without fields by saving the index of the variant, enums
+/// stored in a `Vec` (e.g. scheduler events) also need a default to load into
+macro_rules! save_state_enum {
+    ($type:ident { $($variant:ident),* $(,)* }, default = $default:ident) => {
+        impl ::std::default::Default for $type {
+            fn default() -> Self {
+                $type::$default
+            }
+        }
+
+        save_state_enum!($type { $($variant),* });
+    };
+    ($type:ident { $($variant:ident),* $(,)* }) => {
+        impl ::savestate::SaveState for $type {
+            fn save_state(&self, writer: &mut ::savestate::StateWriter) {
+                #[allow(unused_imports)]
+                use ::savestate::SaveState;
+                let variants = [$($type::$variant),*];
+                let index = variants
+                    .iter()
+                    .position(|variant| ::std::mem::discriminant(variant) == ::std::mem::discriminant(self))
+                    .unwrap() as u8;
+                index.save_state(writer);
+            }
+
+            fn load_state(
+                &mut self,
+                reader: &mut ::savestate::StateReader,
+            ) -> ::std::result::Result<(), ::savestate::SaveStateError> {
+                #[allow(unused_imports)]
+                use ::savestate::SaveState;
+                let mut index = 0u8;
+                index.load_state(reader)?;
+                let mut variants = vec![$($type::$variant),*];
+                if index as usize >= variants.len() {
+                    return Err(::savestate::invalid_state(concat!("unknown ", stringify!($type))));
+                }
+                *self = variants.swap_remove(index as usize);
+                Ok(())
+            }
+        }
+    };
+}
+
+macro_rules! save_state_number {
+    ($($type:ty),*) => {
+        $(
+            impl SaveState for $type {
+                fn save_state(&self, writer: &mut StateWriter) {
+                    writer.write(&self.to_le_bytes());
+                }
+
+                fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+                    let mut bytes = [0u8; std::mem::size_of::<$type>()];
+                    let length = bytes.len();
+                    bytes.copy_from_slice(reader.read(length)?);
+                    *self = <$type>::from_le_bytes(bytes);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+save_state_number!(u8, u16, u32, u64);
+
+impl SaveState for bool {
+    fn save_state(&self, writer: &mut StateWriter) {
+        (*self as u8).save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut value = 0u8;
+        value.load_state(reader)?;
+        *self = value != 0;
+        Ok(())
+    }
+}
+
+/// Saved as 64 bits so states are portable between platforms
+impl SaveState for usize {
+    fn save_state(&self, writer: &mut StateWriter) {
+        (*self as u64).save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut value = 0u64;
+        value.load_state(reader)?;
+        *self = value as usize;
+        Ok(())
+    }
+}
+
+/// Memory and register banks are a fixed size for a given rom so the length isn't stored, the
+/// state is loaded into a slice of the same length it was saved from
+impl<T: SaveState> SaveState for [T] {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for value in self {
+            value.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for value in self {
+            value.load_state(reader)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: SaveState + Default> SaveState for Option<T> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.is_some().save_state(writer);
+        if let Some(value) = self {
+            value.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut is_some = false;
+        is_some.load_state(reader)?;
+        *self = match is_some {
+            true => {
+                let mut value = T::default();
+                value.load_state(reader)?;
+                Some(value)
+            }
+            false => None,
+        };
+        Ok(())
+    }
+}
+
+impl<T: SaveState + Default> SaveState for Vec<T> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.len().save_state(writer);
+        for value in self {
+            value.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut length = 0usize;
+        length.load_state(reader)?;
+        // Every value takes at least a byte, so a corrupt length fails here rather than looping
+        if length > reader.bytes.len() - reader.position {
+            return Err(invalid_state("a list is longer than the rest of the state"));
+        }
+        self.clear();
+        for _ in 0..length {
+            let mut value = T::default();
+            value.load_state(reader)?;
+            self.push(value);
+        }
+        Ok(())
+    }
+}
+
+impl<A: SaveState, B: SaveState> SaveState for (A, B) {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.0.save_state(writer);
+        self.1.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.0.load_state(reader)?;
+        self.1.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod savestate_tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Colour {
+        Red,
+        Green,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Component {
+        counter: u16,
+        flag: bool,
+        ram: [u8; 4],
+        colour: Colour,
+        pending: Option<u8>,
+        banks: Vec<usize>,
+        unsaved: u8,
+    }
+
+    save_state_enum!(Colour { Red, Green });
+    save_state_fields!(Component {
+        counter,
+        flag,
+        ram,
+        colour,
+        pending,
+        banks
+    });
+
+    fn component() -> Component {
+        Component {
+            counter: 0,
+            flag: false,
+            ram: [0; 4],
+            colour: Colour::Red,
+            pending: None,
+            banks: vec![],
+            unsaved: 0,
+        }
+    }
+
+    #[test]
+    fn test_fields_round_trip() {
+        let saved = Component {
+            counter: 0x1234,
+            flag: true,
+            ram: [1, 2, 3, 4],
+            colour: Colour::Green,
+            pending: Some(7),
+            banks: vec![3, 0x1_0000],
+            unsaved: 5,
+        };
+        let mut writer = StateWriter::new();
+        saved.save_state(&mut writer);
+        let bytes = writer.into_bytes();
+
+        let mut loaded = component();
+        let mut reader = StateReader::new(&bytes).unwrap();
+        loaded.load_state(&mut reader).unwrap();
+        reader.finish().unwrap();
+
+        assert_eq!(loaded, Component { unsaved: 0, ..saved });
+    }
+
+    #[test]
+    fn test_invalid_states_rejected() {
+        let mut writer = StateWriter::new();
+        component().save_state(&mut writer);
+        let bytes = writer.into_bytes();
+
+        assert!(StateReader::new(b"NOPE\x01\x00").is_err());
+        assert!(StateReader::new(&[b'R', b'N', b'E', b'S', 0xFF, 0xFF]).is_err());
+
+        let mut reader = StateReader::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(component().load_state(&mut reader).is_err());
+
+        // The length of `banks` is far longer than the state
+        let mut corrupt = bytes.clone();
+        corrupt[15..23].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut reader = StateReader::new(&corrupt).unwrap();
+        assert!(component().load_state(&mut reader).is_err());
+
+        let mut extended = bytes.clone();
+        extended.push(0);
+        let mut reader = StateReader::new(&extended).unwrap();
+        component().load_state(&mut reader).unwrap();
+        assert!(reader.finish().is_err());
+    }
+}
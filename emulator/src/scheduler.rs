@@ -0,0 +1,124 @@
+use savestate::{SaveState, SaveStateError, StateReader, StateWriter};
+use std::ops::Add;
+
+/// Holds effects which take place a fixed number of cycles after whatever caused them (NMI
+/// edges, IRQ lines asserting, delayed resets) so that components don't each need to store
+/// the cycle something happened on and compare it against the current cycle.
+///
+/// Each component owns a scheduler stamped in its own clock (PPU or CPU cycles). Only a
+/// handful of events are ever outstanding so they're kept in a small vec rather than a heap.
+#[derive(Debug)]
+pub(crate) struct Scheduler<C, E> {
+    events: Vec<(C, E)>,
+}
+
+impl<C, E> Scheduler<C, E>
+where
+    C: Copy + Ord + Add<Output = C>,
+    E: Copy + PartialEq,
+{
+    pub(crate) fn new() -> Self {
+        Scheduler { events: Vec::new() }
+    }
+
+    /// Schedule an event `delay` cycles after `now`, replacing it if it was already scheduled
+    pub(crate) fn schedule(&mut self, event: E, now: C, delay: C) {
+        self.cancel(event);
+        self.events.push((now + delay, event));
+    }
+
+    pub(crate) fn cancel(&mut self, event: E) {
+        self.events.retain(|(_, scheduled)| *scheduled != event);
+    }
+
+    pub(crate) fn is_scheduled(&self, event: E) -> bool {
+        self.events.iter().any(|(_, scheduled)| *scheduled == event)
+    }
+
+    /// The event has been scheduled but the cycle it occurs on hasn't been reached yet
+    pub(crate) fn is_pending(&self, event: E, now: C) -> bool {
+        self.events
+            .iter()
+            .any(|(cycle, scheduled)| *scheduled == event && *cycle > now)
+    }
+
+    /// The event has reached the cycle it occurs on and hasn't yet been cancelled or taken
+    pub(crate) fn is_due(&self, event: E, now: C) -> bool {
+        self.events
+            .iter()
+            .any(|(cycle, scheduled)| *scheduled == event && *cycle <= now)
+    }
+
+    /// Cancels the event if it's due, returning whether it was so it can be acted on
+    pub(crate) fn take_due(&mut self, event: E, now: C) -> bool {
+        let is_due = self.is_due(event, now);
+        if is_due {
+            self.cancel(event);
+        }
+
+        is_due
+    }
+}
+
+/// Events need a default so that there's something to load each saved event into
+impl<C, E> SaveState for Scheduler<C, E>
+where
+    C: SaveState + Default,
+    E: SaveState + Default,
+{
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.events.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.events.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod scheduler_tests {
+    use super::*;
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    enum TestEvent {
+        First,
+        Second,
+    }
+
+    #[test]
+    fn test_events_become_due_after_delay() {
+        let mut scheduler = Scheduler::<u32, TestEvent>::new();
+        scheduler.schedule(TestEvent::First, 10, 3);
+
+        assert!(scheduler.is_pending(TestEvent::First, 12));
+        assert!(!scheduler.is_due(TestEvent::First, 12));
+        assert!(!scheduler.is_pending(TestEvent::First, 13));
+        assert!(scheduler.is_due(TestEvent::First, 13));
+        assert!(!scheduler.is_scheduled(TestEvent::Second));
+    }
+
+    #[test]
+    fn test_rescheduling_replaces_event() {
+        let mut scheduler = Scheduler::<u32, TestEvent>::new();
+        scheduler.schedule(TestEvent::First, 10, 3);
+        scheduler.schedule(TestEvent::First, 12, 3);
+
+        assert!(!scheduler.is_due(TestEvent::First, 14));
+        assert!(scheduler.is_due(TestEvent::First, 15));
+
+        scheduler.cancel(TestEvent::First);
+        assert!(!scheduler.is_scheduled(TestEvent::First));
+    }
+
+    #[test]
+    fn test_take_due_only_takes_once() {
+        let mut scheduler = Scheduler::<u32, TestEvent>::new();
+        scheduler.schedule(TestEvent::Second, 0, 5);
+        scheduler.schedule(TestEvent::First, 0, 2);
+
+        assert!(!scheduler.take_due(TestEvent::First, 1));
+        assert!(scheduler.take_due(TestEvent::First, 2));
+        assert!(!scheduler.take_due(TestEvent::First, 3));
+        assert!(scheduler.is_scheduled(TestEvent::Second));
+    }
+}
@@ -0,0 +1,141 @@
+//! Image output for the rom tests. When a frame doesn't match its CRC a PNG with the expected
+//! frame, the actual frame and a heatmap of the differences side by side is written to the
+//! artifacts directory so that the failure can be looked at rather than read as ascii art.
+//!
+//! Expected frames aren't checked in, they're recorded into the artifacts directory by running
+//! the tests on a known good build with `RUST_NES_RECORD_GOLDEN` set. The artifacts directory
+//! is `target/test_artifacts` unless `RUST_NES_TEST_ARTIFACTS` is set.
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 240;
+
+fn artifacts_directory() -> PathBuf {
+    env::var_os("RUST_NES_TEST_ARTIFACTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new("..").join("target").join("test_artifacts"))
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    artifacts_directory().join("golden").join(format!("{}.png", name))
+}
+
+/// Called with every frame which matches its CRC, keeps it as the expected frame if asked to
+pub fn record_golden(name: &str, framebuffer: &[u8]) -> io::Result<()> {
+    if env::var_os("RUST_NES_RECORD_GOLDEN").is_none() {
+        return Ok(());
+    }
+
+    let path = golden_path(name);
+    fs::create_dir_all(path.parent().unwrap())?;
+    write_png(&path, &to_rgb(framebuffer), WIDTH)
+}
+
+/// Called with a frame which doesn't match its CRC, writes the triptych (or just the actual frame
+/// if there's no expected frame recorded) and returns where it went
+pub fn write_failure(name: &str, framebuffer: &[u8]) -> io::Result<PathBuf> {
+    let actual = to_rgb(framebuffer);
+    let expected = match read_png(&golden_path(name)) {
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => None,
+        result => Some(result?),
+    };
+
+    let directory = artifacts_directory();
+    fs::create_dir_all(&directory)?;
+    let path = directory.join(format!("{}.png", name));
+    match expected {
+        Some(expected) => write_png(&path, &triptych(&expected, &actual), WIDTH * 3)?,
+        None => write_png(&path, &actual, WIDTH)?,
+    };
+
+    Ok(path)
+}
+
+/// The framebuffer is stored as BGRA for SDL, PNGs are written as RGB
+fn to_rgb(framebuffer: &[u8]) -> Vec<u8> {
+    framebuffer
+        .chunks(4)
+        .flat_map(|pixel| vec![pixel[2], pixel[1], pixel[0]])
+        .collect()
+}
+
+/// The actual frame dimmed to grey with each differing pixel in red, brighter the more
+/// different it is
+fn heatmap(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    expected
+        .chunks(3)
+        .zip(actual.chunks(3))
+        .flat_map(|(expected, actual)| {
+            let difference = expected
+                .iter()
+                .zip(actual)
+                .map(|(&e, &a)| e.max(a) - e.min(a))
+                .max()
+                .unwrap();
+
+            match difference {
+                0 => vec![(actual.iter().map(|&c| c as u16).sum::<u16>() / 12) as u8; 3],
+                _ => vec![0x80 + difference / 2, 0, 0],
+            }
+        })
+        .collect()
+}
+
+fn triptych(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    let heatmap = heatmap(expected, actual);
+    let row_length = WIDTH * 3;
+
+    (0..HEIGHT)
+        .flat_map(|y| {
+            let row = y * row_length..(y + 1) * row_length;
+            vec![&expected[row.clone()], &actual[row.clone()], &heatmap[row]]
+        })
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+fn read_png(path: &Path) -> io::Result<Vec<u8>> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    match (info.color_type, info.width as usize, info.height as usize) {
+        (png::ColorType::RGB, WIDTH, HEIGHT) => Ok(buffer),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} isn't a {}x{} RGB frame", path.display(), WIDTH, HEIGHT),
+        )),
+    }
+}
+
+fn write_png(path: &Path, pixels: &[u8], width: usize) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+
+    Ok(())
+}
+
+#[test]
+fn heatmap_marks_only_differing_pixels() {
+    let expected = vec![0x40; WIDTH * HEIGHT * 3];
+    let mut actual = expected.clone();
+    actual[3..6].copy_from_slice(&[0x40, 0xC0, 0x40]);
+
+    let heatmap = heatmap(&expected, &actual);
+    assert_eq!(&heatmap[..9], &[0x10, 0x10, 0x10, 0xC0, 0x00, 0x00, 0x10, 0x10, 0x10]);
+
+    let triptych = triptych(&expected, &actual);
+    assert_eq!(triptych.len(), WIDTH * 3 * HEIGHT * 3);
+    assert_eq!(&triptych[WIDTH * 3 + 3..WIDTH * 3 + 6], &[0x40, 0xC0, 0x40]);
+    assert_eq!(&triptych[WIDTH * 6 + 3..WIDTH * 6 + 6], &[0xC0, 0x00, 0x00]);
+}
@@ -1,7 +1,15 @@
 extern crate crc32fast;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+extern crate png;
 extern crate rust_nes;
+extern crate zip;
+
+mod frame_diff;
 
 use crc32fast::Hasher;
+use std::fs::File;
+use std::io::Write;
 use std::path::Path;
 
 macro_rules! rom_tests {
@@ -12,21 +20,50 @@ macro_rules! rom_tests {
             let (cycles, expected_crc32, rom_path) = $value;
             let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
             let framebuffer = rust_nes::run_headless_cycles(cartridge, cycles);
-            let mut hasher = Hasher::new();
-            hasher.update(&framebuffer);
-            let actual_crc32 = hasher.finalize();
-
-            assert_eq!(
-                actual_crc32,
-                expected_crc32,
-                "{}",
-                framebuffer_to_ascii_art(framebuffer)
-            );
+            check_framebuffer(stringify!($name), framebuffer, expected_crc32);
         }
     )*
     }
 }
 
+/// As `rom_tests!` for roms which wait for the reset button, it's pressed at each of the cycles
+/// given after the total
+macro_rules! reset_rom_tests {
+    ($($name:ident: $value:expr,)*) => {
+    $(
+        #[test]
+        fn $name() {
+            let (cycles, resets, expected_crc32, rom_path): (usize, &[usize], u32, std::path::PathBuf) = $value;
+            let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+            let framebuffer = rust_nes::run_headless_cycles_with_resets(cartridge, cycles, resets);
+            check_framebuffer(stringify!($name), framebuffer, expected_crc32);
+        }
+    )*
+    }
+}
+
+fn check_framebuffer(name: &str, framebuffer: [u8; (256 * 240 * 4) as usize], expected_crc32: u32) {
+    let mut hasher = Hasher::new();
+    hasher.update(&framebuffer);
+    let actual_crc32 = hasher.finalize();
+
+    if actual_crc32 == expected_crc32 {
+        frame_diff::record_golden(name, &framebuffer).unwrap();
+    } else {
+        match frame_diff::write_failure(name, &framebuffer) {
+            Ok(path) => println!("Frame written to {}", path.display()),
+            Err(why) => println!("Unable to write frame: {}", why),
+        }
+    }
+
+    assert_eq!(
+        actual_crc32,
+        expected_crc32,
+        "{}",
+        framebuffer_to_ascii_art(framebuffer)
+    );
+}
+
 rom_tests! {
     // ----- General CPU Tests -----
     blargg_nes_cpu_test_official: (0x13399B3 * 3 as usize, 2605351162, Path::new("..").join("roms").join("test").join("blargg_nes_cpu_test5").join("official.nes")),
@@ -102,11 +139,15 @@ rom_tests! {
     mapper_7_p128k: (0x262201 * 3 as usize, 2603256516, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M7_P128K.nes")),
     mapper_7_p128k_cr8k: (0x262201 * 3 as usize, 423779697, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M7_P128K_CR8K.nes")),
     mapper_9_p128k_c64k: (0x4F5DD * 3 as usize, 3084268463, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M9_P128K_C64K.nes")),
-    mapper_10_p128k_c64k_s8k: (0x1C9707 * 3 as usize, 2938351879, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M10_P128K_C64K_S8K.nes")),
-    mapper_10_p128k_c64k_w8k: (0x10521E * 3 as usize, 2938351879, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M10_P128K_C64K_W8K.nes")),
+    // MMC4 has no PRG RAM unless the header says so, these NES 2.0 headers ask for 8KB (battery backed
+    // and volatile) so holy mapperel finds and tests it, which takes longer than the run without RAM
+    mapper_10_p128k_c64k_s8k: (0x10521E * 9 as usize, 2086726143, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M10_P128K_C64K_S8K.nes")),
+    mapper_10_p128k_c64k_w8k: (0x10521E * 9 as usize, 2086726143, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M10_P128K_C64K_W8K.nes")),
     mapper_11_p64k_c64k_v: (0x113AC6 * 3 as usize, 2383587170, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M11_P64K_C64K_V.nes")),
     // TODO - Below renders as BNROM in holy mapperel instead of color dreams because I don't bank CHRRAM
     // mapper_11_p64k_c64k_v: (0x113AC6 * 3 as usize, 2383587170, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M11_P64K_CR32K_V.nes")),
+    mapper_28_p512k: (0x1109CFB * 3 as usize, 1525033402, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M28_P512K.nes")),
+    mapper_28_p512k_cr32k: (0x1109CD9 * 3 as usize, 3907790339, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M28_P512K_CR32K.nes")),
     mapper_34_p128k_h: (0x38C38A * 3 as usize, 3229261591, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M34_P128K_H.nes")),
     mapper_34_p128k_cr8k_h: (0x2A38FA * 3 as usize, 1108494498, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M34_P128K_CR8K_H.nes")),
     mapper_66_p64k_c16k_v: (0x19DD0C * 3 as usize, 2221445495, Path::new("..").join("roms").join("test").join("holy_mapperel").join("M66_P64K_C16K_V.nes")),
@@ -139,8 +180,1138 @@ rom_tests! {
     apu_test_07_irq_flag_timing: (0x163A62 * 3 as usize, 1300901188, Path::new("..").join("roms").join("test").join("blargg_apu_2005.07.30").join("07.irq_flag_timing.nes")),
     //apu_test_08_irq_timing: (0x163A62 * 3 as usize, 1300901188, Path::new("..").join("roms").join("test").join("blargg_apu_2005.07.30").join("08.irq_timing.nes")), - IRQ happening too soon
     apu_test_09_reset_timing: (0xF696D * 3 as usize, 1300901188, Path::new("..").join("roms").join("test").join("blargg_apu_2005.07.30").join("09.reset_timing.nes")), // Suspect. I haven't even implemented reset anywhere!
-    // apu_test_10_len_halt_timing: (0xF696D * 3 as usize, 1300901188, Path::new("..").join("roms").join("test").join("blargg_apu_2005.07.30").join("10.len_halt_timing.nes")), // Failing #03
-    // apu_test_11_len_reload_timing: (0xF696D * 3 as usize, 1300901188, Path::new("..").join("roms").join("test").join("blargg_apu_2005.07.30").join("11.len_reload_timing.nes")), // Failing #04
+    apu_test_10_len_halt_timing: (0xF696D * 3 as usize, 1300901188, Path::new("..").join("roms").join("test").join("blargg_apu_2005.07.30").join("10.len_halt_timing.nes")),
+    apu_test_11_len_reload_timing: (0xF696D * 3 as usize, 1300901188, Path::new("..").join("roms").join("test").join("blargg_apu_2005.07.30").join("11.len_reload_timing.nes")),
+}
+
+reset_rom_tests! {
+    // ----- APU Power & Reset Tests -----
+    apu_reset_4015_cleared: (4_370_552, &[3_000_000], 1321021581, Path::new("..").join("roms").join("test").join("apu_reset").join("4015_cleared.nes")),
+    apu_reset_4017_timing: (5_353_316, &[3_000_000], 359816969, Path::new("..").join("roms").join("test").join("apu_reset").join("4017_timing.nes")),
+    apu_reset_4017_written: (7_676_193, &[3_000_000, 6_000_000], 3632505875, Path::new("..").join("roms").join("test").join("apu_reset").join("4017_written.nes")),
+    apu_reset_irq_flag_cleared: (4_370_552, &[3_000_000], 817144836, Path::new("..").join("roms").join("test").join("apu_reset").join("irq_flag_cleared.nes")),
+    apu_reset_len_ctrs_enabled: (4_549_237, &[3_000_000], 4164270913, Path::new("..").join("roms").join("test").join("apu_reset").join("len_ctrs_enabled.nes")),
+    //apu_reset_works_immediately: (4_500_000, &[3_000_000], 0, Path::new("..").join("roms").join("test").join("apu_reset").join("works_immediately.nes")), - Needs the DMC active flag in $4015
+}
+
+#[test]
+fn instruction_stream_publishes_executed_instructions() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+    let stream = nes.instruction_stream(4);
+
+    for _ in 0..3000 {
+        nes.next();
+    }
+
+    // Only the first 4 instructions fit in the channel, the rest are dropped without blocking
+    let instructions = stream.try_iter().collect::<Vec<_>>();
+    assert_eq!(
+        instructions
+            .iter()
+            .map(|i| (i.pc, i.opcode, i.operands.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (0xC004, 0x78, vec![]),
+            (0xC005, 0xD8, vec![]),
+            (0xC006, 0xA2, vec![0xFF]),
+            (0xC008, 0x9A, vec![]),
+        ]
+    );
+    assert_eq!(instructions[3].registers.x, 0xFF);
+    assert!(instructions.windows(2).all(|w| w[0].cycles < w[1].cycles));
+}
+
+#[test]
+fn run_for_cpu_cycles_and_run_until_stop_at_requested_points() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+
+    let run = nes.run_until(rust_nes::Event::ProgramCounter(0xC006));
+    assert_eq!(nes.registers().program_counter, 0xC006);
+    assert_eq!(run.frames, 0);
+
+    let cycles_before = nes.cycles();
+    let run = nes.run_for_cpu_cycles(1000);
+    assert_eq!(run.cpu_cycles, 1000);
+    assert_eq!(run.samples.len(), 1000);
+    assert_eq!(nes.cycles() - cycles_before, 1000);
+
+    let run = nes.run_until(rust_nes::Event::Frame);
+    assert_eq!(run.frames, 1);
+
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("ny2011")
+        .join("ny2011.nes");
+    let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+    // The demo leaves NMIs disabled for a frame during startup
+    nes.run_until(rust_nes::Event::Nmi);
+    nes.run_until(rust_nes::Event::Nmi);
+    let run = nes.run_until(rust_nes::Event::Nmi);
+    // NMIs happen once per frame (~29781 CPU cycles) but can only start between instructions
+    assert!((29774..=29788).contains(&run.cpu_cycles), "{}", run.cpu_cycles);
+}
+
+#[test]
+fn breakpoints_stop_run_until() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+    nes.add_breakpoint(rust_nes::cpu::Breakpoint::Execute(0xC008));
+    nes.add_breakpoint(rust_nes::cpu::Breakpoint::Write(0x2000));
+
+    let run = nes.run_until(rust_nes::Event::Breakpoint);
+    let hit = run.breakpoint.unwrap();
+    assert_eq!(hit.breakpoint, rust_nes::cpu::Breakpoint::Execute(0xC008));
+    assert_eq!(hit.value, None);
+    assert_eq!(nes.registers().program_counter, 0xC008);
+    assert_eq!(nes.registers().x, 0xFF);
+
+    let run = nes.run_for_cpu_cycles(1_000_000);
+    let hit = run.breakpoint.unwrap();
+    assert!(run.cpu_cycles < 1_000_000);
+    assert_eq!(hit.breakpoint, rust_nes::cpu::Breakpoint::Write(0x2000));
+    assert_eq!(hit.address, 0x2000);
+}
+
+#[test]
+fn conditional_breakpoints_only_stop_when_condition_met() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    for (condition, expected_hit) in &[("x == 0", false), ("x == $FF && sp <= 0xFD && scanline >= 0", true)] {
+        let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+        let mut nes = rust_nes::Nes::new(cartridge);
+        nes.add_conditional_breakpoint(rust_nes::cpu::Breakpoint::Execute(0xC008), condition.parse().unwrap());
+
+        let run = nes.run_for_cpu_cycles(1000);
+        assert_eq!(run.breakpoint.is_some(), *expected_hit, "{}", condition);
+    }
+}
+
+#[test]
+fn ppu_bus_activity_recorded_for_selected_frame() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+    let frame = nes.frame_number() + 10;
+    nes.record_ppu_bus_activity(frame);
+
+    while nes.frame_number() <= frame {
+        assert!(nes.ppu_bus_activity().is_none());
+        nes.run_until(rust_nes::Event::Frame);
+    }
+
+    let accesses = nes.ppu_bus_activity().unwrap();
+    assert!(accesses
+        .iter()
+        .any(|access| access.is_read && (0x2000..=0x2FFF).contains(&access.address) && access.scanline < 240));
+    assert!(accesses
+        .iter()
+        .any(|access| access.is_read && access.address < 0x2000 && access.scanline < 240));
+    assert!(accesses.iter().all(|access| access.scanline < 262 && access.dot < 341));
+}
+
+#[test]
+fn ppu_bus_activity_on_rendered_scanline_is_only_tile_fetches() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+    let frame = nes.frame_number() + 10;
+    nes.record_ppu_bus_activity(frame);
+    while nes.frame_number() <= frame {
+        nes.run_until(rust_nes::Event::Frame);
+    }
+
+    let scanline: Vec<_> = nes
+        .ppu_bus_activity()
+        .unwrap()
+        .iter()
+        .filter(|access| access.scanline == 100)
+        .collect();
+    // 34 background tiles and 8 sprites at 4 fetches each, then the 2 unused nametable fetches
+    assert_eq!(scanline.len(), 34 * 4 + 8 * 4 + 2);
+    assert!(
+        scanline.iter().all(|access| access.is_read && access.address < 0x3000),
+        "{:?}",
+        scanline
+    );
+}
+
+#[test]
+fn roms_selected_from_archive_with_several_entries() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let rom = std::fs::read(rom_path).unwrap();
+    let archive_path = std::env::temp_dir().join(format!("rust_nes_archive_{}.zip", std::process::id()));
+
+    let mut archive = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+    let options = zip::write::FileOptions::default();
+    archive.start_file("readme.txt", options).unwrap();
+    archive.write_all(b"not a rom").unwrap();
+    archive.start_file("nestest.nes", options).unwrap();
+    archive.write_all(&rom).unwrap();
+    archive.add_directory("nested/", options).unwrap();
+    archive.start_file("nested/truncated.NES", options).unwrap();
+    archive.write_all(&rom[..8]).unwrap();
+    archive.finish().unwrap();
+
+    let archive_path = archive_path.to_str().unwrap();
+    let entries = rust_nes::list_archive_entries(archive_path).unwrap();
+    let single_load = rust_nes::get_cartridge(archive_path);
+    let first = rust_nes::from_archive_entry(archive_path, 0);
+    let second = rust_nes::from_archive_entry(archive_path, 1);
+    let missing = rust_nes::from_archive_entry(archive_path, 2);
+    std::fs::remove_file(archive_path).unwrap();
+
+    assert_eq!(entries, vec!["nestest.nes", "nested/truncated.NES"]);
+    assert!(single_load.is_err());
+    assert_eq!(first.unwrap().2.prg_rom_16kb_units, 1);
+    match second {
+        Err(why) => assert!(why.message.contains("header < 16 bytes"), "{}", why.message),
+        Ok(_) => panic!("Truncated rom shouldn't load"),
+    }
+    assert!(missing.is_err());
+}
+
+#[test]
+fn archive_extensions_are_case_insensitive() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let rom = std::fs::read(rom_path).unwrap();
+    let archive_path = std::env::temp_dir().join(format!("rust_nes_archive_{}.ZIP", std::process::id()));
+    let mut archive = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+    archive
+        .start_file("nestest.nes", zip::write::FileOptions::default())
+        .unwrap();
+    archive.write_all(&rom).unwrap();
+    archive.finish().unwrap();
+
+    let result = rust_nes::get_cartridge(archive_path.to_str().unwrap());
+    std::fs::remove_file(&archive_path).unwrap();
+    match result {
+        Ok(cartridge) => assert_eq!(cartridge.2.prg_rom_16kb_units, 1),
+        Err(why) => panic!("{}", why.message),
+    }
+
+    // Not read as an iNES file, it either fails to decompress or needs the gzip feature
+    let gzip_path = std::env::temp_dir().join(format!("rust_nes_gzip_{}.NES.GZ", std::process::id()));
+    File::create(&gzip_path).unwrap().write_all(b"not gzip").unwrap();
+    let result = rust_nes::get_cartridge(gzip_path.to_str().unwrap());
+    std::fs::remove_file(&gzip_path).unwrap();
+    match result {
+        Ok(_) => panic!("An invalid gzip file shouldn't load"),
+        Err(why) => assert!(!why.message.contains("Invalid cartridge file"), "{}", why.message),
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_roms_are_decompressed() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let rom = std::fs::read(rom_path).unwrap();
+    let gzip_path = std::env::temp_dir().join(format!("rust_nes_gzip_{}.nes.gz", std::process::id()));
+    let mut encoder = flate2::write::GzEncoder::new(File::create(&gzip_path).unwrap(), flate2::Compression::default());
+    encoder.write_all(&rom).unwrap();
+    encoder.finish().unwrap();
+
+    let result = rust_nes::get_cartridge(gzip_path.to_str().unwrap());
+    std::fs::remove_file(&gzip_path).unwrap();
+
+    match result {
+        Ok(cartridge) => assert_eq!(cartridge.2.prg_rom_16kb_units, 1),
+        Err(why) => panic!("{}", why.message),
+    }
+}
+
+#[cfg(not(feature = "gzip"))]
+#[test]
+fn gzip_roms_report_missing_feature() {
+    let gzip_path = std::env::temp_dir().join(format!("rust_nes_gzip_{}.nes.gz", std::process::id()));
+    File::create(&gzip_path).unwrap().write_all(b"not gzip").unwrap();
+
+    let result = rust_nes::get_cartridge(gzip_path.to_str().unwrap());
+    std::fs::remove_file(&gzip_path).unwrap();
+
+    match result {
+        Ok(_) => panic!("gzip roms shouldn't load without the gzip feature"),
+        Err(why) => assert!(why.message.contains("\"gzip\" feature"), "{}", why.message),
+    }
+}
+
+#[test]
+fn cartridge_info_read_without_loading() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let info = rust_nes::get_cartridge_info(rom_path.to_str().unwrap()).unwrap();
+
+    assert_eq!(info.mapper_name, Some("NROM"));
+    assert_eq!(info.board, Some("NROM-128"));
+    assert_eq!((info.prg_rom_kb, info.chr_rom_kb), (16, 8));
+    assert_eq!(info.mirroring, rust_nes::cartridge::MirroringMode::Horizontal);
+    assert_eq!(info.prg_rom_crc32, 0x7C50_60F0);
+    assert_eq!(info.chr_rom_crc32, Some(0x6DD1_2DF7));
+    assert_eq!(info.rom_crc32, 0x158B_0388);
+    assert_eq!(info.to_string(), "NROM (NROM-128), 16KB PRG, 8KB CHR, ntsc");
+
+    let header = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap().2;
+    assert_eq!(header.info(), info);
+}
+
+#[test]
+fn header_overrides_applied_on_load() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let overrides = rust_nes::cartridge::CartridgeOverrides {
+        patch: None,
+        mapper: Some(2),
+        mapper_id: None,
+        mirroring: Some(rust_nes::cartridge::MirroringMode::FourScreen),
+        prg_ram_8kb_units: Some(0),
+        battery: Some(true),
+        region: Some(rust_nes::cartridge::Region::Pal),
+    };
+
+    let header = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap().2;
+    assert_eq!(header.mapper, 0);
+    assert!(!header.ram_is_battery_backed);
+    assert_eq!(header.region, rust_nes::cartridge::Region::Ntsc);
+
+    let (prg, _, header) = rust_nes::get_cartridge_with_overrides(rom_path.to_str().unwrap(), &overrides).unwrap();
+    assert_eq!(header.mapper, 2);
+    assert_eq!(header.mirroring, rust_nes::cartridge::MirroringMode::FourScreen);
+    assert_eq!(header.prg_ram_8kb_units, Some(0));
+    assert!(header.ram_is_battery_backed);
+    assert_eq!(header.region, rust_nes::cartridge::Region::Pal);
+    assert!(prg.prg_rom_offset(0xC000).is_some());
+}
+
+#[test]
+fn memory_regions_dumped_and_restored() {
+    let rom_path = Path::new("..").join("roms").join("test").join("nestest.nes");
+    let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+    for _ in 0..10 {
+        nes.run_until(rust_nes::Event::Frame);
+    }
+
+    // Pattern tables are CHR ROM so can't be overwritten, change a nametable byte instead
+    let regions = [
+        (rust_nes::MemoryRegion::CpuRam, 0x800, 0x10),
+        (rust_nes::MemoryRegion::Vram, 0x4000, 0x2010),
+        (rust_nes::MemoryRegion::Oam, 0x100, 0x10),
+        (rust_nes::MemoryRegion::Palette, 0x20, 0x11),
+        (rust_nes::MemoryRegion::PrgRam, 0x2000, 0x10),
+    ];
+    for (region, expected_length, index) in regions.iter() {
+        let mut data = nes.dump_memory(*region);
+        assert_eq!(data.len(), *expected_length, "{}", region);
+
+        data[*index] = !data[*index] & 0x3F;
+        nes.load_memory(*region, &data).unwrap();
+        assert_eq!(nes.dump_memory(*region)[*index], data[*index], "{}", region);
+
+        assert!(nes.load_memory(*region, &data[1..]).is_err());
+    }
+}
+
+#[test]
+fn channel_samples_recorded_alongside_mixed_output() {
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("apu_mixer")
+        .join("square.nes");
+    let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+    let run = nes.run_until(rust_nes::Event::Frame);
+    assert!(run.channel_samples.is_empty());
+
+    nes.record_channel_samples(true);
+    let mut run = rust_nes::CyclesRun::default();
+    for _ in 0..240 {
+        let frame = nes.run_until(rust_nes::Event::Frame);
+        run.samples.extend(frame.samples);
+        run.channel_samples.extend(frame.channel_samples);
+    }
+
+    assert_eq!(run.samples.len(), run.channel_samples.len());
+    assert!(run.channel_samples.iter().any(|channels| channels.pulse_1 > 0.0));
+    assert!(run.channel_samples.iter().all(|channels| channels.noise == 0.0));
+
+    let mut wav = vec![];
+    rust_nes::apu::write_wav(&mut wav, 44_100, &run.samples).unwrap();
+    assert_eq!(wav.len(), 44 + run.samples.len() * 2);
+}
+
+#[test]
+fn sprite_limit_disabled_renders_extra_sprites() {
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("spritecans-2011")
+        .join("spritecans.nes");
+    let mut limited = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    let mut unlimited = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    unlimited.set_sprite_limit(false);
+
+    let mut frames_differ = false;
+    for frame in 0..600 {
+        // Pressing start adds more cans than fit on a line
+        if frame == 60 {
+            limited.button_down(rust_nes::io::Controller::One, rust_nes::io::Button::Start);
+            unlimited.button_down(rust_nes::io::Controller::One, rust_nes::io::Button::Start);
+        }
+        limited.run_until(rust_nes::Event::Frame);
+        unlimited.run_until(rust_nes::Event::Frame);
+        frames_differ |= limited.get_framebuffer()[..] != unlimited.get_framebuffer()[..];
+    }
+    assert!(frames_differ);
+
+    // The overflow flag must behave exactly as it does with the limit in place
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("ppu_sprite_overflow")
+        .join("ppu_sprite_overflow.nes");
+    let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    nes.set_sprite_limit(false);
+    for _ in 0..0xDAFD85 * 3 {
+        nes.next();
+    }
+    nes.resolve_framebuffer();
+    let mut hasher = Hasher::new();
+    hasher.update(nes.get_framebuffer());
+    assert_eq!(hasher.finalize(), 1808572613);
+}
+
+#[test]
+fn scanline_rendering_keeps_sprite_zero_and_mmc3_irq_timing() {
+    let roms = [
+        (
+            0x10E56CB * 3,
+            1340789466,
+            Path::new("ppu_sprite_hit").join("ppu_sprite_hit.nes"),
+        ),
+        (
+            0x105218 * 3,
+            4185058565,
+            Path::new("mmc3_test").join("rom_singles").join("1-clocking.nes"),
+        ),
+        (
+            0x113B3A * 3,
+            820133214,
+            Path::new("mmc3_test").join("rom_singles").join("3-A12_clocking.nes"),
+        ),
+    ];
+
+    for (cycles, expected_crc32, rom) in &roms {
+        let rom_path = Path::new("..").join("roms").join("test").join(rom);
+        let cartridge = rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap();
+        let mut nes = rust_nes::Nes::with_accuracy(cartridge, rust_nes::AccuracyProfile::Fast);
+        for _ in 0..*cycles {
+            nes.next();
+        }
+        nes.resolve_framebuffer();
+        let mut hasher = Hasher::new();
+        hasher.update(nes.get_framebuffer());
+        assert_eq!(hasher.finalize(), *expected_crc32, "{}", rom.display());
+    }
+}
+
+#[test]
+fn savestates_restore_identical_emulation() {
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("mmc3_test")
+        .join("rom_singles")
+        .join("1-clocking.nes");
+    let mut original = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    for _ in 0..20 {
+        original.run_until(rust_nes::Event::Frame);
+    }
+    let state = original.save_state();
+
+    let mut restored = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    restored.load_state(&state).unwrap();
+    for _ in 0..40 {
+        original.run_until(rust_nes::Event::Frame);
+        restored.run_until(rust_nes::Event::Frame);
+        assert_eq!(original.get_framebuffer()[..], restored.get_framebuffer()[..]);
+    }
+    assert_eq!(original.cycles(), restored.cycles());
+
+    // States only load into a console running the same rom, and one which fails part way through
+    // leaves the console as it was
+    let before = restored.save_state();
+    assert!(restored.load_state(&state[..state.len() - 1]).is_err());
+    assert_eq!(restored.save_state(), before);
+    let mut other = rust_nes::Nes::new(rust_nes::get_cartridge("../roms/test/nestest.nes").unwrap());
+    let error = other.load_state(&state).err().unwrap();
+    assert!(
+        error.message.contains("mapper implementation mmc3 version 1"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn repro_replays_recorded_input() {
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("spritecans-2011")
+        .join("spritecans.nes");
+    let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    for _ in 0..30 {
+        nes.run_until(rust_nes::Event::Frame);
+    }
+
+    let mut repro = rust_nes::Repro::record(&mut nes);
+    for frame in 0..120 {
+        match frame {
+            10 => nes.button_down(rust_nes::io::Controller::One, rust_nes::io::Button::Start),
+            20 => nes.button_up(rust_nes::io::Controller::One, rust_nes::io::Button::Start),
+            _ => (),
+        }
+        repro.record_frame(&nes);
+        nes.run_until(rust_nes::Event::Frame);
+    }
+    assert_eq!(repro.inputs.len(), 3);
+
+    let repro = rust_nes::Repro::from_bytes(&repro.to_bytes()).unwrap();
+    let mut replayed = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    repro.replay(&mut replayed).unwrap();
+    assert_eq!(nes.get_framebuffer()[..], replayed.get_framebuffer()[..]);
+    assert_eq!(nes.cycles(), replayed.cycles());
+}
+
+#[test]
+fn sprite_stats_recorded_per_frame() {
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("spritecans-2011")
+        .join("spritecans.nes");
+    let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    nes.record_sprite_stats(true);
+    nes.run_until(rust_nes::Event::Frame);
+    assert!(nes.sprite_stats().is_some());
+
+    // Pressing start adds more cans than fit on a line
+    let mut busiest_line = 0;
+    let mut overflow_frames = 0;
+    for frame in 0..600 {
+        if frame == 60 {
+            nes.button_down(rust_nes::io::Controller::One, rust_nes::io::Button::Start);
+        }
+        nes.run_until(rust_nes::Event::Frame);
+
+        let stats = nes.sprite_stats().unwrap();
+        busiest_line = busiest_line.max(*stats.sprites_per_line.iter().max().unwrap());
+        if let Some(line) = stats.first_overflow_line() {
+            overflow_frames += 1;
+            // The overflow bug can set the flag with only 8 sprites on the line but never fewer
+            assert!(stats.sprites_per_line[line as usize] >= 8);
+        }
+    }
+    assert!(busiest_line > 8);
+    assert!(overflow_frames > 0);
+
+    nes.record_sprite_stats(false);
+    assert!(nes.sprite_stats().is_none());
+}
+
+#[test]
+fn clock_tracks_emulated_time() {
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("spritecans-2011")
+        .join("spritecans.nes");
+    let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    nes.run_until(rust_nes::Event::Frame);
+    let start = nes.clock();
+    for _ in 0..60 {
+        nes.run_until(rust_nes::Event::Frame);
+    }
+    let clock = nes.clock();
+
+    assert_eq!(clock.frame_number, start.frame_number + 60);
+    // The CPU runs every third dot so may be one cycle either side depending on the phase
+    let cpu_cycles = (clock.cpu_cycles - start.cpu_cycles) as i64;
+    assert!((cpu_cycles - (clock.ppu_dots - start.ppu_dots) as i64 / 3).abs() <= 1);
+    assert_eq!(clock.apu_cycles, clock.cpu_cycles / 2);
+    assert_eq!(clock.cpu_cycles, nes.cycles());
+    let seconds = clock.seconds() - start.seconds();
+    assert!((seconds - clock.frames_to_seconds(60)).abs() < 0.001, "{}", seconds);
+    assert_eq!(clock.seconds_to_cpu_cycles(1.0), 1_789_773);
+
+    // The clock is part of the emulated state so is restored with it
+    let state = nes.save_state();
+    nes.run_until(rust_nes::Event::Frame);
+    nes.load_state(&state).unwrap();
+    assert_eq!(nes.clock(), clock);
+}
+
+#[test]
+fn battery_save_written_after_prg_ram_changes() {
+    // The blargg tests write their status and output text to PRG RAM
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("instr_test-v3")
+        .join("official_only.nes");
+    let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    let save_path = std::env::temp_dir().join(format!("rust_nes_battery_{}.sav", std::process::id()));
+    let mut battery_save = rust_nes::BatterySave::with_flush_delay(&save_path, std::time::Duration::from_secs(3600));
+
+    for _ in 0..30 {
+        nes.run_until(rust_nes::Event::Frame);
+        battery_save.update(&mut nes).unwrap();
+    }
+    assert!(battery_save.has_pending_changes());
+    assert!(!save_path.exists());
+
+    let ram = nes.dump_memory(rust_nes::MemoryRegion::PrgRam);
+    drop(battery_save);
+    assert_eq!(std::fs::read(&save_path).unwrap(), ram);
+
+    // Loading the save back doesn't count as the game writing to PRG RAM
+    let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    let battery_save = rust_nes::BatterySave::new(&save_path);
+    assert!(battery_save.load(&mut nes).unwrap());
+    assert_eq!(nes.dump_memory(rust_nes::MemoryRegion::PrgRam), ram);
+    assert!(!nes.take_prg_ram_written());
+    drop(battery_save);
+    std::fs::remove_file(&save_path).unwrap();
+}
+
+#[test]
+fn kil_opcode_jams_cpu_until_reset() {
+    // Increment $00 and then execute KIL
+    let mut nes = nrom_program(&[0xE6, 0x00, 0x02]);
+    nes.enable_diagnostics(4);
+
+    let run = nes.run_until(rust_nes::Event::Frame);
+    assert!(run.jammed);
+    assert_eq!(run.frames, 0);
+    assert!(nes.is_jammed());
+    assert_eq!(nes.dump_memory(rust_nes::MemoryRegion::CpuRam)[0], 1);
+    let events = nes.take_diagnostic_events();
+    assert_eq!(events[0].kind, rust_nes::cpu::DiagnosticKind::Jammed { pc: 0x8002 });
+
+    // Halted so nothing more runs, but the state can still be saved and restored
+    assert_eq!(nes.run_for_cpu_cycles(1000).cpu_cycles, 0);
+    let state = nes.save_state();
+    nes.load_state(&state).unwrap();
+    assert!(nes.is_jammed());
+
+    nes.set_jam_policy(rust_nes::JamPolicy::ResetAfterFrames(2));
+    assert_eq!(nes.run_until(rust_nes::Event::Frame).frames, 1);
+    assert!(nes.is_jammed());
+    nes.run_until(rust_nes::Event::Frame);
+    assert!(!nes.is_jammed());
+    nes.run_for_cpu_cycles(100);
+    assert!(nes.is_jammed());
+    assert_eq!(nes.dump_memory(rust_nes::MemoryRegion::CpuRam)[0], 2);
+}
+
+#[test]
+fn interrupt_vectors_and_stack_inspected() {
+    // JSR to a subroutine at $8010 which executes KIL, the vectors are set in `nrom_program`
+    let mut program = vec![0xEA; 0x11];
+    program[..3].copy_from_slice(&[0x20, 0x10, 0x80]);
+    program[0x10] = 0x02;
+    let mut nes = nrom_program(&program);
+    nes.run_until(rust_nes::Event::Frame);
+
+    let vectors = nes.interrupt_vectors();
+    assert_eq!(
+        (vectors.nmi.address, vectors.reset.address, vectors.irq.address),
+        (0x8020, 0x8000, 0x8030)
+    );
+    assert_eq!(vectors.reset.prg_rom_offset, Some(0));
+    assert_eq!(vectors.nmi.prg_rom_offset, Some(0x20));
+
+    // The stack pointer starts at $FD so the return address sits at $01FC-$01FD
+    let stack = nes.stack();
+    assert_eq!(
+        stack.iter().map(|entry| entry.address).collect::<Vec<_>>(),
+        vec![0x1FC, 0x1FD, 0x1FE, 0x1FF]
+    );
+    assert_eq!((stack[0].value, stack[1].value), (0x02, 0x80));
+    let return_address = stack[0].return_address.as_ref().unwrap();
+    assert_eq!(return_address.address, 0x8003);
+    assert_eq!(return_address.prg_rom_offset, Some(3));
+    assert_eq!(format!("{}", stack[0]), "01FC: 02  returns to 8003 (PRG 00003)");
+    assert!(stack[1..].iter().all(|entry| entry.return_address.is_none()));
+}
+
+#[test]
+fn input_script_drives_controller_reads() {
+    // Enable NMI and spin, the NMI handler reads controller one and appends it to $0300
+    let mut program = vec![0; 0x40];
+    program[..8].copy_from_slice(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80]);
+    program[0x20..0x3F].copy_from_slice(&[
+        0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xA2, 0x08, 0xAD, 0x16, 0x40, 0x4A, 0x26, 0x00,
+        0xCA, 0xD0, 0xF7, 0xA4, 0x01, 0xA5, 0x00, 0x99, 0x00, 0x03, 0xE6, 0x01, 0x40,
+    ]);
+    let mut nes = nrom_program(&program);
+
+    let script = rust_nes::InputScript::new()
+        .wait(2)
+        .press(rust_nes::io::Button::A)
+        .for_frames(2)
+        .then(rust_nes::io::Button::Start)
+        .and(rust_nes::io::Button::Right)
+        .wait(1);
+    script.play(&mut nes);
+
+    // The bits are read A first so end up reversed. The NMI handler runs just after each frame
+    // completes so it sees the buttons held for the following frame.
+    let ram = nes.dump_memory(rust_nes::MemoryRegion::CpuRam);
+    assert_eq!(
+        &ram[0x300..0x300 + ram[1] as usize],
+        &[0x00, 0x80, 0x80, 0x11, 0x00][..]
+    );
+    assert_eq!(nes.controller_state(rust_nes::io::Controller::One), 0);
+}
+
+#[test]
+fn frame_info_describes_each_frame() {
+    // Enable NMI, optionally enable background rendering and then write to $2005 forever
+    let program = |mask: u8| {
+        let mut program = vec![0; 0x21];
+        program[..16].copy_from_slice(&[
+            0xA9, 0x80, 0x8D, 0x00, 0x20, 0xA9, mask, 0x8D, 0x01, 0x20, 0x8D, 0x05, 0x20, 0x4C, 0x0A, 0x80,
+        ]);
+        program[0x20] = 0x40;
+        program
+    };
+
+    let mut nes = nrom_program(&program(0x08));
+    assert_eq!(nes.frame_info(), None);
+    nes.run_until(rust_nes::Event::Frame);
+    let run = nes.run_until(rust_nes::Event::Frame);
+    let info = run.frame_info.unwrap();
+    assert_eq!(Some(info), nes.frame_info());
+    assert_eq!(info.frame_number, nes.frame_number());
+    assert_eq!(info.odd_frame, info.frame_number % 2 == 1);
+    assert!(info.rendering_enabled);
+    assert!(info.mid_frame_scroll_changes > 0);
+    assert!(info.nmi_cycle.is_some());
+
+    // Scroll writes with rendering disabled don't affect the picture
+    let mut nes = nrom_program(&program(0x00));
+    nes.run_until(rust_nes::Event::Frame);
+    let info = nes.run_until(rust_nes::Event::Frame).frame_info.unwrap();
+    assert!(!info.rendering_enabled);
+    assert_eq!(info.mid_frame_scroll_changes, 0);
+    assert!(info.nmi_cycle.is_some());
+}
+
+/// The CRC of the framebuffer at the end of each frame, with start held for a few frames
+fn frame_crcs(mut nes: rust_nes::Nes, frames: u32) -> Vec<u32> {
+    (0..frames)
+        .map(|frame| {
+            match frame {
+                30 => nes.button_down(rust_nes::io::Controller::One, rust_nes::io::Button::Start),
+                35 => nes.button_up(rust_nes::io::Controller::One, rust_nes::io::Button::Start),
+                _ => (),
+            }
+            nes.run_until(rust_nes::Event::Frame);
+            let mut hasher = Hasher::new();
+            hasher.update(nes.get_framebuffer());
+            hasher.finalize()
+        })
+        .collect()
+}
+
+#[test]
+fn instances_run_side_by_side() {
+    let roms = [
+        Path::new("..").join("roms").join("test").join("nestest.nes"),
+        Path::new("..")
+            .join("roms")
+            .join("test")
+            .join("spritecans-2011")
+            .join("spritecans.nes"),
+    ];
+    let create = |index: usize| rust_nes::Nes::new(rust_nes::get_cartridge(roms[index].to_str().unwrap()).unwrap());
+    let alone = [frame_crcs(create(0), 60), frame_crcs(create(1), 60)];
+
+    // Interleaved on one thread
+    let mut consoles = [create(0), create(1)];
+    let mut interleaved = [vec![], vec![]];
+    for _ in 0..60 {
+        for (nes, crcs) in consoles.iter_mut().zip(interleaved.iter_mut()) {
+            nes.run_until(rust_nes::Event::Frame);
+            let mut hasher = Hasher::new();
+            hasher.update(nes.get_framebuffer());
+            crcs.push(hasher.finalize());
+        }
+    }
+    assert_ne!(interleaved[0][30..], interleaved[1][30..]);
+
+    // And concurrently on a thread each, the input only goes to its own console
+    let threads = vec![create(0), create(1)]
+        .into_iter()
+        .map(|nes| std::thread::spawn(move || frame_crcs(nes, 60)))
+        .collect::<Vec<_>>();
+    let concurrent = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(concurrent[..], alone[..]);
+    // Input was only given to the consoles run by frame_crcs
+    assert_eq!(interleaved[0][..30], alone[0][..30]);
+    assert_eq!(interleaved[1][..30], alone[1][..30]);
+}
+
+#[test]
+fn palette_applied_to_index_buffer_without_rerendering() {
+    let rom_path = Path::new("..")
+        .join("roms")
+        .join("test")
+        .join("spritecans-2011")
+        .join("spritecans.nes");
+    let mut nes = rust_nes::Nes::new(rust_nes::get_cartridge(rom_path.to_str().unwrap()).unwrap());
+    for _ in 0..30 {
+        nes.run_until(rust_nes::Event::Frame);
+    }
+    let indices = nes.get_index_buffer().to_vec();
+    // Every pixel is drawn without emphasis, only the palette address is stored above the index
+    assert!(indices.iter().all(|&pixel| pixel & 0x81C0 == 0));
+
+    let mut palette = [0; 0x40];
+    for (index, colour) in palette.iter_mut().enumerate() {
+        *colour = index as u32;
+    }
+    nes.set_palette(palette);
+    let framebuffer = nes.get_framebuffer();
+    for (pixel, bgra) in indices.iter().zip(framebuffer.chunks(4)) {
+        assert_eq!(bgra, &[(*pixel & 0x3F) as u8, 0, 0, 0]);
+    }
+    assert_eq!(nes.get_index_buffer()[..], indices[..]);
+}
+
+#[test]
+fn oam_dma_from_last_page_wraps_within_oam() {
+    // OAMADDR = $05 then DMA the page holding the vectors at $FFFA-$FFFF
+    let mut nes = nrom_program(&[
+        0xA9, 0x05, 0x8D, 0x03, 0x20, 0xA9, 0xFF, 0x8D, 0x14, 0x40, 0x4C, 0x0A, 0x80,
+    ]);
+    nes.run_until(rust_nes::Event::ProgramCounter(0x800A));
+
+    let oam = nes.dump_memory(rust_nes::MemoryRegion::Oam);
+    // $FFFA-$FFFF land at $FF then wrap around to $00-$04
+    let mut expected = vec![0; 0x100];
+    expected[0xFF] = 0x20;
+    expected[..5].copy_from_slice(&[0x80, 0x00, 0x80, 0x30, 0x80]);
+    assert_eq!(oam, expected);
+}
+
+#[test]
+fn step_over_and_out_follow_subroutines_and_interrupts() {
+    let mut program = vec![0; 0x31];
+    // $8000: JSR $8010, JMP $8003
+    program[..6].copy_from_slice(&[0x20, 0x10, 0x80, 0x4C, 0x03, 0x80]);
+    // $8010: JSR $8018, LDA #$01, RTS
+    program[0x10..0x16].copy_from_slice(&[0x20, 0x18, 0x80, 0xA9, 0x01, 0x60]);
+    // $8018: BRK, RTS
+    program[0x18..0x1B].copy_from_slice(&[0x00, 0x00, 0x60]);
+    // IRQ/BRK handler at $8030: RTI
+    program[0x30] = 0x40;
+    let pc = |nes: &rust_nes::Nes| nes.registers().program_counter;
+
+    let mut nes = nrom_program(&program);
+    nes.step_over();
+    assert_eq!(pc(&nes), 0x8003);
+
+    let mut nes = nrom_program(&program);
+    nes.step_instruction();
+    nes.step_instruction();
+    assert_eq!(pc(&nes), 0x8018);
+    nes.step_over();
+    assert_eq!(pc(&nes), 0x801A);
+    nes.step_out();
+    assert_eq!(pc(&nes), 0x8013);
+    nes.step_instruction();
+    nes.step_over();
+    assert_eq!(pc(&nes), 0x8003);
+
+    let mut nes = nrom_program(&program);
+    nes.run_until(rust_nes::Event::ProgramCounter(0x8030));
+    nes.step_out();
+    assert_eq!(pc(&nes), 0x801A);
+}
+
+#[test]
+fn achievement_memory_and_frame_callback() {
+    // $8000: INC $10, JMP $8000
+    let mut nes = nrom_program(&[0xE6, 0x10, 0x4C, 0x00, 0x80]);
+    let frames = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let callback_frames = frames.clone();
+    nes.set_frame_callback(Some(Box::new(move |nes: &rust_nes::Nes| {
+        callback_frames
+            .lock()
+            .unwrap()
+            .push(nes.peek_achievement_memory(0x0010));
+    })));
+    nes.run_until(rust_nes::Event::Frame);
+    nes.run_until(rust_nes::Event::Frame);
+
+    let frames = frames.lock().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_ne!(frames[0], frames[1]);
+
+    let mut memory = [0xFF; 4];
+    nes.read_achievement_memory(0x0810, &mut memory[..1]);
+    assert_eq!(memory[0], nes.peek_achievement_memory(0x0010));
+    nes.read_achievement_memory(0x7FFF, &mut memory);
+    assert_eq!(memory, [0x00, 0xE6, 0x10, 0x4C]);
+    assert_eq!(nes.peek_achievement_memory(0x2002), 0);
+    assert_eq!(nes.peek_achievement_memory(0x10000), 0);
+}
+
+#[test]
+fn overclock_adds_cpu_cycles_without_changing_apu_rate() {
+    let run_frames = |overclock: rust_nes::Overclock| {
+        // $8000: JMP $8000
+        let mut nes = nrom_program(&[0x4C, 0x00, 0x80]);
+        nes.set_overclock(overclock);
+        nes.run_until(rust_nes::Event::Frame);
+        let (mut cycles, mut samples) = (0, 0);
+        for _ in 0..10 {
+            let frame = nes.run_until(rust_nes::Event::Frame);
+            cycles += frame.cpu_cycles;
+            samples += frame.samples.len() as u64;
+        }
+        (cycles, samples)
+    };
+
+    let (stock_cycles, stock_samples) = run_frames(rust_nes::Overclock::default());
+    let overclock = rust_nes::Overclock {
+        extra_scanlines: 30,
+        clock_apu: false,
+    };
+    let (cycles, samples) = run_frames(overclock);
+    // 30 scanlines of 341 dots at 3 dots per CPU cycle for each of the 10 frames
+    assert_eq!(cycles - stock_cycles, 30 * 341 * 10 / 3);
+    assert_eq!(samples, stock_samples);
+
+    let (cycles, samples) = run_frames(rust_nes::Overclock {
+        clock_apu: true,
+        ..overclock
+    });
+    assert_eq!(samples, cycles);
+}
+
+#[test]
+fn run_frame_presents_frames_from_a_jammed_console() {
+    struct Recorder(Vec<(u32, u32, usize, Option<u32>)>);
+    impl rust_nes::VideoSink for Recorder {
+        fn present(&mut self, frame: &rust_nes::Frame) {
+            let number = frame.info.map(|info| info.frame_number);
+            self.0.push((frame.width, frame.height, frame.pixels.len(), number));
+        }
+    }
+
+    // $8000: KIL
+    let mut nes = nrom_program(&[0x02]);
+    let mut recorder = Recorder(vec![]);
+    let mut samples = vec![];
+    for _ in 0..3 {
+        rust_nes::run_frame(&mut nes, &mut recorder, &mut samples);
+    }
+
+    assert!(nes.is_jammed());
+    assert_eq!(nes.run_until(rust_nes::Event::Frame).frames, 0);
+    assert_eq!(recorder.0.len(), 3);
+    assert!(recorder
+        .0
+        .iter()
+        .all(|&(width, height, length, _)| (width, height, length) == (256, 240, 256 * 240 * 4)));
+    assert_eq!(recorder.0[1].3.map(|number| number + 1), recorder.0[2].3);
+    assert!(!samples.is_empty());
+}
+
+#[test]
+fn apply_input_presses_buttons_until_quit() {
+    use rust_nes::io::{Button, Controller};
+    use rust_nes::InputEvent::{ButtonDown, ButtonUp, Quit};
+
+    struct Script(Vec<Vec<rust_nes::InputEvent>>);
+    impl rust_nes::InputProvider for Script {
+        fn poll(&mut self) -> Vec<rust_nes::InputEvent> {
+            self.0.remove(0)
+        }
+    }
+
+    let mut nes = nrom_program(&[0x4C, 0x00, 0x80]);
+    let mut script = Script(vec![
+        vec![
+            ButtonDown(Controller::One, Button::A),
+            ButtonDown(Controller::Two, Button::Start),
+        ],
+        vec![ButtonUp(Controller::One, Button::A)],
+        vec![
+            ButtonDown(Controller::One, Button::B),
+            Quit,
+            ButtonDown(Controller::One, Button::Up),
+        ],
+    ]);
+
+    assert!(rust_nes::apply_input(&mut nes, &mut script));
+    assert_eq!(nes.controller_state(Controller::One), 0b0000_0001);
+    assert_eq!(nes.controller_state(Controller::Two), 0b0000_1000);
+    assert!(rust_nes::apply_input(&mut nes, &mut script));
+    assert_eq!(nes.controller_state(Controller::One), 0);
+    // Events after a quit aren't applied
+    assert!(!rust_nes::apply_input(&mut nes, &mut script));
+    assert_eq!(nes.controller_state(Controller::One), 0b0000_0010);
+}
+
+#[test]
+fn rmw_instructions_write_the_original_value_to_ppudata() {
+    let program = [
+        // Fill $2000-$2003 with $10, $20, $30, $40
+        &[0xA9, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20][..],
+        &[0xA9, 0x10, 0x8D, 0x07, 0x20, 0xA9, 0x20, 0x8D, 0x07, 0x20],
+        &[0xA9, 0x30, 0x8D, 0x07, 0x20, 0xA9, 0x40, 0x8D, 0x07, 0x20],
+        // Back to $2000 and prime the read buffer with $2000
+        &[
+            0xA9, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xAD, 0x07, 0x20,
+        ],
+        // INC $2007: JMP $802E
+        &[0xEE, 0x07, 0x20, 0x4C, 0x2E, 0x80],
+    ]
+    .concat();
+    let mut nes = nrom_program(&program);
+    nes.run_until(rust_nes::Event::ProgramCounter(0x802E));
+
+    // The read of $2001 returns the buffered $10 which is written back to $2002 and the
+    // incremented value to $2003
+    let vram = nes.dump_memory(rust_nes::MemoryRegion::Vram);
+    assert_eq!(&vram[0x2000..0x2004], &[0x10, 0x20, 0x10, 0x11]);
+}
+
+#[test]
+fn rmw_instructions_on_mmc1_registers_only_shift_the_original_value() {
+    // 8 16KB banks each starting with its bank number, the code is in the last bank at $C000
+    let mut prg_rom = vec![0; 0x20000];
+    for bank in 0..8 {
+        prg_rom[bank * 0x4000] = bank as u8;
+    }
+    let program = [
+        // INC $F000 ($F000 holds 0)
+        &[0xEE, 0x00, 0xF0][..],
+        // Shift in 1, 0, 0, 0 which selects PRG bank 0b00010 if the INC only shifted in a 0
+        &[
+            0xA9, 0x01, 0x8D, 0x00, 0xE0, 0xA9, 0x00, 0x8D, 0x00, 0xE0, 0x8D, 0x00, 0xE0, 0x8D, 0x00, 0xE0,
+        ],
+        // LDA $8000: STA $00: JMP $C018
+        &[0xAD, 0x00, 0x80, 0x85, 0x00, 0x4C, 0x18, 0xC0],
+    ]
+    .concat();
+    prg_rom[0x1C000..0x1C000 + program.len()].copy_from_slice(&program);
+    prg_rom[0x1FFFA..].copy_from_slice(&[0x18, 0xC0, 0x00, 0xC0, 0x18, 0xC0]);
+    let cartridge = rust_nes::from_prg_chr(
+        prg_rom,
+        Some(vec![0; 0x2000]),
+        1,
+        rust_nes::cartridge::MirroringMode::Horizontal,
+    )
+    .unwrap();
+    let mut nes = rust_nes::Nes::new(cartridge);
+    nes.run_until(rust_nes::Event::ProgramCounter(0xC018));
+
+    // MMC1 ignores the incremented value as it's written on the cycle after the original, had it
+    // been shifted in then bank 0b00110 would be selected
+    assert_eq!(nes.dump_memory(rust_nes::MemoryRegion::CpuRam)[0], 2);
+}
+
+/// Rewards each increment of $00 and ends the episode once it reaches 10
+struct CounterProbe {
+    last: u8,
+}
+
+impl rust_nes::RewardProbe for CounterProbe {
+    fn reset(&mut self, nes: &rust_nes::Nes) {
+        self.last = nes.peek_achievement_memory(0);
+    }
+
+    fn reward(&mut self, nes: &rust_nes::Nes) -> f32 {
+        let counter = nes.peek_achievement_memory(0);
+        let reward = counter.wrapping_sub(self.last) as f32;
+        self.last = counter;
+        reward
+    }
+
+    fn done(&mut self, nes: &rust_nes::Nes) -> bool {
+        nes.peek_achievement_memory(0) >= 10
+    }
+}
+
+#[test]
+fn environment_episodes_restart_from_the_same_state() {
+    // Enable NMI and spin, the NMI handler adds controller one's A button (plus 1) to $00
+    let mut program = vec![0; 0x32];
+    program[..8].copy_from_slice(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80]);
+    program[0x20..0x32].copy_from_slice(&[
+        0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xAD, 0x16, 0x40, 0x29, 0x01, 0x38, 0x65, 0x00,
+    ]);
+    program.extend_from_slice(&[0x85, 0x00, 0x40]);
+    let mut environment = rust_nes::Environment::new(nrom_program(&program), Box::new(CounterProbe { last: 0 }));
+
+    let episode = |environment: &mut rust_nes::Environment, action: u8| {
+        environment.reset();
+        let mut rewards = vec![];
+        loop {
+            let step = environment.step(action);
+            assert_eq!(step.observation.len(), 256 * 240 * 4);
+            rewards.push(step.reward);
+            if step.done {
+                return rewards;
+            }
+        }
+    };
+
+    // Holding A counts twice as fast, each step is 4 frames and ends as soon as $00 reaches 10
+    let idle = episode(&mut environment, 0);
+    assert_eq!(idle.iter().sum::<f32>(), 10.0);
+    assert_eq!(idle.len(), 3);
+    let held = episode(&mut environment, 0b0000_0001);
+    assert_eq!(held.iter().sum::<f32>(), 10.0);
+    assert_eq!(held.len(), 2);
+    assert_eq!(episode(&mut environment, 0), idle);
+}
+
+#[test]
+fn resume_state_carries_on_where_the_last_run_stopped() {
+    // Increment $00 forever
+    let program = [0xE6, 0x00, 0x4C, 0x00, 0x80];
+    let state_dir = std::env::temp_dir().join(format!("rust_nes_states_{}", std::process::id()));
+    let resume_state = rust_nes::ResumeState::for_rom(&state_dir, 0x1234_ABCD);
+    assert_eq!(resume_state.path(), state_dir.join("1234ABCD.state").as_path());
+
+    let mut nes = nrom_program(&program);
+    assert!(!resume_state.load(&mut nes).unwrap());
+    for _ in 0..5 {
+        nes.run_until(rust_nes::Event::Frame);
+    }
+    resume_state.save(&mut nes).unwrap();
+    let ram = nes.dump_memory(rust_nes::MemoryRegion::CpuRam);
+
+    let mut resumed = nrom_program(&program);
+    let loaded = resume_state.load(&mut resumed);
+    std::fs::remove_dir_all(&state_dir).unwrap();
+
+    assert!(loaded.unwrap());
+    assert_eq!(resumed.dump_memory(rust_nes::MemoryRegion::CpuRam), ram);
+    assert_eq!(resumed.cycles(), nes.cycles());
 }
 
 const ASCII_GRAYSCALE_ARRAY: [char; 96] = [
@@ -170,3 +1341,20 @@ fn framebuffer_to_ascii_art(fb: [u8; (256 * 240 * 4) as usize]) -> String {
         .map(|char_line| char_line.iter().collect::<String>())
         .fold(String::new(), |a, b| a + "\n" + &b)
 }
+
+/// Build a 16KB NROM cartridge with the program at $8000 and load it, NMI and IRQ point at $8020
+/// and $8030 respectively
+fn nrom_program(program: &[u8]) -> rust_nes::Nes {
+    let mut prg_rom = vec![0; 0x4000];
+    prg_rom[..program.len()].copy_from_slice(program);
+    prg_rom[0x3FFA..].copy_from_slice(&[0x20, 0x80, 0x00, 0x80, 0x30, 0x80]);
+    let cartridge = rust_nes::from_prg_chr(
+        prg_rom,
+        Some(vec![0; 0x2000]),
+        0,
+        rust_nes::cartridge::MirroringMode::Horizontal,
+    )
+    .unwrap();
+
+    rust_nes::Nes::new(cartridge)
+}
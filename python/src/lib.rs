@@ -0,0 +1,175 @@
+//! Python bindings for driving the emulator from scripts, e.g. reinforcement learning agents or
+//! analysis of a game's RAM, in the style of nes-py. Build and install into the current virtualenv
+//! with `maturin develop --release --cargo-extra-args="--features extension-module"`.
+//!
+//! ```python
+//! from rust_nes_py import Nes
+//!
+//! nes = Nes("roms/test/nestest.nes")
+//! nes.set_buttons(0, 0b0000_1000)  # Start
+//! nes.step_frame(60)
+//! frame = nes.framebuffer()  # numpy uint8 array of shape (240, 256, 3)
+//! ```
+//!
+//! Emulation is deterministic so a saved state replayed with the same inputs produces the same
+//! frames, which is what an environment's reset needs.
+
+extern crate numpy;
+extern crate pyo3;
+extern crate rust_nes;
+
+use numpy::{PyArray1, PyArray3};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rust_nes::io::Controller;
+use rust_nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rust_nes::{Event, MemoryRegion};
+use std::str::FromStr;
+
+fn controller(index: usize) -> PyResult<Controller> {
+    match index {
+        0 => Ok(Controller::One),
+        1 => Ok(Controller::Two),
+        _ => Err(PyValueError::new_err(format!("There's no controller {}", index))),
+    }
+}
+
+fn load(rom_file: &str) -> PyResult<rust_nes::Nes> {
+    rust_nes::get_cartridge(rom_file)
+        .map(rust_nes::Nes::new)
+        .map_err(|why| PyIOError::new_err(format!("Failed to load {}: {}", rom_file, why.message)))
+}
+
+/// A console running a rom, frames are only run when asked for so there's no realtime pacing
+#[pyclass(unsendable)]
+struct Nes {
+    nes: rust_nes::Nes,
+    /// The audio from the last call to `step_frame`
+    samples: Vec<f32>,
+}
+
+#[pymethods]
+impl Nes {
+    #[new]
+    fn new(rom_file: &str) -> PyResult<Self> {
+        Ok(Nes {
+            nes: load(rom_file)?,
+            samples: vec![],
+        })
+    }
+
+    /// Replace the running rom with another, as if the console was powered off and on
+    fn load_rom(&mut self, rom_file: &str) -> PyResult<()> {
+        self.nes = load(rom_file)?;
+        self.samples.clear();
+        Ok(())
+    }
+
+    /// Press the reset button, RAM is left intact
+    fn reset(&mut self) {
+        self.nes.reset();
+    }
+
+    /// Run the console to the end of the next `frames` frames, stopping early if the CPU jams
+    #[args(frames = "1")]
+    fn step_frame(&mut self, frames: u32) {
+        self.samples.clear();
+        for _ in 0..frames {
+            let run = self.nes.run_until(Event::Frame);
+            self.samples.extend(run.samples);
+            if run.jammed {
+                break;
+            }
+        }
+    }
+
+    /// The last frame as RGB, a uint8 array of shape (240, 256, 3)
+    fn framebuffer<'py>(&mut self, py: Python<'py>) -> PyResult<&'py PyArray3<u8>> {
+        let mut rgb = Vec::with_capacity((SCREEN_WIDTH * SCREEN_HEIGHT * 3) as usize);
+        for bgra in self.nes.get_framebuffer().chunks_exact(4) {
+            rgb.extend_from_slice(&[bgra[2], bgra[1], bgra[0]]);
+        }
+
+        PyArray1::from_vec(py, rgb).reshape([SCREEN_HEIGHT as usize, SCREEN_WIDTH as usize, 3])
+    }
+
+    /// The audio produced by the last call to `step_frame`, one float32 sample in the range 0..1
+    /// per CPU cycle (i.e. at 1789773Hz on NTSC)
+    fn audio<'py>(&self, py: Python<'py>) -> &'py PyArray1<f32> {
+        PyArray1::from_slice(py, &self.samples)
+    }
+
+    /// Set every button on a controller (0 or 1) at once, from bit 0 to 7 they're A, B, Select,
+    /// Start, Up, Down, Left and Right
+    fn set_buttons(&mut self, controller_index: usize, buttons: u8) -> PyResult<()> {
+        self.nes.set_controller_state(controller(controller_index)?, buttons);
+        Ok(())
+    }
+
+    /// The buttons held on a controller, as passed to `set_buttons`
+    fn buttons(&self, controller_index: usize) -> PyResult<u8> {
+        Ok(self.nes.controller_state(controller(controller_index)?))
+    }
+
+    /// A copy of the 2KB of internal RAM as a uint8 array
+    fn ram<'py>(&self, py: Python<'py>) -> &'py PyArray1<u8> {
+        PyArray1::from_vec(py, self.nes.dump_memory(MemoryRegion::CpuRam))
+    }
+
+    /// Read a byte of internal RAM, addresses are mirrored every 2KB as on the CPU bus
+    fn read_ram(&self, address: u16) -> u8 {
+        let ram = self.nes.dump_memory(MemoryRegion::CpuRam);
+        ram[address as usize % ram.len()]
+    }
+
+    /// Write a byte of internal RAM, e.g. to give the player extra lives
+    fn write_ram(&mut self, address: u16, value: u8) -> PyResult<()> {
+        let mut ram = self.nes.dump_memory(MemoryRegion::CpuRam);
+        let len = ram.len();
+        ram[address as usize % len] = value;
+        self.nes
+            .load_memory(MemoryRegion::CpuRam, &ram)
+            .map_err(|why| PyValueError::new_err(why.message))
+    }
+
+    /// A copy of any of the memory regions by name (cpu_ram, vram, oam, palette, prg_ram)
+    fn memory<'py>(&self, py: Python<'py>, region: &str) -> PyResult<&'py PyArray1<u8>> {
+        let region = MemoryRegion::from_str(region).map_err(PyValueError::new_err)?;
+        Ok(PyArray1::from_vec(py, self.nes.dump_memory(region)))
+    }
+
+    /// The state of the whole console as bytes, e.g. to return to at the start of each episode
+    fn save_state<'py>(&mut self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.nes.save_state())
+    }
+
+    /// Restore a state from `save_state`, the console must be running the same rom
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.nes
+            .load_state(state)
+            .map_err(|why| PyValueError::new_err(why.message))
+    }
+
+    #[getter]
+    fn frame_number(&self) -> u32 {
+        self.nes.frame_number()
+    }
+
+    #[getter]
+    fn cycles(&self) -> u64 {
+        self.nes.cycles()
+    }
+
+    /// Set once the CPU executes a KIL opcode, it stays jammed until `reset`
+    #[getter]
+    fn is_jammed(&self) -> bool {
+        self.nes.is_jammed()
+    }
+}
+
+#[pymodule]
+fn rust_nes_py(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<Nes>()?;
+    Ok(())
+}
@@ -0,0 +1,229 @@
+//! Runs a corpus of roms headless, each for a fixed number of PPU cycles, and checks the CRC32 of
+//! the final frame against a manifest. Roms run in parallel with a console per worker thread and
+//! the results are reported in manifest order so that runs can be compared line by line.
+
+extern crate clap;
+extern crate crc32fast;
+extern crate csv;
+extern crate num_cpus;
+extern crate rust_nes;
+extern crate serde;
+
+use clap::Clap;
+use crc32fast::Hasher;
+use serde::Deserialize;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Clap)]
+#[clap(version = "1.0", author = "David Tyler <davet.code@gmail.com>")]
+struct Opts {
+    /// CSV with the columns name, rom, ppu_cycles, crc32 and resets, rom paths are relative to the
+    /// manifest and resets lists the PPU cycles (separated by spaces) to press reset at, if any
+    manifest: String,
+    /// Worker threads, one per core by default
+    #[clap(long = "jobs")]
+    jobs: Option<usize>,
+    /// Run a single shard of the corpus given as INDEX/COUNT (e.g. 2/4) to split it across CI
+    /// machines, roms are dealt out to the shards in turn
+    #[clap(long = "shard", parse(try_from_str = parse_shard))]
+    shard: Option<Shard>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Shard {
+    /// From 1 to `count`
+    index: usize,
+    count: usize,
+}
+
+impl Shard {
+    fn contains(&self, position: usize) -> bool {
+        position % self.count == self.index - 1
+    }
+}
+
+fn parse_shard(value: &str) -> Result<Shard, String> {
+    let mut parts = value.splitn(2, '/').map(str::parse::<usize>);
+    match (parts.next(), parts.next()) {
+        (Some(Ok(index)), Some(Ok(count))) if index >= 1 && index <= count => Ok(Shard { index, count }),
+        _ => Err(format!("Invalid shard {}, expected INDEX/COUNT e.g. 1/4", value)),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestEntry {
+    name: String,
+    rom: String,
+    ppu_cycles: usize,
+    crc32: u32,
+    resets: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Outcome {
+    Passed,
+    Failed {
+        actual_crc32: u32,
+    },
+    /// The rom couldn't be loaded or the emulator panicked
+    Error(String),
+}
+
+fn main() -> Result<(), csv::Error> {
+    let opts: Opts = Opts::parse();
+    let manifest_directory = Path::new(&opts.manifest)
+        .parent()
+        .map_or_else(PathBuf::new, Path::to_path_buf);
+
+    let mut entries = vec![];
+    for (position, entry) in csv::Reader::from_path(&opts.manifest)?.deserialize().enumerate() {
+        let entry: ManifestEntry = entry?;
+        let in_shard = match opts.shard {
+            None => true,
+            Some(shard) => shard.contains(position),
+        };
+        if in_shard {
+            entries.push(entry);
+        }
+    }
+
+    let jobs = opts.jobs.unwrap_or_else(num_cpus::get).max(1);
+    let started = Instant::now();
+    let results = run_parallel(entries.clone(), manifest_directory, jobs);
+
+    let mut failures = 0;
+    for (entry, (outcome, duration)) in entries.iter().zip(results.iter()) {
+        let seconds = duration.as_secs_f64();
+        match outcome {
+            Outcome::Passed => println!("ok    {} ({:.1}s)", entry.name, seconds),
+            Outcome::Failed { actual_crc32 } => println!(
+                "FAIL  {} ({:.1}s): expected crc32 {} but was {}",
+                entry.name, seconds, entry.crc32, actual_crc32
+            ),
+            Outcome::Error(why) => println!("ERROR {}: {}", entry.name, why),
+        }
+        if *outcome != Outcome::Passed {
+            failures += 1;
+        }
+    }
+
+    println!();
+    println!(
+        "{} passed, {} failed of {} roms in {:.1}s on {} threads",
+        entries.len() - failures,
+        failures,
+        entries.len(),
+        started.elapsed().as_secs_f64(),
+        jobs
+    );
+
+    if failures > 0 {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Run every entry, returning the outcomes in the same order as the entries however the work
+/// was split between the threads
+fn run_parallel(entries: Vec<ManifestEntry>, directory: PathBuf, jobs: usize) -> Vec<(Outcome, Duration)> {
+    let count = entries.len();
+    let entries = Arc::new(entries);
+    let directory = Arc::new(directory);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = channel();
+
+    let workers = (0..jobs.min(count))
+        .map(|_| {
+            let (entries, directory, next, sender) = (entries.clone(), directory.clone(), next.clone(), sender.clone());
+            thread::spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                match entries.get(index) {
+                    None => break,
+                    Some(entry) => {
+                        let started = Instant::now();
+                        let outcome = run_entry(entry, &directory);
+                        sender.send((index, outcome, started.elapsed())).unwrap();
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(sender);
+
+    let mut results = vec![(Outcome::Error("Not run".to_string()), Duration::default()); count];
+    for (index, outcome, duration) in receiver {
+        results[index] = (outcome, duration);
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    results
+}
+
+fn run_entry(entry: &ManifestEntry, directory: &Path) -> Outcome {
+    let rom_path = directory.join(&entry.rom);
+    let cartridge = match rust_nes::get_cartridge(&rom_path.to_string_lossy()) {
+        Err(why) => return Outcome::Error(why.message),
+        Ok(cartridge) => cartridge,
+    };
+
+    let resets = match entry
+        .resets
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<usize>, _>>()
+    {
+        Err(why) => return Outcome::Error(format!("Invalid resets {}: {}", entry.resets, why)),
+        Ok(resets) => resets,
+    };
+
+    // A panic in one rom shouldn't take down the worker and the rest of the corpus with it
+    let cycles = entry.ppu_cycles;
+    let framebuffer = panic::catch_unwind(panic::AssertUnwindSafe(move || {
+        rust_nes::run_headless_cycles_with_resets(cartridge, cycles, &resets)
+    }));
+
+    match framebuffer {
+        Err(_) => Outcome::Error("The emulator panicked".to_string()),
+        Ok(framebuffer) => {
+            let mut hasher = Hasher::new();
+            hasher.update(&framebuffer);
+            let actual_crc32 = hasher.finalize();
+            if actual_crc32 == entry.crc32 {
+                Outcome::Passed
+            } else {
+                Outcome::Failed { actual_crc32 }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod regression_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shard() {
+        assert_eq!(parse_shard("2/4"), Ok(Shard { index: 2, count: 4 }));
+        assert!(parse_shard("0/4").is_err());
+        assert!(parse_shard("5/4").is_err());
+        assert!(parse_shard("2").is_err());
+    }
+
+    #[test]
+    fn test_shards_cover_corpus_once() {
+        let shards = (1..=3).map(|index| Shard { index, count: 3 }).collect::<Vec<_>>();
+        for position in 0..10 {
+            assert_eq!(shards.iter().filter(|shard| shard.contains(position)).count(), 1);
+        }
+    }
+}
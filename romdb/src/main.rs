@@ -3,6 +3,7 @@ extern crate rust_nes;
 extern crate serde;
 
 use clap::Clap;
+use rust_nes::cartridge::mapper_info;
 use serde::Serialize;
 use std::fs;
 use std::io;
@@ -17,8 +18,22 @@ struct Opts {
 struct RomResult {
     filename: String,
     mapper: Option<u8>,
-    prg_16kb_units: Option<u8>,
-    chr_8kb_banks: Option<u8>,
+    submapper: Option<u8>,
+    mapper_name: Option<&'static str>,
+    board: Option<&'static str>,
+    /// How well the mapper is expected to run, empty if it isn't supported
+    compat: Option<String>,
+    region: Option<String>,
+    mirroring: Option<String>,
+    battery: Option<bool>,
+    prg_rom_kb: Option<u32>,
+    chr_rom_kb: Option<u32>,
+    prg_ram_kb: Option<u32>,
+    chr_ram_kb: Option<u32>,
+    nes_2: Option<bool>,
+    prg_rom_crc32: Option<String>,
+    chr_rom_crc32: Option<String>,
+    rom_crc32: Option<String>,
     failure: Option<String>,
 }
 
@@ -34,21 +49,50 @@ fn main() -> std::io::Result<()> {
             Ok(s) => s,
             Err(_) => "Non unicode filename".to_string(),
         };
+        let rom_path = p.path();
+        let rom_path = rom_path.to_str().unwrap();
 
-        let result = match rust_nes::get_cartridge(p.path().to_str().unwrap()) {
+        let result = match rust_nes::get_cartridge_info_and_load_error(rom_path) {
             Err(why) => RomResult {
                 filename,
                 mapper: why.mapper,
-                prg_16kb_units: None,
-                chr_8kb_banks: None,
+                submapper: None,
+                mapper_name: None,
+                board: None,
+                compat: None,
+                region: None,
+                mirroring: None,
+                battery: None,
+                prg_rom_kb: None,
+                chr_rom_kb: None,
+                prg_ram_kb: None,
+                chr_ram_kb: None,
+                nes_2: None,
+                prg_rom_crc32: None,
+                chr_rom_crc32: None,
+                rom_crc32: None,
                 failure: Some(why.message),
             },
-            Ok((_, _, header)) => RomResult {
+            Ok((info, load_error)) => RomResult {
                 filename,
-                mapper: Some(header.mapper),
-                prg_16kb_units: Some(header.prg_rom_16kb_units),
-                chr_8kb_banks: Some(header.chr_rom_8kb_units),
-                failure: None,
+                mapper: Some(info.mapper),
+                submapper: info.submapper,
+                mapper_name: info.mapper_name,
+                board: info.board,
+                compat: mapper_info(info.mapper).map(|mapper| mapper.compat.to_string()),
+                region: Some(info.region.to_string()),
+                mirroring: Some(info.mirroring.to_string()),
+                battery: Some(info.battery),
+                prg_rom_kb: Some(info.prg_rom_kb),
+                chr_rom_kb: Some(info.chr_rom_kb),
+                prg_ram_kb: info.prg_ram_kb,
+                chr_ram_kb: info.chr_ram_kb,
+                nes_2: Some(info.nes_2),
+                prg_rom_crc32: Some(format!("{:08X}", info.prg_rom_crc32)),
+                chr_rom_crc32: info.chr_rom_crc32.map(|crc| format!("{:08X}", crc)),
+                rom_crc32: Some(format!("{:08X}", info.rom_crc32)),
+                // The header can be read for roms which still fail to load, e.g. unsupported mappers
+                failure: load_error.map(|why| why.message),
             },
         };
 
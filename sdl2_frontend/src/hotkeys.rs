@@ -0,0 +1,321 @@
+//! Keys for the emulator's own controls, e.g. pausing, fast forward and savestates, loaded from a
+//! text file such as `config/hotkeys.txt`. Each line binds an action to a key with any modifiers:
+//!
+//! ```text
+//! SaveState = Shift+F5
+//! Pause = Space
+//! ```
+//!
+//! Actions missing from the file keep their default key. A key bound to a button in the current
+//! key profile presses the button rather than triggering a hotkey unless the hotkey has modifiers.
+
+use key_bindings::KeyBindings;
+use sdl2::keyboard::{Keycode, Mod};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Hotkey {
+    Quit,
+    Pause,
+    /// Held to run as fast as possible without audio
+    FastForward,
+    SaveState,
+    LoadState,
+    Screenshot,
+    /// Print the cycle count and the CRC32 of the frame, e.g. for writing a test
+    FrameChecksum,
+    ToggleFrameBlend,
+    TogglePaletteTint,
+    DumpMemory,
+    LoadMemory,
+    ToggleRecording,
+    NextKeyProfile,
+    RemapKeyProfile,
+    NewKeyProfile,
+}
+
+/// A key with the modifiers which must be held with it
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Chord {
+    keycode: Keycode,
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+}
+
+impl Chord {
+    const fn key(keycode: Keycode) -> Self {
+        Chord {
+            keycode,
+            shift: false,
+            ctrl: false,
+            alt: false,
+        }
+    }
+
+    const fn ctrl(keycode: Keycode) -> Self {
+        Chord {
+            ctrl: true,
+            ..Chord::key(keycode)
+        }
+    }
+
+    fn has_modifiers(&self) -> bool {
+        self.shift || self.ctrl || self.alt
+    }
+
+    /// Modifiers are written before the key, e.g. `Ctrl+Shift+S`
+    fn parse(text: &str) -> Option<Self> {
+        let (mut shift, mut ctrl, mut alt) = (false, false, false);
+        let mut rest = text.trim();
+        loop {
+            let modifier = match rest.find('+') {
+                Some(index) if index > 0 => &rest[..index],
+                _ => break,
+            };
+            match modifier.trim().to_ascii_lowercase().as_str() {
+                "shift" => shift = true,
+                "ctrl" => ctrl = true,
+                "alt" => alt = true,
+                // Keys such as `Keypad +` contain a plus
+                _ => break,
+            }
+            rest = rest[modifier.len() + 1..].trim_start();
+        }
+
+        Some(Chord {
+            keycode: Keycode::from_name(rest)?,
+            shift,
+            ctrl,
+            alt,
+        })
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.keycode.name())
+    }
+}
+
+/// Each hotkey with its name in the file and its default key
+const HOTKEYS: [(Hotkey, &str, Chord); 15] = [
+    (Hotkey::Quit, "Quit", Chord::key(Keycode::Escape)),
+    (Hotkey::Pause, "Pause", Chord::key(Keycode::Space)),
+    (Hotkey::FastForward, "FastForward", Chord::key(Keycode::Backquote)),
+    (Hotkey::SaveState, "SaveState", Chord::key(Keycode::F5)),
+    (Hotkey::LoadState, "LoadState", Chord::key(Keycode::F7)),
+    (Hotkey::Screenshot, "Screenshot", Chord::key(Keycode::F12)),
+    (Hotkey::FrameChecksum, "FrameChecksum", Chord::key(Keycode::T)),
+    (Hotkey::ToggleFrameBlend, "ToggleFrameBlend", Chord::key(Keycode::B)),
+    (Hotkey::TogglePaletteTint, "TogglePaletteTint", Chord::key(Keycode::P)),
+    (Hotkey::DumpMemory, "DumpMemory", Chord::ctrl(Keycode::D)),
+    (Hotkey::LoadMemory, "LoadMemory", Chord::ctrl(Keycode::L)),
+    (Hotkey::ToggleRecording, "ToggleRecording", Chord::key(Keycode::R)),
+    (Hotkey::NextKeyProfile, "NextKeyProfile", Chord::key(Keycode::F1)),
+    (Hotkey::RemapKeyProfile, "RemapKeyProfile", Chord::key(Keycode::F2)),
+    (Hotkey::NewKeyProfile, "NewKeyProfile", Chord::key(Keycode::F3)),
+];
+
+pub(crate) struct Hotkeys {
+    /// The chord for each hotkey in `HOTKEYS`
+    chords: [Chord; 15],
+}
+
+impl Hotkeys {
+    /// Load the hotkeys from a file, every hotkey has its default key if the file doesn't exist.
+    /// Two hotkeys bound to the same chord are an error.
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let mut hotkeys = Hotkeys {
+            chords: [HOTKEYS[0].2; 15],
+        };
+        for (chord, (_, _, default)) in hotkeys.chords.iter_mut().zip(HOTKEYS.iter()) {
+            *chord = *default;
+        }
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) if !path.exists() => return Ok(hotkeys),
+            Err(why) => return Err(why.to_string()),
+        };
+        hotkeys.parse(&text)?;
+
+        Ok(hotkeys)
+    }
+
+    fn parse(&mut self, text: &str) -> Result<(), String> {
+        for (line_number, line) in text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, chord) = match line.find('=') {
+                Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+                None => return Err(format!("line {} should be Hotkey = Key", line_number)),
+            };
+            let index = HOTKEYS
+                .iter()
+                .position(|(_, hotkey_name, _)| hotkey_name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("line {} has unknown hotkey {}", line_number, name))?;
+            self.chords[index] =
+                Chord::parse(chord).ok_or_else(|| format!("line {} has unknown key {}", line_number, chord))?;
+        }
+
+        for (index, chord) in self.chords.iter().enumerate() {
+            if let Some(other) = self.chords[index + 1..].iter().position(|other| other == chord) {
+                return Err(format!(
+                    "{} and {} are both bound to {}",
+                    HOTKEYS[index].1,
+                    HOTKEYS[index + 1 + other].1,
+                    chord
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The hotkey triggered by a key press, keys bound to buttons in the current key profile only
+    /// trigger hotkeys with modifiers
+    pub(crate) fn hotkey(&self, keycode: Keycode, keymod: Mod, key_bindings: &KeyBindings) -> Option<Hotkey> {
+        let pressed = Chord {
+            keycode,
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        };
+        if !pressed.has_modifiers() && key_bindings.button(keycode).is_some() {
+            return None;
+        }
+
+        self.chords
+            .iter()
+            .position(|&chord| chord == pressed)
+            .map(|index| HOTKEYS[index].0)
+    }
+
+    /// Whether releasing this key ends a held hotkey, modifiers can be released first
+    pub(crate) fn is_key(&self, hotkey: Hotkey, keycode: Keycode) -> bool {
+        HOTKEYS
+            .iter()
+            .zip(self.chords.iter())
+            .any(|((other, _, _), chord)| *other == hotkey && chord.keycode == keycode)
+    }
+
+    /// The keys which trigger a hotkey without modifiers, which key profiles shouldn't use
+    pub(crate) fn unmodified_keys(&self) -> Vec<Keycode> {
+        self.chords
+            .iter()
+            .filter(|chord| !chord.has_modifiers())
+            .map(|chord| chord.keycode)
+            .collect()
+    }
+
+    /// Describe the hotkeys which can't be used with the current key profile as their key is
+    /// bound to a button
+    pub(crate) fn conflicts(&self, key_bindings: &KeyBindings) -> Vec<String> {
+        HOTKEYS
+            .iter()
+            .zip(self.chords.iter())
+            .filter(|(_, chord)| !chord.has_modifiers())
+            .filter_map(|((_, name, _), chord)| {
+                key_bindings.button(chord.keycode).map(|button| {
+                    format!(
+                        "{} is {:?} in key profile {} so it doesn't trigger {}",
+                        chord,
+                        button,
+                        key_bindings.profile_name(),
+                        name
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod hotkeys_tests {
+    use super::*;
+
+    /// Every hotkey on its default key, as when there's no file
+    fn default_hotkeys() -> Hotkeys {
+        Hotkeys::load(Path::new("no_such_hotkeys.txt")).unwrap()
+    }
+
+    #[test]
+    fn test_chord_parse() {
+        let chord = Chord::parse("Shift+F5").unwrap();
+        assert_eq!(
+            chord,
+            Chord {
+                shift: true,
+                ..Chord::key(Keycode::F5)
+            }
+        );
+        assert_eq!(chord.to_string(), "Shift+F5");
+
+        assert_eq!(Chord::parse("Keypad +"), Some(Chord::key(Keycode::KpPlus)));
+        assert_eq!(Chord::parse(" ctrl + Keypad +"), Some(Chord::ctrl(Keycode::KpPlus)));
+        assert_eq!(
+            Chord::parse("Alt+Shift+Ctrl+S").unwrap().to_string(),
+            "Ctrl+Alt+Shift+S"
+        );
+        assert_eq!(Chord::parse("Hyper+S"), None);
+    }
+
+    #[test]
+    fn test_parse_replaces_defaults() {
+        let mut hotkeys = default_hotkeys();
+        hotkeys.parse("# Comment\n\nsavestate = Shift+F5\n").unwrap();
+
+        assert!(hotkeys.is_key(Hotkey::SaveState, Keycode::F5));
+        assert!(hotkeys.is_key(Hotkey::LoadState, Keycode::F7));
+        assert_eq!(
+            hotkeys.parse("Pause = Space\nPuase = F9").err().unwrap(),
+            "line 2 has unknown hotkey Puase"
+        );
+        assert_eq!(hotkeys.parse("Pause").err().unwrap(), "line 1 should be Hotkey = Key");
+    }
+
+    #[test]
+    fn test_duplicate_chords_rejected() {
+        let mut hotkeys = default_hotkeys();
+        assert_eq!(
+            hotkeys.parse("SaveState = T").err().unwrap(),
+            "SaveState and FrameChecksum are both bound to T"
+        );
+
+        // The same key with different modifiers is a different chord
+        let mut hotkeys = default_hotkeys();
+        assert_eq!(hotkeys.parse("SaveState = Ctrl+T"), Ok(()));
+    }
+
+    #[test]
+    fn test_conflicts_with_key_profile() {
+        let key_bindings = KeyBindings::load(Path::new("no_such_key_bindings.txt")).unwrap();
+        let mut hotkeys = default_hotkeys();
+        assert!(hotkeys.conflicts(&key_bindings).is_empty());
+
+        hotkeys.parse("Pause = Z\nScreenshot = Shift+X").unwrap();
+        assert_eq!(
+            hotkeys.conflicts(&key_bindings),
+            vec!["Z is A in key profile Arrows so it doesn't trigger Pause"]
+        );
+        assert_eq!(hotkeys.hotkey(Keycode::Z, Mod::NOMOD, &key_bindings), None);
+        assert_eq!(
+            hotkeys.hotkey(Keycode::X, Mod::LSHIFTMOD, &key_bindings),
+            Some(Hotkey::Screenshot)
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Named profiles mapping keys to the buttons on controller one, loaded from a text file such as
+//! `config/key_bindings.txt` and cycled through at runtime. Profiles can be captured in the window
+//! by pressing the key for each button in turn, which saves them back to the file.
+//!
+//! ```text
+//! [Arrows]
+//! A = Z
+//! B = X
+//! Select = Tab
+//! Start = Return
+//! Up = Up
+//! Down = Down
+//! Left = Left
+//! Right = Right
+//! ```
+//!
+//! Key names are SDL's (e.g. `Left Shift`, `Keypad 8`), every button must have a key.
+
+use rust_nes::io::Button;
+use sdl2::keyboard::Keycode;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Each button with its name in the file, in the order profiles list them
+const BUTTONS: [(Button, &str); 8] = [
+    (Button::A, "A"),
+    (Button::B, "B"),
+    (Button::Select, "Select"),
+    (Button::Start, "Start"),
+    (Button::Up, "Up"),
+    (Button::Down, "Down"),
+    (Button::Left, "Left"),
+    (Button::Right, "Right"),
+];
+
+const FILE_HEADER: &str = "\
+# Keyboard profiles for controller one. F1 switches to the next profile, F2 remaps the current one
+# and F3 captures a new one. Key names are SDL's, keys bound to buttons take precedence over hotkeys
+# without modifiers.
+";
+
+struct Profile {
+    name: String,
+    /// The key for each button in `BUTTONS`
+    keys: [Keycode; 8],
+}
+
+pub(crate) struct KeyBindings {
+    path: PathBuf,
+    profiles: Vec<Profile>,
+    active: usize,
+}
+
+impl KeyBindings {
+    /// Load the profiles from a file, starting with the first. If the file doesn't exist there's
+    /// a single profile for the arrow keys which is written to the file once another is captured.
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let profiles = match fs::read_to_string(path) {
+            Ok(text) => parse(&text)?,
+            Err(_) if !path.exists() => vec![Profile {
+                name: "Arrows".to_string(),
+                keys: [
+                    Keycode::Z,
+                    Keycode::X,
+                    Keycode::Tab,
+                    Keycode::Return,
+                    Keycode::Up,
+                    Keycode::Down,
+                    Keycode::Left,
+                    Keycode::Right,
+                ],
+            }],
+            Err(why) => return Err(why.to_string()),
+        };
+
+        Ok(KeyBindings {
+            path: path.to_path_buf(),
+            profiles,
+            active: 0,
+        })
+    }
+
+    pub(crate) fn button(&self, keycode: Keycode) -> Option<Button> {
+        let keys = &self.profiles[self.active].keys;
+        keys.iter()
+            .position(|&key| key == keycode)
+            .map(|index| BUTTONS[index].0)
+    }
+
+    pub(crate) fn profile_name(&self) -> &str {
+        &self.profiles[self.active].name
+    }
+
+    pub(crate) fn next_profile(&mut self) {
+        self.active = (self.active + 1) % self.profiles.len();
+    }
+
+    /// A name for a new profile which doesn't clash with the existing ones
+    pub(crate) fn new_profile_name(&self) -> String {
+        (self.profiles.len() + 1..)
+            .map(|number| format!("Profile {}", number))
+            .find(|name| self.profiles.iter().all(|profile| profile.name != *name))
+            .unwrap()
+    }
+
+    /// Replace the profile with this name, or add it if there isn't one, and switch to it. The
+    /// file is rewritten with every profile.
+    pub(crate) fn save_profile(&mut self, name: &str, keys: [Keycode; 8]) -> std::io::Result<()> {
+        match self.profiles.iter().position(|profile| profile.name == name) {
+            Some(index) => {
+                self.profiles[index].keys = keys;
+                self.active = index;
+            }
+            None => {
+                self.profiles.push(Profile {
+                    name: name.to_string(),
+                    keys,
+                });
+                self.active = self.profiles.len() - 1;
+            }
+        }
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(&self.path, self.to_text())
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = FILE_HEADER.to_string();
+        for profile in &self.profiles {
+            text.push_str(&format!("\n[{}]\n", profile.name));
+            for ((_, button_name), key) in BUTTONS.iter().zip(profile.keys.iter()) {
+                text.push_str(&format!("{} = {}\n", button_name, key.name()));
+            }
+        }
+
+        text
+    }
+}
+
+fn parse(text: &str) -> Result<Vec<Profile>, String> {
+    let mut profiles = vec![];
+    let mut current: Option<(String, [Option<Keycode>; 8])> = None;
+    for (line_number, line) in text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            if let Some((name, keys)) = current.take() {
+                profiles.push(complete_profile(name, keys)?);
+            }
+            current = Some((line[1..line.len() - 1].trim().to_string(), [None; 8]));
+            continue;
+        }
+
+        let (button, key) = match line.find('=') {
+            Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+            None => return Err(format!("line {} should be [Profile] or Button = Key", line_number)),
+        };
+        let index = BUTTONS
+            .iter()
+            .position(|(_, name)| name.eq_ignore_ascii_case(button))
+            .ok_or_else(|| format!("line {} has unknown button {}", line_number, button))?;
+        let keycode = Keycode::from_name(key).ok_or_else(|| format!("line {} has unknown key {}", line_number, key))?;
+        match current.as_mut() {
+            Some((_, keys)) => keys[index] = Some(keycode),
+            None => return Err(format!("line {} binds a key before the first [Profile]", line_number)),
+        }
+    }
+
+    if let Some((name, keys)) = current {
+        profiles.push(complete_profile(name, keys)?);
+    }
+    if profiles.is_empty() {
+        return Err("there are no profiles".to_string());
+    }
+
+    Ok(profiles)
+}
+
+fn complete_profile(name: String, keys: [Option<Keycode>; 8]) -> Result<Profile, String> {
+    if let Some(index) = keys.iter().position(Option::is_none) {
+        return Err(format!("profile {} has no key for {}", name, BUTTONS[index].1));
+    }
+
+    let mut complete = [Keycode::A; 8];
+    for (complete, key) in complete.iter_mut().zip(keys.iter()) {
+        *complete = key.unwrap();
+    }
+    Ok(Profile { name, keys: complete })
+}
+
+/// Builds a profile from the keys pressed for each button in turn
+pub(crate) struct KeyCapture {
+    name: String,
+    keys: Vec<Keycode>,
+    /// Keys which trigger hotkeys without modifiers, binding them to a button would hide the hotkey
+    reserved: Vec<Keycode>,
+}
+
+impl KeyCapture {
+    pub(crate) fn new(name: String, reserved: Vec<Keycode>) -> Self {
+        KeyCapture {
+            name,
+            keys: vec![],
+            reserved,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// What to show the player while capturing, it names the next button
+    pub(crate) fn prompt(&self) -> String {
+        format!(
+            "{}: press the key for {} (Escape cancels)",
+            self.name,
+            BUTTONS[self.keys.len()].1
+        )
+    }
+
+    /// Use a key for the next button, returns the keys for every button once the last has one.
+    /// Keys which are already used, by another button or a hotkey, are ignored.
+    pub(crate) fn capture(&mut self, keycode: Keycode) -> Option<[Keycode; 8]> {
+        if !self.keys.contains(&keycode) && !self.reserved.contains(&keycode) {
+            self.keys.push(keycode);
+        }
+        if self.keys.len() < BUTTONS.len() {
+            return None;
+        }
+
+        let mut keys = [self.keys[0]; 8];
+        keys.copy_from_slice(&self.keys);
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod key_bindings_tests {
+    use super::*;
+
+    const PROFILES: &str = "\
+[Arrows]
+A = Z
+B = X
+Select = Tab
+Start = Return
+Up = Up
+Down = Down
+Left = Left
+Right = Right
+
+[Keypad]
+a = Left Shift
+b = Left Ctrl
+select = Keypad 5
+start = Keypad Enter
+up = Keypad 8
+down = Keypad 2
+left = Keypad 4
+right = Keypad 6
+";
+
+    #[test]
+    fn test_to_text_round_trips() {
+        let bindings = KeyBindings {
+            path: PathBuf::from("key_bindings.txt"),
+            profiles: parse(PROFILES).unwrap(),
+            active: 0,
+        };
+        assert_eq!(bindings.profiles[1].keys[0], Keycode::LShift);
+        assert_eq!(bindings.profiles[1].keys[7], Keycode::Kp6);
+
+        let text = bindings.to_text();
+        assert!(text.starts_with(FILE_HEADER));
+        let reparsed = parse(&text).unwrap();
+        assert_eq!(reparsed.len(), 2);
+        for (profile, original) in reparsed.iter().zip(bindings.profiles.iter()) {
+            assert_eq!(profile.name, original.name);
+            assert_eq!(profile.keys, original.keys);
+        }
+    }
+
+    #[test]
+    fn test_missing_button_rejected() {
+        let text = PROFILES.replace("Right = Right\n", "");
+        assert_eq!(parse(&text).err().unwrap(), "profile Arrows has no key for Right");
+    }
+
+    #[test]
+    fn test_unknown_key_and_button_rejected() {
+        let text = PROFILES.replace("B = X", "B = Not A Key");
+        assert_eq!(parse(&text).err().unwrap(), "line 3 has unknown key Not A Key");
+
+        let text = PROFILES.replace("B = X", "Turbo = X");
+        assert_eq!(parse(&text).err().unwrap(), "line 3 has unknown button Turbo");
+
+        assert_eq!(
+            parse("A = Z").err().unwrap(),
+            "line 1 binds a key before the first [Profile]"
+        );
+        assert_eq!(parse("# Nothing").err().unwrap(), "there are no profiles");
+    }
+
+    #[test]
+    fn test_capture_ignores_duplicate_keys() {
+        let mut capture = KeyCapture::new("Profile 2".to_string(), vec![]);
+        assert_eq!(capture.prompt(), "Profile 2: press the key for A (Escape cancels)");
+
+        let keys = [
+            Keycode::Z,
+            Keycode::X,
+            Keycode::Tab,
+            Keycode::Return,
+            Keycode::Up,
+            Keycode::Down,
+            Keycode::Left,
+        ];
+        for key in keys.iter() {
+            assert_eq!(capture.capture(*key), None);
+            assert_eq!(capture.capture(Keycode::Z), None);
+        }
+        assert_eq!(capture.prompt(), "Profile 2: press the key for Right (Escape cancels)");
+
+        let captured = capture.capture(Keycode::Right).unwrap();
+        assert_eq!(captured[..7], keys[..]);
+        assert_eq!(captured[7], Keycode::Right);
+    }
+
+    #[test]
+    fn test_capture_ignores_hotkey_keys() {
+        let mut capture = KeyCapture::new("Profile 2".to_string(), vec![Keycode::F1, Keycode::Space]);
+        assert_eq!(capture.capture(Keycode::F1), None);
+        assert_eq!(capture.capture(Keycode::Space), None);
+        assert_eq!(capture.prompt(), "Profile 2: press the key for A (Escape cancels)");
+
+        assert_eq!(capture.capture(Keycode::Z), None);
+        assert_eq!(capture.prompt(), "Profile 2: press the key for B (Escape cancels)");
+    }
+}
@@ -1,46 +1,412 @@
+mod hotkeys;
+mod key_bindings;
+mod repro;
 mod sdl2_app;
+mod sdl2_video;
+mod wav_export;
 
 extern crate clap;
 extern crate crc32fast;
 extern crate log;
 extern crate log4rs;
+extern crate png;
 extern crate rust_nes;
 extern crate sdl2;
 
 use clap::Clap;
-use log::info;
+use hotkeys::Hotkeys;
+use key_bindings::KeyBindings;
+use log::{error, info};
+use rust_nes::apu::{AudioEnhancements, AudioOutputConfig, ResamplerQuality};
+use rust_nes::cartridge::{CartridgeError, CartridgeInfo, CartridgeOverrides, MirroringMode, Region, RomPatch};
+use rust_nes::cpu::SymbolTable;
+use rust_nes::ppu::{HdPack, PaletteRegion, PaletteSettings};
+use rust_nes::{AccuracyProfile, BatterySave, Cartridge, JamPolicy, Nes, Overclock, ResumeState};
+use sdl2::messagebox::{show_simple_message_box, MessageBoxFlag};
+use sdl2_app::{Controls, DisplayOptions, Session};
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+use std::process;
 
 #[derive(Clap)]
 #[clap(version = "1.0", author = "David Tyler <davet.code@gmail.com>")]
 struct Opts {
     rom_file: String,
+    /// Which rom to run from a zip containing several, if not given a list is shown to pick from
+    #[clap(long = "archive_entry")]
+    archive_entry: Option<usize>,
+    /// An IPS or BPS patch to apply to the rom, the rom file itself isn't modified
+    #[clap(long = "patch")]
+    patch: Option<String>,
+    /// Run the rom with this mapper rather than the one in its header
+    #[clap(long = "force_mapper")]
+    force_mapper: Option<u8>,
+    /// Run the mapper with this implementation rather than the default one, e.g. to compare a
+    /// rewrite against the original
+    #[clap(long = "mapper_implementation")]
+    mapper_implementation: Option<String>,
+    /// Override the header mirroring (horizontal, vertical, four_screen, one_screen_lower or one_screen_upper)
+    #[clap(long = "force_mirroring")]
+    force_mirroring: Option<MirroringMode>,
+    /// Override the number of 8KB PRG RAM banks, 0 removes PRG RAM
+    #[clap(long = "force_prg_ram")]
+    force_prg_ram: Option<u8>,
+    /// Override whether the PRG RAM is battery backed
+    #[clap(long = "force_battery")]
+    force_battery: Option<bool>,
+    /// Run at the frame rate of this region (ntsc, pal or dendy) rather than the one detected from
+    /// the header or filename
+    #[clap(long = "region")]
+    region: Option<Region>,
+    /// Set the DIP switches on boards which have them, e.g. 0-15 for the NWC competition timer
+    #[clap(long = "dip_switches")]
+    dip_switches: Option<u8>,
+    /// Reset the console this many frames after a KIL opcode jams the CPU rather than leaving it halted
+    #[clap(long = "jam_reset_frames")]
+    jam_reset_frames: Option<u32>,
     #[clap(short = 'l', long = "log_config", default_value = "config/log4rs.yaml")]
     log_config: String,
+    /// Keyboard profiles for controller one, cycled with F1 and captured with F2 (remap the current
+    /// profile) or F3 (add a profile). The default profile is used if the file doesn't exist.
+    #[clap(long = "key_bindings", default_value = "config/key_bindings.txt")]
+    key_bindings: String,
+    /// Keys for the emulator's controls such as pause and savestates, optionally with modifiers
+    /// (e.g. Shift+F5). Hotkeys missing from the file keep their default key.
+    #[clap(long = "hotkeys", default_value = "config/hotkeys.txt")]
+    hotkeys: String,
     #[clap(short = 'w', long = "width", default_value = "256")]
     screen_width: u32,
     #[clap(short = 'h', long = "height", default_value = "240")]
     screen_height: u32,
+    #[clap(short = 'a', long = "accuracy", default_value = "balanced")]
+    accuracy: AccuracyProfile,
+    #[clap(long = "hd_pack")]
+    hd_pack: Option<String>,
+    /// Symbols for the trace log, either a ca65 .dbg file or a rom with FCEUX .nl files alongside it
+    #[clap(long = "symbols")]
+    symbols: Option<String>,
+    /// Run headless and write the audio to this WAV file rather than opening a window
+    #[clap(long = "wav_output")]
+    wav_output: Option<String>,
+    /// Also write a WAV file for each APU channel alongside the --wav_output file
+    #[clap(long = "wav_stems")]
+    wav_stems: bool,
+    /// The number of frames to run for when writing a WAV file
+    #[clap(long = "frames", default_value = "600")]
+    frames: u32,
+    /// Replay a reproduction recorded with the R hotkey headless and write the final frame to a PNG
+    /// file alongside it
+    #[clap(long = "repro")]
+    repro: Option<String>,
+    /// Directory that the memory dump and load, recording, savestate and screenshot hotkeys write to and
+    /// read from
+    #[clap(long = "memory_dir", default_value = ".")]
+    memory_dir: String,
+    /// Directory that each rom's state is saved to on exit and resumed from the next time it's
+    /// loaded, the file is named after the rom's CRC32
+    #[clap(long = "state_dir", default_value = "states")]
+    state_dir: String,
+    /// Start roms from power on and don't save their state on exit
+    #[clap(long = "no_resume")]
+    no_resume: bool,
+    /// Report when the program appears to crash (PC outside ROM or stack wrapping)
+    #[clap(long = "diagnostics")]
+    diagnostics: bool,
+    /// Also report writes to the nametables while the PPU is rendering, with the PC, scanline and dot
+    #[clap(long = "nametable_write_checks")]
+    nametable_write_checks: bool,
+    /// Show the buttons the game read from controller one in the corner of the screen
+    #[clap(long = "input_display")]
+    input_display: bool,
+    /// Blend each frame with the previous ones to smooth out flickering sprites, from 0 (off) to 1,
+    /// 0.5 averages each frame with the last. Toggled with the B hotkey.
+    #[clap(long = "frame_blend")]
+    frame_blend: Option<f32>,
+    /// Render all sprites on each scanline rather than the hardware limit of 8, removes flicker
+    #[clap(long = "no_sprite_limit")]
+    no_sprite_limit: bool,
+    /// Give the CPU this many extra scanlines' worth of cycles each vblank to reduce slowdown
+    #[clap(long = "overclock_scanlines", default_value = "0")]
+    overclock_scanlines: u16,
+    /// Also clock the APU while overclocked, raising the pitch as on real overclocked hardware
+    #[clap(long = "overclock_apu")]
+    overclock_apu: bool,
+    /// Generate the palette by decoding the NTSC or PAL signal rather than using the built in palette
+    #[clap(long = "palette")]
+    palette: Option<PaletteRegion>,
+    /// Hue adjustment in degrees for a generated palette
+    #[clap(long = "hue", default_value = "0")]
+    hue: f32,
+    #[clap(long = "saturation", default_value = "1")]
+    saturation: f32,
+    #[clap(long = "brightness", default_value = "0")]
+    brightness: f32,
+    #[clap(long = "gamma", default_value = "2.2")]
+    gamma: f32,
+    /// Quality of the filter used to resample audio to the output rate (low, medium or high)
+    #[clap(long = "audio_quality", default_value = "medium")]
+    audio_quality: ResamplerQuality,
+    /// Audio output sample rate in Hz
+    #[clap(long = "sample_rate", default_value = "44100")]
+    sample_rate: u32,
+    /// Samples in each audio device buffer, try a larger power of two if the audio crackles
+    #[clap(long = "audio_buffer_size", default_value = "1024")]
+    audio_buffer_size: u16,
+    /// Milliseconds of audio to keep queued ahead of the device, more avoids underruns at the cost
+    /// of the sound lagging behind the picture
+    #[clap(long = "audio_latency", default_value = "40")]
+    audio_latency: u32,
+    /// Hold the triangle channel at its midpoint when games set an ultrasonic period
+    #[clap(long = "silence_ultrasonic_triangle")]
+    silence_ultrasonic_triangle: bool,
+    /// Smooth the pops caused by games writing directly to the DMC output level
+    #[clap(long = "reduce_dmc_pops")]
+    reduce_dmc_pops: bool,
 }
 
 fn main() -> std::io::Result<()> {
     let opts: Opts = Opts::parse();
-    log4rs::init_file(opts.log_config, Default::default()).unwrap();
+    log4rs::init_file(&opts.log_config, Default::default()).unwrap();
 
     info!("Logging Configured");
 
-    let (prg_address_bus, chr_address_bus, cartridge_header) = match rust_nes::get_cartridge(&opts.rom_file) {
-        Err(why) => panic!("Failed to load cartridge: {}", why.message),
+    let headless = opts.wav_output.is_some() || opts.repro.is_some();
+    let patch = opts.patch.as_ref().map(|path| match RomPatch::load(path) {
+        Err(why) => exit_with_load_failure(path, &why.message, headless),
+        Ok(patch) => patch,
+    });
+    let overrides = CartridgeOverrides {
+        patch,
+        mapper: opts.force_mapper,
+        mapper_id: opts.mapper_implementation.clone(),
+        mirroring: opts.force_mirroring,
+        prg_ram_8kb_units: opts.force_prg_ram,
+        battery: opts.force_battery,
+        region: opts.region,
+    };
+    let cartridge = match load_cartridge(&opts.rom_file, opts.archive_entry, &overrides) {
+        Err(why) => {
+            let mut reason = why.message;
+            if let Some(mapper) = why.mapper {
+                reason.push_str(&format!("\nDetected mapper: {}", mapper));
+            }
+            exit_with_load_failure(&opts.rom_file, &reason, headless)
+        }
         Ok(cartridge) => cartridge,
     };
 
-    info!("Running cartridge {:?}", cartridge_header);
+    let hd_pack = opts.hd_pack.as_ref().map(|directory| match HdPack::load(directory) {
+        Err(why) => exit_with_load_failure(directory, &why.message, headless),
+        Ok(hd_pack) => hd_pack,
+    });
+
+    let symbols = opts.symbols.as_ref().map(|path| match SymbolTable::load(path) {
+        Err(why) => exit_with_load_failure(path, &why.message, headless),
+        Ok(symbols) => symbols,
+    });
+
+    let audio_output = AudioOutputConfig {
+        sample_rate: opts.sample_rate,
+        buffer_size: opts.audio_buffer_size,
+        target_latency_ms: opts.audio_latency,
+        quality: opts.audio_quality,
+    };
+    let (mut nes, info) = create_nes(&opts, cartridge);
+    if let Some(hd_pack) = hd_pack {
+        nes.set_hd_pack(hd_pack);
+    }
+    if let Some(symbols) = symbols {
+        nes.set_symbols(symbols);
+    }
+    if let Some(dip_switches) = opts.dip_switches {
+        nes.set_dip_switches(dip_switches);
+    }
+
+    if let Some(wav_output) = &opts.wav_output {
+        return wav_export::run(nes, opts.frames, wav_output, opts.wav_stems, audio_output);
+    }
+
+    if let Some(repro) = &opts.repro {
+        return repro::run(nes, repro);
+    }
+
+    let key_bindings = match KeyBindings::load(Path::new(&opts.key_bindings)) {
+        Err(why) => exit_with_load_failure(&opts.key_bindings, &why, false),
+        Ok(key_bindings) => key_bindings,
+    };
+    let hotkeys = match Hotkeys::load(Path::new(&opts.hotkeys)) {
+        Err(why) => exit_with_load_failure(&opts.hotkeys, &why, false),
+        Ok(hotkeys) => hotkeys,
+    };
+
+    let state_dir = if opts.no_resume {
+        None
+    } else {
+        Some(opts.state_dir.as_str())
+    };
+    let session = start_session(nes, &info, &opts.rom_file, state_dir)?;
+
+    // Roms dropped onto the window get the console options but not the ones for a particular rom,
+    // i.e. the patch, header overrides, HD pack, symbols and DIP switches
+    let load_rom = |rom_file: &str| -> Result<Session, CartridgeError> {
+        let cartridge = load_cartridge(rom_file, Some(0), &CartridgeOverrides::default())?;
+        let (nes, info) = create_nes(&opts, cartridge);
+        Ok(start_session(nes, &info, rom_file, state_dir)?)
+    };
+
     sdl2_app::run(
-        opts.screen_width,
-        opts.screen_height,
-        prg_address_bus,
-        chr_address_bus,
-        cartridge_header,
+        session,
+        &load_rom,
+        audio_output,
+        &opts.memory_dir,
+        DisplayOptions {
+            screen_width: opts.screen_width,
+            screen_height: opts.screen_height,
+            input_display: opts.input_display,
+            frame_blend: opts.frame_blend,
+        },
+        Controls { key_bindings, hotkeys },
     )?;
 
     Ok(())
 }
+
+/// Create the console for a cartridge with the options that apply to every rom, returns it with
+/// the description of the cartridge
+fn create_nes(opts: &Opts, cartridge: Cartridge) -> (Nes, CartridgeInfo) {
+    info!("Running cartridge {:?}", cartridge.2);
+    let cartridge_info = cartridge.2.info();
+    let audio = AudioEnhancements {
+        silence_ultrasonic_triangle: opts.silence_ultrasonic_triangle,
+        reduce_dmc_pops: opts.reduce_dmc_pops,
+    };
+    let mut nes = Nes::with_options(cartridge, opts.accuracy, audio);
+    if opts.no_sprite_limit {
+        nes.set_sprite_limit(false);
+    }
+    nes.set_overclock(Overclock {
+        extra_scanlines: opts.overclock_scanlines,
+        clock_apu: opts.overclock_apu,
+    });
+    if let Some(frames) = opts.jam_reset_frames {
+        nes.set_jam_policy(JamPolicy::ResetAfterFrames(frames));
+    }
+    if opts.diagnostics || opts.nametable_write_checks {
+        nes.enable_diagnostics(32);
+        nes.set_nametable_write_checks(opts.nametable_write_checks);
+    }
+    if let Some(region) = opts.palette {
+        let settings = PaletteSettings {
+            region,
+            hue: opts.hue,
+            saturation: opts.saturation,
+            brightness: opts.brightness,
+            gamma: opts.gamma,
+        };
+        nes.set_palette(settings.generate());
+    }
+
+    (nes, cartridge_info)
+}
+
+/// Load the save file alongside the rom into battery backed RAM, and resume from the state the
+/// rom was left in if there's a state directory, ready to run it in the window
+fn start_session(
+    mut nes: Nes,
+    cartridge_info: &CartridgeInfo,
+    rom_file: &str,
+    state_dir: Option<&str>,
+) -> std::io::Result<Session> {
+    // SDL turns SIGINT into a quit event so the save is also flushed when interrupted
+    let battery_save = if cartridge_info.battery {
+        let battery_save = BatterySave::new(Path::new(rom_file).with_extension("sav"));
+        battery_save.load(&mut nes)?;
+        Some(battery_save)
+    } else {
+        None
+    };
+
+    // A state which can't be loaded, e.g. one from an older version, starts the rom from power on
+    let resume_state = state_dir.map(|state_dir| ResumeState::for_rom(state_dir, cartridge_info.rom_crc32));
+    if let Some(resume_state) = resume_state.as_ref() {
+        if let Err(why) = resume_state.load(&mut nes) {
+            error!("Unable to resume from {}: {}", resume_state.path().display(), why);
+            eprintln!("Unable to resume from {}: {}", resume_state.path().display(), why);
+        }
+    }
+
+    let file_name = Path::new(rom_file)
+        .file_name()
+        .map_or(rom_file.into(), |file_name| file_name.to_string_lossy());
+    let mapper_name = cartridge_info.mapper_name.unwrap_or("Unknown mapper");
+
+    Ok(Session {
+        nes,
+        title: format!("{} - {}", file_name, mapper_name),
+        region: cartridge_info.region,
+        battery_save,
+        resume_state,
+    })
+}
+
+/// Report why a file given on the command line (e.g. the rom or a patch) couldn't be loaded on
+/// stderr, and in a message box unless running headless, then exit with a failure status rather
+/// than panicking
+fn exit_with_load_failure(file: &str, reason: &str, headless: bool) -> ! {
+    let message = format!("Failed to load {}\n\n{}", file, reason);
+
+    error!("{}", message);
+    eprintln!("{}", message);
+    if !headless {
+        if let Err(box_error) = show_simple_message_box(MessageBoxFlag::ERROR, "NES - Error", &message, None) {
+            error!("Unable to show the error message box: {}", box_error);
+        }
+    }
+
+    process::exit(1)
+}
+
+/// Load the rom, asking which one to run if it's an archive containing several
+fn load_cartridge(
+    rom_file: &str,
+    archive_entry: Option<usize>,
+    overrides: &CartridgeOverrides,
+) -> Result<Cartridge, CartridgeError> {
+    if !rom_file.to_ascii_lowercase().ends_with(".zip") {
+        return rust_nes::get_cartridge_with_overrides(rom_file, overrides);
+    }
+
+    let entries = rust_nes::list_archive_entries(rom_file)?;
+    let index = match archive_entry {
+        Some(index) => index,
+        None if entries.len() > 1 => pick_archive_entry(&entries)?,
+        None => 0,
+    };
+
+    rust_nes::from_archive_entry_with_overrides(rom_file, index, overrides)
+}
+
+fn pick_archive_entry(entries: &[String]) -> Result<usize, CartridgeError> {
+    println!("The archive contains several roms:");
+    for (index, name) in entries.iter().enumerate() {
+        println!("  {}: {}", index, name);
+    }
+
+    loop {
+        print!("Select a rom [0-{}]: ", entries.len() - 1);
+        stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin().read_line(&mut line)? == 0 {
+            return Err(CartridgeError {
+                message: "No rom selected from the archive".to_string(),
+                mapper: None,
+            });
+        }
+
+        match line.trim().parse::<usize>() {
+            Ok(index) if index < entries.len() => return Ok(index),
+            _ => println!("{} isn't one of the listed roms", line.trim()),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crc32fast::Hasher;
+use rust_nes::{Nes, Repro};
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind};
+use std::path::Path;
+
+const SCREEN_WIDTH: u32 = 256;
+const SCREEN_HEIGHT: u32 = 240;
+
+/// Replay a reproduction headless and write the final frame to a PNG file next to it
+/// (e.g. crash.repro.png), the CRC of the frame is printed so that it can be used in a test
+pub(crate) fn run(mut nes: Nes, path: &str) -> std::io::Result<()> {
+    let repro = Repro::from_bytes(&std::fs::read(path)?).map_err(|why| Error::new(ErrorKind::InvalidData, why))?;
+    repro
+        .replay(&mut nes)
+        .map_err(|why| Error::new(ErrorKind::InvalidData, why))?;
+
+    let framebuffer = nes.get_framebuffer();
+    let mut hasher = Hasher::new();
+    hasher.update(framebuffer);
+    println!(
+        "Replayed {} frames, FrameBuffer CRC32, {:}",
+        repro.frames,
+        hasher.finalize()
+    );
+
+    write_png(Path::new(&format!("{}.png", path)), framebuffer)
+}
+
+pub(crate) fn write_png(path: &Path, framebuffer: &[u8]) -> std::io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), SCREEN_WIDTH, SCREEN_HEIGHT);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    // The framebuffer is stored as BGRA for SDL
+    let pixels = framebuffer
+        .chunks(4)
+        .flat_map(|pixel| vec![pixel[2], pixel[1], pixel[0]])
+        .collect::<Vec<u8>>();
+    encoder.write_header()?.write_image_data(&pixels)?;
+
+    Ok(())
+}
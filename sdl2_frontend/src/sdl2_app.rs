@@ -1,223 +1,468 @@
 use crc32fast::Hasher;
-use log::{error, info};
-use rust_nes::apu::Apu;
-use rust_nes::cartridge::{CartridgeHeader, CpuCartridgeAddressBus, PpuCartridgeAddressBus};
-use rust_nes::cpu::Cpu;
-use rust_nes::io::Io;
-use rust_nes::io::{Button, Controller};
-use rust_nes::ppu::{Ppu, PpuIteratorState};
+use hotkeys::{Hotkey, Hotkeys};
+use key_bindings::{KeyBindings, KeyCapture};
+use log::{error, info, warn};
+use repro::write_png;
+use rust_nes::apu::{AudioOutputConfig, Resampler, ResamplerQuality, UnderrunCounter, NTSC_SAMPLE_RATE};
+use rust_nes::cartridge::{CartridgeError, Region};
+use rust_nes::io::Controller;
+use rust_nes::ppu::PaletteTint;
+use rust_nes::{run_frame, BatterySave, FrameLimiter, MemoryRegion, Nes, Repro, ResumeState, NTSC_FRAME_RATE};
 use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::PixelFormatEnum;
+use sdl2_video::SdlVideo;
 use std::fs::File;
 use std::io::Write;
-use std::{thread, time};
-
-/// Used to perform a FIR low pass filter on samples generated by the APU prior
-/// to downsampling
-const FIR_FILTER: [f32; 41] = [
-    0.000191, 0.000455, 0.000889, 0.001545, 0.002473, 0.003718, 0.005315, 0.007283, 0.009625, 0.012319, 0.015321,
-    0.018561, 0.021946, 0.025366, 0.028698, 0.031809, 0.034570, 0.036860, 0.038577, 0.039640, 0.040000, 0.039640,
-    0.038577, 0.036860, 0.034570, 0.031809, 0.028698, 0.025366, 0.021946, 0.018561, 0.015321, 0.012319, 0.009625,
-    0.007283, 0.005315, 0.003718, 0.002473, 0.001545, 0.000889, 0.000455, 0.000191,
-];
-
-struct AudioDac {
-    sample_buffer: Vec<f32>,
-    presample_buffer: [f32; 41],
-    presample_pointer: usize,
-}
+use std::mem::size_of;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-impl AudioDac {
-    fn new() -> Self {
-        AudioDac {
-            sample_buffer: vec![],
-            presample_buffer: [0f32; 41],
-            presample_pointer: 0,
-        }
-    }
+/// How often the frame rate in the window title is updated
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
 
-    fn add_sample(&mut self, sample: f32) {
-        // Roughly 40.5 generated per sample output so we spread the 41st pre sample between
-        // one sample and the next
-        let adjust = match self.presample_pointer {
-            0 => 0.483f32,
-            1 => 0.517f32,
-            _ => 1f32,
-        };
-        self.presample_buffer[self.presample_pointer] = sample * adjust;
-        self.presample_pointer += 1;
-
-        if self.presample_pointer == self.presample_buffer.len() {
-            self.sample_buffer.push(self.down_sample_buffer());
-            self.presample_pointer = 0;
-        }
-    }
+/// A rom running in the window, replaced when another rom is dropped onto it
+pub(crate) struct Session {
+    pub nes: Nes,
+    /// The rom and its mapper, the window title adds the frame rate and fast forward state
+    pub title: String,
+    pub region: Region,
+    pub battery_save: Option<BatterySave>,
+    /// Saved when the window closes or another rom is dropped onto it, None if resuming is off
+    pub resume_state: Option<ResumeState>,
+}
 
-    fn down_sample_buffer(&self) -> f32 {
-        self.presample_buffer
-            .iter()
-            .zip(FIR_FILTER.iter())
-            .map(|(s, f)| s * f)
-            .fold(0f32, |a, b| a + b)
-    }
+/// How frames are presented in the window
+pub(crate) struct DisplayOptions {
+    /// The window is twice this size
+    pub screen_width: u32,
+    pub screen_height: u32,
+    /// Show the buttons the game read from controller one
+    pub input_display: bool,
+    /// Frame blending persistence to start with, None starts with blending off
+    pub frame_blend: Option<f32>,
+}
+
+/// The keys for controller one and the emulator's own controls
+pub(crate) struct Controls {
+    pub key_bindings: KeyBindings,
+    pub hotkeys: Hotkeys,
 }
 
 pub(crate) fn run(
-    screen_width: u32,
-    screen_height: u32,
-    prg_address_bus: Box<dyn CpuCartridgeAddressBus>,
-    chr_address_bus: Box<dyn PpuCartridgeAddressBus>,
-    cartridge_header: CartridgeHeader,
+    session: Session,
+    load_rom: &dyn Fn(&str) -> Result<Session, CartridgeError>,
+    audio_output: AudioOutputConfig,
+    memory_dir: &str,
+    display: DisplayOptions,
+    controls: Controls,
 ) -> std::io::Result<()> {
+    let Session {
+        mut nes,
+        mut title,
+        mut region,
+        mut battery_save,
+        mut resume_state,
+    } = session;
+
+    let Controls {
+        mut key_bindings,
+        hotkeys,
+    } = controls;
+
     let sdl = sdl2::init().unwrap();
 
     // Set up audio subsystem
     let audio = sdl.audio().unwrap();
     let desired_spec = AudioSpecDesired {
-        freq: Some(44_100),
+        freq: Some(audio_output.sample_rate as i32),
         channels: Some(1),
-        samples: Some(1024),
+        samples: Some(audio_output.buffer_size),
     };
     let audio_device = audio.open_queue::<f32, _>(None, &desired_spec).unwrap();
     audio_device.resume();
+    let mut resampler = create_resampler(region, audio_device.spec().freq, audio_output.quality);
+    let mut frame_samples = vec![];
+    let mut samples = vec![];
+    let latency_samples = audio_output.target_latency_samples();
+    let mut underruns = UnderrunCounter::new();
 
     // Set up video subsystem
     let video_subsystem = sdl.video().unwrap();
     let window = video_subsystem
-        .window(
-            &format!("NES - {:}", cartridge_header),
-            screen_width * 2,
-            screen_height * 2,
-        )
+        .window(&title, display.screen_width * 2, display.screen_height * 2)
         .build()
         .unwrap();
 
-    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string()).unwrap();
+    let canvas = window.into_canvas().build().map_err(|e| e.to_string()).unwrap();
     let texture_creator = canvas.texture_creator();
-
-    let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::ARGB8888, screen_width, screen_height)
-        .map_err(|e| e.to_string())
-        .unwrap();
+    let mut video = SdlVideo::new(canvas, &texture_creator, &display);
 
     let mut event_pump = sdl.event_pump().unwrap();
 
-    let mut apu = Apu::new();
-    let mut io = Io::new();
-    let mut ppu = Ppu::new(chr_address_bus);
-    let mut cpu = Cpu::new(prg_address_bus, &mut apu, &mut io, &mut ppu);
-    let mut time_of_last_render = time::Instant::now();
-    let frame_duration = time::Duration::from_millis(17);
+    let mut frame_limiter = FrameLimiter::new(region.frame_rate());
     let mut is_paused = false;
-    let mut dac = AudioDac::new();
+    let mut recording: Option<Repro> = None;
+    let mut was_jammed = false;
+    let mut fast_forward = false;
+    let mut palette_tint = None;
+    let mut capture: Option<KeyCapture> = None;
+    report_conflicts(&hotkeys, &key_bindings);
+    let mut fps_counter = FpsCounter::new();
 
     'main: loop {
         if !is_paused {
-            let (ppu_state, apu_sample) = cpu.next().unwrap();
+            run_frame(&mut nes, &mut video, &mut frame_samples);
+            info!("Frame complete, rendering");
+            for sample in frame_samples.drain(..) {
+                resampler.add_sample(sample);
+            }
+            if let Some(buttons) = nes.take_controller_read(Controller::One) {
+                video.set_buttons_read(buttons);
+            }
 
-            if let Some(sample) = apu_sample {
-                dac.add_sample(sample);
+            // The title shows the prompt while capturing a key profile
+            if fps_counter.frame() && capture.is_none() {
+                let status = status_title(&title, fps_counter.fps, fast_forward, underruns.underruns());
+                video.set_title(&status);
             }
 
-            if let Some(PpuIteratorState::ReadyToRender) = ppu_state {
-                info!("Frame complete, rendering");
+            for diagnostic_event in nes.take_diagnostic_events() {
+                error!("Possible crash detected");
+                eprintln!("{}", diagnostic_event);
+            }
 
-                let framebuffer = cpu.get_framebuffer();
-                texture.update(None, framebuffer, screen_width as usize * 4).unwrap();
-                canvas.clear();
-                canvas.copy(&texture, None, None).unwrap();
-                canvas.present();
+            if nes.is_jammed() && !was_jammed {
+                let pc = nes.registers().program_counter.wrapping_sub(1);
+                error!("CPU jammed");
+                eprintln!("CPU jammed by a KIL opcode at {:04X}", pc);
+            }
+            was_jammed = nes.is_jammed();
 
-                for event in event_pump.poll_iter() {
-                    info!("{:?}", event);
+            for event in event_pump.poll_iter() {
+                info!("{:?}", event);
+                if let Some(mut key_capture) = capture.take() {
                     match event {
-                        Event::Quit { .. }
-                        | Event::KeyDown {
-                            keycode: Some(Keycode::Escape),
-                            ..
-                        } => {
+                        Event::Quit { .. } => {
                             info!("Quitting emulation");
                             break 'main;
                         }
+                        Event::KeyDown {
+                            keycode: Some(Keycode::Escape),
+                            ..
+                        } => video.set_title(&title),
                         Event::KeyDown {
                             keycode: Some(keycode), ..
-                        } => match keycode {
-                            Keycode::Z => cpu.button_down(Controller::One, Button::A),
-                            Keycode::X => cpu.button_down(Controller::One, Button::B),
-                            Keycode::Return => cpu.button_down(Controller::One, Button::Start),
-                            Keycode::Tab => cpu.button_down(Controller::One, Button::Select),
-                            Keycode::Left => cpu.button_down(Controller::One, Button::Left),
-                            Keycode::Right => cpu.button_down(Controller::One, Button::Right),
-                            Keycode::Up => cpu.button_down(Controller::One, Button::Up),
-                            Keycode::Down => cpu.button_down(Controller::One, Button::Down),
-                            Keycode::Space => {
-                                if is_paused {
-                                    audio_device.resume();
-                                } else {
-                                    audio_device.pause();
+                        } => match key_capture.capture(keycode) {
+                            None => {
+                                video.set_title(&key_capture.prompt());
+                                capture = Some(key_capture);
+                            }
+                            Some(keys) => {
+                                match key_bindings.save_profile(key_capture.name(), keys) {
+                                    Err(why) => error!("Unable to write {}: {}", key_bindings.path().display(), why),
+                                    Ok(()) => println!(
+                                        "Saved key profile {} to {}",
+                                        key_bindings.profile_name(),
+                                        key_bindings.path().display()
+                                    ),
                                 }
-                                is_paused = !is_paused;
+                                report_conflicts(&hotkeys, &key_bindings);
+                                video.set_title(&title);
+                            }
+                        },
+                        _ => capture = Some(key_capture),
+                    }
+                    continue;
+                }
+
+                match event {
+                    Event::Quit { .. } => {
+                        info!("Quitting emulation");
+                        break 'main;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        keymod,
+                        ..
+                    } => match hotkeys.hotkey(keycode, keymod, &key_bindings) {
+                        None => {
+                            if let Some(button) = key_bindings.button(keycode) {
+                                nes.button_down(Controller::One, button);
                             }
-                            Keycode::T => {
-                                let framebuffer = cpu.get_framebuffer();
-                                let cycles = cpu.cycles;
-                                let mut hasher = Hasher::new();
-                                hasher.update(framebuffer);
-                                let checksum = hasher.finalize();
-
-                                println!("Cycles: {:X}, FrameBuffer CRC32, {:}", cycles, checksum);
+                        }
+                        Some(Hotkey::Quit) => {
+                            info!("Quitting emulation");
+                            break 'main;
+                        }
+                        Some(Hotkey::FastForward) => fast_forward = true,
+                        Some(Hotkey::Pause) => {
+                            if is_paused {
+                                audio_device.resume();
+                                frame_limiter.reset();
+                                underruns.restart();
+                            } else {
+                                audio_device.pause();
                             }
-                            Keycode::D => {
-                                // Dump contents of PPU
-                                let mut vram = [0; 0x4000];
-                                let oam_ram = cpu.dump_ppu_state(&mut vram);
-                                let mut vram_file = File::create("vram.csv").unwrap();
-                                let mut oam_ram_file = File::create("oam_ram.csv").unwrap();
-
-                                for b in vram.iter() {
-                                    writeln!(vram_file, "{:02X}", b)?;
+                            is_paused = !is_paused;
+                        }
+                        Some(Hotkey::SaveState) => {
+                            let path = Path::new(memory_dir).join("quick.state");
+                            File::create(&path)?.write_all(&nes.save_state())?;
+                            println!("State saved to {}", path.display());
+                        }
+                        Some(Hotkey::LoadState) => {
+                            let path = Path::new(memory_dir).join("quick.state");
+                            match std::fs::read(&path) {
+                                Err(why) => error!("Unable to read {}: {}", path.display(), why),
+                                Ok(state) => {
+                                    if let Err(why) = nes.load_state(&state) {
+                                        error!("{}", why);
+                                    }
                                 }
+                            }
+                        }
+                        Some(Hotkey::Screenshot) => {
+                            let file_name = format!("screenshot_{}.png", nes.frame_number());
+                            let path = Path::new(memory_dir).join(file_name);
+                            write_png(&path, nes.get_framebuffer())?;
+                            println!("Screenshot saved to {}", path.display());
+                        }
+                        Some(Hotkey::FrameChecksum) => {
+                            let cycles = nes.cycles();
+                            let framebuffer = nes.get_framebuffer();
+                            let mut hasher = Hasher::new();
+                            hasher.update(framebuffer);
+                            let checksum = hasher.finalize();
 
-                                for b in oam_ram.iter() {
-                                    writeln!(oam_ram_file, "{:02X}", b)?;
+                            println!("Cycles: {:X}, FrameBuffer CRC32, {:}", cycles, checksum);
+                        }
+                        Some(Hotkey::ToggleFrameBlend) => {
+                            let blend_frames = video.toggle_blend();
+                            println!("Frame blending {}", if blend_frames { "on" } else { "off" });
+                        }
+                        Some(Hotkey::TogglePaletteTint) => {
+                            // Tint pixels by the palette they were drawn with to spot attribute mistakes
+                            palette_tint = match palette_tint {
+                                None => Some(PaletteTint::default()),
+                                Some(_) => None,
+                            };
+                            nes.set_palette_tint(palette_tint);
+                            println!("Palette tint {}", if palette_tint.is_some() { "on" } else { "off" });
+                        }
+                        Some(Hotkey::DumpMemory) => {
+                            // Dump each memory region to a file which can be loaded back with LoadMemory
+                            for region in MemoryRegion::ALL.iter() {
+                                let path = Path::new(memory_dir).join(format!("{}.bin", region));
+                                File::create(&path)?.write_all(&nes.dump_memory(*region))?;
+                            }
+                            println!("Memory dumped to {}", memory_dir);
+                        }
+                        Some(Hotkey::LoadMemory) => {
+                            for region in MemoryRegion::ALL.iter() {
+                                let path = Path::new(memory_dir).join(format!("{}.bin", region));
+                                match std::fs::read(&path) {
+                                    Err(why) => error!("Unable to read {}: {}", path.display(), why),
+                                    Ok(data) => {
+                                        if let Err(why) = nes.load_memory(*region, &data) {
+                                            error!("{}", why);
+                                        }
+                                    }
                                 }
                             }
-                            _ => (),
-                        },
-                        Event::KeyUp {
-                            keycode: Some(keycode), ..
-                        } => match keycode {
-                            Keycode::Z => cpu.button_up(Controller::One, Button::A),
-                            Keycode::X => cpu.button_up(Controller::One, Button::B),
-                            Keycode::Return => cpu.button_up(Controller::One, Button::Start),
-                            Keycode::Tab => cpu.button_up(Controller::One, Button::Select),
-                            Keycode::Left => cpu.button_up(Controller::One, Button::Left),
-                            Keycode::Right => cpu.button_up(Controller::One, Button::Right),
-                            Keycode::Up => cpu.button_up(Controller::One, Button::Up),
-                            Keycode::Down => cpu.button_up(Controller::One, Button::Down),
-                            _ => (),
+                        }
+                        Some(Hotkey::ToggleRecording) => match recording.take() {
+                            // Record a reproduction from this point until the hotkey is pressed again
+                            None => recording = Some(Repro::record(&mut nes)),
+                            Some(repro) => {
+                                let path = Path::new(memory_dir).join("recording.repro");
+                                File::create(&path)?.write_all(&repro.to_bytes())?;
+                                println!("Recorded {} frames to {}", repro.frames, path.display());
+                            }
                         },
-                        _ => (),
-                    };
-                }
+                        Some(Hotkey::NextKeyProfile) => {
+                            // Release everything held with the old profile's keys
+                            key_bindings.next_profile();
+                            nes.set_controller_state(Controller::One, 0);
+                            println!("Key profile {}", key_bindings.profile_name());
+                            report_conflicts(&hotkeys, &key_bindings);
+                        }
+                        Some(hotkey @ Hotkey::RemapKeyProfile) | Some(hotkey @ Hotkey::NewKeyProfile) => {
+                            // Remap the current profile or capture a new one, saving it once every
+                            // button has a key
+                            let name = match hotkey {
+                                Hotkey::RemapKeyProfile => key_bindings.profile_name().to_string(),
+                                _ => key_bindings.new_profile_name(),
+                            };
+                            let key_capture = KeyCapture::new(name, hotkeys.unmodified_keys());
+                            nes.set_controller_state(Controller::One, 0);
+                            video.set_title(&key_capture.prompt());
+                            capture = Some(key_capture);
+                        }
+                    },
+                    Event::DropFile { filename, .. } => {
+                        // Write the old rom's save before loading in case the same rom was dropped
+                        if let Some(battery_save) = battery_save.as_mut() {
+                            if let Err(why) = battery_save.update(&mut nes).and_then(|_| battery_save.flush()) {
+                                error!("Unable to write {}: {}", battery_save.path().display(), why);
+                            }
+                        }
+                        save_resume_state(resume_state.as_ref(), &mut nes);
+
+                        let session = match load_rom(&filename) {
+                            Err(why) => {
+                                error!("Failed to load {}: {}", filename, why.message);
+                                eprintln!("Failed to load {}\n\n{}", filename, why.message);
+                                continue;
+                            }
+                            Ok(session) => session,
+                        };
+                        info!("Swapping to {}", filename);
+
+                        // Drop the old rom's audio rather than playing it over the start of the new one
+                        audio_device.pause();
+                        audio_device.clear();
+                        nes = session.nes;
+                        title = session.title;
+                        region = session.region;
+                        battery_save = session.battery_save;
+                        resume_state = session.resume_state;
+                        nes.set_palette_tint(palette_tint);
+
+                        video.set_title(&title);
+                        resampler = create_resampler(region, audio_device.spec().freq, audio_output.quality);
+                        frame_limiter = FrameLimiter::new(region.frame_rate());
+                        fps_counter = FpsCounter::new();
+                        underruns.restart();
+                        recording = None;
+                        was_jammed = false;
+                        is_paused = false;
+                        video.reset();
+                        audio_device.resume();
+                        break;
+                    }
+                    Event::KeyUp {
+                        keycode: Some(keycode), ..
+                    } => {
+                        if fast_forward && hotkeys.is_key(Hotkey::FastForward, keycode) {
+                            fast_forward = false;
+                            frame_limiter.reset();
+                            underruns.restart();
+                        }
+                        if let Some(button) = key_bindings.button(keycode) {
+                            nes.button_up(Controller::One, button);
+                        }
+                    }
+                    _ => (),
+                };
+            }
 
-                // Wait so that we render at 60fps
-                let diff = time::Instant::now() - time_of_last_render;
-                if diff < frame_duration {
-                    info!("Sleeping {:?}", frame_duration - diff);
-                    thread::sleep(frame_duration - diff);
+            if let Some(repro) = recording.as_mut() {
+                repro.record_frame(&nes);
+            }
+
+            if let Some(battery_save) = battery_save.as_mut() {
+                if let Err(why) = battery_save.update(&mut nes) {
+                    error!("Unable to write {}: {}", battery_save.path().display(), why);
                 }
-                time_of_last_render = time::Instant::now();
+            }
 
-                // Make sure that the audio is sync'd to the framerate before queuing more
-                while audio_device.size() > 0 {}
-                audio_device.queue(&dac.sample_buffer.as_slice());
-                dac.sample_buffer.clear();
+            if fast_forward {
+                // Drop the audio rather than queue more than the device can play
+                resampler.read_samples(&mut samples);
+                samples.clear();
+                continue;
             }
+
+            // Wait so that we render at the console's frame rate
+            let waited = frame_limiter.wait();
+            info!("Waited {:?} for the next frame", waited);
+
+            // Make sure that the audio is sync'd to the framerate before queuing more, keeping
+            // the target latency queued so that a late frame doesn't leave the device with nothing
+            while audio_device.size() as usize / size_of::<f32>() > latency_samples {}
+            if underruns.record(audio_device.size() as usize / size_of::<f32>()) {
+                warn!("Audio underrun, the device ran out of samples");
+                if capture.is_none() {
+                    let status = status_title(&title, fps_counter.fps, fast_forward, underruns.underruns());
+                    video.set_title(&status);
+                }
+                video.show_underrun();
+            }
+            resampler.read_samples(&mut samples);
+            audio_device.queue(&samples);
+            samples.clear();
         }
     }
 
+    save_resume_state(resume_state.as_ref(), &mut nes);
+
     Ok(())
 }
+
+fn save_resume_state(resume_state: Option<&ResumeState>, nes: &mut Nes) {
+    if let Some(resume_state) = resume_state {
+        if let Err(why) = resume_state.save(nes) {
+            error!("Unable to write {}: {}", resume_state.path().display(), why);
+        }
+    }
+}
+
+/// Warn about hotkeys which the current key profile stops from working
+fn report_conflicts(hotkeys: &Hotkeys, key_bindings: &KeyBindings) {
+    for conflict in hotkeys.conflicts(key_bindings) {
+        warn!("{}", conflict);
+        eprintln!("{}", conflict);
+    }
+}
+
+/// Counts the frames presented to measure the frame rate over each `STATUS_INTERVAL`
+struct FpsCounter {
+    frames: u32,
+    since: Instant,
+    fps: f64,
+}
+
+impl FpsCounter {
+    fn new() -> Self {
+        FpsCounter {
+            frames: 0,
+            since: Instant::now(),
+            fps: 0.0,
+        }
+    }
+
+    /// Count a frame, returns true when `fps` has been updated
+    fn frame(&mut self) -> bool {
+        self.frames += 1;
+        let elapsed = self.since.elapsed();
+        if elapsed < STATUS_INTERVAL {
+            return false;
+        }
+
+        self.fps = self.frames as f64 / elapsed.as_secs_f64();
+        self.frames = 0;
+        self.since = Instant::now();
+        true
+    }
+}
+
+/// The window title with the current frame rate and anything else worth knowing about the run
+fn status_title(title: &str, fps: f64, fast_forward: bool, underruns: u32) -> String {
+    let mut status = format!("{} - {:.1} FPS", title, fps);
+    if fast_forward {
+        status.push_str(" - fast forward");
+    }
+    if underruns > 0 {
+        status.push_str(&format!(" - {} audio underruns", underruns));
+    }
+
+    status
+}
+
+/// Frames are always emulated with NTSC timing so running them at another region's frame rate
+/// produces samples at a different rate too
+fn create_resampler(region: Region, output_rate: i32, quality: ResamplerQuality) -> Resampler {
+    let sample_rate = NTSC_SAMPLE_RATE * region.frame_rate() / NTSC_FRAME_RATE;
+    Resampler::new(sample_rate, output_rate as f64, quality)
+}
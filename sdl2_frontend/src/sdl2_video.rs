@@ -0,0 +1,132 @@
+use rust_nes::ppu::FrameBlender;
+use rust_nes::{Frame, VideoSink};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use sdl2_app::DisplayOptions;
+
+/// How long the underrun indicator stays on screen after the audio queue runs dry
+const UNDERRUN_DISPLAY_FRAMES: u32 = 60;
+
+/// Frame blending persistence when it's turned on with the hotkey without `--frame_blend`
+const DEFAULT_FRAME_BLEND: f32 = 0.5;
+
+/// Shows frames in the window, blended and with the overlays from `DisplayOptions`
+pub(crate) struct SdlVideo<'a> {
+    canvas: Canvas<Window>,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    /// Created for the size of the first frame and again whenever the size changes (e.g. when a
+    /// rom with an HD pack is swapped for one without)
+    texture: Option<(Texture<'a>, u32, u32)>,
+    blender: FrameBlender,
+    blend_frames: bool,
+    input_display: bool,
+    /// The buttons the game last read from controller one, for the input display
+    buttons_read: u8,
+    underrun_display_frames: u32,
+}
+
+impl<'a> SdlVideo<'a> {
+    pub(crate) fn new(
+        canvas: Canvas<Window>,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        display: &DisplayOptions,
+    ) -> Self {
+        SdlVideo {
+            canvas,
+            texture_creator,
+            texture: None,
+            blender: FrameBlender::new(display.frame_blend.unwrap_or(DEFAULT_FRAME_BLEND)),
+            blend_frames: display.frame_blend.is_some(),
+            input_display: display.input_display,
+            buttons_read: 0,
+            underrun_display_frames: 0,
+        }
+    }
+
+    pub(crate) fn set_title(&mut self, title: &str) {
+        self.canvas.window_mut().set_title(title).unwrap();
+    }
+
+    /// Turn frame blending on or off, returns whether it's now on
+    pub(crate) fn toggle_blend(&mut self) -> bool {
+        self.blend_frames = !self.blend_frames;
+        self.blender.reset();
+        self.blend_frames
+    }
+
+    pub(crate) fn set_buttons_read(&mut self, buttons: u8) {
+        self.buttons_read = buttons;
+    }
+
+    /// Show the underrun indicator for the next `UNDERRUN_DISPLAY_FRAMES` frames
+    pub(crate) fn show_underrun(&mut self) {
+        self.underrun_display_frames = UNDERRUN_DISPLAY_FRAMES;
+    }
+
+    /// Forget everything about the previous rom's frames
+    pub(crate) fn reset(&mut self) {
+        self.blender.reset();
+        self.buttons_read = 0;
+    }
+
+    /// Draw a box for each button in the bottom left corner, lit if the game read it as pressed
+    /// (A, B, Select, Start, Up, Down, Left, Right from left to right)
+    fn draw_input_display(&mut self) {
+        let (_, height) = self.canvas.output_size().unwrap();
+        for button in 0..8 {
+            let color = match self.buttons_read & (1 << button) {
+                0 => Color::RGB(0x40, 0x40, 0x40),
+                _ => Color::RGB(0xFF, 0xFF, 0xFF),
+            };
+            self.canvas.set_draw_color(color);
+            self.canvas
+                .fill_rect(Rect::new(8 + button * 14, height as i32 - 20, 12, 12))
+                .unwrap();
+        }
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    }
+
+    /// Draw a red box in the top right corner while the audio has recently underrun, the total is
+    /// shown in the window title
+    fn draw_underrun_indicator(&mut self) {
+        let (width, _) = self.canvas.output_size().unwrap();
+        self.canvas.set_draw_color(Color::RGB(0xFF, 0x00, 0x00));
+        self.canvas.fill_rect(Rect::new(width as i32 - 20, 8, 12, 12)).unwrap();
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    }
+}
+
+impl<'a> VideoSink for SdlVideo<'a> {
+    fn present(&mut self, frame: &Frame) {
+        let size = (frame.width, frame.height);
+        if !matches!(self.texture, Some((_, width, height)) if (width, height) == size) {
+            let texture = self
+                .texture_creator
+                .create_texture_streaming(PixelFormatEnum::ARGB8888, frame.width, frame.height)
+                .map_err(|e| e.to_string())
+                .unwrap();
+            self.texture = Some((texture, frame.width, frame.height));
+        }
+        let (texture, _, _) = self.texture.as_mut().unwrap();
+
+        let pixels = if self.blend_frames {
+            self.blender.blend(frame.pixels)
+        } else {
+            frame.pixels
+        };
+        texture.update(None, pixels, frame.width as usize * 4).unwrap();
+
+        self.canvas.clear();
+        self.canvas.copy(texture, None, None).unwrap();
+        if self.input_display {
+            self.draw_input_display();
+        }
+        if self.underrun_display_frames > 0 {
+            self.draw_underrun_indicator();
+            self.underrun_display_frames -= 1;
+        }
+        self.canvas.present();
+    }
+}
@@ -0,0 +1,83 @@
+use rust_nes::apu::{write_wav, AudioOutputConfig, ChannelSamples, Resampler, NTSC_SAMPLE_RATE};
+use rust_nes::{Event, Nes};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+type ChannelSelector = fn(&ChannelSamples) -> f32;
+
+const STEMS: [(&str, ChannelSelector); 5] = [
+    ("pulse_1", |samples| samples.pulse_1),
+    ("pulse_2", |samples| samples.pulse_2),
+    ("triangle", |samples| samples.triangle),
+    ("noise", |samples| samples.noise),
+    ("dmc", |samples| samples.dmc),
+];
+
+/// Run the console headless for a number of frames and write the audio to a WAV file, with
+/// each channel optionally written alongside it (e.g. out_triangle.wav) for comparing changes
+/// to the APU
+pub(crate) fn run(
+    mut nes: Nes,
+    frames: u32,
+    path: &str,
+    stems: bool,
+    config: AudioOutputConfig,
+) -> std::io::Result<()> {
+    nes.record_channel_samples(stems);
+
+    let mut mixed = ResampledOutput::new(config);
+    let mut channels = if stems {
+        STEMS.iter().map(|_| ResampledOutput::new(config)).collect()
+    } else {
+        vec![]
+    };
+
+    for _ in 0..frames {
+        let run = nes.run_until(Event::Frame);
+        mixed.add_samples(run.samples.iter().cloned());
+        for (output, (_, channel)) in channels.iter_mut().zip(STEMS.iter()) {
+            output.add_samples(run.channel_samples.iter().map(channel));
+        }
+    }
+
+    let path = Path::new(path);
+    mixed.write(path, config)?;
+
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("audio");
+    for (output, (name, _)) in channels.iter().zip(STEMS.iter()) {
+        output.write(&path.with_file_name(format!("{}_{}.wav", stem, name)), config)?;
+    }
+
+    Ok(())
+}
+
+/// Resamples each frame's audio as it's produced so only the output rate samples are kept
+struct ResampledOutput {
+    resampler: Resampler,
+    samples: Vec<f32>,
+}
+
+impl ResampledOutput {
+    fn new(config: AudioOutputConfig) -> Self {
+        ResampledOutput {
+            resampler: config.resampler(NTSC_SAMPLE_RATE),
+            samples: vec![],
+        }
+    }
+
+    fn add_samples<I: Iterator<Item = f32>>(&mut self, samples: I) {
+        for sample in samples {
+            self.resampler.add_sample(sample);
+        }
+        self.resampler.read_samples(&mut self.samples);
+    }
+
+    fn write(&self, path: &Path, config: AudioOutputConfig) -> std::io::Result<()> {
+        write_wav(
+            &mut BufWriter::new(File::create(path)?),
+            config.sample_rate,
+            &self.samples,
+        )
+    }
+}
@@ -0,0 +1,219 @@
+//! Runs a rom in lockstep with a trace log from a reference emulator (Mesen, FCEUX or the
+//! nestest log) and reports the first instruction where the registers, flags or cycle counts
+//! differ, along with the instructions leading up to it.
+
+extern crate clap;
+extern crate rust_nes;
+
+mod reference;
+
+use clap::Clap;
+use reference::ReferenceLine;
+use rust_nes::cpu::ExecutedInstruction;
+use rust_nes::Nes;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process;
+use std::sync::mpsc::Receiver;
+
+/// The break and unused flags only exist when the status is pushed to the stack so emulators
+/// disagree on how to log them
+const STATUS_MASK: u8 = 0b1100_1111;
+
+/// Instructions to run looking for the first instruction of the trace before giving up
+const SYNC_INSTRUCTION_LIMIT: usize = 1_000_000;
+
+#[derive(Clap)]
+#[clap(version = "1.0", author = "David Tyler <davet.code@gmail.com>")]
+struct Opts {
+    rom_file: String,
+    trace_file: String,
+    /// Jump here before running, e.g. C000 for nestest's automated mode
+    #[clap(long = "start_pc", parse(try_from_str = parse_address))]
+    start_pc: Option<u16>,
+    /// Instructions shown before the divergence
+    #[clap(long = "context", default_value = "10")]
+    context: usize,
+    /// Don't compare cycle counts, for when only the register values are of interest
+    #[clap(long = "ignore_cycles")]
+    ignore_cycles: bool,
+}
+
+fn parse_address(value: &str) -> Result<u16, String> {
+    u16::from_str_radix(value.trim_start_matches('$'), 16).map_err(|why| why.to_string())
+}
+
+fn main() -> std::io::Result<()> {
+    let opts: Opts = Opts::parse();
+    let cartridge = match rust_nes::get_cartridge(&opts.rom_file) {
+        Err(why) => panic!("Failed to load {}: {}", opts.rom_file, why.message),
+        Ok(cartridge) => cartridge,
+    };
+    let mut nes = Nes::new(cartridge);
+    if let Some(pc) = opts.start_pc {
+        nes.set_program_counter(pc);
+    }
+    let stream = nes.instruction_stream(1);
+
+    let mut reference = BufReader::new(File::open(&opts.trace_file)?)
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| match line {
+            Err(why) => Some(Err(why)),
+            Ok(line) => reference::parse_line(index + 1, &line).map(Ok),
+        });
+
+    let first = match reference.next() {
+        None => {
+            eprintln!("No instructions found in {}", opts.trace_file);
+            process::exit(2);
+        }
+        Some(line) => line?,
+    };
+
+    // Traces often start part way through a run, so run until the first instruction they have
+    let mut actual = next_instruction(&mut nes, &stream);
+    let mut skipped = 0;
+    while actual.pc != first.pc {
+        skipped += 1;
+        if skipped == SYNC_INSTRUCTION_LIMIT {
+            eprintln!(
+                "The rom never ran the first instruction of the trace at {:04X}",
+                first.pc
+            );
+            process::exit(2);
+        }
+        actual = next_instruction(&mut nes, &stream);
+    }
+    if skipped > 0 {
+        println!("Skipped {} instructions to reach {:04X}", skipped, first.pc);
+    }
+
+    // Cycle counts start from different points in different emulators so compare them relative to
+    // the first instruction
+    let cycle_offset = first.cycles.map(|cycles| actual.cycles as i64 - cycles as i64);
+    let mut history = VecDeque::with_capacity(opts.context);
+    let mut expected = first;
+    let mut compared = 0;
+
+    loop {
+        let differences = differences(&expected, &actual, cycle_offset.filter(|_| !opts.ignore_cycles));
+        if !differences.is_empty() {
+            report_divergence(&history, &expected, &actual, &differences);
+            process::exit(1);
+        }
+
+        compared += 1;
+        if history.len() == opts.context {
+            history.pop_front();
+        }
+        history.push_back((expected, actual));
+
+        expected = match reference.next() {
+            None => break,
+            Some(line) => line?,
+        };
+        actual = next_instruction(&mut nes, &stream);
+    }
+
+    println!("All {} instructions in the trace matched", compared);
+
+    Ok(())
+}
+
+/// Run the console until it starts the next instruction
+fn next_instruction(nes: &mut Nes, stream: &Receiver<ExecutedInstruction>) -> ExecutedInstruction {
+    loop {
+        nes.next();
+        if let Ok(instruction) = stream.try_recv() {
+            return instruction;
+        }
+    }
+}
+
+fn differences(expected: &ReferenceLine, actual: &ExecutedInstruction, cycle_offset: Option<i64>) -> Vec<String> {
+    let registers = &actual.registers;
+    let mut differences = vec![];
+    let mut compare = |name: &str, expected: u64, actual: u64, width: usize| {
+        if expected != actual {
+            differences.push(format!(
+                "{}: expected {:0width$X} but was {:0width$X}",
+                name,
+                expected,
+                actual,
+                width = width
+            ));
+        }
+    };
+
+    compare("PC", expected.pc as u64, actual.pc as u64, 4);
+    if let Some(opcode) = expected.opcode {
+        compare("Opcode", opcode as u64, actual.opcode as u64, 2);
+    }
+    compare("A", expected.a as u64, registers.a as u64, 2);
+    compare("X", expected.x as u64, registers.x as u64, 2);
+    compare("Y", expected.y as u64, registers.y as u64, 2);
+    compare(
+        "P",
+        (expected.status & STATUS_MASK) as u64,
+        (registers.status & STATUS_MASK) as u64,
+        2,
+    );
+    compare("SP", expected.stack_pointer as u64, registers.stack_pointer as u64, 2);
+    if let (Some(cycles), Some(offset)) = (expected.cycles, cycle_offset) {
+        let actual_cycles = actual.cycles as i64 - offset;
+        if cycles as i64 != actual_cycles {
+            differences.push(format!(
+                "Cycles: expected {} but was {} ({:+})",
+                cycles,
+                actual_cycles,
+                actual_cycles - cycles as i64
+            ));
+        }
+    }
+
+    differences
+}
+
+fn format_instruction(instruction: &ExecutedInstruction) -> String {
+    let bytes = std::iter::once(instruction.opcode)
+        .chain(instruction.operands.iter().cloned())
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let registers = &instruction.registers;
+
+    format!(
+        "{:04X}  {:<8}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+        instruction.pc,
+        bytes,
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.status,
+        registers.stack_pointer,
+        instruction.cycles
+    )
+}
+
+fn report_divergence(
+    history: &VecDeque<(ReferenceLine, ExecutedInstruction)>,
+    expected: &ReferenceLine,
+    actual: &ExecutedInstruction,
+    differences: &[String],
+) {
+    println!("Matching instructions before the divergence:");
+    for (line, instruction) in history {
+        println!("{}", line);
+        println!("    ours: {}", format_instruction(instruction));
+    }
+
+    println!();
+    println!("First divergence:");
+    println!("{}", expected);
+    println!("    ours: {}", format_instruction(actual));
+    for difference in differences {
+        println!("  {}", difference);
+    }
+}
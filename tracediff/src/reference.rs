@@ -0,0 +1,159 @@
+//! Parses the trace logs written by other emulators, the formats differ in layout and in how
+//! they write flags so rather than parsing each format exactly the fields are picked out by name:
+//!
+//! nestest/Nintendulator: `C000  4C F5 C5  JMP $C5F5    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`
+//! Mesen:                 `C000  4C F5 C5  JMP $C5F5    A:00 X:00 Y:00 S:FD P:nvUbdIzc V:0 H:21 Fr:0 Cycle:7`
+//! FCEUX:                 `c7  i0  A:00 X:00 Y:00 S:FD P:nvUbdIzc  $C000: 4C F5 C5  JMP $C5F5`
+
+use std::fmt::{Display, Formatter, Result};
+
+/// The state at the start of an instruction as written by the reference emulator, fields the
+/// format doesn't include are None
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceLine {
+    pub line_number: usize,
+    pub pc: u16,
+    pub opcode: Option<u8>,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+    pub stack_pointer: u8,
+    /// CPU cycles since power on, old nestest logs only have the PPU dot so don't have this
+    pub cycles: Option<u64>,
+    pub text: String,
+}
+
+impl Display for ReferenceLine {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{:>7}: {}", self.line_number, self.text.trim_end())
+    }
+}
+
+/// Parse a single line, None for lines which aren't instructions (blank lines, headers and
+/// messages such as "Reset" or "NMI")
+pub fn parse_line(line_number: usize, text: &str) -> Option<ReferenceLine> {
+    let tokens = text.split_whitespace().collect::<Vec<_>>();
+
+    let field = |names: &[&str]| -> Option<&str> {
+        tokens.iter().enumerate().find_map(|(index, token)| {
+            let (name, value) = split_field(token)?;
+            if !names.contains(&name) {
+                return None;
+            }
+            // Some formats pad the value after the colon e.g. "CYC:  7"
+            match value {
+                "" => tokens.get(index + 1).copied(),
+                _ => Some(value),
+            }
+        })
+    };
+    let hex_field = |names: &[&str]| field(names).and_then(|value| u8::from_str_radix(value, 16).ok());
+
+    let (pc_index, pc) = tokens.iter().enumerate().find_map(|(index, token)| {
+        let address = token.trim_start_matches('$').trim_end_matches(':');
+        // Addresses are upper case which tells them apart from FCEUX's cycle and instruction counts
+        let is_address = address.len() == 4 && address.chars().all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c));
+        if is_address && (!token.contains(':') || token.ends_with(':')) {
+            u16::from_str_radix(address, 16).ok().map(|pc| (index, pc))
+        } else {
+            None
+        }
+    })?;
+    let opcode = tokens
+        .get(pc_index + 1)
+        .map(|token| token.trim_start_matches('$'))
+        .filter(|token| token.len() == 2)
+        .and_then(|token| u8::from_str_radix(token, 16).ok());
+
+    let cycles = field(&["Cycle", "CYC"])
+        .and_then(|value| value.parse().ok())
+        // Old nestest logs give the PPU dot as CYC alongside the scanline
+        .filter(|_| field(&["SL"]).is_none())
+        .or_else(|| {
+            tokens
+                .iter()
+                .find(|token| token.starts_with('c') && token.len() > 1)
+                .and_then(|token| token[1..].parse().ok())
+        });
+
+    Some(ReferenceLine {
+        line_number,
+        pc,
+        opcode,
+        a: hex_field(&["A"])?,
+        x: hex_field(&["X"])?,
+        y: hex_field(&["Y"])?,
+        status: field(&["P"]).and_then(parse_status)?,
+        stack_pointer: hex_field(&["SP", "S"])?,
+        cycles,
+        text: text.to_string(),
+    })
+}
+
+fn split_field(token: &str) -> Option<(&str, &str)> {
+    let colon = token.find(':')?;
+    Some((&token[..colon], &token[colon + 1..]))
+}
+
+/// Either hex (nestest) or a letter per flag, upper case when set (Mesen and FCEUX)
+fn parse_status(value: &str) -> Option<u8> {
+    if value.len() != 8 {
+        return u8::from_str_radix(value, 16).ok();
+    }
+
+    Some(value.chars().fold(0, |status, flag| {
+        (status << 1) | if flag.is_ascii_uppercase() { 1 } else { 0 }
+    }))
+}
+
+#[cfg(test)]
+mod reference_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nestest_line() {
+        let line = parse_line(
+            1,
+            "C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD CYC: 15 SL:241",
+        )
+        .unwrap();
+        assert_eq!((line.pc, line.opcode), (0xC5F7, Some(0x86)));
+        assert_eq!((line.status, line.stack_pointer, line.cycles), (0x26, 0xFD, None));
+
+        let line = parse_line(
+            2,
+            "C72C  D0 FB     BNE $C729   A:40 X:00 Y:00 P:24 SP:FB PPU:  0, 21 CYC:7",
+        )
+        .unwrap();
+        assert_eq!((line.a, line.cycles), (0x40, Some(7)));
+    }
+
+    #[test]
+    fn test_parse_mesen_line() {
+        let line = parse_line(
+            3,
+            "8000  78        SEI                      A:00 X:00 Y:00 S:FD P:nvUbdIzc V:0   H:21  Fr:0 Cycle:8",
+        )
+        .unwrap();
+        assert_eq!((line.pc, line.opcode), (0x8000, Some(0x78)));
+        assert_eq!((line.status, line.stack_pointer, line.cycles), (0x24, 0xFD, Some(8)));
+    }
+
+    #[test]
+    fn test_parse_fceux_line() {
+        let line = parse_line(
+            4,
+            "c29        i3        A:10 X:FF Y:00 S:FF P:NvUbdIzC  $8007: 8D 00 20  STA $2000 = #$00",
+        )
+        .unwrap();
+        assert_eq!((line.pc, line.opcode), (0x8007, Some(0x8D)));
+        assert_eq!((line.a, line.x, line.status, line.cycles), (0x10, 0xFF, 0xA5, Some(29)));
+    }
+
+    #[test]
+    fn test_non_instruction_lines_skipped() {
+        assert_eq!(parse_line(5, ""), None);
+        assert_eq!(parse_line(6, "Log Start"), None);
+    }
+}
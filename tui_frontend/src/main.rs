@@ -0,0 +1,60 @@
+//! Draws the screen in the terminal with half block characters, two pixels to each character,
+//! for running roms over SSH or as a quick smoke test where SDL isn't available. There's no audio.
+
+extern crate clap;
+#[macro_use]
+extern crate crossterm;
+extern crate rust_nes;
+
+mod terminal_input;
+mod terminal_video;
+
+use clap::Clap;
+use rust_nes::{apply_input, run_frame, FrameLimiter, Nes};
+use std::process;
+use terminal_input::TerminalInput;
+use terminal_video::TerminalVideo;
+
+#[derive(Clap)]
+#[clap(version = "1.0", author = "David Tyler <davet.code@gmail.com>")]
+struct Opts {
+    rom_file: String,
+    /// Only draw every Nth pixel in each direction, at 1 the terminal needs 256 columns and 120 rows
+    #[clap(long = "scale", default_value = "2")]
+    scale: u32,
+    /// Exit after this many frames rather than waiting for Escape or q
+    #[clap(long = "frames")]
+    frames: Option<u32>,
+}
+
+fn main() -> crossterm::Result<()> {
+    let opts: Opts = Opts::parse();
+
+    let cartridge = match rust_nes::get_cartridge(&opts.rom_file) {
+        Err(why) => {
+            eprintln!("Failed to load {}\n\n{}", opts.rom_file, why.message);
+            process::exit(1);
+        }
+        Ok(cartridge) => cartridge,
+    };
+    let mut frame_limiter = FrameLimiter::new(cartridge.2.region.frame_rate());
+    let mut nes = Nes::new(cartridge);
+
+    let mut video = TerminalVideo::new(opts.scale)?;
+    let mut input = TerminalInput::new();
+    let mut samples = vec![];
+    let mut frames = 0;
+    while opts.frames.is_none_or(|limit| frames < limit) {
+        run_frame(&mut nes, &mut video, &mut samples);
+        video.take_error()?;
+        samples.clear();
+        if !apply_input(&mut nes, &mut input) {
+            break;
+        }
+
+        frame_limiter.wait();
+        frames += 1;
+    }
+
+    Ok(())
+}
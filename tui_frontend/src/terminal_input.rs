@@ -0,0 +1,72 @@
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use rust_nes::io::{Button, Controller};
+use rust_nes::{InputEvent, InputProvider};
+use std::time::Duration;
+
+/// Terminals only report key presses (repeated while the key is held) so each button is held for
+/// this many frames after the last press of its key. This is long enough to cover the gap before
+/// a held key starts repeating on most terminals.
+const HOLD_FRAMES: u32 = 30;
+
+/// Controller one from the keyboard with the same keys as the SDL frontend, Escape or q quits
+pub(crate) struct TerminalInput {
+    /// The buttons held and the number of polls until each is released
+    held: Vec<(Button, u32)>,
+}
+
+impl TerminalInput {
+    pub(crate) fn new() -> Self {
+        TerminalInput { held: vec![] }
+    }
+}
+
+impl InputProvider for TerminalInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = vec![];
+        for (button, frames) in self.held.iter_mut() {
+            *frames -= 1;
+            if *frames == 0 {
+                events.push(InputEvent::ButtonUp(Controller::One, *button));
+            }
+        }
+        self.held.retain(|&(_, frames)| frames > 0);
+
+        while let Ok(true) = event::poll(Duration::from_secs(0)) {
+            let code = match event::read() {
+                Ok(Event::Key(KeyEvent { code, modifiers })) => match code {
+                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Esc,
+                    code => code,
+                },
+                _ => continue,
+            };
+
+            match (code, button(code)) {
+                (KeyCode::Esc, _) | (KeyCode::Char('q'), _) => events.push(InputEvent::Quit),
+                (_, Some(button)) => match self.held.iter_mut().find(|(held, _)| *held == button) {
+                    Some((_, frames)) => *frames = HOLD_FRAMES,
+                    None => {
+                        self.held.push((button, HOLD_FRAMES));
+                        events.push(InputEvent::ButtonDown(Controller::One, button));
+                    }
+                },
+                (_, None) => (),
+            }
+        }
+
+        events
+    }
+}
+
+fn button(code: KeyCode) -> Option<Button> {
+    match code {
+        KeyCode::Char('z') => Some(Button::A),
+        KeyCode::Char('x') => Some(Button::B),
+        KeyCode::Enter => Some(Button::Start),
+        KeyCode::Tab => Some(Button::Select),
+        KeyCode::Up => Some(Button::Up),
+        KeyCode::Down => Some(Button::Down),
+        KeyCode::Left => Some(Button::Left),
+        KeyCode::Right => Some(Button::Right),
+        _ => None,
+    }
+}
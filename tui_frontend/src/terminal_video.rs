@@ -0,0 +1,86 @@
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use rust_nes::{Frame, VideoSink};
+use std::io::{stdout, Stdout, Write};
+
+/// Draws each frame over the last in the terminal's alternate screen, which is left (and the
+/// terminal restored) when this is dropped
+pub(crate) struct TerminalVideo {
+    stdout: Stdout,
+    /// Only every `scale` pixels are drawn in each direction
+    scale: u32,
+    /// The first failure writing to the terminal, `present` can't return it
+    error: Option<crossterm::ErrorKind>,
+}
+
+impl TerminalVideo {
+    pub(crate) fn new(scale: u32) -> crossterm::Result<Self> {
+        let mut stdout = stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+
+        Ok(TerminalVideo {
+            stdout,
+            scale: scale.max(1),
+            error: None,
+        })
+    }
+
+    /// Fails if a frame couldn't be written to the terminal since this was last called
+    pub(crate) fn take_error(&mut self) -> crossterm::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Each character is an upper half block coloured with the top pixel on a background of the
+    /// bottom pixel, the colours are only sent when they change as that's most of the output
+    fn draw(&mut self, frame: &Frame) -> crossterm::Result<()> {
+        let colour = |x: u32, y: u32| {
+            let offset = ((y.min(frame.height - 1) * frame.width + x) * 4) as usize;
+            let bgra = &frame.pixels[offset..offset + 4];
+            Color::Rgb {
+                r: bgra[2],
+                g: bgra[1],
+                b: bgra[0],
+            }
+        };
+
+        let mut last_colours = None;
+        for (row, y) in (0..frame.height).step_by(self.scale as usize * 2).enumerate() {
+            queue!(self.stdout, MoveTo(0, row as u16))?;
+            for x in (0..frame.width).step_by(self.scale as usize) {
+                let colours = (colour(x, y), colour(x, y + self.scale));
+                if last_colours != Some(colours) {
+                    queue!(
+                        self.stdout,
+                        SetForegroundColor(colours.0),
+                        SetBackgroundColor(colours.1)
+                    )?;
+                    last_colours = Some(colours);
+                }
+                queue!(self.stdout, Print('▀'))?;
+            }
+        }
+
+        self.stdout.flush()
+    }
+}
+
+impl VideoSink for TerminalVideo {
+    fn present(&mut self, frame: &Frame) {
+        if let Err(error) = self.draw(frame) {
+            self.error.get_or_insert(error);
+        }
+    }
+}
+
+impl Drop for TerminalVideo {
+    fn drop(&mut self) {
+        // Nothing more can be done if the terminal can't be restored
+        let _ = execute!(self.stdout, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}